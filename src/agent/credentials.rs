@@ -0,0 +1,28 @@
+//! Credentials each known provider needs before it can be created.
+
+/// Credential keys each known provider needs; providers not listed are not checked
+pub const PROVIDER_CREDENTIALS: &[(&str, &[&str])] = &[
+    ("openai", &["OPENAI_API_KEY"]),
+    ("anthropic", &["ANTHROPIC_API_KEY"]),
+    ("google", &["GOOGLE_API_KEY"]),
+    ("groq", &["GROQ_API_KEY"]),
+    ("openrouter", &["OPENROUTER_API_KEY"]),
+    ("azure_openai", &["AZURE_OPENAI_API_KEY", "AZURE_OPENAI_ENDPOINT"]),
+    ("databricks", &["DATABRICKS_HOST"]),
+    ("ollama", &[]),
+];
+
+/// Credential keys of a provider that do not resolve through Goose's config, from the
+/// environment or the keyring; `None` for a provider whose keys are not known
+pub fn missing_credentials(provider: &str) -> Option<Vec<&'static str>> {
+    let (_, keys) = PROVIDER_CREDENTIALS
+        .iter()
+        .find(|(known, _)| *known == provider)?;
+    let config = goose::config::Config::global();
+    Some(
+        keys.iter()
+            .copied()
+            .filter(|key| config.get_secret::<String>(key).is_err())
+            .collect(),
+    )
+}
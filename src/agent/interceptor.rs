@@ -0,0 +1,224 @@
+//! Hooks around agent model exchanges, for recording prompts and responses in production.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use goose::conversation::message::{Message, MessageContent};
+use tracing::warn;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Environment variable name suffixes whose values are treated as secrets
+const SECRET_ENV_SUFFIXES: &[&str] = &["_API_KEY", "_TOKEN", "_SECRET", "_PASSWORD"];
+
+/// Token prefixes commonly used by provider and forge credentials
+const SECRET_TOKEN_PREFIXES: &[&str] = &["sk-", "ghp_", "gho_", "github_pat_", "xoxb-", "AKIA"];
+
+/// The prompt Kaiak sent for a request, passed to every hook
+#[derive(Debug, Clone, Copy)]
+pub struct ModelCall<'a> {
+    pub request_id: &'a str,
+    pub session_id: &'a str,
+    pub prompt: &'a str,
+}
+
+/// Observes model exchanges made by an agent on Kaiak's behalf.
+/// Hooks run inline on the request path, so implementations should be cheap.
+pub trait ModelInterceptor: Send + Sync {
+    /// Called once before the prompt is sent to the agent
+    fn before_call(&self, _call: &ModelCall<'_>) {}
+
+    /// Called for every message the agent produces in response
+    fn after_call(&self, _call: &ModelCall<'_>, _response: &Message) {}
+}
+
+/// Appends redacted prompt/response pairs as JSON lines to `<dir>/<session_id>.jsonl`
+pub struct FileRecordingInterceptor {
+    dir: PathBuf,
+    /// Serializes appends so lines from concurrent requests never interleave
+    write_lock: Mutex<()>,
+}
+
+impl FileRecordingInterceptor {
+    pub fn new(dir: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    fn record(&self, call: &ModelCall<'_>, kind: &str, role: Option<String>, text: &str) {
+        let entry = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "request_id": call.request_id,
+            "kind": kind,
+            "role": role,
+            "text": redact(text),
+        });
+
+        let path = self.dir.join(format!("{}.jsonl", call.session_id));
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| writeln!(file, "{}", entry));
+        if let Err(e) = result {
+            warn!(
+                "Failed to record model exchange to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+impl ModelInterceptor for FileRecordingInterceptor {
+    fn before_call(&self, call: &ModelCall<'_>) {
+        self.record(call, "prompt", None, call.prompt);
+    }
+
+    fn after_call(&self, call: &ModelCall<'_>, response: &Message) {
+        let text: Vec<String> = response
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                MessageContent::Text(text) => Some(text.text.to_string()),
+                MessageContent::ToolRequest(request) => match &request.tool_call {
+                    Ok(tool_call) => Some(format!("[tool call: {}]", tool_call.name)),
+                    Err(_) => None,
+                },
+                _ => None,
+            })
+            .collect();
+        if text.is_empty() {
+            return;
+        }
+        self.record(
+            call,
+            "response",
+            Some(format!("{:?}", response.role)),
+            &text.join("\n"),
+        );
+    }
+}
+
+/// Mask secret environment variable values and well-known credential tokens
+pub fn redact(text: &str) -> String {
+    let mut redacted = text.to_string();
+
+    for (name, value) in std::env::vars() {
+        // Short values would mask unrelated text
+        if value.len() >= 8
+            && SECRET_ENV_SUFFIXES
+                .iter()
+                .any(|suffix| name.ends_with(suffix))
+        {
+            redacted = redacted.replace(&value, REDACTED);
+        }
+    }
+
+    redacted
+        .split_inclusive(char::is_whitespace)
+        .map(|word| {
+            let token = word.trim_end();
+            let is_secret = SECRET_TOKEN_PREFIXES
+                .iter()
+                .any(|prefix| token.starts_with(prefix) && token.len() >= prefix.len() + 12);
+            if is_secret {
+                format!("{}{}", REDACTED, &word[token.len()..])
+            } else {
+                word.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Apply `redact` to every string in a JSON value
+pub fn redact_value(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => *text = redact(text),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_value),
+        serde_json::Value::Object(map) => map.values_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct CountingInterceptor {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl ModelInterceptor for CountingInterceptor {
+        fn before_call(&self, call: &ModelCall<'_>) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("before:{}", call.prompt));
+        }
+
+        fn after_call(&self, call: &ModelCall<'_>, _response: &Message) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("after:{}", call.request_id));
+        }
+    }
+
+    /// Stands in for a provider: replies with a canned message, running hooks around the call
+    fn mock_model_call(interceptor: &dyn ModelInterceptor, call: &ModelCall<'_>) -> Message {
+        interceptor.before_call(call);
+        let response = Message::assistant().with_text("Replaced javax with jakarta");
+        interceptor.after_call(call, &response);
+        response
+    }
+
+    #[test]
+    fn test_hooks_fire_around_model_call() {
+        let interceptor = Arc::new(CountingInterceptor::default());
+        let call = ModelCall {
+            request_id: "req-1",
+            session_id: "session-1",
+            prompt: "fix it",
+        };
+
+        mock_model_call(interceptor.as_ref(), &call);
+
+        assert_eq!(
+            *interceptor.events.lock().unwrap(),
+            vec!["before:fix it".to_string(), "after:req-1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_file_recording_is_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let interceptor = FileRecordingInterceptor::new(dir.path()).unwrap();
+        let call = ModelCall {
+            request_id: "req-1",
+            session_id: "session-1",
+            prompt: "use key sk-abcdefghijklmnopqrstuvwxyz please",
+        };
+
+        mock_model_call(&interceptor, &call);
+
+        let recorded = std::fs::read_to_string(dir.path().join("session-1.jsonl")).unwrap();
+        let lines: Vec<serde_json::Value> = recorded
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["kind"], "prompt");
+        assert_eq!(lines[0]["text"], "use key [REDACTED] please");
+        assert_eq!(lines[1]["kind"], "response");
+        assert_eq!(lines[1]["text"], "Replaced javax with jakarta");
+    }
+}
@@ -1,26 +1,102 @@
 // Goose agent integration and management
 
+pub mod credentials;
+pub mod interceptor;
+pub mod pool;
+pub mod prompt_builder;
+pub mod prompt_template;
+pub mod scheduler;
+pub mod scratch;
 pub mod session_wrapper;
+pub mod stats;
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use tracing::debug;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 use goose::agents::{Agent, SessionConfig};
-
+use goose::providers::base::Provider;
+
+pub use interceptor::{FileRecordingInterceptor, ModelCall, ModelInterceptor};
+pub use pool::{PoolFactory, WarmPool};
+pub use prompt_builder::{FixPrompt, PromptBuilder};
+pub use prompt_template::{PromptTemplate, DEFAULT_PROMPT_TEMPLATE};
+pub use scheduler::{ModelCallLimiter, ModelCallPermit};
+pub use scratch::ScratchDirs;
 pub use session_wrapper::{GooseSessionWrapper, SessionInfo};
+pub use stats::{ServerStats, StatsSnapshot};
 
-use crate::models::configuration::AgentConfig;
+use crate::models::configuration::{
+    permission_strictness, AgentConfig, BaseConfig, ExtensionTimeouts, ModelConfig,
+    PermissionLevel, SessionConfigUpdate,
+};
 use crate::KaiakResult;
+use validator::Validate;
 
 /// This will manage the lifecycle of Goose agents
 /// we can have multiple agents running at any given time
 /// we store the state of the agents (tied to goose sessions)
 pub struct GooseAgentManager {
     session_wrapper: Arc<GooseSessionWrapper>,
-    agents: Arc<RwLock<HashMap<String, Arc<Agent>>>>,
-    configurations: Arc<RwLock<HashMap<String, AgentConfig>>>,
+    /// Agent and configuration of each session, kept in one map so they are always
+    /// added and removed together
+    agents: Arc<RwLock<HashMap<String, SessionAgent>>>,
+    /// Bounds model calls across every session
+    model_calls: Arc<ModelCallLimiter>,
+    model_interceptor: Option<Arc<dyn ModelInterceptor>>,
+    agent_pool: Option<AgentPool>,
+    extension_timeouts: ExtensionTimeouts,
+    /// Server configuration used by sessions whose request has no override
+    base_config: Arc<BaseConfig>,
+    /// Providers sessions may use; empty allows any
+    allowed_providers: Vec<String>,
+    /// Settings changed with `kaiak/update_session_config`, applied to every later
+    /// request of the session
+    session_updates: Arc<RwLock<HashMap<String, SessionConfigUpdate>>>,
+    /// Scratch directory of each session, removed with the session
+    scratch_dirs: Arc<ScratchDirs>,
+    /// Server-wide counters for `kaiak/stats`
+    stats: Arc<ServerStats>,
+}
+
+/// A session's agent together with the configuration it was created from
+struct SessionAgent {
+    agent: Arc<Agent>,
+    config: AgentConfig,
+}
+
+/// Everything Kaiak keeps about a session, with secrets masked, for support tickets and
+/// reproducing runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub session_id: String,
+    pub name: String,
+    pub working_dir: PathBuf,
+    pub message_count: usize,
+    /// Input and output tokens the provider reported for the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_counters: Option<(u64, u64)>,
+    /// Configuration of the session's agent; absent when no agent is loaded for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<serde_json::Value>,
+    /// Conversation history, including tool calls, tool results and interaction responses
+    pub messages: Vec<serde_json::Value>,
+    pub exported_at: String,
+}
+
+/// Agent with extensions loaded and a provider created, waiting for a session
+pub struct PooledAgent {
+    agent: Agent,
+    provider: Arc<dyn Provider>,
+}
+
+/// Warm agents, all built for the same model
+struct AgentPool {
+    model: ModelConfig,
+    agents: Arc<WarmPool<PooledAgent>>,
 }
 
 impl GooseAgentManager {
@@ -28,14 +104,122 @@ impl GooseAgentManager {
         Self {
             session_wrapper: Arc::new(GooseSessionWrapper::new()),
             agents: Arc::new(RwLock::new(HashMap::new())),
-            configurations: Arc::new(RwLock::new(HashMap::new())),
+            model_calls: Arc::new(ModelCallLimiter::default()),
+            model_interceptor: None,
+            agent_pool: None,
+            extension_timeouts: ExtensionTimeouts::default(),
+            base_config: Arc::new(BaseConfig::default()),
+            allowed_providers: Vec::new(),
+            session_updates: Arc::new(RwLock::new(HashMap::new())),
+            scratch_dirs: Arc::new(ScratchDirs::default()),
+            stats: Arc::new(ServerStats::default()),
         }
     }
 
+    /// Create session scratch directories below `base` instead of the system temp
+    /// directory; a relative `base` is resolved against each session's workspace
+    pub fn with_scratch_dir(mut self, base: Option<PathBuf>) -> Self {
+        if let Some(base) = base {
+            self.scratch_dirs = Arc::new(ScratchDirs::new(base));
+        }
+        self
+    }
+
+    /// Set extension tool call timeouts; call before `with_agent_pool` so pooled agents use them
+    pub fn with_extension_timeouts(mut self, extension_timeouts: ExtensionTimeouts) -> Self {
+        self.extension_timeouts = extension_timeouts;
+        self
+    }
+
+    /// Run sessions whose request has no `override_base_config` with the server's
+    /// configuration, as the generate_fix handler assumes
+    pub fn with_base_config(mut self, base_config: Arc<BaseConfig>) -> Self {
+        self.base_config = base_config;
+        self
+    }
+
+    /// Only let sessions use the given providers; an empty list allows any
+    pub fn with_allowed_providers(mut self, allowed_providers: Vec<String>) -> Self {
+        self.allowed_providers = allowed_providers;
+        self
+    }
+
+    /// Reject a provider that is not on the configured allowlist
+    pub fn check_provider_allowed(&self, provider: &str) -> KaiakResult<()> {
+        if self.allowed_providers.is_empty()
+            || self
+                .allowed_providers
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(provider))
+        {
+            return Ok(());
+        }
+        Err(crate::KaiakError::configuration(format!(
+            "Provider '{}' is not allowed on this server; allowed providers: {}",
+            provider,
+            self.allowed_providers.join(", ")
+        )))
+    }
+
+    /// Limit how many model calls may run at once across all sessions; `None` is unbounded
+    pub fn with_max_concurrent_model_calls(mut self, max_concurrent: Option<usize>) -> Self {
+        self.model_calls = Arc::new(ModelCallLimiter::new(max_concurrent));
+        self
+    }
+
+    /// Observe prompts and responses of every agent run
+    pub fn with_model_interceptor(mut self, interceptor: Arc<dyn ModelInterceptor>) -> Self {
+        self.model_interceptor = Some(interceptor);
+        self
+    }
+
+    /// Keep `size` agents for `model` pre-initialized; 0 disables the pool.
+    /// Call `agent_pool().fill()` to warm it.
+    pub fn with_agent_pool(mut self, size: usize, model: ModelConfig) -> Self {
+        if size == 0 {
+            self.agent_pool = None;
+            return self;
+        }
+
+        let pool_model = model.clone();
+        let extension_timeouts = self.extension_timeouts.clone();
+        let factory: PoolFactory<PooledAgent> = Arc::new(move || {
+            let model = pool_model.clone();
+            let extension_timeouts = extension_timeouts.clone();
+            Box::pin(async move {
+                let agent = Agent::new();
+                Self::add_extensions(&agent, &extension_timeouts).await?;
+                let provider = Self::create_provider(&model).await?;
+                Ok(PooledAgent { agent, provider })
+            })
+        });
+        self.agent_pool = Some(AgentPool {
+            model,
+            agents: Arc::new(WarmPool::new(size, factory)),
+        });
+        self
+    }
+
+    pub fn agent_pool(&self) -> Option<&Arc<WarmPool<PooledAgent>>> {
+        self.agent_pool.as_ref().map(|pool| &pool.agents)
+    }
+
+    pub fn model_interceptor(&self) -> Option<&Arc<dyn ModelInterceptor>> {
+        self.model_interceptor.as_ref()
+    }
+
     pub fn session_wrapper(&self) -> &Arc<GooseSessionWrapper> {
         &self.session_wrapper
     }
 
+    pub fn model_calls(&self) -> &Arc<ModelCallLimiter> {
+        &self.model_calls
+    }
+
+    pub fn stats(&self) -> &Arc<ServerStats> {
+        &self.stats
+    }
+
     pub async fn active_agent_count(&self) -> usize {
         let agents = self.agents.read().await;
         agents.len()
@@ -46,13 +230,134 @@ impl GooseAgentManager {
         session_id: Option<&str>,
         config: &AgentConfig,
     ) -> KaiakResult<SessionInfo> {
-        self.session_wrapper
+        let session_info = self
+            .session_wrapper
             .get_or_create_session(session_id, config)
-            .await
+            .await?;
+        if session_id.is_none() {
+            self.stats.session_created();
+        }
+        self.scratch_dirs
+            .create(&session_info.session.id, &session_info.session.working_dir)?;
+        Ok(session_info)
     }
 
     pub async fn delete_session(&self, session_id: &str) -> KaiakResult<bool> {
-        self.session_wrapper.delete_session(session_id).await
+        let deleted = self.session_wrapper.delete_session(session_id).await?;
+        self.session_updates.write().await.remove(session_id);
+        self.scratch_dirs.remove(session_id);
+        if deleted {
+            self.stats.session_deleted();
+        }
+        Ok(deleted)
+    }
+
+    /// Directory the session's tools may use for temporary files
+    pub fn scratch_dir(&self, session_id: &str) -> Option<PathBuf> {
+        self.scratch_dirs.get(session_id)
+    }
+
+    /// Merge `update` into the session's earlier updates and apply it to the configuration
+    /// of its live agent. Returns every setting changed on the session so far.
+    pub async fn update_session_config(
+        &self,
+        session_id: &str,
+        update: SessionConfigUpdate,
+    ) -> KaiakResult<SessionConfigUpdate> {
+        if !self.session_exists(session_id).await {
+            return Err(crate::KaiakError::SessionNotFound(session_id.to_string()));
+        }
+
+        let merged = {
+            let mut updates = self.session_updates.write().await;
+            let merged = updates.entry(session_id.to_string()).or_default();
+            merged.merge(update);
+            merged.clone()
+        };
+        if let Some(entry) = self.agents.write().await.get_mut(session_id) {
+            merged.apply(&mut entry.config);
+        }
+        Ok(merged)
+    }
+
+    /// A request's agent configuration with the session's updates applied
+    async fn effective_config(&self, session_id: &str, config: &AgentConfig) -> AgentConfig {
+        let mut config = config.clone();
+        if let Some(update) = self.session_updates.read().await.get(session_id) {
+            update.apply(&mut config);
+        }
+        config
+    }
+
+    /// The stricter of `server` and the session's own permission for a tool
+    pub async fn session_tool_permission(
+        &self,
+        session_id: &str,
+        tool_name: &str,
+        server: &PermissionLevel,
+    ) -> PermissionLevel {
+        let updates = self.session_updates.read().await;
+        match updates
+            .get(session_id)
+            .and_then(|update| update.tool_permissions.get(tool_name))
+        {
+            Some(session) if permission_strictness(session) > permission_strictness(server) => {
+                session.clone()
+            }
+            _ => server.clone(),
+        }
+    }
+
+    /// Clear the conversation history of a session; its agent and configuration are kept
+    pub async fn reset_session_context(&self, session_id: &str) -> KaiakResult<SessionInfo> {
+        self.session_wrapper.reset_context(session_id).await
+    }
+
+    /// Snapshot of a session's configuration and conversation, with secrets masked
+    pub async fn export_session(&self, session_id: &str) -> KaiakResult<SessionExport> {
+        let session = self
+            .session_wrapper
+            .get_session(session_id)
+            .await?
+            .ok_or_else(|| crate::KaiakError::SessionNotFound(session_id.to_string()))?
+            .session;
+
+        let mut config = match self.get_configuration(session_id).await {
+            Some(config) => Some(serde_json::to_value(config).map_err(|e| {
+                crate::KaiakError::Serialization {
+                    message: format!("Failed to serialize session configuration: {}", e),
+                }
+            })?),
+            None => None,
+        };
+        let mut messages: Vec<serde_json::Value> = self
+            .session_wrapper
+            .messages(session_id)
+            .await?
+            .iter()
+            .map(|message| serde_json::to_value(message).unwrap_or_default())
+            .collect();
+        config.iter_mut().chain(messages.iter_mut()).for_each(interceptor::redact_value);
+
+        Ok(SessionExport {
+            session_id: session.id.clone(),
+            name: session.name.clone(),
+            working_dir: session.working_dir.clone(),
+            message_count: session.message_count,
+            token_counters: self.token_counters(session_id).await,
+            config,
+            messages,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    pub async fn get_configuration(&self, session_id: &str) -> Option<AgentConfig> {
+        let agents = self.agents.read().await;
+        agents.get(session_id).map(|entry| entry.config.clone())
+    }
+
+    pub async fn token_counters(&self, session_id: &str) -> Option<(u64, u64)> {
+        self.session_wrapper.token_counters(session_id).await
     }
 
     pub async fn lock_session(&self, session_id: &str) -> KaiakResult<()> {
@@ -72,22 +377,41 @@ impl GooseAgentManager {
 
         info!("Creating new Goose agent for session: {}", session_id);
 
-        let agent = Agent::new();
-        {
-            let mut configs = self.configurations.write().await;
-            configs.insert(session_id.to_string(), config.clone());
-        }
-        self.add_extensions(&agent).await?;
-        self.setup_model_provider(&agent, session_id, config)
-            .await?;
+        let config = &self.effective_config(session_id, config).await;
+        let model_config = self.model_config(config);
+        self.check_provider_allowed(&model_config.provider)?;
+        // Reject invalid model settings before loading extensions or claiming a pooled agent
+        Self::goose_model_config(&model_config)?;
+        let agent = match self.claim_pooled_agent(&model_config) {
+            Some(pooled) => {
+                debug!("Using pre-initialized agent for session: {}", session_id);
+                Self::attach_provider(&pooled.agent, pooled.provider, session_id, &model_config)
+                    .await?;
+                pooled.agent
+            }
+            None => {
+                let agent = Agent::new();
+                Self::add_extensions(&agent, &self.extension_timeouts).await?;
+                self.setup_model_provider(&agent, session_id, config)
+                    .await?;
+                agent
+            }
+        };
         let session_config = self.create_session_config(session_id, config)?;
         debug!("Created session config: {:?}", session_config);
 
         let agent_arc = Arc::new(agent);
 
+        // Only a fully configured agent is registered, so a failure above leaves no entry
         {
             let mut agents = self.agents.write().await;
-            agents.insert(session_id.to_string(), agent_arc.clone());
+            agents.insert(
+                session_id.to_string(),
+                SessionAgent {
+                    agent: agent_arc.clone(),
+                    config: config.clone(),
+                },
+            );
         }
 
         info!(
@@ -99,17 +423,14 @@ impl GooseAgentManager {
 
     pub async fn get_agent(&self, session_id: &str) -> Option<Arc<Agent>> {
         let agents = self.agents.read().await;
-        agents.get(session_id).cloned()
+        agents.get(session_id).map(|entry| entry.agent.clone())
     }
 
     pub async fn remove_agent(&self, session_id: &str) -> bool {
         use tracing::info;
 
         let mut agents = self.agents.write().await;
-        let mut configs = self.configurations.write().await;
-
         let removed_agent = agents.remove(session_id).is_some();
-        configs.remove(session_id);
 
         if removed_agent {
             info!("Removed Goose agent for session: {}", session_id);
@@ -122,9 +443,19 @@ impl GooseAgentManager {
         self.session_wrapper.session_exists(session_id).await
     }
 
-    async fn add_extensions(&self, agent: &Agent) -> KaiakResult<()> {
+    /// Take a pooled agent built for the same provider and model, if one is ready
+    fn claim_pooled_agent(&self, model_config: &ModelConfig) -> Option<PooledAgent> {
+        let pool = self.agent_pool.as_ref()?;
+        if pool.model.provider != model_config.provider || pool.model.model != model_config.model {
+            return None;
+        }
+        pool.agents.claim()
+    }
+
+    /// Extensions every agent gets, with their configured timeouts
+    fn extension_configs(timeouts: &ExtensionTimeouts) -> Vec<goose::agents::ExtensionConfig> {
         use goose::agents::ExtensionConfig;
-        let extensions = vec![
+        vec![
             // Developer tools (file system operations)
             ExtensionConfig::Stdio {
                 name: "developer".to_string(),
@@ -133,7 +464,7 @@ impl GooseAgentManager {
                 args: vec!["mcp".to_string(), "developer".to_string()],
                 envs: Default::default(),
                 env_keys: Vec::new(),
-                timeout: Some(300),
+                timeout: Some(timeouts.for_extension("developer")),
                 bundled: Some(true),
                 available_tools: Vec::new(),
             },
@@ -144,13 +475,18 @@ impl GooseAgentManager {
                 bundled: Some(true),
                 available_tools: Vec::new(),
             }
-        ];
+        ]
+    }
 
-        for extension in extensions {
-            agent
-                .add_extension(extension)
-                .await
-                .expect("Failed to add extension");
+    async fn add_extensions(agent: &Agent, timeouts: &ExtensionTimeouts) -> KaiakResult<()> {
+        for extension in Self::extension_configs(timeouts) {
+            let name = extension.name().to_string();
+            agent.add_extension(extension).await.map_err(|e| {
+                crate::KaiakError::agent_initialization(format!(
+                    "Failed to add extension '{}': {}",
+                    name, e
+                ))
+            })?;
         }
 
         debug!("Adding extensions to agent");
@@ -164,60 +500,93 @@ impl GooseAgentManager {
         session_id: &str,
         config: &AgentConfig,
     ) -> KaiakResult<()> {
-        use crate::models::configuration::ModelConfig;
-        use goose::providers::create_with_named_model;
-        use tracing::{debug, error, info};
-
         debug!(
             "Setting up model provider for agent with session: {}",
             session_id
         );
 
-        // Use override_base_config if provided, otherwise fall back to defaults
-        let default_model = ModelConfig::default();
-        let model_config = config
-            .override_base_config
-            .as_ref()
-            .map(|c| &c.model)
-            .unwrap_or(&default_model);
+        let model_config = self.model_config(config);
+        let provider = Self::create_provider(&model_config).await?;
+        Self::attach_provider(agent, provider, session_id, &model_config).await
+    }
 
-        let provider_name = model_config.provider.clone();
-        let model_name = model_config.model.clone();
+    /// The session's model: from its override_base_config if provided, else the server's
+    fn model_config(&self, config: &AgentConfig) -> ModelConfig {
+        config.effective_base_config(&self.base_config).model.clone()
+    }
+
+    /// Translate a model configuration into the provider's completion settings.
+    /// Rejects out-of-range values so request overrides cannot reach the provider unchecked.
+    fn goose_model_config(model_config: &ModelConfig) -> KaiakResult<goose::model::ModelConfig> {
+        model_config.validate().map_err(|e| {
+            crate::KaiakError::configuration(format!("Invalid model configuration: {}", e))
+        })?;
+
+        let goose_model_config = goose::model::ModelConfig::new(&model_config.model)
+            .map_err(|e| {
+                crate::KaiakError::configuration(format!(
+                    "Invalid model '{}': {}",
+                    model_config.model, e
+                ))
+            })?
+            .with_temperature(model_config.temperature)
+            .with_max_tokens(
+                model_config
+                    .max_tokens
+                    .map(|n| i32::try_from(n).unwrap_or(i32::MAX)),
+            );
+        Ok(goose_model_config)
+    }
+
+    pub(crate) async fn create_provider(
+        model_config: &ModelConfig,
+    ) -> KaiakResult<Arc<dyn Provider>> {
+        use goose::providers::create;
+        use tracing::{error, info};
 
         info!(
             "Creating provider '{}' with model '{}'",
-            provider_name, model_name
+            model_config.provider, model_config.model
         );
 
-        match create_with_named_model(&provider_name.to_string(), &model_name.to_string()).await {
-            Ok(provider) => {
-                debug!("Successfully created provider, updating agent");
-
-                match agent.update_provider(provider, session_id).await {
-                    Ok(()) => {
-                        info!(
-                            "Successfully updated agent with provider '{}' and model '{}'",
-                            provider_name, model_name
-                        );
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!("Failed to update agent with provider: {}", e);
-                        Err(crate::KaiakError::agent_initialization(format!(
-                            "Failed to update agent with provider: {}",
-                            e
-                        )))
-                    }
-                }
-            }
-            Err(e) => {
+        let goose_model_config = Self::goose_model_config(model_config)?;
+        create(&model_config.provider, goose_model_config)
+            .await
+            .map_err(|e| {
                 error!(
                     "Failed to create provider '{}' with model '{}': {}",
-                    provider_name, model_name, e
+                    model_config.provider, model_config.model, e
                 );
-                Err(crate::KaiakError::agent_initialization(format!(
+                crate::KaiakError::agent_initialization(format!(
                     "Failed to create provider '{}' with model '{}': {}",
-                    provider_name, model_name, e
+                    model_config.provider, model_config.model, e
+                ))
+            })
+    }
+
+    async fn attach_provider(
+        agent: &Agent,
+        provider: Arc<dyn Provider>,
+        session_id: &str,
+        model_config: &ModelConfig,
+    ) -> KaiakResult<()> {
+        use tracing::{error, info};
+
+        debug!("Successfully created provider, updating agent");
+
+        match agent.update_provider(provider, session_id).await {
+            Ok(()) => {
+                info!(
+                    "Successfully updated agent with provider '{}' and model '{}'",
+                    model_config.provider, model_config.model
+                );
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to update agent with provider: {}", e);
+                Err(crate::KaiakError::agent_initialization(format!(
+                    "Failed to update agent with provider: {}",
+                    e
                 )))
             }
         }
@@ -275,3 +644,295 @@ impl Default for GooseAgentManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goose::conversation::message::Message;
+    use goose::session::SessionManager;
+
+    #[tokio::test]
+    async fn test_disallowed_provider_is_rejected() {
+        let manager = GooseAgentManager::new().with_allowed_providers(vec!["gateway".to_string()]);
+        let mut base_config = crate::models::configuration::BaseConfig::default();
+        base_config.model.provider = "openai".to_string();
+        let config = AgentConfig {
+            override_base_config: Some(base_config),
+            ..AgentConfig::default()
+        };
+
+        let error = manager.create_agent("session-1", &config).await.err().unwrap();
+        assert!(matches!(error, crate::KaiakError::Configuration { .. }), "{error:?}");
+        assert!(error.to_string().contains("'openai' is not allowed"));
+        assert!(manager.get_configuration("session-1").await.is_none());
+
+        assert!(manager.check_provider_allowed("gateway").is_ok());
+        assert!(GooseAgentManager::new().check_provider_allowed("openai").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_failed_agent_creation_leaves_no_partial_entry() {
+        let mut server_config = crate::models::configuration::BaseConfig::default();
+        server_config.model.provider = "ollama".to_string();
+        server_config.model.model = "server-model".to_string();
+        let manager = GooseAgentManager::new().with_base_config(Arc::new(server_config));
+
+        // No override: the session runs the server's model, as the handler expects
+        let config = AgentConfig {
+            override_base_config: None,
+            ..AgentConfig::default()
+        };
+        let model = manager.model_config(&config);
+        assert_eq!(model.provider, "ollama");
+        assert_eq!(model.model, "server-model");
+
+        let mut base_config = crate::models::configuration::BaseConfig::default();
+        base_config.model.temperature = Some(5.0);
+        let config = AgentConfig {
+            override_base_config: Some(base_config),
+            ..AgentConfig::default()
+        };
+
+        let error = manager.create_agent("session-1", &config).await.err().unwrap();
+        assert!(matches!(error, crate::KaiakError::Configuration { .. }), "{error:?}");
+        assert!(manager.get_agent("session-1").await.is_none());
+        assert!(manager.get_configuration("session-1").await.is_none());
+        assert_eq!(manager.active_agent_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_reset_session_context_keeps_session_and_config() {
+        let workspace = tempfile::tempdir().unwrap();
+        let config = AgentConfig {
+            workspace: workspace.path().to_path_buf(),
+            ..AgentConfig::default()
+        };
+        let manager = GooseAgentManager::new();
+
+        let session = manager.get_or_create_session(None, &config).await.unwrap().session;
+        manager.agents.write().await.insert(
+            session.id.clone(),
+            SessionAgent {
+                agent: Arc::new(Agent::new()),
+                config: config.clone(),
+            },
+        );
+
+        // Two fix exchanges worth of history
+        for text in ["fix incident 1", "done", "fix incident 2", "done"] {
+            SessionManager::add_message(&session.id, &Message::user().with_text(text))
+                .await
+                .unwrap();
+        }
+        let before = manager.session_wrapper().get_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(before.session.message_count, 4);
+
+        let after = manager.reset_session_context(&session.id).await.unwrap();
+
+        assert_eq!(after.session.message_count, 0);
+        assert_eq!(after.session.id, session.id);
+        assert!(manager.get_agent(&session.id).await.is_some());
+        assert_eq!(
+            manager.get_configuration(&session.id).await.map(|c| c.workspace),
+            Some(config.workspace)
+        );
+
+        manager.delete_session(&session.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scratch_dir_lives_as_long_as_the_session() {
+        let workspace = tempfile::tempdir().unwrap();
+        let scratch = tempfile::tempdir().unwrap();
+        let config = AgentConfig {
+            workspace: workspace.path().to_path_buf(),
+            ..AgentConfig::default()
+        };
+        let manager =
+            GooseAgentManager::new().with_scratch_dir(Some(scratch.path().to_path_buf()));
+
+        let session = manager.get_or_create_session(None, &config).await.unwrap().session;
+        let dir = manager.scratch_dir(&session.id).expect("session should have a scratch dir");
+        assert_eq!(dir, scratch.path().join(&session.id));
+        assert!(dir.is_dir());
+        std::fs::write(dir.join("build.log"), "scratch output").unwrap();
+
+        manager.delete_session(&session.id).await.unwrap();
+        assert!(!dir.exists());
+        assert!(manager.scratch_dir(&session.id).is_none());
+        assert!(std::fs::read_dir(workspace.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_model_settings_reach_provider_config() {
+        let model_config = ModelConfig {
+            temperature: Some(0.7),
+            max_tokens: Some(2048),
+            ..ModelConfig::default()
+        };
+        let goose_config = GooseAgentManager::goose_model_config(&model_config).unwrap();
+        assert_eq!(goose_config.temperature, Some(0.7));
+        assert_eq!(goose_config.max_tokens, Some(2048));
+
+        for invalid in [
+            ModelConfig { temperature: Some(2.5), ..ModelConfig::default() },
+            ModelConfig { temperature: Some(-0.1), ..ModelConfig::default() },
+            ModelConfig { max_tokens: Some(0), ..ModelConfig::default() },
+        ] {
+            assert!(GooseAgentManager::goose_model_config(&invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_extension_timeout_reaches_extension_config() {
+        let developer_timeout = |timeouts: &ExtensionTimeouts| {
+            GooseAgentManager::extension_configs(timeouts)
+                .into_iter()
+                .find_map(|extension| match extension {
+                    goose::agents::ExtensionConfig::Stdio { name, timeout, .. }
+                        if name == "developer" =>
+                    {
+                        timeout
+                    }
+                    _ => None,
+                })
+        };
+
+        assert_eq!(developer_timeout(&ExtensionTimeouts::default()), Some(300));
+
+        let timeouts = ExtensionTimeouts {
+            default: 120,
+            extensions: HashMap::from([("developer".to_string(), 900)]),
+        };
+        assert_eq!(developer_timeout(&timeouts), Some(900));
+
+        let timeouts = ExtensionTimeouts {
+            extensions: HashMap::from([("developer".to_string(), 0)]),
+            ..ExtensionTimeouts::default()
+        };
+        assert!(timeouts.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_session_includes_config_and_conversation() {
+        let workspace = tempfile::tempdir().unwrap();
+        let mut base_config = crate::models::configuration::BaseConfig::default();
+        base_config.model.model = "sk-abcdefghijklmnopqrstuvwxyz".to_string();
+        let config = AgentConfig {
+            workspace: workspace.path().to_path_buf(),
+            override_base_config: Some(base_config),
+            ..AgentConfig::default()
+        };
+        let manager = GooseAgentManager::new();
+
+        let session = manager.get_or_create_session(None, &config).await.unwrap().session;
+        manager.agents.write().await.insert(
+            session.id.clone(),
+            SessionAgent {
+                agent: Arc::new(Agent::new()),
+                config: config.clone(),
+            },
+        );
+
+        // The fix request and the edit the agent proposed for it
+        let proposal: rmcp::model::JsonObject = serde_json::from_value(serde_json::json!({
+            "command": "str_replace",
+            "path": "src/Main.java",
+            "old_str": "javax.servlet",
+            "new_str": "jakarta.servlet",
+        }))
+        .unwrap();
+        for message in [
+            Message::user().with_text("Fix javax.servlet imports in src/Main.java"),
+            Message::assistant().with_tool_request(
+                "call-1",
+                Ok(rmcp::model::CallToolRequestParam {
+                    name: "developer__text_editor".into(),
+                    arguments: Some(proposal),
+                }),
+            ),
+        ] {
+            SessionManager::add_message(&session.id, &message).await.unwrap();
+        }
+
+        let export = manager.export_session(&session.id).await.unwrap();
+        let exported = serde_json::to_string(&export).unwrap();
+
+        assert_eq!(export.session_id, session.id);
+        assert_eq!(export.message_count, 2);
+        let config = export.config.as_ref().unwrap();
+        assert_eq!(config["workspace"], serde_json::json!(workspace.path()));
+        assert_eq!(config["override_base_config"]["model"]["model"], "[REDACTED]");
+        assert_eq!(export.messages.len(), 2);
+        assert!(exported.contains("Fix javax.servlet imports in src/Main.java"));
+        assert!(exported.contains("jakarta.servlet"));
+        assert!(!exported.contains("sk-abcdefghijklmnopqrstuvwxyz"));
+
+        manager.delete_session(&session.id).await.unwrap();
+        assert!(matches!(
+            manager.export_session(&session.id).await,
+            Err(crate::KaiakError::SessionNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_updated_max_turns_applies_to_later_requests() {
+        let workspace = tempfile::tempdir().unwrap();
+        let config = AgentConfig {
+            workspace: workspace.path().to_path_buf(),
+            ..AgentConfig::default()
+        };
+        let manager = GooseAgentManager::new();
+        let session = manager.get_or_create_session(None, &config).await.unwrap().session;
+
+        let before = manager.effective_config(&session.id, &config).await;
+        let max_turns = |config: &AgentConfig| {
+            manager.create_session_config(&session.id, config).unwrap().max_turns
+        };
+        assert_eq!(max_turns(&before), Some(1000));
+
+        let update = SessionConfigUpdate {
+            max_turns: Some(25),
+            tool_permissions: HashMap::from([(
+                "developer__shell".to_string(),
+                PermissionLevel::NeverAllow,
+            )]),
+        };
+        let merged = manager.update_session_config(&session.id, update).await.unwrap();
+        assert_eq!(merged.max_turns, Some(25));
+
+        // The next request sends the same configuration it always has
+        let after = manager.effective_config(&session.id, &config).await;
+        assert_eq!(max_turns(&after), Some(25));
+
+        // Session permissions only ever tighten the server's
+        let shell = manager
+            .session_tool_permission(&session.id, "developer__shell", &PermissionLevel::AskBefore)
+            .await;
+        assert_eq!(shell, PermissionLevel::NeverAllow);
+        let loosened = SessionConfigUpdate {
+            tool_permissions: HashMap::from([(
+                "developer__text_editor".to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..SessionConfigUpdate::default()
+        };
+        manager.update_session_config(&session.id, loosened).await.unwrap();
+        let editor = manager
+            .session_tool_permission(
+                &session.id,
+                "developer__text_editor",
+                &PermissionLevel::AskBefore,
+            )
+            .await;
+        assert_eq!(editor, PermissionLevel::AskBefore);
+
+        manager.delete_session(&session.id).await.unwrap();
+        assert!(matches!(
+            manager
+                .update_session_config(&session.id, SessionConfigUpdate::default())
+                .await,
+            Err(crate::KaiakError::SessionNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,165 @@
+//! Pool of pre-initialized items that hides cold-start latency from requests.
+
+use futures::future::BoxFuture;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+use crate::KaiakResult;
+
+/// Creates one pooled item
+pub type PoolFactory<T> = Arc<dyn Fn() -> BoxFuture<'static, KaiakResult<T>> + Send + Sync>;
+
+/// Keeps up to `size` items ready; claiming one triggers an asynchronous refill
+pub struct WarmPool<T> {
+    size: usize,
+    factory: PoolFactory<T>,
+    state: Mutex<PoolState<T>>,
+}
+
+struct PoolState<T> {
+    ready: VecDeque<T>,
+    /// Items being created by background refills
+    pending: usize,
+}
+
+impl<T: Send + 'static> WarmPool<T> {
+    pub fn new(size: usize, factory: PoolFactory<T>) -> Self {
+        Self {
+            size,
+            factory,
+            state: Mutex::new(PoolState {
+                ready: VecDeque::new(),
+                pending: 0,
+            }),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of items ready to be claimed
+    pub fn available(&self) -> usize {
+        self.state.lock().unwrap().ready.len()
+    }
+
+    /// Create items until the pool is full. Stops at the first failure, so a factory that
+    /// keeps failing, e.g. on bad credentials, is not retried in a loop; the next `claim`
+    /// tries again.
+    pub async fn fill(&self) {
+        while self.reserve_slot() {
+            let created = (self.factory)().await;
+            if !self.complete_slot(created) {
+                break;
+            }
+        }
+    }
+
+    /// Take a ready item, if any, and refill the pool in the background
+    pub fn claim(self: &Arc<Self>) -> Option<T> {
+        let (item, refill) = {
+            let mut state = self.state.lock().unwrap();
+            let item = state.ready.pop_front();
+            (item, state.ready.len() + state.pending < self.size)
+        };
+        if refill {
+            let pool = self.clone();
+            tokio::spawn(async move { pool.fill().await });
+        }
+        item
+    }
+
+    /// Reserve room for one more item; false when ready and pending items fill the pool
+    fn reserve_slot(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.ready.len() + state.pending >= self.size {
+            return false;
+        }
+        state.pending += 1;
+        true
+    }
+
+    /// Release a reserved slot, adding the item if it was created. Returns whether it was.
+    fn complete_slot(&self, created: KaiakResult<T>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.pending -= 1;
+        match created {
+            Ok(item) => {
+                state.ready.push_back(item);
+                debug!("Warm pool has {}/{} items ready", state.ready.len(), self.size);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to create pooled item: {}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting_pool(size: usize) -> (Arc<WarmPool<usize>>, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let factory_calls = calls.clone();
+        let factory: PoolFactory<usize> = Arc::new(move || {
+            let n = factory_calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move { Ok(n) })
+        });
+        (Arc::new(WarmPool::new(size, factory)), calls)
+    }
+
+    #[tokio::test]
+    async fn test_claim_does_not_call_factory() {
+        let (pool, calls) = counting_pool(2);
+        pool.fill().await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.available(), 2);
+
+        // Claiming hands out a pre-built item; the factory only runs in the background refill
+        let claimed = pool.claim();
+        assert_eq!(claimed, Some(0));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        while pool.available() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failing_factory_stops_fill_until_next_claim() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let factory_calls = calls.clone();
+        let factory: PoolFactory<usize> = Arc::new(move || {
+            factory_calls.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Err(crate::KaiakError::agent_initialization("no credentials")) })
+        });
+        let pool = Arc::new(WarmPool::new(2, factory));
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), pool.fill())
+            .await
+            .expect("fill returns after a failure");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.available(), 0);
+
+        // A claim on the empty pool tries again in the background
+        assert_eq!(pool.claim(), None);
+        while calls.load(Ordering::SeqCst) < 2 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_pool_claims_nothing() {
+        let (pool, calls) = counting_pool(0);
+        pool.fill().await;
+
+        assert_eq!(pool.claim(), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+}
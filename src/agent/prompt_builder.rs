@@ -0,0 +1,823 @@
+//! Builds the user prompt sent to the Goose agent for a set of migration incidents.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::prompt_template::{PromptContext, PromptTemplate, TemplateIncident};
+use crate::models::incidents::MigrationIncident;
+use crate::models::text_encoding::DEFAULT_MAX_READABLE_FILE_BYTES;
+
+const FIX_GENERATION_PREAMBLE: &str =
+    "We found migration issues identified by static analysis tools in the project. Help fix them. Here are the issues:";
+
+/// Upper bound on context lines per side of an incident, to avoid huge reads
+pub const MAX_CONTEXT_LINES: usize = 50;
+
+/// Inserted where the middle of an oversized incident field was cut out
+const TRUNCATION_MARKER: &str = "\n[... truncated ...]\n";
+
+/// Rough characters-per-token ratio used to estimate prompt size
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Estimate the token count of a prompt using a character heuristic
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// A generated prompt along with the incidents dropped to fit the budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixPrompt {
+    pub text: String,
+    /// IDs of incidents left out of the prompt to stay under `max_prompt_tokens`
+    pub trimmed_incident_ids: Vec<String>,
+    /// IDs of incidents whose message or description was cut to `incident_text_limit`
+    pub truncated_incident_ids: Vec<String>,
+}
+
+/// Constructs fix generation prompts from migration incidents
+#[derive(Debug, Clone, Default)]
+pub struct PromptBuilder {
+    /// Emit shared context once per rule instead of repeating it per incident
+    group_by_rule: bool,
+    /// Approximate token budget for the generated prompt
+    max_prompt_tokens: Option<usize>,
+    /// Lines of source shown before and after each incident line; 0 disables snippets
+    context_lines: usize,
+    /// Base directory for incident URIs that are relative paths
+    workspace: Option<PathBuf>,
+    /// Maximum characters of an incident's message or description, head and tail kept
+    incident_text_limit: Option<usize>,
+    /// List links from incident metadata (e.g. rule documentation) under each incident
+    reference_links: bool,
+    /// Custom layout replacing the built-in flat and grouped prompts
+    template: Option<Arc<PromptTemplate>>,
+    /// Files that earlier turns of the request already changed
+    edited_files: Vec<String>,
+    /// Largest file read for snippets; `DEFAULT_MAX_READABLE_FILE_BYTES` when unset
+    max_readable_file_bytes: Option<u64>,
+}
+
+/// An incident paired with its (possibly truncated) text and the source snippet shown alongside it
+struct PromptItem<'a> {
+    incident: &'a MigrationIncident,
+    message: Cow<'a, str>,
+    description: Cow<'a, str>,
+    snippet: Option<String>,
+    references: Vec<&'a str>,
+    /// The incident's `suggested_replacement`, as a hint to the model
+    suggestion: Option<String>,
+}
+
+impl PromptItem<'_> {
+    fn is_truncated(&self) -> bool {
+        matches!(self.message, Cow::Owned(_)) || matches!(self.description, Cow::Owned(_))
+    }
+
+    /// The incident's message and file, the rule it refers to, its description when
+    /// `description` is set, and its details; the fields every layout shows
+    fn entry(&self, rule_id: Option<&str>, description: bool) -> String {
+        let mut entry = format!("{} (file: {}", self.message, self.incident.uri);
+        if let Some(rule_id) = rule_id {
+            entry.push_str(&format!(", rule: [{}]", rule_id));
+        }
+        entry.push(')');
+        if description && !self.description.is_empty() {
+            entry.push_str(&format!("\n     {}", self.description));
+        }
+        entry.push_str(&self.details());
+        entry
+    }
+
+    /// Snippet followed by reference links and the suggested fix, whichever are present
+    fn details(&self) -> String {
+        let mut details = self.snippet.clone().unwrap_or_default();
+        if !self.references.is_empty() {
+            details.push_str(&format!("\n     See: {}", self.references.join(", ")));
+        }
+        if let Some(suggestion) = &self.suggestion {
+            details.push_str(suggestion);
+        }
+        details
+    }
+}
+
+impl PromptBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable grouping of incidents by `rule_id`
+    pub fn with_rule_grouping(mut self, group_by_rule: bool) -> Self {
+        self.group_by_rule = group_by_rule;
+        self
+    }
+
+    /// Limit the prompt to roughly `max_prompt_tokens` tokens
+    pub fn with_max_prompt_tokens(mut self, max_prompt_tokens: Option<usize>) -> Self {
+        self.max_prompt_tokens = max_prompt_tokens;
+        self
+    }
+
+    /// Include `context_lines` lines of source around each incident, clamped to `MAX_CONTEXT_LINES`
+    pub fn with_context_lines(mut self, context_lines: usize) -> Self {
+        self.context_lines = context_lines.min(MAX_CONTEXT_LINES);
+        self
+    }
+
+    /// Show no snippet for files over `max_bytes`, instead of reading them whole
+    pub fn with_max_readable_file_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_readable_file_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Resolve relative incident paths against `workspace` when reading context
+    pub fn with_workspace(mut self, workspace: impl Into<PathBuf>) -> Self {
+        self.workspace = Some(workspace.into());
+        self
+    }
+
+    /// Cut incident messages and descriptions longer than `limit` characters; 0 means no limit
+    pub fn with_incident_text_limit(mut self, limit: usize) -> Self {
+        self.incident_text_limit = (limit > 0).then_some(limit);
+        self
+    }
+
+    /// List HTTP(S) links from each incident's `metadata` as references in the prompt
+    pub fn with_reference_links(mut self, reference_links: bool) -> Self {
+        self.reference_links = reference_links;
+        self
+    }
+
+    /// Lay out the prompt with `template` instead of the built-in format
+    pub fn with_template(mut self, template: Option<Arc<PromptTemplate>>) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Tell the agent that earlier turns already changed `files`, so it re-reads them
+    /// instead of trusting the incidents' line numbers
+    pub fn with_edited_files(mut self, files: Vec<String>) -> Self {
+        self.edited_files = files;
+        self
+    }
+
+    /// Build the prompt asking the agent to fix the given incidents
+    pub fn fix_generation_prompt(&self, incidents: &[MigrationIncident]) -> String {
+        self.build(incidents).text
+    }
+
+    /// Build the prompt, dropping lowest-severity incidents first until it fits the budget.
+    /// At least one incident is always kept.
+    pub fn build(&self, incidents: &[MigrationIncident]) -> FixPrompt {
+        // Read each snippet once; trimming renders the prompt again
+        let items: Vec<PromptItem> = incidents
+            .iter()
+            .map(|incident| PromptItem {
+                incident,
+                message: self.truncate(&incident.message),
+                description: self.truncate(&incident.description),
+                snippet: self.snippet(incident),
+                references: self.references(incident),
+                suggestion: suggestion(incident),
+            })
+            .collect();
+        let truncated_incident_ids: Vec<String> = items
+            .iter()
+            .filter(|item| item.is_truncated())
+            .map(|item| item.incident.id.clone())
+            .collect();
+        let mut kept: Vec<&PromptItem> = items.iter().collect();
+        let mut text = self.render(&kept);
+
+        if let Some(budget) = self.max_prompt_tokens {
+            // Drop order: lowest severity first, later incidents first within a severity
+            let mut drop_order: Vec<usize> = (0..incidents.len()).collect();
+            drop_order.sort_by_key(|&idx| {
+                (
+                    incidents[idx].severity.unwrap_or_default(),
+                    std::cmp::Reverse(idx),
+                )
+            });
+
+            let mut dropped = vec![false; incidents.len()];
+            let mut drop_iter = drop_order.into_iter();
+            // Subtract each dropped incident's share of the full prompt instead of rendering
+            // it again per drop; a rule's shared context goes with its last incident
+            let grouped =
+                self.group_by_rule && items.iter().any(|item| item.incident.rule_id.is_some());
+            let mut chars = text.chars().count();
+            let mut kept_count = items.len();
+            let mut rule_incidents: BTreeMap<&str, usize> = BTreeMap::new();
+            for item in &items {
+                if let Some(rule_id) = item.incident.rule_id.as_deref() {
+                    *rule_incidents.entry(rule_id).or_default() += 1;
+                }
+            }
+            while chars.div_ceil(CHARS_PER_TOKEN) > budget && kept_count > 1 {
+                let Some(idx) = drop_iter.next() else { break };
+                dropped[idx] = true;
+                kept_count -= 1;
+                let item = &items[idx];
+                let entry = if grouped {
+                    grouped_entry(idx, item)
+                } else {
+                    flat_entry(idx, item)
+                };
+                let mut share = entry.chars().count();
+                if let Some(rule_id) = item.incident.rule_id.as_deref() {
+                    let left = rule_incidents.get_mut(rule_id).unwrap();
+                    *left -= 1;
+                    if *left == 0 && grouped {
+                        share += rule_line(rule_id, &item.description).chars().count();
+                    }
+                }
+                chars = chars.saturating_sub(share);
+            }
+            if kept_count < items.len() {
+                kept = items
+                    .iter()
+                    .zip(&dropped)
+                    .filter(|(_, dropped)| !**dropped)
+                    .map(|(item, _)| item)
+                    .collect();
+                text = self.render(&kept);
+            }
+            // The shares are estimates (numbering shifts, templates lay incidents out their
+            // own way), so keep dropping if the rendered prompt is still over
+            while estimate_tokens(&text) > budget && kept.len() > 1 {
+                let Some(idx) = drop_iter.next() else { break };
+                dropped[idx] = true;
+                kept.retain(|item| !std::ptr::eq(*item, &items[idx]));
+                text = self.render(&kept);
+            }
+
+            let trimmed_incident_ids = incidents
+                .iter()
+                .zip(&dropped)
+                .filter(|(_, dropped)| **dropped)
+                .map(|(incident, _)| incident.id.clone())
+                .collect();
+
+            return FixPrompt {
+                text,
+                trimmed_incident_ids,
+                truncated_incident_ids,
+            };
+        }
+
+        FixPrompt {
+            text,
+            trimmed_incident_ids: Vec::new(),
+            truncated_incident_ids,
+        }
+    }
+
+    fn truncate<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.incident_text_limit {
+            Some(limit) => truncate_middle(text, limit),
+            None => Cow::Borrowed(text),
+        }
+    }
+
+    fn render(&self, items: &[&PromptItem]) -> String {
+        let mut prompt = self.render_incidents(items);
+        if !self.edited_files.is_empty() {
+            prompt.push_str(&format!(
+                "\n\nEarlier turns already changed {}. Read these files again before editing them; the line numbers above may have moved.",
+                self.edited_files.join(", ")
+            ));
+        }
+        prompt
+    }
+
+    fn render_incidents(&self, items: &[&PromptItem]) -> String {
+        if let Some(template) = &self.template {
+            match template.render(&Self::template_context(items)) {
+                Ok(text) => return text,
+                Err(e) => tracing::warn!("{}; falling back to the built-in prompt", e),
+            }
+        }
+
+        if self.group_by_rule && items.iter().any(|item| item.incident.rule_id.is_some()) {
+            return self.grouped_prompt(items);
+        }
+
+        format!(
+            "{}{}{}",
+            FIX_GENERATION_PREAMBLE,
+            if items.is_empty() { " (no incidents provided)" } else { "" },
+            if items.len() == 1 {
+                format!(" {}", items[0].entry(None, true))
+            } else {
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, item)| flat_entry(idx, item))
+                    .collect::<String>()
+            }
+        )
+    }
+
+    /// Source lines around the incident line, or None if disabled or the file can't be read
+    fn snippet(&self, incident: &MigrationIncident) -> Option<String> {
+        let line_number = incident.line_number?;
+        if self.context_lines == 0 || line_number == 0 {
+            return None;
+        }
+
+        let path = incident.file_path();
+        let path = match &self.workspace {
+            Some(workspace) if path.is_relative() => workspace.join(path),
+            _ => path,
+        };
+        let content = read_source(
+            &path,
+            self.max_readable_file_bytes
+                .unwrap_or(DEFAULT_MAX_READABLE_FILE_BYTES),
+        )?;
+
+        let first = line_number.saturating_sub(self.context_lines).max(1);
+        let last = line_number + self.context_lines;
+        let width = last.to_string().len();
+
+        let lines: String = content
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line))
+            .skip(first - 1)
+            .take_while(|(number, _)| *number <= last)
+            .map(|(number, line)| {
+                let marker = if number == line_number { ">" } else { " " };
+                format!("\n     {} {:>width$} | {}", marker, number, line, width = width)
+            })
+            .collect();
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines)
+        }
+    }
+
+    /// The incident's metadata links, or none if reference links are disabled
+    fn references<'a>(&self, incident: &'a MigrationIncident) -> Vec<&'a str> {
+        if self.reference_links {
+            incident.reference_links()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn template_context<'a>(items: &'a [&PromptItem]) -> PromptContext<'a> {
+        PromptContext {
+            preamble: FIX_GENERATION_PREAMBLE,
+            incidents: items
+                .iter()
+                .map(|item| TemplateIncident {
+                    id: &item.incident.id,
+                    rule_id: item.incident.rule_id.as_deref(),
+                    uri: &item.incident.uri,
+                    line_number: item.incident.line_number,
+                    message: &item.message,
+                    description: &item.description,
+                    effort: item.incident.effort.as_deref(),
+                    severity: item.incident.severity.map(|severity| severity.to_string()),
+                    snippet: item.snippet.as_deref().unwrap_or_default(),
+                    references: item.references.clone(),
+                    suggestion: item.suggestion.as_deref().unwrap_or_default(),
+                    metadata: &item.incident.metadata,
+                })
+                .collect(),
+        }
+    }
+
+    /// Build a prompt with one shared context segment per rule, referenced by each incident
+    fn grouped_prompt(&self, items: &[&PromptItem]) -> String {
+        // The first incident of a rule supplies the rule context; BTreeMap keeps output stable
+        let mut rule_contexts: BTreeMap<&str, &str> = BTreeMap::new();
+        for item in items {
+            if let Some(rule_id) = item.incident.rule_id.as_deref() {
+                rule_contexts.entry(rule_id).or_insert(&item.description);
+            }
+        }
+
+        let mut prompt = String::from(FIX_GENERATION_PREAMBLE);
+
+        prompt.push_str("\n\nRules:");
+        for (rule_id, context) in &rule_contexts {
+            prompt.push_str(&rule_line(rule_id, context));
+        }
+
+        prompt.push_str("\n\nIssues:");
+        for (idx, item) in items.iter().enumerate() {
+            prompt.push_str(&grouped_entry(idx, item));
+        }
+
+        prompt
+    }
+}
+
+/// An incident in the flat prompt, numbered from its position
+fn flat_entry(idx: usize, item: &PromptItem) -> String {
+    format!("\n  {}. {}", idx + 1, item.entry(None, true))
+}
+
+/// An incident in the grouped prompt. One with a rule refers to the rule's shared context
+/// for its description; one without keeps its own, as in the flat prompt.
+fn grouped_entry(idx: usize, item: &PromptItem) -> String {
+    let rule_id = item.incident.rule_id.as_deref();
+    format!("\n  {}. {}", idx + 1, item.entry(rule_id, rule_id.is_none()))
+}
+
+/// A rule's context shared by its incidents in the grouped prompt
+fn rule_line(rule_id: &str, context: &str) -> String {
+    format!("\n  [{}] {}", rule_id, context)
+}
+
+/// The incident's `suggested_replacement` as an instruction, starting with a newline
+fn suggestion(incident: &MigrationIncident) -> Option<String> {
+    let replacement = incident.suggested_replacement.as_deref()?;
+    let target = match incident.affected_lines() {
+        Some(range) if range.start_line == range.end_line => format!("line {}", range.start_line),
+        Some(range) => format!("lines {}-{}", range.start_line, range.end_line),
+        None => "the affected code".to_string(),
+    };
+    if replacement.is_empty() {
+        return Some(format!(
+            "\n     Known fix, apply it unless it is wrong here: delete {}",
+            target
+        ));
+    }
+    let lines: String = replacement
+        .lines()
+        .map(|line| format!("\n       | {}", line))
+        .collect();
+    Some(format!(
+        "\n     Known fix, apply it unless it is wrong here: replace {} with:{}",
+        target, lines
+    ))
+}
+
+/// Split incidents into model turns of at most `max_incidents` incidents and `max_chars`
+/// characters of message and description; 0 leaves that limit off. A file's incidents stay
+/// together and in order, so later turns of a file follow the earlier ones; a single
+/// incident over the character budget gets a turn of its own.
+pub fn split_into_turns(
+    incidents: &[MigrationIncident],
+    max_incidents: usize,
+    max_chars: usize,
+) -> Vec<Vec<MigrationIncident>> {
+    if max_incidents == 0 && max_chars == 0 {
+        return vec![incidents.to_vec()];
+    }
+
+    let mut files: Vec<(PathBuf, Vec<&MigrationIncident>)> = Vec::new();
+    for incident in incidents {
+        let path = incident.file_path();
+        match files.iter_mut().find(|(file, _)| *file == path) {
+            Some((_, file_incidents)) => file_incidents.push(incident),
+            None => files.push((path, vec![incident])),
+        }
+    }
+
+    let mut turns: Vec<Vec<MigrationIncident>> = Vec::new();
+    let mut turn: Vec<MigrationIncident> = Vec::new();
+    let mut turn_chars = 0;
+    for incident in files.into_iter().flat_map(|(_, file_incidents)| file_incidents) {
+        let chars = incident.message.chars().count() + incident.description.chars().count();
+        let full = (max_incidents > 0 && turn.len() >= max_incidents)
+            || (max_chars > 0 && turn_chars + chars > max_chars);
+        if full && !turn.is_empty() {
+            turns.push(std::mem::take(&mut turn));
+            turn_chars = 0;
+        }
+        turn.push(incident.clone());
+        turn_chars += chars;
+    }
+    if !turn.is_empty() || turns.is_empty() {
+        turns.push(turn);
+    }
+    turns
+}
+
+/// Keep the head and tail of `text` around a marker so the result is at most `limit` characters
+fn truncate_middle(text: &str, limit: usize) -> Cow<'_, str> {
+    let length = text.chars().count();
+    if length <= limit {
+        return Cow::Borrowed(text);
+    }
+
+    let marker_length = TRUNCATION_MARKER.chars().count();
+    if limit <= marker_length {
+        return Cow::Owned(text.chars().take(limit).collect());
+    }
+    let kept = limit - marker_length;
+    let head: String = text.chars().take(kept.div_ceil(2)).collect();
+    let tail: String = text.chars().skip(length - kept / 2).collect();
+    Cow::Owned(format!("{}{}{}", head, TRUNCATION_MARKER, tail))
+}
+
+fn read_source(path: &Path, max_bytes: u64) -> Option<String> {
+    match crate::models::text_encoding::read_text(path, max_bytes) {
+        Ok(decoded) => Some(decoded.text),
+        Err(e) => {
+            tracing::debug!("No context for {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::incidents::IncidentSeverity;
+
+    fn incident(id: &str, uri: &str, rule_id: Option<&str>) -> MigrationIncident {
+        let incident = MigrationIncident::new(
+            id.to_string(),
+            uri.to_string(),
+            format!("Issue {}", id),
+            "Replace javax.xml.bind with java.util.Base64".to_string(),
+        );
+        match rule_id {
+            Some(rule_id) => incident.with_rule_id(rule_id),
+            None => incident,
+        }
+    }
+
+    #[test]
+    fn test_grouped_prompt_keeps_descriptions_of_incidents_without_rule() {
+        let incidents = vec![
+            incident("1", "file:///src/A.java", Some("javax-to-jakarta")),
+            incident("2", "file:///src/B.java", Some("javax-to-jakarta")),
+            MigrationIncident::new(
+                "3".to_string(),
+                "file:///src/C.java".to_string(),
+                "Issue 3".to_string(),
+                "Use SLF4J instead of commons-logging".to_string(),
+            ),
+        ];
+
+        let grouped = PromptBuilder::new()
+            .with_rule_grouping(true)
+            .fix_generation_prompt(&incidents);
+        let flat = PromptBuilder::new().fix_generation_prompt(&incidents);
+
+        // The rule's incidents share its context; the other keeps its own description
+        assert_eq!(grouped.matches("Replace javax.xml.bind").count(), 1);
+        assert!(grouped.contains(
+            "\n  3. Issue 3 (file: file:///src/C.java)\n     Use SLF4J instead of commons-logging"
+        ));
+        // Every incident's message, file and description is in both prompts
+        for incident in &incidents {
+            for field in [&incident.message, &incident.uri, &incident.description] {
+                assert!(grouped.contains(field.as_str()), "{field}");
+                assert!(flat.contains(field.as_str()), "{field}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_incident_prompt() {
+        let prompt = PromptBuilder::new()
+            .fix_generation_prompt(&[incident("1", "file:///src/A.java", None)]);
+        assert_eq!(
+            prompt,
+            format!(
+                "{} Issue 1 (file: file:///src/A.java)\n     Replace javax.xml.bind with java.util.Base64",
+                FIX_GENERATION_PREAMBLE
+            )
+        );
+    }
+
+    #[test]
+    fn test_multiple_incidents_prompt() {
+        let prompt = PromptBuilder::new().fix_generation_prompt(&[
+            incident("1", "file:///src/A.java", None),
+            incident("2", "file:///src/B.java", None),
+        ]);
+        assert!(prompt.ends_with(concat!(
+            "\n  1. Issue 1 (file: file:///src/A.java)\n     Replace javax.xml.bind with java.util.Base64",
+            "\n  2. Issue 2 (file: file:///src/B.java)\n     Replace javax.xml.bind with java.util.Base64",
+        )));
+    }
+
+    #[test]
+    fn test_grouped_prompt_emits_rule_context_once() {
+        let incidents = vec![
+            incident("1", "file:///src/A.java", Some("javax-to-jakarta")),
+            incident("2", "file:///src/B.java", Some("javax-to-jakarta")),
+            incident("3", "file:///src/C.java", Some("javax-to-jakarta")),
+        ];
+
+        let prompt = PromptBuilder::new()
+            .with_rule_grouping(true)
+            .fix_generation_prompt(&incidents);
+
+        assert_eq!(
+            prompt
+                .matches("Replace javax.xml.bind with java.util.Base64")
+                .count(),
+            1
+        );
+        // Every incident is still listed and references its rule
+        for uri in ["file:///src/A.java", "file:///src/B.java", "file:///src/C.java"] {
+            assert!(prompt.contains(uri));
+        }
+        assert_eq!(prompt.matches("rule: [javax-to-jakarta]").count(), 3);
+    }
+
+    #[test]
+    fn test_grouping_without_rule_ids_falls_back_to_flat_prompt() {
+        let incidents = vec![
+            incident("1", "file:///src/A.java", None),
+            incident("2", "file:///src/B.java", None),
+        ];
+
+        let grouped = PromptBuilder::new()
+            .with_rule_grouping(true)
+            .fix_generation_prompt(&incidents);
+        let flat = PromptBuilder::new().fix_generation_prompt(&incidents);
+
+        assert_eq!(grouped, flat);
+    }
+
+    #[test]
+    fn test_context_lines_control_snippet_size() {
+        let workspace = tempfile::tempdir().unwrap();
+        let source: String = (1..=40).map(|n| format!("line {}\n", n)).collect();
+        std::fs::write(workspace.path().join("A.java"), source).unwrap();
+        let incidents = vec![incident("1", "A.java", None).with_line_number(20)];
+
+        let builder = PromptBuilder::new().with_workspace(workspace.path());
+
+        let prompt = builder.clone().fix_generation_prompt(&incidents);
+        assert!(!prompt.contains("line 20"));
+
+        let prompt = builder.clone().with_context_lines(2).fix_generation_prompt(&incidents);
+        let shown: Vec<&str> = prompt.lines().filter(|l| l.contains(" | line ")).collect();
+        assert_eq!(shown.len(), 5);
+        assert!(shown[0].ends_with("18 | line 18"));
+        assert!(shown[2].contains("> 20 | line 20"));
+        assert!(shown[4].ends_with("22 | line 22"));
+
+        // Large requests are clamped, and the window stops at the file boundaries
+        let prompt = builder.with_context_lines(10_000).fix_generation_prompt(&incidents);
+        assert_eq!(prompt.lines().filter(|l| l.contains(" | line ")).count(), 40);
+    }
+
+    #[test]
+    fn test_budget_drops_lowest_severity_first() {
+        let incidents: Vec<MigrationIncident> = (0..200)
+            .map(|i| {
+                let severity = if i % 10 == 0 {
+                    IncidentSeverity::Critical
+                } else {
+                    IncidentSeverity::Info
+                };
+                MigrationIncident::with_details(
+                    format!("inc-{}", i),
+                    format!("file:///src/File{}.java", i),
+                    format!("Replace deprecated API usage number {} in this file", i),
+                    String::new(),
+                    None,
+                    Some(severity),
+                )
+            })
+            .collect();
+
+        let unbounded = PromptBuilder::new().build(&incidents);
+        assert!(unbounded.trimmed_incident_ids.is_empty());
+
+        let budget = 500;
+        let prompt = PromptBuilder::new()
+            .with_max_prompt_tokens(Some(budget))
+            .build(&incidents);
+
+        assert!(estimate_tokens(&prompt.text) <= budget);
+        assert!(!prompt.trimmed_incident_ids.is_empty());
+        assert_eq!(
+            prompt.trimmed_incident_ids.len(),
+            incidents.len() - prompt.text.matches("Replace deprecated API").count()
+        );
+        // Critical incidents survive while info incidents are trimmed
+        for i in (0..200).step_by(10) {
+            assert!(!prompt.trimmed_incident_ids.contains(&format!("inc-{}", i)));
+        }
+    }
+
+    #[test]
+    fn test_budget_trims_no_more_than_needed() {
+        let incidents: Vec<MigrationIncident> = (0..200)
+            .map(|i| {
+                incident(
+                    &format!("inc-{}", i),
+                    &format!("file:///src/File{}.java", i),
+                    Some(["rule-a", "rule-b", "rule-c"][i % 3]),
+                )
+            })
+            .collect();
+
+        for group_by_rule in [false, true] {
+            let builder = PromptBuilder::new().with_rule_grouping(group_by_rule);
+            let budget = 1000;
+            let prompt = builder
+                .clone()
+                .with_max_prompt_tokens(Some(budget))
+                .build(&incidents);
+            assert!(estimate_tokens(&prompt.text) <= budget, "{group_by_rule}");
+
+            // Later incidents go first, so the earliest trimmed one was the last dropped;
+            // keeping it too would not fit
+            let last_dropped = &prompt.trimmed_incident_ids[0];
+            let with_one_more: Vec<MigrationIncident> = incidents
+                .iter()
+                .filter(|incident| {
+                    incident.id == *last_dropped
+                        || !prompt.trimmed_incident_ids.contains(&incident.id)
+                })
+                .cloned()
+                .collect();
+            assert!(
+                estimate_tokens(&builder.fix_generation_prompt(&with_one_more)) > budget,
+                "{group_by_rule}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_description_is_truncated_to_limit() {
+        let stack_trace: String = (0..2_000)
+            .map(|n| format!("\tat com.example.Frame{}.call(Frame{}.java:{})\n", n, n, n))
+            .collect();
+        let incidents = vec![
+            MigrationIncident::new(
+                "1".to_string(),
+                "file:///src/A.java".to_string(),
+                "Uncaught exception during startup".to_string(),
+                format!("Exception in thread \"main\"\n{}Caused by: root cause", stack_trace),
+            )
+            .with_rule_id("startup-failure"),
+            incident("2", "file:///src/B.java", Some("other-rule")),
+        ];
+
+        let limit = 300;
+        let prompt = PromptBuilder::new()
+            .with_rule_grouping(true)
+            .with_incident_text_limit(limit)
+            .build(&incidents);
+
+        assert_eq!(prompt.truncated_incident_ids, vec!["1".to_string()]);
+        let segment = prompt
+            .text
+            .split("\n  [startup-failure] ")
+            .nth(1)
+            .and_then(|rest| rest.split("\n\nIssues:").next())
+            .unwrap();
+        assert_eq!(segment.chars().count(), limit);
+        assert!(segment.starts_with("Exception in thread \"main\""));
+        assert!(segment.contains(TRUNCATION_MARKER));
+        assert!(segment.ends_with("Caused by: root cause"));
+        // Short fields are left alone
+        assert!(prompt.text.contains("[other-rule] Replace javax.xml.bind with java.util.Base64"));
+    }
+
+    #[test]
+    fn test_many_same_file_incidents_split_into_turns() {
+        let mut incidents: Vec<MigrationIncident> = (1..=7)
+            .map(|i| incident(&format!("a{i}"), "file:///src/A.java", None))
+            .collect();
+        incidents.insert(2, incident("b1", "file:///src/B.java", None));
+
+        let turns = split_into_turns(&incidents, 3, 0);
+        let ids: Vec<Vec<&str>> = turns
+            .iter()
+            .map(|turn| turn.iter().map(|incident| incident.id.as_str()).collect())
+            .collect();
+        // A.java's incidents stay together and in order; B.java follows them
+        assert_eq!(
+            ids,
+            vec![
+                vec!["a1", "a2", "a3"],
+                vec!["a4", "a5", "a6"],
+                vec!["a7", "b1"],
+            ]
+        );
+        assert_eq!(turns.iter().map(Vec::len).sum::<usize>(), incidents.len());
+
+        // Each incident carries ~50 characters, so a 120 character budget fits two
+        assert_eq!(split_into_turns(&incidents, 0, 120).len(), 4);
+        // Without limits the request is a single turn, in request order
+        assert_eq!(split_into_turns(&incidents, 0, 0), vec![incidents.clone()]);
+
+        // Later turns are told which files earlier turns already changed
+        let prompt = PromptBuilder::new()
+            .with_edited_files(vec!["/src/A.java".to_string()])
+            .fix_generation_prompt(&turns[1]);
+        assert!(prompt.ends_with(
+            "Earlier turns already changed /src/A.java. Read these files again before editing them; the line numbers above may have moved."
+        ));
+    }
+}
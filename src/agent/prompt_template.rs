@@ -0,0 +1,187 @@
+//! User-configurable layout of the fix generation prompt, rendered with minijinja.
+
+use std::path::Path;
+
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::{KaiakError, KaiakResult};
+
+const TEMPLATE_NAME: &str = "prompt";
+
+/// Template producing the same prompt as the built-in (ungrouped) layout
+pub const DEFAULT_PROMPT_TEMPLATE: &str = concat!(
+    "{{ preamble }}",
+    "{% if incidents | length == 1 %}",
+    "{% for incident in incidents %} {{ incident.message }} (file: {{ incident.uri }})",
+    "{% if incident.description %}\n     {{ incident.description }}{% endif %}{{ incident.snippet }}",
+    "{% if incident.references %}\n     See: {{ incident.references | join(\", \") }}{% endif %}",
+    "{{ incident.suggestion }}",
+    "{% endfor %}",
+    "{% else %}",
+    "{% for incident in incidents %}\n  {{ loop.index }}. {{ incident.message }} (file: {{ incident.uri }})",
+    "{% if incident.description %}\n     {{ incident.description }}{% endif %}{{ incident.snippet }}",
+    "{% if incident.references %}\n     See: {{ incident.references | join(\", \") }}{% endif %}",
+    "{{ incident.suggestion }}",
+    "{% else %} (no incidents provided){% endfor %}",
+    "{% endif %}",
+);
+
+/// Values available to a prompt template
+#[derive(Debug, Serialize)]
+pub struct PromptContext<'a> {
+    pub preamble: &'a str,
+    pub incidents: Vec<TemplateIncident<'a>>,
+}
+
+/// An incident as seen by a prompt template. `message` and `description` are already
+/// truncated to the server's `incident_text_limit`.
+#[derive(Debug, Serialize)]
+pub struct TemplateIncident<'a> {
+    pub id: &'a str,
+    pub rule_id: Option<&'a str>,
+    pub uri: &'a str,
+    pub line_number: Option<usize>,
+    pub message: &'a str,
+    pub description: &'a str,
+    pub effort: Option<&'a str>,
+    pub severity: Option<String>,
+    /// Numbered source lines around `line_number`, each starting with a newline; empty if none
+    pub snippet: &'a str,
+    /// Links from the incident's metadata, when reference links are enabled
+    pub references: Vec<&'a str>,
+    /// The incident's `suggested_replacement` as an instruction, starting with a newline;
+    /// empty if none
+    pub suggestion: &'a str,
+    pub metadata: &'a serde_json::Value,
+}
+
+/// A compiled prompt template
+#[derive(Debug)]
+pub struct PromptTemplate {
+    env: Environment<'static>,
+}
+
+impl PromptTemplate {
+    /// Compile `source`, failing on syntax errors or errors rendering a sample incident
+    pub fn new(source: impl Into<String>) -> KaiakResult<Self> {
+        let mut env = Environment::new();
+        env.add_template_owned(TEMPLATE_NAME, source.into())
+            .map_err(|e| KaiakError::configuration(format!("Invalid prompt template: {}", e)))?;
+        let template = Self { env };
+
+        let metadata = serde_json::Value::Null;
+        let sample = PromptContext {
+            preamble: "",
+            incidents: vec![TemplateIncident {
+                id: "sample",
+                rule_id: Some("sample-rule"),
+                uri: "file:///sample.java",
+                line_number: Some(1),
+                message: "message",
+                description: "description",
+                effort: None,
+                severity: None,
+                snippet: "",
+                references: Vec::new(),
+                suggestion: "",
+                metadata: &metadata,
+            }],
+        };
+        template.render(&sample)?;
+        Ok(template)
+    }
+
+    /// Read and compile the template at `path`
+    pub fn from_file(path: &Path) -> KaiakResult<Self> {
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            KaiakError::configuration(format!(
+                "Failed to read prompt template {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::new(source)
+    }
+
+    pub fn render(&self, context: &PromptContext) -> KaiakResult<String> {
+        self.env
+            .get_template(TEMPLATE_NAME)
+            .and_then(|template| template.render(context))
+            .map_err(|e| {
+                KaiakError::configuration(format!("Failed to render prompt template: {}", e))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::PromptBuilder;
+    use crate::models::incidents::{IncidentSeverity, MigrationIncident};
+    use std::sync::Arc;
+
+    fn incidents() -> Vec<MigrationIncident> {
+        vec![
+            MigrationIncident::with_details(
+                "javax-1".to_string(),
+                "file:///ws/src/Main.java".to_string(),
+                "Replace javax.servlet import".to_string(),
+                "Jakarta EE renamed the javax namespace".to_string(),
+                None,
+                Some(IncidentSeverity::Error),
+            )
+            .with_rule_id("javax-to-jakarta")
+            .with_line_number(3)
+            .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)
+            .with_metadata(serde_json::json!({ "docs": "https://example.com/javax" })),
+            MigrationIncident::new(
+                "log-1".to_string(),
+                "file:///ws/src/Log.java".to_string(),
+                "Use SLF4J".to_string(),
+                String::new(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_default_template_matches_builtin_prompt() {
+        let template = Arc::new(PromptTemplate::new(DEFAULT_PROMPT_TEMPLATE).unwrap());
+        let incidents = incidents();
+
+        for count in 0..=incidents.len() {
+            let builtin = PromptBuilder::new().with_reference_links(true);
+            let templated = builtin.clone().with_template(Some(template.clone()));
+            assert_eq!(
+                templated.fix_generation_prompt(&incidents[..count]),
+                builtin.fix_generation_prompt(&incidents[..count])
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_template_lays_out_incident_fields() {
+        let template = PromptTemplate::new(
+            "{% for i in incidents %}<issue id=\"{{ i.id }}\" rule=\"{{ i.rule_id }}\" \
+             severity=\"{{ i.severity }}\" line=\"{{ i.line_number }}\">{{ i.uri }}: {{ i.message }} \
+             ({{ i.description }}) docs={{ i.metadata.docs }}</issue>\n{% endfor %}",
+        )
+        .unwrap();
+        let prompt = PromptBuilder::new()
+            .with_template(Some(Arc::new(template)))
+            .fix_generation_prompt(&incidents()[..1]);
+
+        assert_eq!(
+            prompt,
+            "<issue id=\"javax-1\" rule=\"javax-to-jakarta\" severity=\"error\" line=\"3\">\
+             file:///ws/src/Main.java: Replace javax.servlet import \
+             (Jakarta EE renamed the javax namespace) docs=https://example.com/javax</issue>\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_template_is_rejected_at_load() {
+        let error = PromptTemplate::new("{% for i in incidents %}{{ i.message }}").unwrap_err();
+        assert!(matches!(error, KaiakError::Configuration { .. }), "{error:?}");
+    }
+}
@@ -0,0 +1,103 @@
+//! Server-wide bound on concurrent model calls.
+
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Bounds model calls across all sessions, so bursts queue instead of tripping provider
+/// rate limits. Unbounded when no limit is configured.
+#[derive(Debug, Default)]
+pub struct ModelCallLimiter {
+    slots: Option<Arc<Semaphore>>,
+    limit: Option<usize>,
+}
+
+/// Slot held while a model call runs; dropping it lets the next waiting call start
+#[derive(Debug)]
+pub struct ModelCallPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ModelCallLimiter {
+    pub fn new(max_concurrent: Option<usize>) -> Self {
+        let limit = max_concurrent.map(|max| max.max(1));
+        Self {
+            slots: limit.map(|max| Arc::new(Semaphore::new(max))),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Wait for a free slot, calling `on_wait` first if none is free right away
+    pub async fn acquire(&self, on_wait: impl FnOnce()) -> ModelCallPermit {
+        let Some(slots) = &self.slots else {
+            return ModelCallPermit { _permit: None };
+        };
+        let permit = match slots.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                on_wait();
+                slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("model call semaphore is never closed")
+            }
+        };
+        ModelCallPermit {
+            _permit: Some(permit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_model_calls_serialize_at_limit_of_one() {
+        let limiter = Arc::new(ModelCallLimiter::new(Some(1)));
+        let running = Arc::new(Mutex::new(0usize));
+        let max_running = Arc::new(Mutex::new(0usize));
+        let waited = Arc::new(Mutex::new(Vec::new()));
+
+        let mut handles = Vec::new();
+        for request in ["req-1", "req-2"] {
+            let (limiter, running, max_running, waited) =
+                (limiter.clone(), running.clone(), max_running.clone(), waited.clone());
+            handles.push(tokio::spawn(async move {
+                let _slot = limiter
+                    .acquire(|| waited.lock().unwrap().push(request))
+                    .await;
+                {
+                    let mut running = running.lock().unwrap();
+                    *running += 1;
+                    let mut max_running = max_running.lock().unwrap();
+                    *max_running = (*max_running).max(*running);
+                }
+                // The model call
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                *running.lock().unwrap() -= 1;
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(*max_running.lock().unwrap(), 1);
+        assert_eq!(waited.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unbounded_model_calls_never_wait() {
+        let limiter = ModelCallLimiter::default();
+        let first = limiter.acquire(|| panic!("unbounded calls never wait")).await;
+        let _second = limiter.acquire(|| panic!("unbounded calls never wait")).await;
+        drop(first);
+        assert_eq!(limiter.limit(), None);
+    }
+}
@@ -0,0 +1,62 @@
+//! Per-session scratch directories, where tools keep temporary files instead of writing
+//! them into the workspace or leaving them in the system temp directory.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::{KaiakError, KaiakResult};
+
+/// Scratch directory of each live session, each below a shared base directory
+#[derive(Debug)]
+pub struct ScratchDirs {
+    /// A relative base is resolved against each session's workspace
+    base: PathBuf,
+    dirs: Mutex<HashMap<String, PathBuf>>,
+}
+
+impl Default for ScratchDirs {
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join("kaiak-scratch"))
+    }
+}
+
+impl ScratchDirs {
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            dirs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create the session's scratch directory, `<base>/<session_id>`, unless it exists
+    pub fn create(&self, session_id: &str, workspace: &Path) -> KaiakResult<PathBuf> {
+        let dir = workspace.join(&self.base).join(session_id);
+        std::fs::create_dir_all(&dir).map_err(|e| KaiakError::Io {
+            message: format!("Failed to create scratch directory {}: {}", dir.display(), e),
+        })?;
+        debug!("Scratch directory of session {}: {}", session_id, dir.display());
+        self.dirs
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), dir.clone());
+        Ok(dir)
+    }
+
+    pub fn get(&self, session_id: &str) -> Option<PathBuf> {
+        self.dirs.lock().unwrap().get(session_id).cloned()
+    }
+
+    /// Remove the session's scratch directory and everything tools left in it
+    pub fn remove(&self, session_id: &str) {
+        let Some(dir) = self.dirs.lock().unwrap().remove(session_id) else {
+            return;
+        };
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => debug!("Removed scratch directory {}", dir.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove scratch directory {}: {}", dir.display(), e),
+        }
+    }
+}
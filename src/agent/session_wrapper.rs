@@ -3,6 +3,8 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
+use goose::conversation::message::Message;
+use goose::conversation::Conversation;
 use goose::session::{Session, SessionManager, SessionType};
 
 use crate::models::configuration::AgentConfig;
@@ -115,6 +117,26 @@ impl GooseSessionWrapper {
         }
     }
 
+    /// Accumulated (input, output) token counts the provider reported for the session,
+    /// or None when the session is unknown or has no usage recorded
+    pub async fn token_counters(&self, session_id: &str) -> Option<(u64, u64)> {
+        let session = SessionManager::get_session(session_id, false).await.ok()?;
+        let input = session.accumulated_input_tokens?;
+        let output = session.accumulated_output_tokens.unwrap_or(0);
+        Some((input.max(0) as u64, output.max(0) as u64))
+    }
+
+    /// Conversation history of a session, oldest message first
+    pub async fn messages(&self, session_id: &str) -> KaiakResult<Vec<Message>> {
+        let session = SessionManager::get_session(session_id, true)
+            .await
+            .map_err(|_| KaiakError::SessionNotFound(session_id.to_string()))?;
+        Ok(session
+            .conversation
+            .map(|conversation| conversation.messages().clone())
+            .unwrap_or_default())
+    }
+
     /// Check if a session exists
     pub async fn session_exists(&self, session_id: &str) -> bool {
         match self.get_session(session_id).await {
@@ -155,6 +177,35 @@ impl GooseSessionWrapper {
         }
     }
 
+    /// Clear a session's conversation history, keeping the session itself
+    pub async fn reset_context(&self, session_id: &str) -> KaiakResult<SessionInfo> {
+        info!("Resetting conversation context for Goose session: {}", session_id);
+
+        if self.is_session_locked(session_id).await {
+            return Err(KaiakError::session_in_use(
+                session_id.to_string(),
+                self.get_session_lock_time(session_id).await
+            ));
+        }
+
+        if !self.session_exists(session_id).await {
+            return Err(KaiakError::SessionNotFound(session_id.to_string()));
+        }
+
+        SessionManager::replace_conversation(session_id, &Conversation::empty())
+            .await
+            .map_err(|e| {
+                KaiakError::goose_integration(
+                    format!("Failed to reset session context: {}", e),
+                    Some("session_reset".to_string())
+                )
+            })?;
+
+        self.get_session(session_id)
+            .await?
+            .ok_or_else(|| KaiakError::SessionNotFound(session_id.to_string()))
+    }
+
     /// Lock a session to prevent concurrent access
     pub async fn lock_session(&self, session_id: &str) -> KaiakResult<()> {
         debug!("Locking session: {}", session_id);
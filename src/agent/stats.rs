@@ -0,0 +1,129 @@
+//! Server-wide counters, updated as sessions and requests come and go, for `kaiak/stats`.
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::models::report::MigrationReport;
+
+/// Counters of everything the server has done since it started, or since they were last
+/// reset
+#[derive(Debug)]
+pub struct ServerStats {
+    started_at: Instant,
+    sessions_created: AtomicU64,
+    sessions_active: AtomicU64,
+    requests_processed: AtomicU64,
+    requests_running: AtomicU64,
+    requests_succeeded: AtomicU64,
+    requests_failed: AtomicU64,
+    files_modified: AtomicU64,
+    tool_calls: AtomicU64,
+    /// Summed latency of processed requests, for the average
+    latency_ms: AtomicU64,
+}
+
+/// Point-in-time copy of the server's counters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    pub sessions_created: u64,
+    /// Sessions created and not deleted since
+    pub sessions_active: u64,
+    /// Finished generate_fix and retry_failed requests, successful or not
+    pub requests_processed: u64,
+    pub requests_running: u64,
+    pub requests_succeeded: u64,
+    pub requests_failed: u64,
+    pub files_modified: u64,
+    pub tool_calls: u64,
+    /// Mean latency of processed requests; 0 before the first one
+    pub average_latency_ms: f64,
+    pub uptime_secs: u64,
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            sessions_created: AtomicU64::new(0),
+            sessions_active: AtomicU64::new(0),
+            requests_processed: AtomicU64::new(0),
+            requests_running: AtomicU64::new(0),
+            requests_succeeded: AtomicU64::new(0),
+            requests_failed: AtomicU64::new(0),
+            files_modified: AtomicU64::new(0),
+            tool_calls: AtomicU64::new(0),
+            latency_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ServerStats {
+    pub fn session_created(&self) {
+        self.sessions_created.fetch_add(1, Ordering::Relaxed);
+        self.sessions_active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn session_deleted(&self) {
+        decrement(&self.sessions_active);
+    }
+
+    pub fn request_started(&self) {
+        self.requests_running.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a finished request with what its report says it did; failed requests may
+    /// still have modified files
+    pub fn request_finished(&self, succeeded: bool, report: &MigrationReport, latency_ms: u64) {
+        decrement(&self.requests_running);
+        self.requests_processed.fetch_add(1, Ordering::Relaxed);
+        let outcome = if succeeded {
+            &self.requests_succeeded
+        } else {
+            &self.requests_failed
+        };
+        outcome.fetch_add(1, Ordering::Relaxed);
+        self.files_modified
+            .fetch_add(report.files_modified as u64, Ordering::Relaxed);
+        self.tool_calls
+            .fetch_add(report.tool_calls as u64, Ordering::Relaxed);
+        self.latency_ms.fetch_add(latency_ms, Ordering::Relaxed);
+    }
+
+    /// Current counters; with `reset`, the totals start over from zero while the running
+    /// and active gauges and the uptime are kept
+    pub fn snapshot(&self, reset: bool) -> StatsSnapshot {
+        let total = |counter: &AtomicU64| {
+            if reset {
+                counter.swap(0, Ordering::Relaxed)
+            } else {
+                counter.load(Ordering::Relaxed)
+            }
+        };
+        let requests_processed = total(&self.requests_processed);
+        let latency_ms = total(&self.latency_ms);
+        StatsSnapshot {
+            sessions_created: total(&self.sessions_created),
+            sessions_active: self.sessions_active.load(Ordering::Relaxed),
+            requests_processed,
+            requests_running: self.requests_running.load(Ordering::Relaxed),
+            requests_succeeded: total(&self.requests_succeeded),
+            requests_failed: total(&self.requests_failed),
+            files_modified: total(&self.files_modified),
+            tool_calls: total(&self.tool_calls),
+            average_latency_ms: if requests_processed > 0 {
+                latency_ms as f64 / requests_processed as f64
+            } else {
+                0.0
+            },
+            uptime_secs: self.started_at.elapsed().as_secs(),
+        }
+    }
+}
+
+/// Decrement a gauge without wrapping below zero
+fn decrement(gauge: &AtomicU64) {
+    let _ = gauge.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |value| {
+        value.checked_sub(1)
+    });
+}
@@ -0,0 +1,192 @@
+//! Throughput measurement for the `kaiak benchmark` command.
+
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::client::JsonRpcClient;
+
+/// Outcome of driving a server with concurrent generate_fix requests
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BenchmarkReport {
+    pub requests: usize,
+    pub concurrency: usize,
+    pub errors: usize,
+    /// Wall-clock time for all requests, in seconds
+    pub elapsed_secs: f64,
+    /// Completed requests per second, failed ones included
+    pub throughput: f64,
+    /// Fraction of requests that failed, from 0 to 1
+    pub error_rate: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p90_ms: f64,
+    pub latency_p99_ms: f64,
+    pub latency_max_ms: f64,
+    /// First error seen, to tell why requests failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_error: Option<String>,
+}
+
+/// Send `requests` generate_fix calls with `params`, at most `concurrency` at a time.
+/// Each call uses its own connection; without a `session_id` each creates a session.
+pub async fn run_benchmark(
+    client: &JsonRpcClient,
+    params: serde_json::Value,
+    concurrency: usize,
+    requests: usize,
+) -> BenchmarkReport {
+    let concurrency = concurrency.max(1);
+    let started = Instant::now();
+    let results: Vec<(Duration, Result<(), String>)> = stream::iter(0..requests)
+        .map(|_| {
+            let params = params.clone();
+            async move {
+                let sent = Instant::now();
+                let result = client
+                    .generate_fix(params, |_| {})
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string());
+                (sent.elapsed(), result)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    let elapsed = started.elapsed();
+
+    let mut latencies: Vec<f64> = results
+        .iter()
+        .map(|(latency, _)| latency.as_secs_f64() * 1000.0)
+        .collect();
+    latencies.sort_by(|a, b| a.total_cmp(b));
+    let errors = results.iter().filter(|(_, result)| result.is_err()).count();
+    let elapsed_secs = elapsed.as_secs_f64();
+
+    BenchmarkReport {
+        requests,
+        concurrency,
+        errors,
+        elapsed_secs,
+        throughput: if elapsed_secs > 0.0 {
+            requests as f64 / elapsed_secs
+        } else {
+            0.0
+        },
+        error_rate: if requests > 0 {
+            errors as f64 / requests as f64
+        } else {
+            0.0
+        },
+        latency_p50_ms: percentile(&latencies, 50.0),
+        latency_p90_ms: percentile(&latencies, 90.0),
+        latency_p99_ms: percentile(&latencies, 99.0),
+        latency_max_ms: latencies.last().copied().unwrap_or_default(),
+        first_error: results.into_iter().find_map(|(_, result)| result.err()),
+    }
+}
+
+/// Nearest-rank percentile of sorted values; 0 when there are none
+fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Human-readable report lines
+pub fn benchmark_lines(report: &BenchmarkReport) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "Requests: {} ({} concurrent) in {:.2}s",
+            report.requests, report.concurrency, report.elapsed_secs
+        ),
+        format!("Throughput: {:.2} requests/s", report.throughput),
+        format!(
+            "Errors: {} ({:.1}%)",
+            report.errors,
+            report.error_rate * 100.0
+        ),
+        format!(
+            "Latency: p50 {:.0}ms, p90 {:.0}ms, p99 {:.0}ms, max {:.0}ms",
+            report.latency_p50_ms, report.latency_p90_ms, report.latency_p99_ms, report.latency_max_ms
+        ),
+    ];
+    if let Some(error) = &report.first_error {
+        lines.push(format!("  First error: {}", error));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    /// Answer every request on every connection with an empty report
+    async fn stub_server(listener: tokio::net::UnixListener) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (read_half, mut write_half) = stream.into_split();
+                let mut reader = BufReader::new(read_half);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).await.unwrap() == 0 {
+                        return;
+                    }
+                    match line.trim_end().strip_prefix("Content-Length: ") {
+                        Some(length) => content_length = length.parse().unwrap(),
+                        None if line.trim_end().is_empty() => break,
+                        None => {}
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).await.unwrap();
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": { "incidents": [] },
+                })
+                .to_string();
+                let message = format!("Content-Length: {}\r\n\r\n{}", response.len(), response);
+                write_half.write_all(message.as_bytes()).await.unwrap();
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_against_stub_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("kaiak.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        let server = tokio::spawn(stub_server(listener));
+
+        let client = JsonRpcClient::new(socket_path.display().to_string());
+        let report = run_benchmark(&client, serde_json::json!({ "incidents": [] }), 4, 12).await;
+        server.abort();
+
+        assert_eq!(report.requests, 12);
+        assert_eq!(report.errors, 0, "{:?}", report.first_error);
+        assert_eq!(report.error_rate, 0.0);
+        assert!(report.throughput > 0.0);
+        assert!(report.latency_p50_ms > 0.0);
+        assert!(report.latency_p50_ms <= report.latency_p99_ms);
+        assert!(report.latency_p99_ms <= report.latency_max_ms);
+        assert!(benchmark_lines(&report)[2].starts_with("Errors: 0"));
+    }
+
+    #[test]
+    fn test_percentile_uses_nearest_rank() {
+        let latencies: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&latencies, 50.0), 5.0);
+        assert_eq!(percentile(&latencies, 90.0), 9.0);
+        assert_eq!(percentile(&latencies, 99.0), 10.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+}
@@ -4,11 +4,19 @@ use anyhow::Result;
 use std::path::PathBuf;
 use tracing::{error, info};
 
-use crate::client::ConnectionState;
+use crate::client::{ConnectionState, DEFAULT_PROFILE};
 use crate::models::configuration::ServerConfig;
-use crate::server::{start_server, TransportConfig};
-
-use super::utils::{load_request_params, load_server_config, print_notification};
+use crate::server::{check_server, start_server, TransportConfig};
+use crate::KaiakError;
+
+use super::benchmark::{benchmark_lines, run_benchmark};
+use super::doctor::{print_check_results, run_checks, CheckStatus};
+use super::exit_codes::InvalidParams;
+use super::status::{check_status, status_lines};
+use super::utils::{
+    load_incidents_dir, load_request_params, load_server_config, print_migration_report,
+    print_notification, write_initial_config,
+};
 
 /// Start the Kaiak JSON-RPC server
 pub async fn serve(
@@ -16,12 +24,21 @@ pub async fn serve(
     socket_path: Option<String>,
     config_path: Option<PathBuf>,
     config_json: Option<String>,
+    check: bool,
+    read_only: bool,
 ) -> Result<()> {
     info!("Starting Kaiak server with {} transport", transport_type);
     info!("Loading configuration...");
 
-    let server_config = load_server_config(config_path, config_json)?;
-    server_config.validate()?;
+    let mut server_config = load_server_config(config_path, config_json)
+        .and_then(|config| config.validate().map(|_| config))
+        .map_err(|e| KaiakError::configuration(format!("{:#}", e)))?;
+    if read_only {
+        server_config.base_config.read_only = true;
+    }
+    if server_config.base_config.read_only {
+        info!("Read-only mode: all file modifications will be refused");
+    }
 
     let transport_config = match transport_type.as_str() {
         "stdio" => TransportConfig::Stdio,
@@ -30,7 +47,12 @@ pub async fn serve(
                 .or(server_config.init_config.socket_path.clone())
                 .unwrap_or_else(|| "/tmp/kaiak.sock".to_string()),
         },
-        _ => anyhow::bail!("Invalid transport type: {}", transport_type),
+        _ => {
+            return Err(
+                KaiakError::configuration(format!("Invalid transport type: {}", transport_type))
+                    .into(),
+            )
+        }
     };
 
     info!(
@@ -38,6 +60,18 @@ pub async fn serve(
         transport_config
     );
 
+    if check {
+        let result = check_server(std::sync::Arc::new(server_config), Some(transport_config)).await?;
+        println!("✓ Configuration is valid");
+        println!("✓ Transport: {}", result.transport);
+        println!("✓ Registered {} methods:", result.methods.len());
+        for method in &result.methods {
+            println!("    {}", method);
+        }
+        println!("Check passed; not serving.");
+        return Ok(());
+    }
+
     info!("Server starting...");
     start_server(std::sync::Arc::new(server_config), Some(transport_config)).await?;
 
@@ -46,25 +80,26 @@ pub async fn serve(
 }
 
 /// Connect to a Kaiak server via Unix socket
-pub async fn connect(socket_path: String) -> Result<()> {
+pub async fn connect(socket_path: String, name: Option<String>) -> Result<()> {
     use crate::client::JsonRpcClient;
 
     info!("Connecting to Kaiak server at: {}", socket_path);
 
     let path = std::path::Path::new(&socket_path);
     if !path.exists() {
-        anyhow::bail!("Socket path does not exist: {}", socket_path);
+        return Err(KaiakError::transport(format!("Socket path does not exist: {}", socket_path)).into());
     }
 
     let client = JsonRpcClient::new(socket_path.clone());
     if !client.validate_connection().await? {
-        anyhow::bail!("Failed to connect to server at: {}", socket_path);
+        return Err(KaiakError::transport(format!("Failed to connect to server at: {}", socket_path)).into());
     }
 
-    ConnectionState::save(&socket_path)?;
+    let name = name.unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    ConnectionState::save_named(&name, &socket_path)?;
 
     println!("✓ Connected to Kaiak server at: {}", socket_path);
-    println!("  Connection saved. Use 'kaiak status' to check connection.");
+    println!("  Connection saved as profile '{}'. Use 'kaiak status' to check connection.", name);
     println!("  Use 'kaiak disconnect' to disconnect.");
 
     Ok(())
@@ -85,13 +120,63 @@ pub async fn disconnect() -> Result<()> {
     Ok(())
 }
 
+/// Make a saved connection profile active, or list the profiles
+pub async fn use_profile(name: Option<String>) -> Result<()> {
+    let Some(name) = name else {
+        let profiles = ConnectionState::list()?;
+        if profiles.profiles.is_empty() {
+            println!("No saved connections. Use 'kaiak connect --name <name> <socket_path>' to save one.");
+        }
+        for (name, socket_path) in &profiles.profiles {
+            let marker = if profiles.active.as_ref() == Some(name) { "*" } else { " " };
+            println!("{} {}  {}", marker, name, socket_path);
+        }
+        return Ok(());
+    };
+
+    ConnectionState::use_profile(&name).map_err(|e| InvalidParams(e.to_string()))?;
+    let socket_path = ConnectionState::load()?.unwrap_or_default();
+    println!("✓ Using profile '{}': {}", name, socket_path);
+
+    Ok(())
+}
+
+/// Show the saved connection, validating it and querying the server when reachable
+pub async fn status() -> Result<()> {
+    let status = check_status(ConnectionState::load()?).await;
+    for line in status_lines(&status) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
 /// Generate fix for migration incidents
 pub async fn generate_fix(
     params_file: Option<PathBuf>,
     params_json: Option<String>,
+    incidents_dir: Option<PathBuf>,
+    validate_only: bool,
+    no_cache: bool,
+    timeout: u64,
 ) -> Result<()> {
-    let params = load_request_params(params_file, params_json, "generate_fix")?;
-    let client = ConnectionState::get_client()?;
+    let mut params = load_request_params(params_file, params_json, "generate_fix")
+        .map_err(|e| InvalidParams(format!("{:#}", e)))?;
+    let Some(object) = params.as_object_mut() else {
+        return Err(InvalidParams("generate_fix parameters must be a JSON object".to_string()).into());
+    };
+    if let Some(dir) = incidents_dir {
+        let incidents =
+            load_incidents_dir(&dir).map_err(|e| InvalidParams(format!("{:#}", e)))?;
+        object.insert("incidents".to_string(), serde_json::to_value(incidents)?);
+    }
+    if validate_only {
+        object.insert("validate_only".to_string(), serde_json::Value::Bool(true));
+    }
+    if no_cache {
+        object.insert("no_cache".to_string(), serde_json::Value::Bool(true));
+    }
+    let client = ConnectionState::get_client()?
+        .with_generate_fix_timeout((timeout > 0).then_some(timeout));
 
     info!("Sending generate_fix request to: {}", client.socket_path());
 
@@ -103,14 +188,54 @@ pub async fn generate_fix(
 
     let output = serde_json::to_string_pretty(&result)?;
     println!("{}", output);
+    print_migration_report(&result);
 
     Ok(())
 }
 
+/// Drive the connected server with concurrent generate_fix requests and report
+/// throughput, latency percentiles and error rate
+pub async fn benchmark(
+    concurrency: usize,
+    requests: usize,
+    params_file: Option<PathBuf>,
+    params_json: Option<String>,
+    json: bool,
+    timeout: u64,
+) -> Result<()> {
+    let params = load_request_params(params_file, params_json, "benchmark")
+        .map_err(|e| InvalidParams(format!("{:#}", e)))?;
+    if !params.is_object() {
+        return Err(InvalidParams("generate_fix parameters must be a JSON object".to_string()).into());
+    }
+    if concurrency == 0 || requests == 0 {
+        return Err(InvalidParams("--concurrency and --requests must be greater than 0".to_string()).into());
+    }
+    let client = ConnectionState::get_client()?
+        .with_generate_fix_timeout((timeout > 0).then_some(timeout));
+
+    info!(
+        "Benchmarking {} with {} requests, {} concurrent",
+        client.socket_path(),
+        requests,
+        concurrency
+    );
+    let report = run_benchmark(&client, params, concurrency, requests).await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for line in benchmark_lines(&report) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
 /// Delete an agent session
 pub async fn delete_session(session_id: String) -> Result<()> {
     uuid::Uuid::parse_str(&session_id)
-        .map_err(|_| anyhow::anyhow!("Invalid session ID: must be a valid UUID"))?;
+        .map_err(|_| InvalidParams("Invalid session ID: must be a valid UUID".to_string()))?;
 
     let params = serde_json::json!({
         "inner": {
@@ -134,24 +259,34 @@ pub async fn delete_session(session_id: String) -> Result<()> {
     Ok(())
 }
 
-/// Initialize default configuration
-pub async fn init(force: bool) -> Result<()> {
-    let config_path = ServerConfig::config_path()?;
+/// Export a session's configuration and conversation, e.g. to attach to a support ticket
+pub async fn export_session(session_id: String, output: Option<PathBuf>) -> Result<()> {
+    let client = ConnectionState::get_client()?;
 
-    if config_path.exists() && !force {
-        anyhow::bail!(
-            "Configuration file already exists at {:?}. Use --force to overwrite.",
-            config_path
-        );
-    }
+    info!(
+        "Exporting session {} via: {}",
+        session_id,
+        client.socket_path()
+    );
 
-    if let Some(parent) = config_path.parent() {
-        std::fs::create_dir_all(parent)?;
+    let result = client.export_session(&session_id).await?;
+    let export = serde_json::to_string_pretty(&result)?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(&path, export)?;
+            println!("✓ Exported session {} to: {}", session_id, path.display());
+        }
+        None => println!("{}", export),
     }
 
-    let config = ServerConfig::default();
-    let toml_content = toml::to_string_pretty(&config)?;
-    std::fs::write(&config_path, toml_content)?;
+    Ok(())
+}
+
+/// Initialize default configuration
+pub async fn init(force: bool, from_json: Option<String>) -> Result<()> {
+    let config_path = ServerConfig::config_path()?;
+    write_initial_config(&config_path, from_json.as_deref(), force)?;
 
     println!("✓ Configuration initialized at {:?}", config_path);
     Ok(())
@@ -186,6 +321,28 @@ pub async fn config(show: bool, validate: bool, edit: bool) -> Result<()> {
     Ok(())
 }
 
+/// Run self-test checks; fails if any check fails
+pub async fn doctor(
+    config_path: Option<PathBuf>,
+    config_json: Option<String>,
+    socket_path: Option<String>,
+    no_network: bool,
+) -> Result<()> {
+    let results = run_checks(config_path, config_json, socket_path, no_network).await;
+    print_check_results(&results);
+
+    let failed = results
+        .iter()
+        .filter(|r| r.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{} doctor check(s) failed", failed);
+    }
+
+    println!("✓ All checks passed");
+    Ok(())
+}
+
 /// Show version information
 pub async fn version() -> Result<()> {
     println!("Kaiak {}", env!("CARGO_PKG_VERSION"));
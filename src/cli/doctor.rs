@@ -0,0 +1,233 @@
+//! Self-test checks for the `kaiak doctor` command.
+
+use std::path::{Path, PathBuf};
+
+use crate::agent::credentials::missing_credentials;
+use crate::models::configuration::{ModelConfig, ServerConfig};
+
+use super::utils::load_server_config;
+
+/// Outcome of a single doctor check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// Result of a single doctor check, with a remediation hint on failure
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Skip,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+}
+
+/// Run every doctor check; later checks are skipped when the configuration does not load
+pub async fn run_checks(
+    config_path: Option<PathBuf>,
+    config_json: Option<String>,
+    socket_path: Option<String>,
+    no_network: bool,
+) -> Vec<CheckResult> {
+    let config = match load_server_config(config_path, config_json) {
+        Ok(config) => config,
+        Err(e) => {
+            return vec![
+                CheckResult::fail(
+                    "configuration",
+                    format!("{:#}", e),
+                    "Fix the reported fields, or run `kaiak init --force` to write a default config",
+                ),
+                CheckResult::skip("socket path", "configuration did not load"),
+                CheckResult::skip("provider credentials", "configuration did not load"),
+                CheckResult::skip("provider", "configuration did not load"),
+            ];
+        }
+    };
+
+    let model = &config.base_config.model;
+    vec![
+        CheckResult::pass("configuration", "loaded and validated"),
+        check_socket_path(&config, socket_path),
+        check_credentials(model),
+        check_provider(model, no_network).await,
+    ]
+}
+
+fn check_socket_path(config: &ServerConfig, socket_path: Option<String>) -> CheckResult {
+    const NAME: &str = "socket path";
+
+    let Some(socket_path) = socket_path.or_else(|| config.init_config.socket_path.clone()) else {
+        return CheckResult::skip(NAME, "no socket path configured (stdio transport)");
+    };
+
+    let parent = match Path::new(&socket_path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    if !parent.is_dir() {
+        return CheckResult::fail(
+            NAME,
+            format!("{} does not exist", parent.display()),
+            format!("Create the directory with `mkdir -p {}`", parent.display()),
+        );
+    }
+
+    // Creating a file is the only reliable writability test across permission models
+    let probe = parent.join(format!(".kaiak-doctor-{}", std::process::id()));
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            CheckResult::pass(NAME, format!("{} is writable", parent.display()))
+        }
+        Err(e) => CheckResult::fail(
+            NAME,
+            format!("{} is not writable: {}", parent.display(), e),
+            "Choose a socket path in a directory you own, e.g. $XDG_RUNTIME_DIR/kaiak.sock",
+        ),
+    }
+}
+
+fn check_credentials(model: &ModelConfig) -> CheckResult {
+    const NAME: &str = "provider credentials";
+
+    let Some(missing) = missing_credentials(&model.provider) else {
+        return CheckResult::skip(
+            NAME,
+            format!("no known credential keys for provider '{}'", model.provider),
+        );
+    };
+
+    if missing.is_empty() {
+        CheckResult::pass(
+            NAME,
+            format!("credentials for '{}' resolved", model.provider),
+        )
+    } else {
+        CheckResult::fail(
+            NAME,
+            format!("missing {}", missing.join(", ")),
+            format!(
+                "Export {} or store it with `goose configure`",
+                missing.join(" and ")
+            ),
+        )
+    }
+}
+
+async fn check_provider(model: &ModelConfig, no_network: bool) -> CheckResult {
+    const NAME: &str = "provider";
+
+    if no_network {
+        return CheckResult::skip(NAME, "skipped (--no-network)");
+    }
+
+    match goose::providers::create_with_named_model(&model.provider, &model.model).await {
+        Ok(_) => CheckResult::pass(
+            NAME,
+            format!("created '{}' with model '{}'", model.provider, model.model),
+        ),
+        Err(e) => CheckResult::fail(
+            NAME,
+            format!(
+                "could not create '{}' with model '{}': {}",
+                model.provider, model.model, e
+            ),
+            "Check the provider and model names in base_config.model and your network access",
+        ),
+    }
+}
+
+/// Print one line per check, with the remediation hint under failures
+pub fn print_check_results(results: &[CheckResult]) {
+    for result in results {
+        let marker = match result.status {
+            CheckStatus::Pass => "✓",
+            CheckStatus::Fail => "✗",
+            CheckStatus::Skip => "-",
+        };
+        println!("{} {}: {}", marker, result.name, result.detail);
+        if let Some(hint) = &result.hint {
+            println!("    hint: {}", hint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_json(log_level: &str, socket_path: &Path) -> String {
+        let mut config = ServerConfig::default();
+        config.init_config.log_level = log_level.to_string();
+        config.init_config.socket_path = Some(socket_path.display().to_string());
+        config.base_config.model.provider = "ollama".to_string();
+        serde_json::to_string(&config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_doctor_valid_config_passes() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = config_json("info", &dir.path().join("kaiak.sock"));
+
+        let results = run_checks(None, Some(json), None, true).await;
+
+        let statuses: Vec<_> = results.iter().map(|r| (r.name, r.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("configuration", CheckStatus::Pass),
+                ("socket path", CheckStatus::Pass),
+                ("provider credentials", CheckStatus::Pass),
+                ("provider", CheckStatus::Skip),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_doctor_invalid_config_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = config_json("loud", &dir.path().join("kaiak.sock"));
+
+        let results = run_checks(None, Some(json), None, true).await;
+
+        assert_eq!(results[0].status, CheckStatus::Fail);
+        assert!(results[0].hint.is_some());
+        assert!(results[1..].iter().all(|r| r.status == CheckStatus::Skip));
+
+        let missing_dir = dir.path().join("missing").join("kaiak.sock");
+        let results = run_checks(None, Some(config_json("info", &missing_dir)), None, true).await;
+        assert_eq!(results[1].status, CheckStatus::Fail);
+    }
+}
@@ -0,0 +1,82 @@
+//! Process exit codes, so scripts can tell why a command failed.
+
+use crate::jsonrpc::protocol::error_codes;
+use crate::jsonrpc::JsonRpcError;
+use crate::KaiakError;
+
+/// Any failure without a more specific code
+pub const EXIT_FAILURE: u8 = 1;
+/// The server configuration could not be loaded or is invalid
+pub const EXIT_CONFIG_ERROR: u8 = 2;
+/// The server could not be reached, or the connection failed mid-request
+pub const EXIT_CONNECTION_ERROR: u8 = 3;
+/// The server answered the request with an error
+pub const EXIT_SERVER_ERROR: u8 = 4;
+/// The request parameters were rejected, locally or by the server
+pub const EXIT_INVALID_PARAMS: u8 = 5;
+
+/// Shown at the end of `kaiak --help`
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Other failure
+  2  Configuration error
+  3  Connection error: no server, or the connection failed
+  4  Server error: the server answered with an error
+  5  Invalid parameters: rejected by the CLI or the server";
+
+/// Request parameters the CLI rejected before sending them
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct InvalidParams(pub String);
+
+/// Exit code for a command's error, from the first categorized error in its chain
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    for cause in error.chain() {
+        if cause.is::<InvalidParams>() {
+            return EXIT_INVALID_PARAMS;
+        }
+        if let Some(error) = cause.downcast_ref::<JsonRpcError>() {
+            return match error.code {
+                error_codes::INVALID_PARAMS => EXIT_INVALID_PARAMS,
+                _ => EXIT_SERVER_ERROR,
+            };
+        }
+        match cause.downcast_ref::<KaiakError>() {
+            Some(KaiakError::Configuration { .. }) => return EXIT_CONFIG_ERROR,
+            Some(KaiakError::Transport { .. }) => return EXIT_CONNECTION_ERROR,
+            _ => {}
+        }
+    }
+    EXIT_FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{ClientRequest, JsonRpcClient};
+
+    #[tokio::test]
+    async fn test_connection_failure_exits_with_connection_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = JsonRpcClient::new(dir.path().join("missing.sock").display().to_string());
+        let request = ClientRequest::new("kaiak/server_info".to_string(), serde_json::json!({}));
+
+        let err = client.call(request, |_| {}).await.unwrap_err();
+        assert_eq!(exit_code(&err), EXIT_CONNECTION_ERROR);
+
+        let server = |code| anyhow::Error::from(JsonRpcError::custom(code, "failed".to_string(), None));
+        assert_eq!(exit_code(&server(error_codes::INVALID_PARAMS)), EXIT_INVALID_PARAMS);
+        assert_eq!(exit_code(&server(-32010)), EXIT_SERVER_ERROR);
+        // Configuration errors and interaction timeouts share a code; neither is a bad param
+        let timeout = JsonRpcError::from(KaiakError::interaction_timeout("no answer"));
+        assert_eq!(exit_code(&timeout.into()), EXIT_SERVER_ERROR);
+        let invalid = JsonRpcError::from(KaiakError::InvalidParams("Field 'incidents'".to_string()));
+        assert_eq!(exit_code(&invalid.into()), EXIT_INVALID_PARAMS);
+        assert_eq!(
+            exit_code(&KaiakError::configuration("bad config").into()),
+            EXIT_CONFIG_ERROR
+        );
+        assert_eq!(exit_code(&anyhow::anyhow!("other")), EXIT_FAILURE);
+    }
+}
@@ -2,20 +2,29 @@
 //!
 //! This module provides the command-line interface for both server and client operations.
 
+mod benchmark;
 mod commands;
+mod doctor;
+mod exit_codes;
+mod status;
 mod utils;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+pub use benchmark::{benchmark_lines, run_benchmark, BenchmarkReport};
 pub use commands::*;
+pub use doctor::{run_checks, CheckResult, CheckStatus};
+pub use exit_codes::*;
+pub use status::{check_status, ConnectionStatus};
 pub use utils::*;
 
 #[derive(Parser)]
 #[command(name = "kaiak")]
 #[command(about = "Standalone server integrating Goose AI agent for code migration workflows")]
 #[command(version)]
+#[command(after_help = EXIT_CODES_HELP)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
@@ -47,23 +56,88 @@ pub enum Commands {
         /// Inline JSON configuration (overrides file-based config)
         #[arg(long, short = 'j', conflicts_with = "config_path")]
         config_json: Option<String>,
+
+        /// Validate the configuration, open the transport and register methods, then exit without serving
+        #[arg(long)]
+        check: bool,
+
+        /// Refuse every file modification, whatever the user approves (sets `base_config.read_only`)
+        #[arg(long)]
+        read_only: bool,
     },
 
     /// Connect to a Kaiak server via Unix socket
     Connect {
         socket_path: String,
+
+        /// Save the connection as a named profile, for switching back with `kaiak use`
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Switch to a saved connection profile, or list the profiles without a name
+    Use {
+        name: Option<String>,
     },
 
     /// Disconnect from the current Kaiak server
     Disconnect,
 
+    /// Show the saved connection and whether the server is reachable
+    Status,
+
     /// Generate fix for migration incidents (requires active connection)
     GenerateFix {
+        /// Path to a JSON file with the request parameters, or `-` to read them from stdin
+        #[arg(long, short = 'p', conflicts_with = "params_json")]
+        params_file: Option<PathBuf>,
+
+        #[arg(long, short = 'j', conflicts_with = "params_file")]
+        params_json: Option<String>,
+
+        /// Directory of incident reports (Konveyor, SARIF or Kaiak JSON) whose incidents
+        /// replace the `incidents` in the parameters
+        #[arg(long)]
+        incidents_dir: Option<PathBuf>,
+
+        /// Validate and normalize the parameters without running the agent
+        #[arg(long)]
+        validate_only: bool,
+
+        /// Send every incident to the model, even ones the server's fix cache could fix
+        #[arg(long)]
+        no_cache: bool,
+
+        /// Seconds to wait for any response or notification from the server; 0 waits indefinitely
+        #[arg(long, default_value_t = crate::client::DEFAULT_GENERATE_FIX_TIMEOUT_SECS)]
+        timeout: u64,
+    },
+
+    /// Measure how many generate_fix requests the server handles per second (requires
+    /// active connection). Point the parameters at a test provider to avoid model cost.
+    Benchmark {
+        /// Requests in flight at once
+        #[arg(long, short = 'n', default_value_t = 4)]
+        concurrency: usize,
+
+        /// Total requests to send
+        #[arg(long, short = 'r', default_value_t = 20)]
+        requests: usize,
+
+        /// Path to a JSON file with the generate_fix parameters, or `-` to read them from stdin
         #[arg(long, short = 'p', conflicts_with = "params_json")]
         params_file: Option<PathBuf>,
 
         #[arg(long, short = 'j', conflicts_with = "params_file")]
         params_json: Option<String>,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Seconds each request waits for any message from the server; 0 waits indefinitely
+        #[arg(long, default_value_t = crate::client::DEFAULT_GENERATE_FIX_TIMEOUT_SECS)]
+        timeout: u64,
     },
 
     /// Delete a session (requires active connection)
@@ -71,10 +145,23 @@ pub enum Commands {
         session_id: String,
     },
 
+    /// Export a session's configuration and conversation as JSON (requires active connection)
+    ExportSession {
+        session_id: String,
+
+        /// Write the export to this file instead of stdout
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+
     /// Initialize default configuration at default location
     Init {
         #[arg(long)]
         force: bool,
+
+        /// Write this JSON server configuration instead of the defaults, after validating it
+        #[arg(long)]
+        from_json: Option<String>,
     },
 
     /// Manage configuration
@@ -89,6 +176,25 @@ pub enum Commands {
         edit: bool,
     },
 
+    /// Check configuration, socket path, and provider setup
+    Doctor {
+        /// Path to a custom configuration file (TOML format)
+        #[arg(long, short = 'c', conflicts_with = "config_json")]
+        config_path: Option<PathBuf>,
+
+        /// Inline JSON configuration (overrides file-based config)
+        #[arg(long, short = 'j', conflicts_with = "config_path")]
+        config_json: Option<String>,
+
+        /// Unix socket path to check (defaults to init_config.socket_path)
+        #[arg(long, short = 's')]
+        socket_path: Option<String>,
+
+        /// Skip checks that contact the model provider
+        #[arg(long)]
+        no_network: bool,
+    },
+
     /// Show version information
     Version,
 }
@@ -106,25 +212,58 @@ impl Cli {
                 socket_path,
                 config_path,
                 config_json,
-            } => serve(transport, socket_path, config_path, config_json).await,
+                check,
+                read_only,
+            } => serve(transport, socket_path, config_path, config_json, check, read_only).await,
 
-            Commands::Connect { socket_path } => connect(socket_path).await,
+            Commands::Connect { socket_path, name } => connect(socket_path, name).await,
+            Commands::Use { name } => use_profile(name).await,
             Commands::Disconnect => disconnect().await,
+            Commands::Status => status().await,
 
             Commands::GenerateFix {
                 params_file,
                 params_json,
-            } => generate_fix(params_file, params_json).await,
+                incidents_dir,
+                validate_only,
+                no_cache,
+                timeout,
+            } => {
+                generate_fix(params_file, params_json, incidents_dir, validate_only, no_cache, timeout)
+                    .await
+            }
+
+            Commands::Benchmark {
+                concurrency,
+                requests,
+                params_file,
+                params_json,
+                json,
+                timeout,
+            } => {
+                benchmark(concurrency, requests, params_file, params_json, json, timeout).await
+            }
 
             Commands::DeleteSession { session_id } => delete_session(session_id).await,
 
-            Commands::Init { force } => init(force).await,
+            Commands::ExportSession { session_id, output } => {
+                export_session(session_id, output).await
+            }
+
+            Commands::Init { force, from_json } => init(force, from_json).await,
             Commands::Config {
                 show,
                 validate,
                 edit,
             } => config(show, validate, edit).await,
 
+            Commands::Doctor {
+                config_path,
+                config_json,
+                socket_path,
+                no_network,
+            } => doctor(config_path, config_json, socket_path, no_network).await,
+
             Commands::Version => version().await,
         }
     }
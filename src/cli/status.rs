@@ -0,0 +1,127 @@
+//! Saved connection inspection for the `kaiak status` command.
+
+use std::time::Duration;
+
+use crate::client::JsonRpcClient;
+
+/// How long to wait for server_info; a server busy with a request answers only afterwards
+const SERVER_INFO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of the saved connection as of now
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStatus {
+    /// Socket path saved by `kaiak connect`
+    pub socket_path: Option<String>,
+    /// Whether the socket accepts connections right now
+    pub reachable: bool,
+    /// Result of kaiak/server_info, when the server answered
+    pub server_info: Option<serde_json::Value>,
+    /// Why server_info could not be fetched from a reachable server
+    pub server_info_error: Option<String>,
+}
+
+/// Validate a saved connection and ask the server to describe itself
+pub async fn check_status(socket_path: Option<String>) -> ConnectionStatus {
+    let Some(socket_path) = socket_path else {
+        return ConnectionStatus::default();
+    };
+
+    let client = JsonRpcClient::new(socket_path.clone());
+    let reachable = client.validate_connection().await.unwrap_or(false);
+    let mut status = ConnectionStatus {
+        socket_path: Some(socket_path),
+        reachable,
+        ..ConnectionStatus::default()
+    };
+    if !reachable {
+        return status;
+    }
+
+    match tokio::time::timeout(SERVER_INFO_TIMEOUT, client.server_info()).await {
+        Ok(Ok(info)) => status.server_info = Some(info),
+        Ok(Err(e)) => status.server_info_error = Some(e.to_string()),
+        Err(_) => {
+            status.server_info_error = Some(format!(
+                "no response within {}s (the server may be busy with another request)",
+                SERVER_INFO_TIMEOUT.as_secs()
+            ))
+        }
+    }
+    status
+}
+
+/// Human-readable status lines
+pub fn status_lines(status: &ConnectionStatus) -> Vec<String> {
+    let Some(socket_path) = &status.socket_path else {
+        return vec![
+            "Not connected to any server.".to_string(),
+            "  Use 'kaiak connect <socket_path>' to connect.".to_string(),
+        ];
+    };
+
+    let mut lines = vec![format!("Connection: unix socket {}", socket_path)];
+    if !status.reachable {
+        lines.push("✗ Server is not reachable".to_string());
+        lines.push(format!(
+            "  Start it with 'kaiak serve --transport socket --socket-path {}', or use 'kaiak connect' to switch servers.",
+            socket_path
+        ));
+        return lines;
+    }
+
+    lines.push("✓ Server is reachable".to_string());
+    if let Some(info) = &status.server_info {
+        let field = |name: &str| info.get(name).and_then(|v| v.as_str()).unwrap_or("unknown");
+        lines.push(format!("  Server: {} {}", field("name"), field("version")));
+        let list = |name: &str| {
+            info.get(name)
+                .and_then(|v| v.as_array())
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(|item| item.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default()
+        };
+        lines.push(format!("  Methods: {}", list("methods")));
+        let namespaces = list("namespaces");
+        if !namespaces.is_empty() {
+            lines.push(format!("  Namespaces: {}", namespaces));
+        }
+    } else if let Some(error) = &status.server_info_error {
+        lines.push(format!("  Server info unavailable: {}", error));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_status_without_saved_connection() {
+        let status = check_status(None).await;
+
+        assert!(status.socket_path.is_none());
+        assert!(!status.reachable);
+        let lines = status_lines(&status);
+        assert_eq!(lines[0], "Not connected to any server.");
+        assert!(lines[1].contains("kaiak connect"));
+    }
+
+    #[tokio::test]
+    async fn test_status_with_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("gone.sock").display().to_string();
+
+        let status = check_status(Some(socket_path.clone())).await;
+
+        assert!(!status.reachable);
+        assert!(status.server_info.is_none());
+        let lines = status_lines(&status);
+        assert_eq!(lines[0], format!("Connection: unix socket {}", socket_path));
+        assert_eq!(lines[1], "✗ Server is not reachable");
+    }
+}
@@ -1,19 +1,32 @@
 //! CLI utility functions.
 
 use anyhow::Result;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
 use tracing::info;
 
 use crate::client::JsonRpcNotification;
 use crate::models::configuration::{ConfigurationHierarchy, ServerConfig};
+use crate::models::{IncidentParserRegistry, MigrationIncident, MigrationReport};
 
-/// Load request parameters from file or inline JSON
+/// Load request parameters from file or inline JSON. A params file of `-` reads stdin.
 pub fn load_request_params(
     params_file: Option<PathBuf>,
     params_json: Option<String>,
     command_name: &str,
 ) -> Result<serde_json::Value> {
     match (params_file, params_json) {
+        (Some(path), None) if path.as_os_str() == "-" => {
+            let stdin = std::io::stdin();
+            if stdin.is_terminal() {
+                anyhow::bail!(
+                    "--params-file - reads the request JSON from stdin, but stdin is a terminal. \
+                     Pipe the parameters in, e.g. `cat params.json | kaiak {} --params-file -`",
+                    command_name.replace('_', "-")
+                );
+            }
+            read_params(stdin.lock())
+        }
         (Some(path), None) => {
             if !path.exists() {
                 anyhow::bail!("Parameters file not found: {}", path.display());
@@ -40,6 +53,40 @@ pub fn load_request_params(
     }
 }
 
+/// Parse request parameters piped in on a reader
+fn read_params(mut reader: impl Read) -> Result<serde_json::Value> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(|e| anyhow::anyhow!("Failed to read parameters from stdin: {}", e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| anyhow::anyhow!("Failed to parse JSON from stdin: {}", e))
+}
+
+/// Parse every `.json` report in `dir`, in file name order, detecting each file's format
+pub fn load_incidents_dir(dir: &Path) -> Result<Vec<MigrationIncident>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| anyhow::anyhow!("Failed to read incidents directory {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+
+    let registry = IncidentParserRegistry::default();
+    let mut incidents = Vec::new();
+    for path in files {
+        let content = std::fs::read_to_string(&path)?;
+        let document: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse JSON from {}: {}", path.display(), e))?;
+        let parsed = registry
+            .parse(&document)
+            .map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        info!("Loaded {} incidents from {}", parsed.len(), path.display());
+        incidents.extend(parsed);
+    }
+    Ok(incidents)
+}
+
 /// Load server configuration with hierarchy (CLI > file > defaults)
 pub fn load_server_config(
     config_path: Option<PathBuf>,
@@ -125,3 +172,98 @@ pub fn print_notification(notification: &JsonRpcNotification) {
         }
     }
 }
+
+/// Write a server configuration to `config_path` as TOML: the one given as JSON, or the
+/// defaults. The configuration is validated first, so nothing is written when it is invalid.
+pub fn write_initial_config(config_path: &Path, from_json: Option<&str>, force: bool) -> Result<()> {
+    if config_path.exists() && !force {
+        anyhow::bail!(
+            "Configuration file already exists at {:?}. Use --force to overwrite.",
+            config_path
+        );
+    }
+
+    let config = match from_json {
+        Some(json) => serde_json::from_str::<ServerConfig>(json)
+            .map_err(|e| anyhow::anyhow!("Failed to parse config JSON: {}", e))?,
+        None => ServerConfig::default(),
+    };
+    config
+        .validate()
+        .map_err(|e| anyhow::anyhow!("Refusing to write invalid configuration: {}", e))?;
+
+    if let Some(parent) = config_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(config_path, toml::to_string_pretty(&config)?)?;
+    Ok(())
+}
+
+/// Print the migration report from a generate_fix result as a summary block
+pub fn print_migration_report(result: &serde_json::Value) {
+    let Some(report) = result
+        .get("report")
+        .and_then(|r| serde_json::from_value::<MigrationReport>(r.clone()).ok())
+    else {
+        return;
+    };
+
+    println!();
+    println!("Migration summary");
+    println!("  Incidents attempted: {}", report.incidents_attempted);
+    println!("    fixed:   {}", report.incidents_fixed);
+    println!("    failed:  {}", report.incidents_failed);
+    println!("    skipped: {}", report.incidents_skipped);
+    if !report.already_applied.is_empty() {
+        println!("    already applied: {}", report.already_applied.len());
+    }
+    println!("  Files modified:      {}", report.files_modified);
+    println!("  Tool calls:          {}", report.tool_calls);
+    println!("  Elapsed:             {:.1}s", report.elapsed_ms as f64 / 1000.0);
+    for change in &report.file_changes {
+        println!("  - {} ({})", change.path, change.changes.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piped_params_match_file_params() {
+        let json = r#"{"incidents": [{"id": "issue-1", "uri": "src/A.java", "message": "m", "description": "d"}], "agent_config": {"workspace": "."}}"#;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("params.json");
+        std::fs::write(&path, json).unwrap();
+
+        let from_file = load_request_params(Some(path), None, "generate_fix").unwrap();
+        let from_stdin = read_params(std::io::Cursor::new(json)).unwrap();
+
+        assert_eq!(from_stdin, from_file);
+    }
+
+    #[test]
+    fn test_init_from_json_validates_before_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kaiak/server.conf");
+
+        // Socket transport without a socket path
+        let mut invalid = serde_json::to_value(ServerConfig::default()).unwrap();
+        invalid["init_config"]["transport"] = "socket".into();
+        let error = write_initial_config(&path, Some(&invalid.to_string()), false).unwrap_err();
+        assert!(error.to_string().contains("Socket path is required"), "{error}");
+        assert!(!path.exists());
+
+        let mut valid = invalid;
+        valid["init_config"]["socket_path"] = "/tmp/kaiak.sock".into();
+        write_initial_config(&path, Some(&valid.to_string()), false).unwrap();
+
+        let written: ServerConfig = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written.init_config.socket_path.as_deref(), Some("/tmp/kaiak.sock"));
+        assert!(written.validate().is_ok());
+
+        // An existing file is only replaced with --force
+        assert!(write_initial_config(&path, None, false).is_err());
+        write_initial_config(&path, None, true).unwrap();
+    }
+}
@@ -7,6 +7,9 @@
 
 pub mod transport;
 
-pub use transport::{JsonRpcClient, ClientInfo, ClientRequest, ConnectionState};
+pub use transport::{
+    JsonRpcClient, ClientInfo, ClientRequest, ConnectionProfiles, ConnectionState,
+    DEFAULT_GENERATE_FIX_TIMEOUT_SECS, DEFAULT_PROFILE,
+};
 
 pub use crate::jsonrpc::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, JsonRpcError};
\ No newline at end of file
@@ -4,12 +4,15 @@ use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixStream;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 use uuid::Uuid;
 
+use crate::jsonrpc::transport::{lsp_format, ndjson_format};
 use crate::jsonrpc::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse};
+use crate::models::configuration::Framing;
 
 /// Client information for debugging and tracing
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,17 +71,39 @@ impl ClientRequest {
     }
 }
 
+/// Seconds `generate_fix` waits for any message from the server before giving up
+pub const DEFAULT_GENERATE_FIX_TIMEOUT_SECS: u64 = 300;
+
 /// JSON-RPC client for Unix socket communication
 /// 
-/// Uses LSP-style Content-Length framing to match the server protocol.
+/// Uses LSP-style Content-Length framing by default; the framing must match the
+/// server's `init_config.framing`.
 pub struct JsonRpcClient {
     socket_path: String,
+    generate_fix_timeout: Option<u64>,
+    framing: Framing,
 }
 
 impl JsonRpcClient {
     /// Create a new JSON-RPC client
     pub fn new(socket_path: String) -> Self {
-        Self { socket_path }
+        Self {
+            socket_path,
+            generate_fix_timeout: Some(DEFAULT_GENERATE_FIX_TIMEOUT_SECS),
+            framing: Framing::default(),
+        }
+    }
+
+    /// Frame messages as given instead of with Content-Length headers
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Set how long `generate_fix` waits between server messages; None waits indefinitely
+    pub fn with_generate_fix_timeout(mut self, timeout_seconds: Option<u64>) -> Self {
+        self.generate_fix_timeout = timeout_seconds;
+        self
     }
 
     /// Validate that the socket exists and is accessible
@@ -96,10 +121,16 @@ impl JsonRpcClient {
         }
     }
 
-    /// Execute a JSON-RPC procedure call using LSP-style framing
+    /// Execute a JSON-RPC procedure call in the client's framing
     /// 
     /// Reads all messages from the server until it receives the final response.
     /// Notifications are passed to the provided callback.
+    ///
+    /// The request's timeout bounds the wait for each message, so a long fix that keeps
+    /// streaming notifications is not cut off. On expiry the connection is closed and a
+    /// `KaiakError::Transport` is returned; the server is not told to stop, so it may
+    /// still finish the request. Connection failures are also
+    /// `KaiakError::Transport`; an error response is returned as its `JsonRpcError`.
     /// 
     /// # Example
     /// ```ignore
@@ -119,7 +150,12 @@ impl JsonRpcClient {
 
         let stream = UnixStream::connect(&self.socket_path)
             .await
-            .map_err(|e| anyhow!("Failed to connect to socket {}: {}", self.socket_path, e))?;
+            .map_err(|e| {
+                crate::KaiakError::transport(format!(
+                    "Failed to connect to socket {}: {}",
+                    self.socket_path, e
+                ))
+            })?;
 
         let (read_half, mut write_half) = stream.into_split();
         let mut reader = BufReader::new(read_half);
@@ -129,14 +165,28 @@ impl JsonRpcClient {
 
         debug!("Sending request: {}", request_json);
 
-        let message = format!("Content-Length: {}\r\n\r\n{}", request_json.len(), request_json);
+        let message = self.format_message(&request_json);
         write_half.write_all(message.as_bytes()).await
-            .map_err(|e| anyhow!("Failed to write message: {}", e))?;
+            .map_err(|e| crate::KaiakError::transport(format!("Failed to write message: {}", e)))?;
         write_half.flush().await
-            .map_err(|e| anyhow!("Failed to flush: {}", e))?;
+            .map_err(|e| crate::KaiakError::transport(format!("Failed to flush: {}", e)))?;
 
         loop {
-            let message_json = Self::read_lsp_message(&mut reader).await?;
+            let read = self.read_message(&mut reader);
+            let message_json = match request.timeout {
+                Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), read).await {
+                    Ok(message) => message?,
+                    Err(_) => {
+                        warn!("No message from server within {}s, giving up on request {}", secs, request_id);
+                        return Err(crate::KaiakError::transport(format!(
+                            "Timed out after {}s waiting for a response to {}",
+                            secs, request.method
+                        ))
+                        .into());
+                    }
+                },
+                None => read.await?,
+            };
             debug!("Received message: {}", message_json);
 
             let msg: Value = serde_json::from_str(&message_json)
@@ -163,7 +213,7 @@ impl JsonRpcClient {
                 }
 
                 if let Some(ref error) = response.error {
-                    return Err(anyhow!("JSON-RPC error {}: {}", error.code, error.message));
+                    return Err(error.clone().into());
                 }
 
                 return response.result
@@ -172,6 +222,27 @@ impl JsonRpcClient {
         }
     }
 
+    /// Frame one message for sending
+    fn format_message(&self, content: &str) -> String {
+        match self.framing {
+            Framing::ContentLength => lsp_format::format_message(content),
+            Framing::Ndjson => ndjson_format::format_message(content),
+        }
+    }
+
+    /// Read one message in the client's framing
+    async fn read_message<R: tokio::io::AsyncBufRead + Unpin>(
+        &self,
+        reader: &mut R,
+    ) -> Result<String> {
+        match self.framing {
+            Framing::ContentLength => Self::read_lsp_message(reader).await,
+            Framing::Ndjson => ndjson_format::read_message(reader).await.map_err(|e| {
+                crate::KaiakError::transport(format!("Failed to read message: {}", e)).into()
+            }),
+        }
+    }
+
     /// Read an LSP-style message with Content-Length header
     async fn read_lsp_message<R: tokio::io::AsyncBufRead + Unpin>(reader: &mut R) -> Result<String> {
         let mut content_length: Option<usize> = None;
@@ -179,10 +250,10 @@ impl JsonRpcClient {
         loop {
             let mut line = String::new();
             let bytes_read = reader.read_line(&mut line).await
-                .map_err(|e| anyhow!("Failed to read header line: {}", e))?;
+                .map_err(|e| crate::KaiakError::transport(format!("Failed to read header line: {}", e)))?;
 
             if bytes_read == 0 {
-                return Err(anyhow!("Connection closed while reading headers"));
+                return Err(crate::KaiakError::transport("Connection closed while reading headers").into());
             }
 
             let line = line.trim_end();
@@ -203,7 +274,7 @@ impl JsonRpcClient {
 
         let mut buffer = vec![0u8; content_length];
         reader.read_exact(&mut buffer).await
-            .map_err(|e| anyhow!("Failed to read message body: {}", e))?;
+            .map_err(|e| crate::KaiakError::transport(format!("Failed to read message body: {}", e)))?;
 
         String::from_utf8(buffer)
             .map_err(|e| anyhow!("Invalid UTF-8 in response: {}", e))
@@ -214,9 +285,9 @@ impl JsonRpcClient {
     where
         F: FnMut(JsonRpcNotification),
     {
-        let request = ClientRequest::new("kaiak/generate_fix".to_string(), params)
-            .with_timeout(300) // 5 minute timeout for AI operations
+        let mut request = ClientRequest::new("kaiak/generate_fix".to_string(), params)
             .with_client_info(ClientInfo::new(self.socket_path.clone()));
+        request.timeout = self.generate_fix_timeout;
 
         self.call(request, on_notification).await
     }
@@ -232,6 +303,25 @@ impl JsonRpcClient {
         self.call(request, on_notification).await
     }
 
+    /// Fetch a session's configuration and conversation history
+    pub async fn export_session(&self, session_id: &str) -> Result<Value> {
+        let request = ClientRequest::new(
+            "kaiak/export_session".to_string(),
+            serde_json::json!({ "session_id": session_id }),
+        )
+        .with_client_info(ClientInfo::new(self.socket_path.clone()));
+
+        self.call(request, |_| {}).await
+    }
+
+    /// Fetch the server's name, version, and registered methods
+    pub async fn server_info(&self) -> Result<Value> {
+        let request = ClientRequest::new("kaiak/server_info".to_string(), serde_json::json!({}))
+            .with_client_info(ClientInfo::new(self.socket_path.clone()));
+
+        self.call(request, |_| {}).await
+    }
+
     /// Send a notification to the server (no response expected)
     ///
     /// Sends a JSON-RPC notification that doesn't expect a response.
@@ -264,7 +354,7 @@ impl JsonRpcClient {
         let notification_json = serde_json::to_string(&notification)
             .map_err(|e| anyhow!("Failed to serialize notification: {}", e))?;
 
-        let message = format!("Content-Length: {}\r\n\r\n{}", notification_json.len(), notification_json);
+        let message = self.format_message(&notification_json);
 
         let mut attempt = 0;
         let mut last_error = None;
@@ -359,10 +449,99 @@ impl JsonRpcClient {
 }
 
 
+/// Profile an unnamed `kaiak connect` saves to
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Saved server connections by name, with at most one active
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionProfiles {
+    /// Name of the profile requests go to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+    /// Socket path of each profile, by name
+    #[serde(default)]
+    pub profiles: std::collections::BTreeMap<String, String>,
+}
+
+impl ConnectionProfiles {
+    /// Read the profiles from a state file. A file holding only a socket path, as
+    /// written by older versions, becomes the active `default` profile.
+    pub fn read(state_file: &Path) -> Result<Self> {
+        if !state_file.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(state_file)?;
+        let content = content.trim();
+        if content.is_empty() {
+            return Ok(Self::default());
+        }
+        if !content.starts_with('{') {
+            let mut profiles = Self::default();
+            profiles.save_named(DEFAULT_PROFILE, content);
+            return Ok(profiles);
+        }
+        serde_json::from_str(content).map_err(|e| {
+            anyhow!("Invalid connection state file {}: {}", state_file.display(), e)
+        })
+    }
+
+    /// Write the profiles to a state file, removing it when there are none
+    pub fn write(&self, state_file: &Path) -> Result<()> {
+        if self.profiles.is_empty() {
+            if state_file.exists() {
+                std::fs::remove_file(state_file)?;
+            }
+            return Ok(());
+        }
+        std::fs::write(state_file, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Save a profile, replacing one of the same name, and make it active
+    pub fn save_named(&mut self, name: &str, socket_path: &str) {
+        self.profiles
+            .insert(name.to_string(), socket_path.to_string());
+        self.active = Some(name.to_string());
+    }
+
+    /// Make a saved profile active
+    pub fn use_profile(&mut self, name: &str) -> Result<()> {
+        if !self.profiles.contains_key(name) {
+            return Err(anyhow!(
+                "No connection profile '{}'; saved profiles: {}",
+                name,
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        self.active = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Socket path of the active profile
+    pub fn active_socket_path(&self) -> Option<&str> {
+        self.active
+            .as_ref()
+            .and_then(|name| self.profiles.get(name))
+            .map(String::as_str)
+    }
+
+    /// Client for the active profile
+    pub fn client(&self) -> Result<JsonRpcClient> {
+        let socket_path = self.active_socket_path().ok_or_else(|| {
+            crate::KaiakError::transport(
+                "Not connected to any server. Use 'kaiak connect <socket_path>' or 'kaiak use <name>' first.",
+            )
+        })?;
+        Ok(JsonRpcClient::new(socket_path.to_string()))
+    }
+}
+
 /// Manages the connection state file (~/.kaiak/connection)
 /// Everytime user connects to a server, we store the path to
-/// the socket file as connection state so all subsequent requests
-/// can be made to the same server until disconnect is called
+/// the socket file as a named profile and make it active, so all
+/// subsequent requests go to the same server until another profile
+/// is used or disconnect is called
 pub struct ConnectionState;
 
 impl ConnectionState {
@@ -378,38 +557,43 @@ impl ConnectionState {
         Ok(kaiak_dir.join("connection"))
     }
 
-    /// Save the current connection (socket path)
+    /// Save the current connection (socket path) as the `default` profile
     pub fn save(socket_path: &str) -> Result<()> {
+        Self::save_named(DEFAULT_PROFILE, socket_path)
+    }
+
+    /// Save a connection under `name` and make it active
+    pub fn save_named(name: &str, socket_path: &str) -> Result<()> {
         let state_file = Self::state_file_path()?;
-        std::fs::write(&state_file, socket_path)?;
-        Ok(())
+        let mut profiles = ConnectionProfiles::read(&state_file)?;
+        profiles.save_named(name, socket_path);
+        profiles.write(&state_file)
     }
 
-    pub fn load() -> Result<Option<String>> {
+    /// Make a saved profile active
+    pub fn use_profile(name: &str) -> Result<()> {
         let state_file = Self::state_file_path()?;
-        
-        if !state_file.exists() {
-            return Ok(None);
-        }
-        
-        let socket_path = std::fs::read_to_string(&state_file)?;
-        let socket_path = socket_path.trim().to_string();
-        
-        if socket_path.is_empty() {
-            return Ok(None);
-        }
-        
-        Ok(Some(socket_path))
+        let mut profiles = ConnectionProfiles::read(&state_file)?;
+        profiles.use_profile(name)?;
+        profiles.write(&state_file)
+    }
+
+    /// Every saved profile and which one is active
+    pub fn list() -> Result<ConnectionProfiles> {
+        ConnectionProfiles::read(&Self::state_file_path()?)
     }
 
+    /// Socket path of the active profile
+    pub fn load() -> Result<Option<String>> {
+        Ok(Self::list()?.active_socket_path().map(str::to_string))
+    }
+
+    /// Deactivate the active profile; saved profiles are kept for `use_profile`
     pub fn clear() -> Result<()> {
         let state_file = Self::state_file_path()?;
-        
-        if state_file.exists() {
-            std::fs::remove_file(&state_file)?;
-        }
-        
-        Ok(())
+        let mut profiles = ConnectionProfiles::read(&state_file)?;
+        profiles.active = None;
+        profiles.write(&state_file)
     }
 
     pub fn is_connected() -> Result<bool> {
@@ -417,9 +601,100 @@ impl ConnectionState {
     }
 
     pub fn get_client() -> Result<JsonRpcClient> {
-        let socket_path = Self::load()?
-            .ok_or_else(|| anyhow!("Not connected to any server. Use 'kaiak connect <socket_path>' first."))?;
-        
-        Ok(JsonRpcClient::new(socket_path))
+        Self::list()?.client()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_call_times_out_when_server_never_responds() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("kaiak.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+
+        // Accept the request, never answer, and read until the client hangs up
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).await.unwrap();
+            String::from_utf8(received).unwrap()
+        });
+
+        let client = JsonRpcClient::new(socket_path.display().to_string());
+        let request =
+            ClientRequest::new("kaiak/generate_fix".to_string(), serde_json::json!({})).with_timeout(1);
+        let err = client.call(request, |_| {}).await.unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<crate::KaiakError>(),
+            Some(crate::KaiakError::Transport { .. })
+        ));
+        // The client closed the connection after sending only its request
+        let received = server.await.unwrap();
+        assert!(received.contains("kaiak/generate_fix"));
+        assert_eq!(received.matches("Content-Length").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_call_with_ndjson_framing() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("kaiak.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+
+        // Answer the request line with a notification and the response, one per line
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut line = String::new();
+            let mut reader = BufReader::new(read_half);
+            reader.read_line(&mut line).await.unwrap();
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let notification = serde_json::json!({"jsonrpc": "2.0", "method": "kaiak/progress"});
+            let response = serde_json::json!({"jsonrpc": "2.0", "id": request["id"], "result": 1});
+            let reply = format!("{}\n\n{}\n", notification, response);
+            write_half.write_all(reply.as_bytes()).await.unwrap();
+        });
+
+        let socket_path = socket_path.display().to_string();
+        let client = JsonRpcClient::new(socket_path).with_framing(Framing::Ndjson);
+        let request = ClientRequest::new("kaiak/server_info".to_string(), serde_json::json!({}));
+        let mut notifications = Vec::new();
+        let call = client.call(request, |n| notifications.push(n.method));
+
+        assert_eq!(call.await.unwrap(), serde_json::json!(1));
+        assert_eq!(notifications, vec!["kaiak/progress".to_string()]);
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_switching_profiles_changes_active_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("connection");
+
+        // A state file from before profiles holds just the socket path
+        std::fs::write(&state_file, "/tmp/legacy.sock\n").unwrap();
+        let mut profiles = ConnectionProfiles::read(&state_file).unwrap();
+        assert_eq!(profiles.active.as_deref(), Some(DEFAULT_PROFILE));
+
+        profiles.save_named("prod", "/tmp/prod.sock");
+        profiles.save_named("staging", "/tmp/staging.sock");
+        profiles.write(&state_file).unwrap();
+
+        let mut profiles = ConnectionProfiles::read(&state_file).unwrap();
+        assert_eq!(profiles.profiles.len(), 3);
+        assert_eq!(profiles.client().unwrap().socket_path(), "/tmp/staging.sock");
+
+        profiles.use_profile("prod").unwrap();
+        profiles.write(&state_file).unwrap();
+        let profiles = ConnectionProfiles::read(&state_file).unwrap();
+        assert_eq!(profiles.active.as_deref(), Some("prod"));
+        assert_eq!(profiles.client().unwrap().socket_path(), "/tmp/prod.sock");
+
+        let mut profiles = profiles;
+        assert!(profiles.use_profile("missing").is_err());
+        assert_eq!(profiles.active.as_deref(), Some("prod"));
     }
 }
@@ -0,0 +1,86 @@
+//! Audit trail of decisions on tool calls that modify files.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Whether a file modification was let through to the agent
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditDecision {
+    Allowed,
+    Denied,
+}
+
+/// One decision on a file-modifying tool call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    /// Client identity the modification was made for
+    pub identity: String,
+    pub request_id: String,
+    pub session_id: String,
+    pub tool_call_id: String,
+    pub tool_name: String,
+    /// The tool's `path` argument, as sent by the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub decision: AuditDecision,
+    /// What decided, e.g. `user`, `tool_permission` or a file guard code
+    pub reason: String,
+}
+
+/// Writes audit entries to the `kaiak::audit` log target and, when configured, appends
+/// them as JSON lines to `audit_log_path`
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    write_lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(&self, entry: &AuditEntry) {
+        info!(
+            target: "kaiak::audit",
+            identity = %entry.identity,
+            request_id = %entry.request_id,
+            tool_call_id = %entry.tool_call_id,
+            decision = ?entry.decision,
+            reason = %entry.reason,
+            "{} {} ({})",
+            entry.tool_name,
+            entry.path.as_deref().unwrap_or("-"),
+            entry.identity
+        );
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize audit entry: {}", e);
+                return;
+            }
+        };
+
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let result = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(e) = result {
+            warn!("Failed to write audit log {}: {}", path.display(), e);
+        }
+    }
+}
@@ -5,6 +5,7 @@ use tracing::{debug, error, info, warn};
 use validator::Validate;
 
 use crate::agent::GooseAgentManager;
+use crate::handlers::ToolResultStore;
 use crate::KaiakResult;
 
 /// Request type for kaiak/delete_session endpoint
@@ -16,6 +17,10 @@ pub struct DeleteSessionRequest {
     /// Cleanup options for session deletion
     #[validate(nested)]
     pub cleanup_options: Option<SessionCleanupOptions>,
+    /// Fail with a session-not-found error instead of reporting `deleted: false` when
+    /// the session does not exist
+    #[serde(default)]
+    pub strict: bool,
 }
 
 /// Response type for kaiak/delete_session endpoint
@@ -23,6 +28,9 @@ pub struct DeleteSessionRequest {
 pub struct DeleteSessionResponse {
     /// Session identifier that was deleted
     pub session_id: String,
+    /// Whether this call removed the session; false when it was already gone, so
+    /// retried deletes succeed
+    pub deleted: bool,
     /// Deletion status
     pub status: DeleteSessionStatus,
     /// Cleanup results
@@ -94,6 +102,8 @@ pub struct DeleteSessionHandler {
     agent_manager: Arc<GooseAgentManager>,
     /// Tracking of deletion operations in progress
     deletion_operations: Arc<RwLock<std::collections::HashMap<String, chrono::DateTime<chrono::Utc>>>>,
+    /// Spilled tool results, removed with their session
+    tool_results: Arc<ToolResultStore>,
 }
 
 impl DeleteSessionHandler {
@@ -101,9 +111,16 @@ impl DeleteSessionHandler {
         Self {
             agent_manager,
             deletion_operations: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            tool_results: Arc::new(ToolResultStore::default()),
         }
     }
 
+    /// Share the store of spilled tool results with the handlers that spill them
+    pub fn with_tool_results(mut self, tool_results: Arc<ToolResultStore>) -> Self {
+        self.tool_results = tool_results;
+        self
+    }
+
     /// Handle delete session request
     pub async fn handle_delete_session(&self, request: DeleteSessionRequest) -> KaiakResult<DeleteSessionResponse> {
         info!("Processing delete_session request for: {}", request.session_id);
@@ -121,10 +138,10 @@ impl DeleteSessionHandler {
                 })
                 .collect();
 
-            return Err(crate::KaiakError::session(
-                format!("Request validation failed: {}", error_messages.join(", ")),
-                Some(request.session_id),
-            ));
+            return Err(crate::KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                error_messages.join(", ")
+            )));
         }
 
         // Additional custom validation
@@ -137,6 +154,7 @@ impl DeleteSessionHandler {
         if self.is_deletion_in_progress(&request.session_id).await {
             return Ok(DeleteSessionResponse {
                 session_id: request.session_id,
+                deleted: false,
                 status: DeleteSessionStatus::InProgress,
                 cleanup_results: SessionCleanupResults {
                     session_removed: false,
@@ -149,6 +167,26 @@ impl DeleteSessionHandler {
             });
         }
 
+        if !self.agent_manager.session_exists(&request.session_id).await {
+            if request.strict {
+                return Err(crate::KaiakError::SessionNotFound(request.session_id));
+            }
+            info!("Session {} does not exist; nothing to delete", request.session_id);
+            return Ok(DeleteSessionResponse {
+                session_id: request.session_id,
+                deleted: false,
+                status: DeleteSessionStatus::NotFound,
+                cleanup_results: SessionCleanupResults {
+                    session_removed: false,
+                    temp_files_cleaned: false,
+                    logs_preserved: true,
+                    warnings: vec![],
+                    files_removed: 0,
+                },
+                deleted_at: chrono::Utc::now().to_rfc3339(),
+            });
+        }
+
         // Mark deletion as in progress
         self.mark_deletion_in_progress(&request.session_id).await;
 
@@ -160,6 +198,7 @@ impl DeleteSessionHandler {
 
                 Ok(DeleteSessionResponse {
                     session_id: request.session_id,
+                    deleted: cleanup_results.session_removed,
                     status: DeleteSessionStatus::Deleted,
                     cleanup_results,
                     deleted_at: chrono::Utc::now().to_rfc3339(),
@@ -180,6 +219,7 @@ impl DeleteSessionHandler {
 
                 Ok(DeleteSessionResponse {
                     session_id: request.session_id,
+                    deleted: false,
                     status,
                     cleanup_results: SessionCleanupResults {
                         session_removed: false,
@@ -306,6 +346,7 @@ impl DeleteSessionHandler {
         // Delete session using Goose SessionManager
         match self.agent_manager.delete_session(session_id).await {
             Ok(deleted) => {
+                self.tool_results.release_session(session_id);
                 if deleted {
                     info!("Successfully deleted Goose session: {}", session_id);
                     cleanup_results.session_removed = true;
@@ -342,4 +383,46 @@ impl DeleteSessionHandler {
 
         operations.retain(|_session_id, started_at| *started_at > cutoff);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::configuration::AgentConfig;
+
+    fn request(session_id: &str, strict: bool) -> DeleteSessionRequest {
+        DeleteSessionRequest {
+            session_id: session_id.to_string(),
+            cleanup_options: None,
+            strict,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_delete_succeeds() {
+        let workspace = tempfile::tempdir().unwrap();
+        let config = AgentConfig {
+            workspace: workspace.path().to_path_buf(),
+            ..AgentConfig::default()
+        };
+        let agent_manager = Arc::new(GooseAgentManager::new());
+        let session_id = agent_manager
+            .get_or_create_session(None, &config)
+            .await
+            .unwrap()
+            .session
+            .id;
+        let handler = DeleteSessionHandler::new(agent_manager);
+
+        let first = handler.handle_delete_session(request(&session_id, false)).await.unwrap();
+        assert!(first.deleted);
+        assert!(matches!(first.status, DeleteSessionStatus::Deleted));
+
+        let second = handler.handle_delete_session(request(&session_id, false)).await.unwrap();
+        assert!(!second.deleted);
+        assert!(matches!(second.status, DeleteSessionStatus::NotFound));
+
+        let strict = handler.handle_delete_session(request(&session_id, true)).await;
+        assert!(matches!(strict, Err(crate::KaiakError::SessionNotFound(_))));
+    }
+}
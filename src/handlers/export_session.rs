@@ -0,0 +1,49 @@
+//! Export session handler for dumping a session's configuration and conversation.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use validator::Validate;
+
+use crate::agent::{GooseAgentManager, SessionExport};
+use crate::{KaiakError, KaiakResult};
+
+/// Request type for kaiak/export_session endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ExportSessionRequest {
+    #[validate(length(min = 1, message = "Session ID cannot be empty"))]
+    pub session_id: String,
+}
+
+/// Handler for kaiak/export_session endpoint
+pub struct ExportSessionHandler {
+    agent_manager: Arc<GooseAgentManager>,
+}
+
+impl ExportSessionHandler {
+    pub fn new(agent_manager: Arc<GooseAgentManager>) -> Self {
+        Self { agent_manager }
+    }
+
+    pub async fn handle_export_session(
+        &self,
+        request: ExportSessionRequest,
+    ) -> KaiakResult<SessionExport> {
+        info!("Processing export_session request for: {}", request.session_id);
+
+        if let Err(validation_errors) = request.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        let export = self.agent_manager.export_session(&request.session_id).await?;
+        info!(
+            "Exported {} messages from session {}",
+            export.messages.len(),
+            request.session_id
+        );
+        Ok(export)
+    }
+}
@@ -0,0 +1,392 @@
+//! Checks applied to tool calls that modify files, independent of user approval.
+
+use std::fmt;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use wildmatch::WildMatch;
+
+/// Number of leading bytes inspected when sniffing for binary content
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+/// Content is binary when more than one in this many sniffed bytes is a control byte
+const MAX_CONTROL_BYTE_RATIO: usize = 10;
+
+/// Dangling links followed when resolving a path before giving up, as the OS does on a loop
+const MAX_LINK_DEPTH: usize = 40;
+
+/// Reason a file modification is refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileGuardViolation {
+    /// Target file holds binary content that must never be rewritten as text
+    BinaryFile(PathBuf),
+    /// Target path matches a configured `protected_paths` pattern
+    ProtectedPath { path: PathBuf, pattern: String },
+    /// The server runs with `read_only`, so no file may be modified
+    ReadOnly(PathBuf),
+    /// Target path is neither in the workspace nor in the session's scratch directory
+    OutsideWorkspace(PathBuf),
+}
+
+impl FileGuardViolation {
+    /// Stable error code sent to clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            FileGuardViolation::BinaryFile(_) => "binary_file",
+            FileGuardViolation::ProtectedPath { .. } => "protected_path",
+            FileGuardViolation::ReadOnly(_) => "read_only",
+            FileGuardViolation::OutsideWorkspace(_) => "outside_workspace",
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            FileGuardViolation::BinaryFile(path) => path,
+            FileGuardViolation::ProtectedPath { path, .. } => path,
+            FileGuardViolation::ReadOnly(path) => path,
+            FileGuardViolation::OutsideWorkspace(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for FileGuardViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileGuardViolation::BinaryFile(path) => {
+                write!(f, "Refusing to modify binary file: {}", path.display())
+            }
+            FileGuardViolation::ProtectedPath { path, pattern } => write!(
+                f,
+                "Refusing to modify protected path {} (matches '{}')",
+                path.display(),
+                pattern
+            ),
+            FileGuardViolation::ReadOnly(path) => write!(
+                f,
+                "Refusing to modify {}: the server is read-only",
+                path.display()
+            ),
+            FileGuardViolation::OutsideWorkspace(path) => write!(
+                f,
+                "Refusing to modify {}: outside the workspace and the session's scratch directory",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Whether a tool call with these arguments modifies the file at its `path` argument
+pub fn is_modifying_call(arguments: &rmcp::model::JsonObject) -> bool {
+    arguments.get("path").and_then(|p| p.as_str()).is_some()
+        && arguments.get("command").and_then(|c| c.as_str()) != Some("view")
+}
+
+/// Whether a tool can modify files without naming them in a `path` argument, as a shell
+/// command can
+pub fn modifies_unnamed_files(tool_name: &str) -> bool {
+    tool_name == "shell" || tool_name.ends_with("__shell")
+}
+
+/// Detect binary content from null bytes or a high share of control bytes in the leading
+/// bytes. Text in legacy encodings such as Latin-1 or Shift-JIS is not binary.
+/// Missing files are treated as text since the agent is creating them.
+pub fn is_binary_file(path: &Path) -> std::io::Result<bool> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    let mut buffer = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    file.by_ref()
+        .take(BINARY_SNIFF_BYTES as u64)
+        .read_to_end(&mut buffer)?;
+
+    Ok(is_binary_content(&buffer))
+}
+
+fn is_binary_content(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return true;
+    }
+    let control = bytes.iter().filter(|&&byte| is_control_byte(byte)).count();
+    control * MAX_CONTROL_BYTE_RATIO > bytes.len()
+}
+
+/// Control bytes that do not occur in text: everything below space except tab, line feed,
+/// form feed, carriage return and escape, and DEL
+fn is_control_byte(byte: u8) -> bool {
+    (byte < 0x20 && !matches!(byte, b'\t' | b'\n' | 0x0c | b'\r' | 0x1b)) || byte == 0x7f
+}
+
+/// Collapse `.` and `..` components so `src/../.git/config` cannot dodge a pattern
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Where `path` really is: its longest existing ancestor with symbolic links resolved,
+/// followed by the rest of the path, so neither a link nor `link/..` can lead a write
+/// somewhere its text does not show
+fn resolve(path: &Path) -> PathBuf {
+    resolve_links(path, MAX_LINK_DEPTH)
+}
+
+fn resolve_links(path: &Path, depth: usize) -> PathBuf {
+    let path = std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    for ancestor in path.ancestors() {
+        let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+        if let Ok(resolved) = std::fs::canonicalize(ancestor) {
+            return normalize(&resolved.join(rest));
+        }
+        // A write through a dangling link creates its target, wherever that is
+        if let (Ok(target), Some(parent), true) =
+            (std::fs::read_link(ancestor), ancestor.parent(), depth > 0)
+        {
+            return resolve_links(&parent.join(target).join(rest), depth - 1);
+        }
+    }
+    normalize(&path)
+}
+
+/// Find the first protected pattern matching `path`, checked relative to the workspace,
+/// both as written and with symbolic links resolved
+pub fn protected_pattern<'a>(
+    path: &Path,
+    workspace: &Path,
+    protected_paths: &'a [String],
+) -> Option<&'a str> {
+    let candidates = [
+        (normalize(path), normalize(workspace)),
+        (resolve(path), resolve(workspace)),
+    ];
+
+    protected_paths
+        .iter()
+        .find(|pattern| {
+            let pattern = match pattern.strip_suffix('/') {
+                Some(dir) => format!("{}/*", dir),
+                None => pattern.to_string(),
+            };
+            let matcher = WildMatch::new(&pattern);
+            candidates.iter().any(|(path, workspace)| {
+                let relative = path.strip_prefix(workspace).unwrap_or(path);
+                matcher.matches(&relative.to_string_lossy())
+                    || matcher.matches(&path.to_string_lossy())
+            })
+        })
+        .map(|pattern| pattern.as_str())
+}
+
+/// Refuse a modification outside `workspace` and the session's `scratch_dir`, following
+/// symbolic links
+pub fn check_location(
+    path: &Path,
+    workspace: &Path,
+    scratch_dir: Option<&Path>,
+) -> Option<FileGuardViolation> {
+    let target = resolve(path);
+    let allowed = std::iter::once(workspace)
+        .chain(scratch_dir)
+        .any(|dir| target.starts_with(resolve(dir)));
+    (!allowed).then(|| FileGuardViolation::OutsideWorkspace(path.to_path_buf()))
+}
+
+/// Check whether modifying `path` is allowed
+pub fn check_modification(
+    path: &Path,
+    workspace: &Path,
+    protected_paths: &[String],
+) -> Option<FileGuardViolation> {
+    if let Some(pattern) = protected_pattern(path, workspace, protected_paths) {
+        return Some(FileGuardViolation::ProtectedPath {
+            path: path.to_path_buf(),
+            pattern: pattern.to_string(),
+        });
+    }
+
+    match is_binary_file(path) {
+        Ok(true) => Some(FileGuardViolation::BinaryFile(path.to_path_buf())),
+        Ok(false) => None,
+        Err(e) => {
+            tracing::warn!(
+                "Could not inspect {} before modification: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_detection() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let binary = dir.path().join("logo.png");
+        std::fs::write(
+            &binary,
+            [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00],
+        )
+        .unwrap();
+        let text = dir.path().join("Main.java");
+        std::fs::write(&text, "class Main { /* héllo */ }\n").unwrap();
+
+        assert!(is_binary_file(&binary).unwrap());
+        assert!(!is_binary_file(&text).unwrap());
+        assert!(!is_binary_file(&dir.path().join("New.java")).unwrap());
+
+        assert_eq!(
+            check_modification(&binary, dir.path(), &[]).map(|v| v.code()),
+            Some("binary_file")
+        );
+        assert_eq!(check_modification(&text, dir.path(), &[]), None);
+    }
+
+    #[test]
+    fn test_protected_paths() {
+        let workspace = Path::new("/ws");
+        let protected = vec![
+            ".git/".to_string(),
+            "*.lock".to_string(),
+            ".github/workflows/*".to_string(),
+        ];
+
+        for path in [
+            "/ws/.git/config",
+            "/ws/Cargo.lock",
+            "/ws/.github/workflows/ci.yml",
+            "/ws/src/../.git/HEAD",
+        ] {
+            assert_eq!(
+                check_modification(Path::new(path), workspace, &protected).map(|v| v.code()),
+                Some("protected_path"),
+                "{} should be protected",
+                path
+            );
+        }
+        assert_eq!(
+            check_modification(Path::new("/ws/src/Main.java"), workspace, &protected),
+            None
+        );
+    }
+
+    #[test]
+    fn test_writes_stay_in_workspace_or_scratch_dir() {
+        let workspace = Path::new("/ws");
+        let scratch = Path::new("/tmp/kaiak-scratch/session-1");
+
+        assert_eq!(
+            check_location(Path::new("/ws/src/Main.java"), workspace, None),
+            None
+        );
+        assert_eq!(
+            check_location(
+                Path::new("/tmp/kaiak-scratch/session-1/out.txt"),
+                workspace,
+                Some(scratch)
+            ),
+            None
+        );
+        for path in [
+            "/etc/passwd",
+            "/ws/../etc/passwd",
+            "/tmp/kaiak-scratch/session-2/out.txt",
+        ] {
+            assert_eq!(
+                check_location(Path::new(path), workspace, Some(scratch)).map(|v| v.code()),
+                Some("outside_workspace"),
+                "{} should be refused",
+                path
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_cannot_lead_writes_out_of_the_workspace() {
+        let outside = tempfile::tempdir().unwrap();
+        let workspace = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(workspace.path().join("src")).unwrap();
+        std::fs::create_dir_all(workspace.path().join(".git")).unwrap();
+        std::fs::write(workspace.path().join(".git/config"), "[core]\n").unwrap();
+        std::os::unix::fs::symlink(outside.path(), workspace.path().join("src/link")).unwrap();
+        std::os::unix::fs::symlink(
+            workspace.path().join(".git/config"),
+            workspace.path().join("config"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("created.txt"),
+            workspace.path().join("dangling.txt"),
+        )
+        .unwrap();
+
+        for path in [
+            // Through a link to a directory outside, to a file that exists or not yet
+            workspace.path().join("src/link/Main.java"),
+            workspace.path().join("src/link/new/Main.java"),
+            // `..` after a link is taken from where the link leads
+            workspace.path().join("src/link/../x"),
+            // A dangling link would create its target outside
+            workspace.path().join("dangling.txt"),
+        ] {
+            assert_eq!(
+                check_location(&path, workspace.path(), None).map(|v| v.code()),
+                Some("outside_workspace"),
+                "{} should be refused",
+                path.display()
+            );
+        }
+        assert_eq!(
+            check_location(
+                &workspace.path().join("src/Main.java"),
+                workspace.path(),
+                None
+            ),
+            None
+        );
+
+        // A link inside the workspace to a protected file is protected too
+        let protected = vec![".git/".to_string()];
+        assert_eq!(
+            check_modification(
+                &workspace.path().join("config"),
+                workspace.path(),
+                &protected
+            )
+            .map(|v| v.code()),
+            Some("protected_path")
+        );
+    }
+
+    #[test]
+    fn test_legacy_encodings_are_text() {
+        let content = "é".repeat(BINARY_SNIFF_BYTES);
+        assert!(!is_binary_content(
+            &content.as_bytes()[..BINARY_SNIFF_BYTES - 1]
+        ));
+        // Latin-1 and Shift-JIS bytes are not valid UTF-8 but are still text
+        assert!(!is_binary_content(b"caf\xe9 cr\xe8me\r\n"));
+        assert!(!is_binary_content(&[0x82, 0xa0, 0x82, 0xa2, b'\n']));
+        // Control bytes beyond what text uses mark binary content, even without nulls
+        assert!(is_binary_content(&[
+            0x01, 0x02, 0x03, b'a', b'b', 0x04, 0x05, b'c'
+        ]));
+        assert!(!is_binary_content(b"\x1b[1mbold\x1b[0m\tand\x0cpaged\n"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Fixes of earlier generate_fix requests by incident fingerprint and model, so a
+//! repeated incident can be fixed again without a model call.
+
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::generate_fix::{
+    is_applied, record_applied, resolve_incident_path, GenerateFixDataKind, GenerateFixHandler,
+    GenerateFixRequest, SUGGESTION_TOOL,
+};
+use crate::jsonrpc::NotificationSender;
+use crate::models::applied_edits;
+use crate::models::configuration::ModelConfig;
+use crate::models::fingerprint::IncidentFingerprint;
+use crate::models::incidents::MigrationIncident;
+use crate::models::report::{FixSource, MigrationReport, MigrationReportCollector, TextEdit};
+
+/// Incidents kept in the fix cache; older ones are forgotten first
+pub const MAX_CACHED_FIXES: usize = 1000;
+
+/// Edits that fixed an incident, and when
+#[derive(Debug, Clone)]
+struct CachedFix {
+    edits: Vec<TextEdit>,
+    cached_at: Instant,
+}
+
+/// Edits that fixed recent incidents, by model and incident fingerprint
+#[derive(Debug)]
+pub struct FixCache {
+    fixes: Mutex<LruCache<String, CachedFix>>,
+}
+
+impl Default for FixCache {
+    fn default() -> Self {
+        Self::new(MAX_CACHED_FIXES)
+    }
+}
+
+impl FixCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            fixes: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Remember the edits that fixed an incident with `model`
+    pub fn record(&self, model: &ModelConfig, fingerprint: &str, edits: Vec<TextEdit>) {
+        if edits.is_empty() {
+            return;
+        }
+        self.fixes.lock().unwrap().put(
+            key(model, fingerprint),
+            CachedFix {
+                edits,
+                cached_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Edits that fixed an incident with `model` less than `ttl` ago
+    pub fn get(
+        &self,
+        model: &ModelConfig,
+        fingerprint: &str,
+        ttl: Duration,
+    ) -> Option<Vec<TextEdit>> {
+        let key = key(model, fingerprint);
+        let mut fixes = self.fixes.lock().unwrap();
+        let fix = fixes.get(&key)?;
+        if fix.cached_at.elapsed() >= ttl {
+            fixes.pop(&key);
+            return None;
+        }
+        Some(fix.edits.clone())
+    }
+}
+
+fn key(model: &ModelConfig, fingerprint: &str) -> String {
+    format!("{}/{}/{}", model.provider, model.model, fingerprint)
+}
+
+impl GenerateFixHandler {
+    /// Replay the cached edits of incidents fixed before with the same model, decided like
+    /// the model's edits but without a model call. Each incident's edits are proposed under
+    /// that incident, but a file's edits are only replayed on the content they were made
+    /// on, and only together once every one of them is approved. Returns the
+    /// request's session with the report of the replayed fixes; incidents not in the
+    /// report are left to the model.
+    pub(super) async fn apply_cached_fixes(
+        &self,
+        request_id: &str,
+        request: &GenerateFixRequest,
+        fingerprints: &[IncidentFingerprint],
+        ttl: Duration,
+        notifier: &NotificationSender,
+        started_at: std::time::Instant,
+    ) -> Option<(String, MigrationReport)> {
+        let workspace = &request.agent_config.workspace;
+        let model = self.primary_model(request);
+        let mut hits: std::collections::BTreeMap<
+            std::path::PathBuf,
+            Vec<(MigrationIncident, Vec<TextEdit>)>,
+        > = std::collections::BTreeMap::new();
+        for incident in &request.incidents {
+            let Some(edits) = fingerprints
+                .iter()
+                .find(|fingerprint| fingerprint.incident_id == incident.id)
+                .and_then(|fingerprint| self.fix_cache.get(model, &fingerprint.fingerprint, ttl))
+            else {
+                continue;
+            };
+            hits.entry(resolve_incident_path(workspace, incident))
+                .or_default()
+                .push((incident.clone(), edits));
+        }
+        if hits.is_empty() {
+            return None;
+        }
+
+        let session_id = self.suggestion_session(request, None).await?;
+        info!(
+            "Replaying cached fixes for {} incidents",
+            hits.values().map(Vec::len).sum::<usize>()
+        );
+
+        let mut applied_edits = self.applied_edits(request);
+        let mut report = MigrationReport::default();
+        for (path, incidents) in hits {
+            // Incidents fixed together may share an edit; the file gets it once
+            let mut edits: Vec<TextEdit> = Vec::new();
+            for edit in incidents.iter().flat_map(|(_, edits)| edits) {
+                if !edits.contains(edit) {
+                    edits.push(edit.clone());
+                }
+            }
+            let proposal = applied_edits::proposal_id(
+                &path,
+                &edits
+                    .iter()
+                    .map(|edit| format!("{}\0{}", edit.old_str, edit.new_str))
+                    .collect::<Vec<_>>()
+                    .join("\0"),
+            );
+            if is_applied(&applied_edits, &path, &proposal) {
+                info!(
+                    "Cached fixes to {} were applied by an earlier run",
+                    path.display()
+                );
+                let incidents: Vec<_> = incidents
+                    .into_iter()
+                    .map(|(incident, _)| incident)
+                    .collect();
+                report.merge(self.already_applied(
+                    notifier,
+                    request_id,
+                    &session_id,
+                    &incidents,
+                    FixSource::Cache,
+                ));
+                continue;
+            }
+            let replayable = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| apply_edits(&content, &edits))
+                .is_some();
+            if !replayable {
+                info!(
+                    "{} changed since its fix was cached; leaving its incidents to the model",
+                    path.display()
+                );
+                continue;
+            }
+
+            // Each incident's edits are decided under that incident, so its tool calls and
+            // audit entries are its own
+            let proposed: usize = incidents.iter().map(|(_, edits)| edits.len()).sum();
+            let mut approved: Vec<(usize, String, serde_json::Map<String, serde_json::Value>)> =
+                Vec::new();
+            'proposals: for (position, (incident, incident_edits)) in incidents.iter().enumerate() {
+                for (index, edit) in incident_edits.iter().enumerate() {
+                    let id = format!("cached-{}-{}", incident.id, index);
+                    let arguments = serde_json::json!({
+                        "command": "str_replace",
+                        "path": path.display().to_string(),
+                        "old_str": edit.old_str,
+                        "new_str": edit.new_str,
+                    })
+                    .as_object()
+                    .cloned()
+                    .unwrap_or_default();
+                    if !self
+                        .propose_edit(
+                            notifier,
+                            request_id,
+                            &session_id,
+                            &incident.id,
+                            &id,
+                            &arguments,
+                            "cache",
+                        )
+                        .await
+                    {
+                        break 'proposals;
+                    }
+                    approved.push((position, id, arguments));
+                }
+            }
+
+            // The file is written once, when every edit was approved, so a denied edit
+            // leaves none of the others behind; it may have changed while the client decided
+            let written = (approved.len() == proposed)
+                .then(|| {
+                    let content = std::fs::read_to_string(&path).ok()?;
+                    let updated = apply_edits(&content, &edits)?;
+                    std::fs::write(&path, &updated).ok()?;
+                    Some((content, updated))
+                })
+                .flatten();
+            let success = written.is_some();
+            for (_, id, _) in &approved {
+                self.send_notification(
+                    notifier,
+                    request_id,
+                    &session_id,
+                    GenerateFixDataKind::ToolResponse,
+                    serde_json::json!({ "id": id, "is_error": !success }),
+                );
+            }
+            if !success {
+                warn!(
+                    "Replayed none of the {} cached edits to {}, {} of which were approved; \
+                     leaving its incidents to the model",
+                    proposed,
+                    path.display(),
+                    approved.len()
+                );
+                continue;
+            }
+            if let Some((before, after)) = &written {
+                record_applied(&mut applied_edits, &path, &proposal, before, after);
+            }
+
+            // Each incident is reported fixed by its own edits
+            for (position, (incident, _)) in incidents.iter().enumerate() {
+                let mut collector = MigrationReportCollector::new(workspace)
+                    .with_incidents(std::slice::from_ref(incident), &[])
+                    .with_fix_source(FixSource::Cache);
+                for (_, id, arguments) in approved.iter().filter(|(owner, ..)| *owner == position) {
+                    collector.record_tool_request(id, SUGGESTION_TOOL, Some(arguments));
+                    for status in collector.record_tool_response(id, true) {
+                        self.send_incident_notification(
+                            notifier,
+                            request_id,
+                            &session_id,
+                            GenerateFixDataKind::IncidentStatus,
+                            Some(status.incident_id),
+                            serde_json::json!({ "outcome": status.outcome }),
+                        );
+                    }
+                }
+                report.merge(collector.finish(started_at.elapsed().as_millis() as u64));
+            }
+        }
+
+        info!(
+            "Fixed {} incidents from the fix cache without the model",
+            report.incidents_fixed
+        );
+        Some((session_id, report))
+    }
+}
+
+/// Apply `str_replace` edits to `content` in order, or None if one's `old_str` does not
+/// occur exactly once in the content it is applied to
+fn apply_edits(content: &str, edits: &[TextEdit]) -> Option<String> {
+    let mut content = content.to_string();
+    for edit in edits {
+        if edit.old_str.is_empty() || content.matches(&edit.old_str).count() != 1 {
+            return None;
+        }
+        content = content.replacen(&edit.old_str, &edit.new_str, 1);
+    }
+    Some(content)
+}
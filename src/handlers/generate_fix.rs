@@ -1,26 +1,57 @@
 //! Generate fix handler for processing migration incidents with Goose agent.
 
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use validator::Validate;
+use wildmatch::WildMatch;
 
 use goose::agents::AgentEvent;
 use goose::conversation::message::{ActionRequiredData, Message, MessageContent};
 use goose::permission::permission_confirmation::PrincipalType;
 use goose::permission::{Permission, PermissionConfirmation};
 
+use super::audit::{AuditDecision, AuditEntry, AuditLog};
+use super::file_guard::{self, FileGuardViolation};
+use super::fix_cache::FixCache;
+use super::identity::ClientIdentity;
 use super::interaction_manager::InteractionManager;
-use crate::agent::GooseAgentManager;
-use crate::jsonrpc::{methods::GENERATE_FIX_DATA, JsonRpcNotification, NotificationSender};
-use crate::models::{configuration::AgentConfig, incidents::MigrationIncident};
+use super::retry_failed::CompletedFixes;
+use super::rollback::CompletedBackups;
+use super::tool_results::ToolResultStore;
+use crate::agent::prompt_builder::{estimate_tokens, split_into_turns};
+use crate::agent::{FixPrompt, GooseAgentManager, ModelCall, PromptBuilder, PromptTemplate};
+use crate::jsonrpc::{
+    methods::{GENERATE_FIX_DATA, USAGE, WARNING},
+    JsonRpcNotification, NotificationSender,
+};
+use crate::models::{
+    applied_edits::AppliedEdits,
+    backup::WorkspaceBackup,
+    configuration::{AgentConfig, ApprovalCachePolicy, PermissionLevel},
+    fingerprint::{self, FingerprintHistory, IncidentFingerprint},
+    incidents::{IncidentSeverity, MigrationIncident},
+    line_endings,
+    patch::{ApplyMode, PatchRecorder, WorkspaceCopy},
+    report::{FixSource, IncidentOutcome, MigrationReport, MigrationReportCollector},
+    text_encoding,
+    usage::{TokenUsage, UsageTracker},
+    warning::Warning,
+};
 use crate::KaiakResult;
 
 const INTERACTION_TIMEOUT_SECS: u64 = 300;
 
+/// Most output streamed for one tool call; the full output still reaches the tool result
+const MAX_STREAMED_TOOL_OUTPUT_BYTES: usize = 64 * 1024;
+/// Tool suggested replacements are proposed as, so the tool permissions and approvals that
+/// cover the model's edits cover them too
+pub(super) const SUGGESTION_TOOL: &str = "developer__text_editor";
+
 /// Request type for kaiak/generate_fix endpoint
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct GenerateFixRequest {
@@ -35,6 +66,82 @@ pub struct GenerateFixRequest {
     /// Optional context for the migration process
     pub migration_context: Option<serde_json::Value>,
     pub agent_config: AgentConfig,
+    /// Group incidents sharing a rule_id so the rule context is sent once
+    #[serde(default)]
+    pub group_by_rule: bool,
+    /// Approximate token budget for the prompt; lowest-severity incidents are dropped to fit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "max_prompt_tokens must be positive"))]
+    pub max_prompt_tokens: Option<usize>,
+    /// Lines of source shown around each incident; defaults to the server's `incident_context_lines`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_lines: Option<usize>,
+    /// Persist incident fingerprints for the workspace and tag incidents new/recurring
+    #[serde(default)]
+    pub track_fingerprints: bool,
+    /// Send the model's thinking as `thinking` notifications; defaults to the server's
+    /// `stream_thinking`. Suppressed thinking is still logged server-side.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_thinking: Option<bool>,
+    /// Only validate and normalize the request; no agent is created and no model is called
+    #[serde(default)]
+    pub validate_only: bool,
+    /// Also send each warning as a `kaiak/warning` notification as it is collected
+    #[serde(default)]
+    pub stream_warnings: bool,
+    /// How much of the agent's activity is streamed as `kaiak/generate_fix/data`
+    #[serde(default)]
+    pub verbosity: Verbosity,
+    /// List links found in each incident's `metadata` (e.g. rule docs) in the prompt
+    #[serde(default)]
+    pub reference_links: bool,
+    /// Leave approved edits in the workspace, or collect them into a patch file
+    #[serde(default)]
+    pub apply_mode: ApplyMode,
+    /// Where `patch` mode writes the diff; defaults to `kaiak-<request_id>.patch` in the
+    /// system temp directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch_path: Option<std::path::PathBuf>,
+    /// Only process incidents whose `rule_id` matches one of these globs (`*` and `?`);
+    /// empty processes every rule
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_rules: Vec<String>,
+    /// Skip incidents whose `rule_id` matches one of these globs, even if included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_rules: Vec<String>,
+    /// Which later tool calls an approval also covers; defaults to the server's `approval_cache`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval_cache: Option<ApprovalCachePolicy>,
+    /// Send every incident to the model even when the fix cache has a fix for it; the
+    /// request's fixes are still cached
+    #[serde(default)]
+    pub no_cache: bool,
+}
+
+/// Granularity of the events streamed while a fix runs
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Only errors and interactions that need a client answer; results come in the response
+    Minimal,
+    /// Messages, tool calls and their results, thinking, and system events
+    #[default]
+    Normal,
+    /// Everything, including tool progress notifications
+    Verbose,
+}
+
+impl Verbosity {
+    fn streams(self, kind: &GenerateFixDataKind) -> bool {
+        match self {
+            Verbosity::Minimal => matches!(
+                kind,
+                GenerateFixDataKind::Error | GenerateFixDataKind::UserInteraction
+            ),
+            Verbosity::Normal => !matches!(kind, GenerateFixDataKind::ToolProgress),
+            Verbosity::Verbose => true,
+        }
+    }
 }
 
 /// Response type for kaiak/generate_fix endpoint
@@ -43,6 +150,87 @@ pub struct GenerateFixResponse {
     pub request_id: String,
     pub session_id: String,
     pub created_at: String,
+    /// IDs of incidents skipped by `include_rules`/`exclude_rules`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filtered_incidents: Vec<String>,
+    /// IDs of incidents left out of the prompt to fit `max_prompt_tokens`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trimmed_incidents: Vec<String>,
+    /// IDs of incidents whose message or description was truncated in the prompt
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncated_incidents: Vec<String>,
+    /// The prompt exceeded the model's context length and was retried with fewer
+    /// incidents and less surrounding code; see `trimmed_incidents`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reduced_for_context_length: bool,
+    /// The primary model failed and the request ran on `fallback_model`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub used_fallback: bool,
+    /// Error from the primary model that caused the switch to `fallback_model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_reason: Option<String>,
+    /// Non-fatal issues met while processing the request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    /// IDs of incidents fixed by replaying cached edits instead of calling the model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cache_hits: Vec<String>,
+    /// Summary of what the fix attempted and changed
+    pub report: MigrationReport,
+    /// Model tokens used across all turns of the request
+    pub usage: TokenUsage,
+    /// Fingerprint of each incident, in request order
+    pub fingerprints: Vec<IncidentFingerprint>,
+    /// Fingerprints from the previous tracked run that no longer appear
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolved_fingerprints: Vec<String>,
+}
+
+/// Result of a generate_fix request sent with `validate_only`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidateOnlyResponse {
+    /// Incidents deduplicated by ID and sorted by file, line, and ID
+    pub incidents: Vec<NormalizedIncident>,
+    /// IDs of incidents skipped by `include_rules`/`exclude_rules`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filtered_incidents: Vec<String>,
+    /// Problems that would not fail the request but likely hurt the fix
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// The prompts a generate_fix request would send, from kaiak/preview_prompt
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptPreview {
+    /// One entry per model turn; a single turn unless `incident_turn_budget` splits the request
+    pub turns: Vec<PromptPreviewTurn>,
+    /// Estimated tokens of all turns' prompts
+    pub estimated_tokens: usize,
+    /// IDs of incidents skipped by `include_rules`/`exclude_rules`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub filtered_incidents: Vec<String>,
+    /// IDs of incidents that would be left out to fit `max_prompt_tokens`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trimmed_incidents: Vec<String>,
+    /// IDs of incidents whose message or description would be truncated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncated_incidents: Vec<String>,
+}
+
+/// The user prompt of one model turn
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptPreviewTurn {
+    pub incident_ids: Vec<String>,
+    pub prompt: String,
+    pub estimated_tokens: usize,
+}
+
+/// An incident with its file resolved against the workspace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizedIncident {
+    #[serde(flatten)]
+    pub incident: MigrationIncident,
+    pub resolved_path: std::path::PathBuf,
 }
 
 /// Kind of data being sent in generate_fix notifications
@@ -52,10 +240,13 @@ pub enum GenerateFixDataKind {
     AiMessage,
     ToolCall,
     ToolResponse,
+    ToolProgress,
     UserInteraction,
     Thinking,
     Error,
     System,
+    /// An incident's outcome changed; the final report carries the last one streamed
+    IncidentStatus,
 }
 
 /// Data notification sent to client during generate_fix processing
@@ -64,6 +255,9 @@ pub struct GenerateFixData {
     pub request_id: String,
     pub session_id: String,
     pub kind: GenerateFixDataKind,
+    /// Incident the event was attributed to, from the file a tool call targets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub incident_id: Option<String>,
     pub payload: serde_json::Value,
 }
 
@@ -87,14 +281,35 @@ pub enum UserInteractionPayload {
     },
 }
 
+/// Running generate_fix requests by request ID
+pub type ActiveRequests = Arc<RwLock<std::collections::HashMap<String, GenerateFixRequest>>>;
+
 /// Handler for kaiak/generate_fix endpoint
 /// Coordinates with Goose agent to process migration incidents
 pub struct GenerateFixHandler {
     agent_manager: Arc<GooseAgentManager>,
     interaction_manager: Arc<InteractionManager>,
-    active_requests: Arc<RwLock<std::collections::HashMap<String, GenerateFixRequest>>>,
-    #[allow(dead_code)]
+    active_requests: ActiveRequests,
+    /// Verbosity of each active request, readable from the synchronous notification path
+    verbosities: Arc<std::sync::Mutex<std::collections::HashMap<String, Verbosity>>>,
+    /// Original file contents of each active `patch` mode request
+    patch_recorders: Arc<std::sync::Mutex<std::collections::HashMap<String, PatchRecorder>>>,
+    /// Files backed up by each active request, when `backup_strategy` is set
+    backups: Arc<std::sync::Mutex<std::collections::HashMap<String, WorkspaceBackup>>>,
+    /// Approval keys of each active request's approved tool calls, see `approval_key`
+    approvals:
+        Arc<std::sync::Mutex<std::collections::HashMap<String, std::collections::HashSet<String>>>>,
     base_config: Arc<crate::models::configuration::BaseConfig>,
+    identity: ClientIdentity,
+    audit_log: Arc<AuditLog>,
+    /// Tool results above `tool_result_spill_bytes`, removed when their request ends
+    tool_results: Arc<ToolResultStore>,
+    /// Finished requests, for `kaiak/retry_failed`
+    completed_fixes: Arc<CompletedFixes>,
+    /// Backups of finished requests, for `kaiak/rollback_request`
+    completed_backups: Arc<CompletedBackups>,
+    /// Edits that fixed earlier incidents, when `fix_cache_ttl_secs` is set
+    pub(super) fix_cache: Arc<FixCache>,
 }
 
 impl GenerateFixHandler {
@@ -107,13 +322,175 @@ impl GenerateFixHandler {
             agent_manager,
             interaction_manager,
             active_requests: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            verbosities: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+            patch_recorders: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+            backups: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
+            approvals: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
             base_config,
+            identity: ClientIdentity::default(),
+            audit_log: Arc::new(AuditLog::default()),
+            tool_results: Arc::new(ToolResultStore::default()),
+            completed_fixes: Arc::new(CompletedFixes::default()),
+            completed_backups: Arc::new(CompletedBackups::default()),
+            fix_cache: Arc::new(FixCache::default()),
+        }
+    }
+
+    /// Identity of the client, recorded in logs and audit entries
+    pub fn with_identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit_log: Arc<AuditLog>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Store shared with `kaiak/get_tool_result`, which serves the spilled results
+    pub fn with_tool_results(mut self, tool_results: Arc<ToolResultStore>) -> Self {
+        self.tool_results = tool_results;
+        self
+    }
+
+    /// Store shared with `kaiak/retry_failed`, which retries a finished request's failures
+    pub fn with_completed_fixes(mut self, completed_fixes: Arc<CompletedFixes>) -> Self {
+        self.completed_fixes = completed_fixes;
+        self
+    }
+
+    /// Store shared with `kaiak/rollback_request`, which undoes a finished request's
+    /// modifications from its backups
+    pub fn with_completed_backups(mut self, completed_backups: Arc<CompletedBackups>) -> Self {
+        self.completed_backups = completed_backups;
+        self
+    }
+
+    /// Registry shared by every request of the server, so request IDs are unique across
+    /// them
+    pub fn with_active_requests(mut self, active_requests: ActiveRequests) -> Self {
+        self.active_requests = active_requests;
+        self
+    }
+
+    /// Cache shared by every request of the server, so a request can replay the fixes of
+    /// earlier ones
+    pub fn with_fix_cache(mut self, fix_cache: Arc<FixCache>) -> Self {
+        self.fix_cache = fix_cache;
+        self
+    }
+
+    /// Build the prompts a request would send, turn by turn, without creating an agent or
+    /// calling the provider
+    pub fn preview_prompt(
+        request: &GenerateFixRequest,
+        base_config: &crate::models::configuration::BaseConfig,
+    ) -> KaiakResult<PromptPreview> {
+        check_request(request)?;
+        let mut request = request.clone();
+        assign_incident_ids(&mut request);
+        apply_severity_overrides(&mut request, &base_config.severity_overrides);
+        let filtered_incidents = filter_incidents_by_rule(&mut request)?;
+
+        let template = prompt_template(base_config)?;
+        let (builder, _) = prompt_builder(base_config, &request, template);
+        let budget = &base_config.incident_turn_budget;
+        let mut preview = PromptPreview {
+            filtered_incidents,
+            ..PromptPreview::default()
+        };
+        for incidents in
+            split_into_turns(&request.incidents, budget.max_incidents, budget.max_chars)
+        {
+            let prompt = builder
+                .clone()
+                .with_max_prompt_tokens(request.max_prompt_tokens)
+                .build(&incidents);
+            let estimated_tokens = estimate_tokens(&prompt.text);
+            preview.estimated_tokens += estimated_tokens;
+            preview
+                .trimmed_incidents
+                .extend(prompt.trimmed_incident_ids);
+            preview
+                .truncated_incidents
+                .extend(prompt.truncated_incident_ids);
+            preview.turns.push(PromptPreviewTurn {
+                incident_ids: incidents.into_iter().map(|incident| incident.id).collect(),
+                prompt: prompt.text,
+                estimated_tokens,
+            });
+        }
+        Ok(preview)
+    }
+
+    /// Validate and normalize a request without touching the agent manager or provider
+    pub fn validate_only(
+        request: &GenerateFixRequest,
+        base_config: &crate::models::configuration::BaseConfig,
+    ) -> KaiakResult<ValidateOnlyResponse> {
+        check_request(request)?;
+        let mut request = request.clone();
+        assign_incident_ids(&mut request);
+        apply_severity_overrides(&mut request, &base_config.severity_overrides);
+        let filtered_incidents = filter_incidents_by_rule(&mut request)?;
+        request.agent_config.validate().map_err(|e| {
+            crate::KaiakError::configuration(format!("Invalid agent_config: {}", e))
+        })?;
+
+        let workspace = &request.agent_config.workspace;
+        let mut warnings = Vec::new();
+        if !workspace.is_dir() {
+            warnings.push(format!(
+                "Workspace {} is not a directory",
+                workspace.display()
+            ));
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let mut incidents = Vec::new();
+        for incident in &request.incidents {
+            if !seen.insert(incident.id.as_str()) {
+                warnings.push(format!("Duplicate incident ID '{}' ignored", incident.id));
+                continue;
+            }
+            let resolved_path = resolve_incident_path(workspace, incident);
+            if !resolved_path.starts_with(workspace) {
+                warnings.push(format!(
+                    "Incident '{}' points outside the workspace: {}",
+                    incident.id,
+                    resolved_path.display()
+                ));
+            } else if !resolved_path.is_file() {
+                warnings.push(format!(
+                    "Incident '{}' file not found: {}",
+                    incident.id,
+                    resolved_path.display()
+                ));
+            }
+            incidents.push(NormalizedIncident {
+                incident: incident.clone(),
+                resolved_path,
+            });
         }
+
+        incidents.sort_by(|a, b| {
+            (&a.resolved_path, a.incident.line_number, &a.incident.id).cmp(&(
+                &b.resolved_path,
+                b.incident.line_number,
+                &b.incident.id,
+            ))
+        });
+
+        Ok(ValidateOnlyResponse {
+            incidents,
+            filtered_incidents,
+            warnings,
+        })
     }
 
     pub async fn handle_generate_fix(
         &self,
-        request: GenerateFixRequest,
+        mut request: GenerateFixRequest,
         notifier: NotificationSender,
     ) -> KaiakResult<GenerateFixResponse> {
         info!(
@@ -121,92 +498,752 @@ impl GenerateFixHandler {
             request.session_id
         );
 
-        if let Err(validation_errors) = request.validate() {
-            error!("Request validation failed: {:?}", validation_errors);
-            let error_messages: Vec<String> = validation_errors
-                .field_errors()
-                .into_iter()
-                .flat_map(|(field, errors)| {
-                    errors.iter().map(move |error| {
-                        format!(
-                            "Field '{}': {}",
-                            field,
-                            error
-                                .message
-                                .as_ref()
-                                .map(|m| m.as_ref())
-                                .unwrap_or("validation error")
-                        )
-                    })
-                })
-                .collect();
-
-            return Err(crate::KaiakError::agent(
-                format!("Request validation failed: {}", error_messages.join(", ")),
-                None,
-            ));
+        check_request(&request)?;
+        let model = self.primary_model(&request);
+        check_model_provider(model)?;
+        self.agent_manager.check_provider_allowed(&model.provider)?;
+        let fallback_model = self.fallback_model(&request).cloned();
+        if let Some(fallback) = &fallback_model {
+            check_model_provider(fallback)?;
+            self.agent_manager
+                .check_provider_allowed(&fallback.provider)?;
         }
+        // Read per request so template edits apply without a restart
+        let template = prompt_template(&self.base_config)?;
+        assign_incident_ids(&mut request);
+        apply_severity_overrides(&mut request, &self.base_config.severity_overrides);
+        let filtered_incidents = filter_incidents_by_rule(&mut request)?;
 
+        let started_at = std::time::Instant::now();
         let request_id = Uuid::new_v4().to_string();
-        {
-            let mut active = self.active_requests.write().await;
-            active.insert(request_id.clone(), request.clone());
+        // Routes this request's logs to its own file when request logs are enabled
+        tracing::Span::current()
+            .record("request_id", request_id.as_str())
+            .record("identity", self.identity.get().as_str());
+        // Fingerprint before the agent edits the code the fingerprints hash
+        let (fingerprints, resolved_fingerprints) = self.fingerprint_request(&request);
+
+        // Patch mode works on a copy of the workspace, so the workspace itself is never
+        // modified, however the request ends. The request as sent is kept for the report.
+        let mut patch_copy = None;
+        if request.apply_mode == ApplyMode::Patch {
+            let copy = create_workspace_copy(&request, &request_id).await?;
+            let original = request.clone();
+            redirect_to_copy(&mut request, &copy);
+            patch_copy = Some((copy, original));
         }
 
+        let active_request = self.track_active_request(&request_id, &request).await?;
+        self.agent_manager.stats().request_started();
+        self.verbosities
+            .lock()
+            .unwrap()
+            .insert(request_id.clone(), request.verbosity);
+
         info!("Processing {} migration incidents", request.incidents.len());
 
-        match self
-            .initiate_agent_processing(&request_id, &request, &notifier)
-            .await
+        let (builder, context_lines) = prompt_builder(&self.base_config, &request, template);
+        let mut warnings = Vec::new();
+
+        // The prompt decodes legacy files itself; the agent's tools need them as UTF-8 too
+        let incident_paths = Self::incident_paths(&request);
+        let transcoded = if self.base_config.transcode_legacy_encodings
+            && !self.base_config.read_only
+            && request.apply_mode != ApplyMode::Patch
+        {
+            text_encoding::transcode_to_utf8(
+                incident_paths.iter().map(|path| path.as_path()),
+                self.base_config.max_readable_file_bytes,
+            )
+        } else {
+            Vec::new()
+        };
+        let original_endings =
+            if !self.base_config.read_only && request.apply_mode != ApplyMode::Patch {
+                line_endings::snapshot(incident_paths.iter().map(|path| path.as_path()))
+            } else {
+                Vec::new()
+            };
+        if request.apply_mode == ApplyMode::Patch {
+            let mut recorder = PatchRecorder::new(&request.agent_config.workspace);
+            for path in &incident_paths {
+                recorder.record(path);
+            }
+            self.patch_recorders
+                .lock()
+                .unwrap()
+                .insert(request_id.clone(), recorder);
+        }
+        // Nothing a patch mode request does reaches the workspace, so there is nothing to back up
+        if let (Some(strategy), ApplyMode::InPlace) =
+            (self.base_config.backup_strategy, request.apply_mode)
         {
-            Ok(session_id) => {
+            let backup_dir = self
+                .base_config
+                .backup_dir
+                .clone()
+                .unwrap_or_else(|| std::env::temp_dir().join("kaiak-backups"));
+            let backup = WorkspaceBackup::new(
+                strategy,
+                &request.agent_config.workspace,
+                &backup_dir,
+                &request_id,
+            )
+            .with_originals(
+                transcoded
+                    .iter()
+                    .map(|file| (file.path.clone(), file.original.clone())),
+            );
+            self.backups
+                .lock()
+                .unwrap()
+                .insert(request_id.clone(), backup);
+        }
+
+        // Turns run one after another in the same session, each on the files as the
+        // earlier turns left them
+        let mut session_id = request.session_id.clone();
+        let mut report = MigrationReport::default();
+
+        // Known fixes need no model call; whatever they leave unfixed goes to the model
+        let mut model_incidents = request.incidents.clone();
+        // Edits that fixed each incident, for the fix cache
+        let mut fixed_edits = Vec::new();
+        let mut cache_hits = Vec::new();
+        if let (Some(ttl), false) = (self.base_config.fix_cache_ttl_secs, request.no_cache) {
+            if let Some((cache_session_id, cache_report)) = self
+                .apply_cached_fixes(
+                    &request_id,
+                    &request,
+                    &fingerprints,
+                    Duration::from_secs(ttl),
+                    &notifier,
+                    started_at,
+                )
+                .await
+            {
+                cache_hits = cache_report
+                    .incidents
+                    .iter()
+                    .map(|result| result.incident_id.clone())
+                    .collect();
+                model_incidents.retain(|incident| !cache_hits.contains(&incident.id));
+                session_id = Some(cache_session_id);
+                report.merge(cache_report);
+            }
+        }
+        if self.base_config.apply_suggested_replacements {
+            if let Some((suggestion_session_id, suggestion_report)) = self
+                .apply_suggested_replacements(
+                    &request_id,
+                    &request,
+                    &model_incidents,
+                    session_id.as_deref(),
+                    &mut fixed_edits,
+                    &notifier,
+                    started_at,
+                )
+                .await
+            {
+                model_incidents.retain(|incident| {
+                    !suggestion_report
+                        .incidents
+                        .iter()
+                        .any(|result| result.incident_id == incident.id)
+                });
+                session_id = Some(suggestion_session_id);
+                report.merge(suggestion_report);
+            }
+        }
+
+        let budget = &self.base_config.incident_turn_budget;
+        let turns = if model_incidents.is_empty() {
+            Vec::new()
+        } else {
+            split_into_turns(&model_incidents, budget.max_incidents, budget.max_chars)
+        };
+        if turns.len() > 1 {
+            info!(
+                "Splitting {} incidents into {} model turns",
+                model_incidents.len(),
+                turns.len()
+            );
+        }
+        let mut usage = TokenUsage::default();
+        let mut trimmed_incidents = Vec::new();
+        let mut truncated_incidents = Vec::new();
+        let mut reduced_for_context_length = false;
+        let mut fallback_reason: Option<String> = None;
+        let mut failure = None;
+        for (turn, incidents) in turns.iter().enumerate() {
+            let mut turn_request = request.clone();
+            turn_request.incidents = incidents.clone();
+            turn_request.session_id = session_id.clone();
+            let mut turn_fallback = fallback_model.as_ref();
+            if let (Some(_), Some(fallback)) = (&fallback_reason, turn_fallback) {
+                // Later turns start on the fallback the first turn switched to
+                turn_request.agent_config = self.with_model(&request.agent_config, fallback);
+                turn_fallback = None;
+            }
+            let turn_paths: std::collections::BTreeSet<String> =
+                Self::incident_paths(&turn_request)
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+            let edited_files = report
+                .file_changes
+                .iter()
+                .filter(|change| turn_paths.contains(&change.path))
+                .map(|change| change.path.clone())
+                .collect();
+
+            let (result, prompt, reduced) = self
+                .run_turn(
+                    &request_id,
+                    &turn_request,
+                    &builder.clone().with_edited_files(edited_files),
+                    context_lines,
+                    turn_fallback,
+                    &mut warnings,
+                    &notifier,
+                )
+                .await;
+            reduced_for_context_length |= reduced;
+            trimmed_incidents.extend(prompt.trimmed_incident_ids);
+            truncated_incidents.extend(prompt.truncated_incident_ids);
+            match result {
+                Ok(((turn_session_id, collector, turn_usage), turn_fallback_reason)) => {
+                    if turns.len() > 1 {
+                        self.send_notification(
+                            &notifier,
+                            &request_id,
+                            &turn_session_id,
+                            GenerateFixDataKind::System,
+                            serde_json::json!({
+                                "event": "turn_completed",
+                                "turn": turn + 1,
+                                "turns": turns.len(),
+                                "incident_ids": incidents
+                                    .iter()
+                                    .map(|incident| &incident.id)
+                                    .collect::<Vec<_>>(),
+                            }),
+                        );
+                    }
+                    session_id = Some(turn_session_id);
+                    fixed_edits.extend(collector.fixed_edits());
+                    report.merge(collector.finish(started_at.elapsed().as_millis() as u64));
+                    usage.add(&turn_usage);
+                    if fallback_reason.is_none() {
+                        fallback_reason = turn_fallback_reason;
+                    }
+                }
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+        if turns.len() > 1 {
+            report.turns_per_file = turns_per_file(&request.agent_config.workspace, &turns);
+        }
+
+        // Collect the edits while files are still UTF-8 as the agent wrote them
+        let patch_path = self.finish_patch(&request, &request_id, &mut warnings, &notifier);
+        let backup = self.backups.lock().unwrap().remove(&request_id);
+        line_endings::restore_line_endings(&original_endings);
+        text_encoding::restore_encodings(&transcoded);
+        if let Some((copy, original)) = patch_copy {
+            // The session's working directory goes with the copy
+            if let Some(session_id) = &session_id {
+                if let Err(e) = self.agent_manager.delete_session(session_id).await {
+                    warn!("Failed to delete patch mode session {}: {}", session_id, e);
+                }
+            }
+            for change in &mut report.file_changes {
+                change.path = copy
+                    .to_workspace(std::path::Path::new(&change.path))
+                    .display()
+                    .to_string();
+            }
+            request = original;
+        }
+
+        drop(active_request);
+        self.agent_manager.stats().request_finished(
+            failure.is_none() && session_id.is_some(),
+            &report,
+            started_at.elapsed().as_millis() as u64,
+        );
+
+        match (failure, session_id) {
+            (None, Some(session_id)) => {
+                report.record_encodings(&transcoded);
+                report.record_line_endings(&original_endings);
+                report.patch_path = patch_path.map(|path| path.display().to_string());
+                if let Some(backup) = &backup {
+                    report.backup_dir = backup.dir().map(|dir| dir.display().to_string());
+                    report.backup_commit = backup.commit().map(str::to_string);
+                }
+                self.completed_backups.record(&request_id, backup);
+                self.completed_fixes
+                    .record(&request_id, &request, &session_id, &report);
+                if self.base_config.fix_cache_ttl_secs.is_some() {
+                    // Edits are cached under the model that made them
+                    let model = match (&fallback_reason, &fallback_model) {
+                        (Some(_), Some(fallback)) => fallback,
+                        _ => self.primary_model(&request),
+                    };
+                    for (incident_id, edits) in fixed_edits {
+                        if let Some(incident) = fingerprints
+                            .iter()
+                            .find(|fingerprint| fingerprint.incident_id == incident_id)
+                        {
+                            self.fix_cache.record(model, &incident.fingerprint, edits);
+                        }
+                    }
+                }
                 info!(
                     "Generate fix request {} completed successfully with session {}",
                     request_id, session_id
                 );
 
-                // Clean up active request
-                {
-                    let mut active = self.active_requests.write().await;
-                    active.remove(&request_id);
-                }
-
                 Ok(GenerateFixResponse {
                     request_id,
                     session_id,
                     created_at: chrono::Utc::now().to_rfc3339(),
+                    filtered_incidents,
+                    trimmed_incidents,
+                    truncated_incidents,
+                    reduced_for_context_length,
+                    used_fallback: fallback_reason.is_some(),
+                    fallback_reason,
+                    warnings,
+                    cache_hits,
+                    report,
+                    usage,
+                    fingerprints,
+                    resolved_fingerprints,
                 })
             }
-            Err(e) => {
+            (failure, _) => {
+                // A failed request may still have modified files worth rolling back
+                self.completed_backups.record(&request_id, backup);
+                let e = failure.unwrap_or_else(|| {
+                    crate::KaiakError::Internal("Request ran no model turns".to_string())
+                });
                 error!(
                     "Failed to initiate generate fix request {}: {}",
                     request_id, e
                 );
+                Err(e)
+            }
+        }
+    }
 
-                // Remove from active requests on failure
-                {
-                    let mut active = self.active_requests.write().await;
-                    active.remove(&request_id);
-                }
+    /// The applied edits ledger of the request's workspace with the file it is kept in, when
+    /// `applied_edits_dir` is set. Patch mode requests never write the workspace, so they
+    /// keep none.
+    pub(super) fn applied_edits(
+        &self,
+        request: &GenerateFixRequest,
+    ) -> Option<(std::path::PathBuf, AppliedEdits)> {
+        let dir = self.base_config.applied_edits_dir.as_ref()?;
+        if request.apply_mode != ApplyMode::InPlace {
+            return None;
+        }
+        let path = AppliedEdits::path_in(dir, &request.agent_config.workspace);
+        match AppliedEdits::load(&path) {
+            Ok(applied) => Some((path, applied)),
+            Err(e) => {
+                warn!("Not skipping edits applied by earlier runs: {}", e);
+                None
+            }
+        }
+    }
 
-                Err(e)
+    /// Report `incidents` fixed by edits an earlier run applied, streaming their status
+    pub(super) fn already_applied(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        incidents: &[MigrationIncident],
+        source: FixSource,
+    ) -> MigrationReport {
+        for incident in incidents {
+            self.send_incident_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::IncidentStatus,
+                Some(incident.id.clone()),
+                serde_json::json!({ "outcome": IncidentOutcome::Fixed, "already_applied": true }),
+            );
+        }
+        MigrationReport::already_applied(incidents, source)
+    }
+
+    /// The session fixes applied without the model run in, creating it when the request
+    /// has none; `None` when it can't be created, leaving the model turns to report it
+    pub(super) async fn suggestion_session(
+        &self,
+        request: &GenerateFixRequest,
+        session_id: Option<&str>,
+    ) -> Option<String> {
+        let session_id = session_id.or(request.session_id.as_deref());
+        match self
+            .agent_manager
+            .get_or_create_session(session_id, &request.agent_config)
+            .await
+        {
+            Ok(session_info) => {
+                tracing::Span::current().record("session_id", session_info.session.id.as_str());
+                Some(session_info.session.id)
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to get or create session for fixes without the model: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Stream a text editor edit found without the model as a `tool_call` event and decide
+    /// it like one of the model's edits. Returns whether it may be applied.
+    pub(super) async fn propose_edit(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        incident_id: &str,
+        id: &str,
+        arguments: &serde_json::Map<String, serde_json::Value>,
+        source: &str,
+    ) -> bool {
+        self.send_incident_notification(
+            notifier,
+            request_id,
+            session_id,
+            GenerateFixDataKind::ToolCall,
+            Some(incident_id.to_string()),
+            serde_json::json!({
+                "id": id,
+                "tool_name": SUGGESTION_TOOL,
+                "arguments": arguments,
+                "source": source,
+            }),
+        );
+
+        let (mut confirmation, reason) = self
+            .decide_tool_call(
+                notifier,
+                request_id,
+                session_id,
+                id,
+                SUGGESTION_TOOL,
+                arguments,
+                &None,
+            )
+            .await;
+        self.record_tool_decision(
+            notifier,
+            request_id,
+            session_id,
+            id,
+            SUGGESTION_TOOL,
+            arguments,
+            &mut confirmation,
+            reason,
+        )
+        .await
+    }
+
+    /// Build one turn's prompt and run it, retrying with less context if it exceeded the
+    /// model's context length and on the fallback model if the request's model failed.
+    /// Returns the outcome with the fallback reason, the prompt used, and whether it was
+    /// reduced.
+    async fn run_turn(
+        &self,
+        request_id: &str,
+        request: &GenerateFixRequest,
+        builder: &PromptBuilder,
+        context_lines: usize,
+        fallback_model: Option<&crate::models::configuration::ModelConfig>,
+        warnings: &mut Vec<Warning>,
+        notifier: &NotificationSender,
+    ) -> (
+        KaiakResult<(
+            (String, MigrationReportCollector, TokenUsage),
+            Option<String>,
+        )>,
+        FixPrompt,
+        bool,
+    ) {
+        let prompt = builder
+            .clone()
+            .with_max_prompt_tokens(request.max_prompt_tokens)
+            .build(&request.incidents);
+        if !prompt.truncated_incident_ids.is_empty() {
+            warn!(
+                "Truncated text of {} incidents to {} characters",
+                prompt.truncated_incident_ids.len(),
+                self.base_config.incident_text_limit
+            );
+        }
+        if !prompt.trimmed_incident_ids.is_empty() {
+            warn!(
+                "Trimmed {} incidents to fit prompt budget of {:?} tokens",
+                prompt.trimmed_incident_ids.len(),
+                request.max_prompt_tokens
+            );
+        }
+        self.record_warnings(
+            warnings,
+            prompt_warnings(&prompt),
+            request,
+            request_id,
+            notifier,
+        );
+
+        let attempt_session = &std::sync::Mutex::new(None);
+        let (result, prompt, reduced_for_context_length) = retry_on_context_length(
+            prompt,
+            |prompt| {
+                // Halve the budget so lowest-severity incidents are dropped, and show less code
+                builder
+                    .clone()
+                    .with_context_lines(context_lines / 2)
+                    .with_max_prompt_tokens(Some(estimate_tokens(&prompt.text) / 2))
+                    .build(&request.incidents)
+            },
+            |prompt| async move {
+                let prompt = &prompt;
+                run_with_fallback(fallback_model, |model| async move {
+                    self.initiate_agent_processing(
+                        request_id,
+                        request,
+                        prompt,
+                        notifier,
+                        model.as_ref(),
+                        attempt_session,
+                    )
+                    .await
+                })
+                .await
+            },
+        )
+        .await;
+        if reduced_for_context_length {
+            let mut reduced = vec![Warning::new(
+                "prompt_reduced",
+                "The prompt exceeded the model's context length and was retried with less context",
+            )];
+            reduced.extend(prompt_warnings(&prompt));
+            self.record_warnings(warnings, reduced, request, request_id, notifier);
+        }
+        (result, prompt, reduced_for_context_length)
+    }
+
+    /// Fingerprint the request's incidents, updating workspace history when tracking is on.
+    /// History errors are logged rather than failing the fix.
+    fn fingerprint_request(
+        &self,
+        request: &GenerateFixRequest,
+    ) -> (Vec<IncidentFingerprint>, Vec<String>) {
+        let workspace = &request.agent_config.workspace;
+        let mut fingerprints = fingerprint::fingerprint_incidents(&request.incidents, workspace);
+        if !request.track_fingerprints {
+            return (fingerprints, Vec::new());
+        }
+
+        let tracked = FingerprintHistory::default_path(workspace).and_then(|path| {
+            let mut history = FingerprintHistory::load(&path)?;
+            let resolved = history.update(&mut fingerprints);
+            history.save(&path)?;
+            Ok(resolved)
+        });
+
+        match tracked {
+            Ok(resolved) => {
+                info!(
+                    "Incident fingerprints: {:?}, {} resolved",
+                    fingerprint::status_counts(&fingerprints),
+                    resolved.len()
+                );
+                (fingerprints, resolved)
             }
+            Err(e) => {
+                warn!("Failed to track incident fingerprints: {}", e);
+                (fingerprints, Vec::new())
+            }
+        }
+    }
+
+    /// Absolute paths of the files the request's incidents point at, without duplicates
+    fn incident_paths(
+        request: &GenerateFixRequest,
+    ) -> std::collections::BTreeSet<std::path::PathBuf> {
+        let workspace = &request.agent_config.workspace;
+        request
+            .incidents
+            .iter()
+            .map(|incident| resolve_incident_path(workspace, incident))
+            .collect()
+    }
+
+    /// Write a `patch` mode request's edits to its patch file. Returns the patch path; on
+    /// failure the edits are discarded with the workspace copy, with a warning.
+    fn finish_patch(
+        &self,
+        request: &GenerateFixRequest,
+        request_id: &str,
+        warnings: &mut Vec<Warning>,
+        notifier: &NotificationSender,
+    ) -> Option<std::path::PathBuf> {
+        let recorder = self.patch_recorders.lock().unwrap().remove(request_id)?;
+        let path = request
+            .patch_path
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join(format!("kaiak-{}.patch", request_id)));
+        match recorder.finish(&path) {
+            Ok(_) => Some(path),
+            Err(e) => {
+                error!("Failed to write patch {}: {}", path.display(), e);
+                let warning = Warning::new(
+                    "patch_failed",
+                    format!(
+                        "Could not write patch {}: {}; the changes were discarded",
+                        path.display(),
+                        e
+                    ),
+                );
+                self.record_warnings(warnings, vec![warning], request, request_id, notifier);
+                None
+            }
+        }
+    }
+
+    /// Remember a file a `patch` mode request is about to modify, before the tool runs.
+    /// Only `patch` mode requests have a recorder, kept until the request finishes even
+    /// when it is cancelled and no longer active.
+    fn record_patch_target(&self, request_id: &str, arguments: &rmcp::model::JsonObject) {
+        if !file_guard::is_modifying_call(arguments) {
+            return;
+        }
+        if let Some(recorder) = self.patch_recorders.lock().unwrap().get_mut(request_id) {
+            if let Some(target) = resolve_tool_path(recorder.workspace(), arguments) {
+                recorder.record(&target);
+            }
+        }
+    }
+
+    /// Back up the file an approved tool call is about to modify, when backups are enabled
+    async fn backup_target(
+        &self,
+        request_id: &str,
+        arguments: &rmcp::model::JsonObject,
+    ) -> KaiakResult<()> {
+        if !self.backups.lock().unwrap().contains_key(request_id) {
+            return Ok(());
+        }
+        let target = {
+            let active = self.active_requests.read().await;
+            let Some(request) = active.get(request_id) else {
+                return Ok(());
+            };
+            resolve_tool_path(&request.agent_config.workspace, arguments)
+        };
+        match (target, self.backups.lock().unwrap().get_mut(request_id)) {
+            (Some(target), Some(backup)) => backup.backup(&target),
+            _ => Ok(()),
         }
     }
 
     /// Send a typed notification to the client
-    fn send_notification(
+    pub(super) fn send_notification(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        kind: GenerateFixDataKind,
+        payload: serde_json::Value,
+    ) {
+        self.send_incident_notification(notifier, request_id, session_id, kind, None, payload);
+    }
+
+    /// Add warnings not yet collected, streaming them when the request asked for it
+    fn record_warnings(
+        &self,
+        warnings: &mut Vec<Warning>,
+        new: Vec<Warning>,
+        request: &GenerateFixRequest,
+        request_id: &str,
+        notifier: &NotificationSender,
+    ) {
+        for warning in new {
+            if warnings.contains(&warning) {
+                continue;
+            }
+            if request.stream_warnings {
+                let mut params = serde_json::to_value(&warning).unwrap_or_default();
+                params["request_id"] = serde_json::Value::String(request_id.to_string());
+                if let Err(e) = notifier.send(JsonRpcNotification::new(WARNING, Some(params))) {
+                    warn!("Failed to send notification: {}", e);
+                }
+            }
+            warnings.push(warning);
+        }
+    }
+
+    /// Stream the usage of one model turn along with the request's running total
+    fn send_usage(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        turn: TokenUsage,
+        total: TokenUsage,
+    ) {
+        let params = serde_json::json!({
+            "request_id": request_id,
+            "session_id": session_id,
+            "turn": turn,
+            "total": total,
+        });
+        if let Err(e) = notifier.send(JsonRpcNotification::new(USAGE, Some(params))) {
+            warn!("Failed to send notification: {}", e);
+        }
+    }
+
+    /// Send a notification attributed to one of the request's incidents
+    pub(super) fn send_incident_notification(
         &self,
         notifier: &NotificationSender,
         request_id: &str,
         session_id: &str,
         kind: GenerateFixDataKind,
+        incident_id: Option<String>,
         payload: serde_json::Value,
     ) {
+        let verbosity = self
+            .verbosities
+            .lock()
+            .unwrap()
+            .get(request_id)
+            .copied()
+            .unwrap_or_default();
+        if !verbosity.streams(&kind) {
+            return;
+        }
+
         let data = GenerateFixData {
             request_id: request_id.to_string(),
             session_id: session_id.to_string(),
             kind,
+            incident_id,
             payload,
         };
 
@@ -220,7 +1257,8 @@ impl GenerateFixHandler {
         }
     }
 
-    /// Send user interaction notification and wait for response
+    /// Decide a tool call, asking the client when needed, and forward the decision to the
+    /// agent. Decisions on file modifications are audited under the client's identity.
     async fn handle_tool_confirmation(
         &self,
         notifier: &NotificationSender,
@@ -232,12 +1270,307 @@ impl GenerateFixHandler {
         arguments: &rmcp::model::JsonObject,
         prompt: &Option<String>,
     ) {
-        // Register that we're waiting for this confirmation
-        let rx = self
-            .interaction_manager
-            .register_confirmation(id.to_string())
+        let (mut confirmation, reason) = self
+            .decide_tool_call(
+                notifier, request_id, session_id, id, tool_name, arguments, prompt,
+            )
             .await;
-
+        self.record_tool_decision(
+            notifier,
+            request_id,
+            session_id,
+            id,
+            tool_name,
+            arguments,
+            &mut confirmation,
+            reason,
+        )
+        .await;
+
+        agent
+            .handle_confirmation(id.to_string(), confirmation)
+            .await;
+    }
+
+    /// Audit a decision on a file-modifying tool call, and back up and note an allowed
+    /// call's file for `patch` mode. A call whose file cannot be backed up is denied.
+    /// Returns whether the call was allowed.
+    async fn record_tool_decision(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        id: &str,
+        tool_name: &str,
+        arguments: &rmcp::model::JsonObject,
+        confirmation: &mut PermissionConfirmation,
+        mut reason: &str,
+    ) -> bool {
+        let mut allowed = matches!(
+            confirmation.permission,
+            Permission::AllowOnce | Permission::AlwaysAllow
+        );
+        if file_guard::is_modifying_call(arguments) {
+            if allowed {
+                if let Err(e) = self.backup_target(request_id, arguments).await {
+                    warn!("Denying tool call {} ({}): {}", id, tool_name, e);
+                    self.send_notification(
+                        notifier,
+                        request_id,
+                        session_id,
+                        GenerateFixDataKind::Error,
+                        serde_json::json!({
+                            "error": "backup_failed",
+                            "message": e.to_string(),
+                            "id": id,
+                            "tool_name": tool_name,
+                        }),
+                    );
+                    confirmation.permission = Permission::DenyOnce;
+                    allowed = false;
+                    reason = "backup_failed";
+                }
+            }
+            if allowed {
+                self.record_patch_target(request_id, arguments);
+            }
+            self.audit_log.record(&AuditEntry {
+                timestamp: chrono::Utc::now().to_rfc3339(),
+                identity: self.identity.get(),
+                request_id: request_id.to_string(),
+                session_id: session_id.to_string(),
+                tool_call_id: id.to_string(),
+                tool_name: tool_name.to_string(),
+                path: arguments
+                    .get("path")
+                    .and_then(|path| path.as_str())
+                    .map(str::to_string),
+                decision: if allowed {
+                    AuditDecision::Allowed
+                } else {
+                    AuditDecision::Denied
+                },
+                reason: reason.to_string(),
+            });
+        }
+        allowed
+    }
+
+    /// Send user interaction notification and wait for response.
+    /// Returns the decision and what made it.
+    async fn decide_tool_call(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        id: &str,
+        tool_name: &str,
+        arguments: &rmcp::model::JsonObject,
+        prompt: &Option<String>,
+    ) -> (PermissionConfirmation, &'static str) {
+        // File guards apply regardless of what the user would answer
+        if let Some(violation) = self
+            .guard_tool_call(request_id, session_id, arguments)
+            .await
+        {
+            warn!("Denying tool call {} ({}): {}", id, tool_name, violation);
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::Error,
+                serde_json::json!({
+                    "error": violation.code(),
+                    "message": violation.to_string(),
+                    "id": id,
+                    "tool_name": tool_name,
+                    "path": violation.path(),
+                }),
+            );
+            let confirmation = PermissionConfirmation {
+                principal_type: PrincipalType::Tool,
+                permission: Permission::DenyOnce,
+            };
+            return (confirmation, violation.code());
+        }
+
+        // A read-only server can't let through a tool that may write files without naming
+        // them, whatever the user or the tool permissions would allow
+        if file_guard::modifies_unnamed_files(tool_name) && self.base_config.read_only {
+            warn!(
+                "Denying tool call {} ({}): the server is read-only",
+                id, tool_name
+            );
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::Error,
+                serde_json::json!({
+                    "error": "read_only",
+                    "message": format!(
+                        "Refusing to run tool '{}', which may modify files: the server is read-only",
+                        tool_name
+                    ),
+                    "id": id,
+                    "tool_name": tool_name,
+                }),
+            );
+            let confirmation = PermissionConfirmation {
+                principal_type: PrincipalType::Tool,
+                permission: Permission::DenyOnce,
+            };
+            return (confirmation, "read_only");
+        }
+
+        // A patch only holds the files recorded before they change, which a tool that
+        // does not name its files can't give
+        if file_guard::modifies_unnamed_files(tool_name)
+            && self
+                .patch_recorders
+                .lock()
+                .unwrap()
+                .contains_key(request_id)
+        {
+            warn!(
+                "Denying tool call {} ({}): not allowed in patch mode",
+                id, tool_name
+            );
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::Error,
+                serde_json::json!({
+                    "error": "untracked_modification",
+                    "message": format!(
+                        "Tool '{}' may modify files the patch can't track; patch mode only \
+                         allows modifications through tools that name their file",
+                        tool_name
+                    ),
+                    "id": id,
+                    "tool_name": tool_name,
+                }),
+            );
+            let confirmation = PermissionConfirmation {
+                principal_type: PrincipalType::Tool,
+                permission: Permission::DenyOnce,
+            };
+            return (confirmation, "untracked_modification");
+        }
+
+        // Configured tool permissions; unlisted tools follow default_tool_permission, and a
+        // session's own permissions may make them stricter
+        let permission = self
+            .agent_manager
+            .session_tool_permission(
+                session_id,
+                tool_name,
+                self.base_config.tool_permission(tool_name),
+            )
+            .await;
+        match permission {
+            PermissionLevel::NeverAllow => {
+                warn!(
+                    "Denying tool call {} ({}): tool is not permitted",
+                    id, tool_name
+                );
+                self.send_notification(
+                    notifier,
+                    request_id,
+                    session_id,
+                    GenerateFixDataKind::Error,
+                    serde_json::json!({
+                        "error": "tool_not_permitted",
+                        "message": format!("Tool '{}' is not permitted by the server", tool_name),
+                        "id": id,
+                        "tool_name": tool_name,
+                    }),
+                );
+                let confirmation = PermissionConfirmation {
+                    principal_type: PrincipalType::Tool,
+                    permission: Permission::DenyOnce,
+                };
+                return (confirmation, "tool_permission");
+            }
+            PermissionLevel::AlwaysAllow => {
+                info!(
+                    "Allowing tool call {} ({}) by tool permission",
+                    id, tool_name
+                );
+                let confirmation = PermissionConfirmation {
+                    principal_type: PrincipalType::Tool,
+                    permission: Permission::AllowOnce,
+                };
+                return (confirmation, "tool_permission");
+            }
+            PermissionLevel::AskBefore => {}
+        }
+
+        // Low-risk incidents may skip the approval round-trip when the server allows it
+        if let Some(incident_id) = self.auto_apply_incident(request_id, arguments).await {
+            info!(
+                "Auto-applying tool call {} ({}) for incident {}",
+                id, tool_name, incident_id
+            );
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::System,
+                serde_json::json!({
+                    "event": "auto_applied",
+                    "id": id,
+                    "tool_name": tool_name,
+                    "incident_id": incident_id,
+                }),
+            );
+            let confirmation = PermissionConfirmation {
+                principal_type: PrincipalType::Tool,
+                permission: Permission::AllowOnce,
+            };
+            return (confirmation, "auto_apply");
+        }
+
+        // An earlier approval in this request may already cover the call
+        let approval_key = self.approval_key(request_id, tool_name, arguments).await;
+        if let Some(key) = &approval_key {
+            let approved = self
+                .approvals
+                .lock()
+                .unwrap()
+                .get(request_id)
+                .is_some_and(|keys| keys.contains(key));
+            if approved {
+                info!(
+                    "Allowing tool call {} ({}) by earlier approval",
+                    id, tool_name
+                );
+                self.send_notification(
+                    notifier,
+                    request_id,
+                    session_id,
+                    GenerateFixDataKind::System,
+                    serde_json::json!({
+                        "event": "approval_reused",
+                        "id": id,
+                        "tool_name": tool_name,
+                    }),
+                );
+                let confirmation = PermissionConfirmation {
+                    principal_type: PrincipalType::Tool,
+                    permission: Permission::AllowOnce,
+                };
+                return (confirmation, "approval_cache");
+            }
+        }
+
+        // Register that we're waiting for this confirmation
+        let rx = self
+            .interaction_manager
+            .register_confirmation(id.to_string())
+            .await;
+
         // Send notification to client
         let payload = UserInteractionPayload::ToolConfirmation {
             id: id.to_string(),
@@ -246,44 +1579,170 @@ impl GenerateFixHandler {
             prompt: prompt.clone(),
         };
 
-        self.send_notification(
+        let incident_id = self.incident_for_tool(request_id, arguments).await;
+        self.send_incident_notification(
             notifier,
             request_id,
             session_id,
             GenerateFixDataKind::UserInteraction,
+            incident_id,
             serde_json::to_value(&payload).unwrap_or_default(),
         );
 
         // Wait for client response (with timeout)
-        let confirmation = match tokio::time::timeout(
-            Duration::from_secs(INTERACTION_TIMEOUT_SECS),
-            rx,
-        )
-        .await
-        {
+        match tokio::time::timeout(Duration::from_secs(INTERACTION_TIMEOUT_SECS), rx).await {
             Ok(Ok(confirmation)) => {
                 debug!("Received tool confirmation for {}: {:?}", id, confirmation);
-                confirmation
+                let allowed = matches!(
+                    confirmation.permission,
+                    Permission::AllowOnce | Permission::AlwaysAllow
+                );
+                if let (true, Some(key)) = (allowed, approval_key) {
+                    self.approvals
+                        .lock()
+                        .unwrap()
+                        .entry(request_id.to_string())
+                        .or_default()
+                        .insert(key);
+                }
+                (confirmation, "user")
             }
             Ok(Err(_)) => {
                 warn!("Tool confirmation channel closed for {}, denying", id);
-                PermissionConfirmation {
+                let confirmation = PermissionConfirmation {
                     principal_type: PrincipalType::Tool,
                     permission: Permission::DenyOnce,
-                }
+                };
+                (confirmation, "confirmation_closed")
             }
             Err(_) => {
                 warn!("Tool confirmation timeout for {}, denying", id);
                 self.interaction_manager.cancel_confirmation(id).await;
-                PermissionConfirmation {
+                let confirmation = PermissionConfirmation {
                     principal_type: PrincipalType::Tool,
                     permission: Permission::DenyOnce,
+                };
+                (confirmation, "confirmation_timeout")
+            }
+        }
+    }
+
+    /// What an approval of a tool call covers under the request's approval cache policy;
+    /// None when every call must be asked. Modifications are only ever covered for the
+    /// same file, and shell commands, which may touch any file, are always asked.
+    async fn approval_key(
+        &self,
+        request_id: &str,
+        tool_name: &str,
+        arguments: &rmcp::model::JsonObject,
+    ) -> Option<String> {
+        if file_guard::modifies_unnamed_files(tool_name) {
+            return None;
+        }
+
+        let active = self.active_requests.read().await;
+        let request = active.get(request_id)?;
+        let workspace = &request.agent_config.workspace;
+        match request
+            .approval_cache
+            .unwrap_or(self.base_config.approval_cache)
+        {
+            ApprovalCachePolicy::AlwaysAsk => None,
+            ApprovalCachePolicy::AskOncePerFile => match resolve_tool_path(workspace, arguments) {
+                Some(path) => {
+                    let access = if file_guard::is_modifying_call(arguments) {
+                        "modify"
+                    } else {
+                        "read"
+                    };
+                    Some(format!("{} {} {}", tool_name, access, path.display()))
+                }
+                None => Some(format!(
+                    "{} {}",
+                    tool_name,
+                    serde_json::to_string(arguments).unwrap_or_default()
+                )),
+            },
+            ApprovalCachePolicy::AskOncePerRequest => {
+                if file_guard::is_modifying_call(arguments) {
+                    let path = resolve_tool_path(workspace, arguments)?;
+                    Some(format!("{} modify {}", tool_name, path.display()))
+                } else {
+                    Some(tool_name.to_string())
                 }
             }
-        };
+        }
+    }
+
+    /// Find the `auto_apply` incident a tool call targets, if the server permits auto-apply.
+    /// Returns the incident ID when the tool's `path` argument matches the incident's file.
+    async fn auto_apply_incident(
+        &self,
+        request_id: &str,
+        arguments: &rmcp::model::JsonObject,
+    ) -> Option<String> {
+        if !self.base_config.allow_incident_auto_apply {
+            return None;
+        }
+
+        let active = self.active_requests.read().await;
+        let request = active.get(request_id)?;
+        let workspace = &request.agent_config.workspace;
+        let target = resolve_tool_path(workspace, arguments)?;
 
-        // Forward to agent
-        agent.handle_confirmation(id.to_string(), confirmation).await;
+        request
+            .incidents
+            .iter()
+            .find(|incident| {
+                incident.auto_apply && resolve_incident_path(workspace, incident) == target
+            })
+            .map(|incident| incident.id.clone())
+    }
+
+    /// The first incident, in request order, whose file a tool call's `path` argument targets
+    async fn incident_for_tool(
+        &self,
+        request_id: &str,
+        arguments: &rmcp::model::JsonObject,
+    ) -> Option<String> {
+        let active = self.active_requests.read().await;
+        let request = active.get(request_id)?;
+        let workspace = &request.agent_config.workspace;
+        let target = resolve_tool_path(workspace, arguments)?;
+
+        request
+            .incidents
+            .iter()
+            .find(|incident| resolve_incident_path(workspace, incident) == target)
+            .map(|incident| incident.id.clone())
+    }
+
+    /// Check a file-modifying tool call against the file guards
+    async fn guard_tool_call(
+        &self,
+        request_id: &str,
+        session_id: &str,
+        arguments: &rmcp::model::JsonObject,
+    ) -> Option<FileGuardViolation> {
+        if !file_guard::is_modifying_call(arguments) {
+            return None;
+        }
+
+        let workspace = {
+            let active = self.active_requests.read().await;
+            active.get(request_id)?.agent_config.workspace.clone()
+        };
+        let target = resolve_tool_path(&workspace, arguments)?;
+        if self.base_config.read_only {
+            return Some(FileGuardViolation::ReadOnly(target));
+        }
+        let scratch_dir = self.agent_manager.scratch_dir(session_id);
+        if let Some(violation) =
+            file_guard::check_location(&target, &workspace, scratch_dir.as_deref())
+        {
+            return Some(violation);
+        }
+        file_guard::check_modification(&target, &workspace, &self.base_config.protected_paths)
     }
 
     /// Handle elicitation request
@@ -354,24 +1813,61 @@ impl GenerateFixHandler {
         active.len()
     }
 
+    /// The session an attempt at a turn runs in: the request's, or a new one. A retry runs
+    /// in the session of the attempt before it, recorded in `attempt_session`, cleared of
+    /// the history that attempt left, so retries neither leave sessions behind nor resend
+    /// the prompt that failed.
+    async fn attempt_session(
+        &self,
+        request: &GenerateFixRequest,
+        agent_config: &AgentConfig,
+        attempt_session: &std::sync::Mutex<Option<String>>,
+    ) -> KaiakResult<crate::agent::SessionInfo> {
+        let retried = attempt_session.lock().unwrap().clone();
+        if let Some(session_id) = &retried {
+            info!("Clearing session {} before retrying in it", session_id);
+            self.agent_manager.reset_session_context(session_id).await?;
+        }
+        let session_id = retried.as_deref().or(request.session_id.as_deref());
+        let session_info = self
+            .agent_manager
+            .get_or_create_session(session_id, agent_config)
+            .await?;
+        *attempt_session.lock().unwrap() = Some(session_info.session.id.clone());
+        Ok(session_info)
+    }
+
     /// Initiate agent processing with Goose session management
     async fn initiate_agent_processing(
         &self,
         request_id: &str,
         request: &GenerateFixRequest,
+        fix_prompt: &FixPrompt,
         notifier: &NotificationSender,
-    ) -> KaiakResult<String> {
+        fallback_model: Option<&crate::models::configuration::ModelConfig>,
+        attempt_session: &std::sync::Mutex<Option<String>>,
+    ) -> KaiakResult<(String, MigrationReportCollector, TokenUsage)> {
         debug!("Initiating agent processing for request: {}", request_id);
+        let prompt = fix_prompt.text.as_str();
+        // Stream errors only fail the request when there is a fallback left to switch to
+        let can_fall_back = fallback_model.is_none() && self.fallback_model(request).is_some();
+        let fallback_config;
+        let agent_config = match fallback_model {
+            Some(model) => {
+                fallback_config = self.with_model(&request.agent_config, model);
+                &fallback_config
+            }
+            None => &request.agent_config,
+        };
 
         let session_info = match self
-            .agent_manager
-            .get_or_create_session(request.session_id.as_deref(), &request.agent_config)
+            .attempt_session(request, agent_config, attempt_session)
             .await
         {
             Ok(session_info) => {
                 debug!(
                     "Session ready for processing: {} (workspace: {:?})",
-                    session_info.session.id, request.agent_config.workspace
+                    session_info.session.id, agent_config.workspace
                 );
                 session_info
             }
@@ -382,6 +1878,21 @@ impl GenerateFixHandler {
         };
 
         let session_id = session_info.session.id.clone();
+        // Lets `kaiak/stream_logs` subscribers filter this request's lines by session
+        tracing::Span::current().record("session_id", session_id.as_str());
+        if let Some(model) = fallback_model {
+            self.send_notification(
+                notifier,
+                request_id,
+                &session_id,
+                GenerateFixDataKind::System,
+                serde_json::json!({
+                    "event": "model_fallback",
+                    "provider": model.provider,
+                    "model": model.model,
+                }),
+            );
+        }
 
         // Lock the session to prevent other requests from using it
         match self.agent_manager.lock_session(&session_id).await {
@@ -394,29 +1905,55 @@ impl GenerateFixHandler {
             }
         }
 
-        let (agent, session_config) = self
+        let (agent, session_config) = match self
             .agent_manager
-            .create_agent(&session_id, &request.agent_config)
-            .await?;
-
-        let incident_messages: Vec<String> =
-            request.incidents.iter().map(|i| i.message.clone()).collect();
-        let prompt = format!(
-            "We found migration issues identified by static analysis tools in the project. Help fix them. Here are the issues:{}{}",
-            if incident_messages.is_empty() { " (no incidents provided)" } else { "" },
-            if incident_messages.len() == 1 {
-                format!(" {}", incident_messages[0])
-            } else {
-                incident_messages
-                    .iter()
-                    .enumerate()
-                    .map(|(idx, msg)| format!("\n  {}. {}", idx + 1, msg))
-                    .collect::<String>()
+            .create_agent(&session_id, agent_config)
+            .await
+        {
+            Ok(created) => created,
+            Err(e) => {
+                if let Err(unlock_err) = self.agent_manager.unlock_session(&session_id).await {
+                    warn!("Failed to unlock session after error: {}", unlock_err);
+                }
+                return Err(e);
             }
+        };
+
+        // The prompt is the first new message of the session
+        let mut message_limit = MessageLimit::new(
+            self.base_config.max_messages_per_session,
+            session_info.session.message_count,
         );
+        if let Err(e) =
+            self.check_message_limit(notifier, request_id, &session_id, &mut message_limit)
+        {
+            if let Err(unlock_err) = self.agent_manager.unlock_session(&session_id).await {
+                warn!("Failed to unlock session after error: {}", unlock_err);
+            }
+            return Err(e);
+        }
 
-        let message = Message::user().with_text(&prompt);
+        // Held while the agent calls the model, so bursts across sessions queue here
+        let mut model_slot = Some(self.model_slot(notifier, request_id, &session_id).await);
+
+        let mut message = Message::user().with_text(prompt);
+        if let Some(dir) = self.agent_manager.scratch_dir(&session_id) {
+            message = message.with_text(scratch_dir_note(&dir));
+        }
+        let interceptor = self.agent_manager.model_interceptor().cloned();
+        let model_call = ModelCall {
+            request_id,
+            session_id: &session_id,
+            prompt,
+        };
+        if let Some(interceptor) = &interceptor {
+            interceptor.before_call(&model_call);
+        }
 
+        let mut usage = UsageTracker::new(
+            estimate_tokens(prompt) as u64,
+            self.agent_manager.token_counters(&session_id).await,
+        );
         let mut stream = match agent.reply(message, session_config.clone(), None).await {
             Ok(stream) => stream,
             Err(e) => {
@@ -424,17 +1961,69 @@ impl GenerateFixHandler {
                 if let Err(unlock_err) = self.agent_manager.unlock_session(&session_id).await {
                     warn!("Failed to unlock session after error: {}", unlock_err);
                 }
-                return Err(crate::KaiakError::agent(
-                    format!("Failed to reply to message: {}", e),
-                    None,
-                ));
+                return Err(agent_error(format!("Failed to reply to message: {}", e)));
             }
         };
 
+        let mut collector = MigrationReportCollector::new(&agent_config.workspace)
+            .with_incidents(&request.incidents, &fix_prompt.trimmed_incident_ids);
+        let stream_thinking = request
+            .stream_thinking
+            .unwrap_or(self.base_config.stream_thinking);
+
         // Process the stream
+        let mut failure = None;
+        let mut tool_output = ToolOutputBudget::default();
+        let mut progress = ProgressBuffer::new(self.base_config.progress_coalesce_ms);
+        let mut loop_detector = LoopDetector::new(&self.base_config.loop_detection);
+        let mut replied = false;
         while let Some(event) = futures::StreamExt::next(&mut stream).await {
+            // Held back progress goes out before tool results, errors and anything else
+            if !matches!(event, Ok(AgentEvent::McpNotification(_))) {
+                self.flush_tool_progress(notifier, request_id, &session_id, &mut progress);
+            }
             match event {
-                Ok(AgentEvent::Message(msg)) => {
+                Ok(AgentEvent::Message(mut msg)) => {
+                    replied = true;
+                    // Dropping the stream below stops the agent
+                    if let Err(e) = self.check_message_limit(
+                        notifier,
+                        request_id,
+                        &session_id,
+                        &mut message_limit,
+                    ) {
+                        failure = Some(e);
+                        break;
+                    }
+                    if let Err(e) = self.check_tool_loop(
+                        notifier,
+                        request_id,
+                        &session_id,
+                        &mut loop_detector,
+                        &msg,
+                    ) {
+                        failure = Some(e);
+                        break;
+                    }
+                    if let Some(interceptor) = &interceptor {
+                        interceptor.after_call(&model_call, &msg);
+                    }
+                    let (completion_tokens, input_tokens) = message_tokens(&msg);
+                    if completion_tokens > 0 {
+                        let reported = self.agent_manager.token_counters(&session_id).await;
+                        let turn = usage.record_turn(completion_tokens, reported);
+                        self.send_usage(notifier, request_id, &session_id, turn, usage.total());
+                    }
+                    usage.record_input(input_tokens);
+                    if !stream_thinking {
+                        drop_thinking(&mut msg);
+                    }
+                    // A confirmation may wait minutes on the user; other requests' model
+                    // calls need not wait with it
+                    let waits_on_client = awaits_interaction(&msg);
+                    if waits_on_client {
+                        drop(model_slot.take());
+                    }
                     self.process_message(
                         notifier,
                         request_id,
@@ -442,14 +2031,27 @@ impl GenerateFixHandler {
                         &agent,
                         &session_config,
                         &msg,
+                        &mut collector,
                     )
                     .await;
+                    if waits_on_client {
+                        model_slot = Some(self.model_slot(notifier, request_id, &session_id).await);
+                    }
                 }
                 Ok(AgentEvent::HistoryReplaced(_history)) => {
                     debug!("History replaced");
                 }
-                Ok(AgentEvent::McpNotification((_req_id, notif))) => {
+                Ok(AgentEvent::McpNotification((tool_request_id, notif))) => {
                     debug!("MCP notification: {:?}", notif);
+                    self.send_tool_progress(
+                        notifier,
+                        request_id,
+                        &session_id,
+                        &tool_request_id,
+                        &serde_json::to_value(&notif).unwrap_or_default(),
+                        &mut tool_output,
+                        &mut progress,
+                    );
                 }
                 Ok(AgentEvent::ModelChange { model, mode }) => {
                     debug!("Model change: {} ({})", model, mode);
@@ -465,6 +2067,19 @@ impl GenerateFixHandler {
                         }),
                     );
                 }
+                Err(e) if is_context_length_error(&e.to_string()) => {
+                    error!("Model context length exceeded: {}", e);
+                    failure = Some(agent_error(e.to_string()));
+                    break;
+                }
+                // Nothing was done with the primary model yet, so the fallback can start over
+                Err(e)
+                    if can_fall_back && !replied && !is_transient_model_error(&e.to_string()) =>
+                {
+                    error!("Model call failed before the agent replied: {}", e);
+                    failure = Some(agent_error(e.to_string()));
+                    break;
+                }
                 Err(e) => {
                     error!("Error getting stream event: {:?}", e);
                     self.send_notification(
@@ -479,83 +2094,341 @@ impl GenerateFixHandler {
                 }
             }
         }
+        self.flush_tool_progress(notifier, request_id, &session_id, &mut progress);
+        drop(model_slot);
 
         if let Err(unlock_err) = self.agent_manager.unlock_session(&session_id).await {
             warn!("Failed to unlock session after processing: {}", unlock_err);
         }
 
-        Ok(session_id)
+        match failure {
+            Some(e) => Err(e),
+            None => Ok((session_id, collector, usage.total())),
+        }
     }
 
-    /// Process a single message from the agent stream
-    async fn process_message(
+    /// Wait for a model call slot, telling the client when it has to wait
+    async fn model_slot(
         &self,
         notifier: &NotificationSender,
         request_id: &str,
         session_id: &str,
-        agent: &goose::agents::Agent,
-        session_config: &goose::agents::SessionConfig,
-        message: &Message,
-    ) {
-        for content in &message.content {
-            match content {
-                MessageContent::Text(text) => {
-                    self.send_notification(
-                        notifier,
-                        request_id,
-                        session_id,
-                        GenerateFixDataKind::AiMessage,
-                        serde_json::json!({
-                            "role": format!("{:?}", message.role),
-                            "text": text.text,
-                        }),
-                    );
-                }
+    ) -> crate::agent::ModelCallPermit {
+        let model_calls = self.agent_manager.model_calls();
+        model_calls
+            .acquire(|| {
+                info!("Waiting for a model call slot for request {}", request_id);
+                self.send_notification(
+                    notifier,
+                    request_id,
+                    session_id,
+                    GenerateFixDataKind::System,
+                    serde_json::json!({
+                        "event": "waiting_for_model_slot",
+                        "max_concurrent_model_calls": model_calls.limit(),
+                    }),
+                );
+            })
+            .await
+    }
 
-                MessageContent::Thinking(thinking) => {
-                    self.send_notification(
-                        notifier,
-                        request_id,
-                        session_id,
-                        GenerateFixDataKind::Thinking,
-                        serde_json::json!({
-                            "thinking": thinking.thinking,
-                        }),
-                    );
-                }
+    /// The request's model: from its override when it has one, else the server's
+    pub(super) fn primary_model<'a>(
+        &'a self,
+        request: &'a GenerateFixRequest,
+    ) -> &'a crate::models::configuration::ModelConfig {
+        &request
+            .agent_config
+            .effective_base_config(&self.base_config)
+            .model
+    }
 
-                MessageContent::ToolRequest(req) => {
-                    let tool_info = match &req.tool_call {
-                        Ok(call) => serde_json::json!({
-                            "id": req.id,
-                            "tool_name": call.name,
-                            "arguments": call.arguments,
-                        }),
-                        Err(e) => serde_json::json!({
-                            "id": req.id,
-                            "error": format!("{:?}", e),
-                        }),
-                    };
-                    self.send_notification(
-                        notifier,
-                        request_id,
-                        session_id,
-                        GenerateFixDataKind::ToolCall,
-                        tool_info,
-                    );
-                }
+    /// The request's fallback model: from its override when it has one, else the server's
+    fn fallback_model<'a>(
+        &'a self,
+        request: &'a GenerateFixRequest,
+    ) -> Option<&'a crate::models::configuration::ModelConfig> {
+        request
+            .agent_config
+            .effective_base_config(&self.base_config)
+            .fallback_model
+            .as_ref()
+    }
 
-                MessageContent::ToolResponse(resp) => {
-                    let result_info = match &resp.tool_result {
-                        Ok(result) => serde_json::json!({
-                            "id": resp.id,
-                            "is_error": result.is_error,
-                            "content_count": result.content.len(),
-                        }),
-                        Err(e) => serde_json::json!({
-                            "id": resp.id,
-                            "error": format!("{:?}", e),
-                        }),
+    /// The request's agent configuration with `model` in place of its model, and no further
+    /// fallback
+    fn with_model(
+        &self,
+        config: &AgentConfig,
+        model: &crate::models::configuration::ModelConfig,
+    ) -> AgentConfig {
+        let mut base_config = config.effective_base_config(&self.base_config).clone();
+        base_config.model = model.clone();
+        base_config.fallback_model = None;
+        AgentConfig {
+            override_base_config: Some(base_config),
+            ..config.clone()
+        }
+    }
+
+    /// Count one message against the session's cap. Once the cap is passed, streams a
+    /// `message_limit_exceeded` event and fails with `ResourceExhausted`.
+    fn check_message_limit(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        limit: &mut MessageLimit,
+    ) -> KaiakResult<()> {
+        let Some(cap) = limit.record() else {
+            return Ok(());
+        };
+        warn!(
+            "Session {} passed max_messages_per_session ({}); stopping request {}",
+            session_id, cap, request_id
+        );
+        self.send_notification(
+            notifier,
+            request_id,
+            session_id,
+            GenerateFixDataKind::System,
+            serde_json::json!({
+                "event": "message_limit_exceeded",
+                "max_messages_per_session": cap,
+                "message_count": limit.count,
+            }),
+        );
+        Err(crate::KaiakError::ResourceExhausted(format!(
+            "Session {} exceeded max_messages_per_session ({})",
+            session_id, cap
+        )))
+    }
+
+    /// Add a tool result's content to its `tool_response` payload, or a `result_ref` to the
+    /// file it was spilled to when it is larger than `tool_result_spill_bytes`
+    fn attach_tool_result<T: Serialize>(
+        &self,
+        session_id: &str,
+        info: &mut serde_json::Value,
+        content: &T,
+    ) {
+        let Ok(content) = serde_json::to_value(content) else {
+            return;
+        };
+        let threshold = self.base_config.tool_result_spill_bytes;
+        let serialized = content.to_string();
+        if threshold == 0 || serialized.len() <= threshold {
+            info["content"] = content;
+            return;
+        }
+        match self.tool_results.spill(session_id, &serialized) {
+            Ok(result) => info["result_ref"] = serde_json::to_value(result).unwrap_or_default(),
+            // Streaming the content anyway would defeat the threshold
+            Err(e) => warn!(
+                "Tool result of {} bytes was not streamed: {}",
+                serialized.len(),
+                e
+            ),
+        }
+    }
+
+    /// Record the message's tool calls. When one repeats often enough to look like a loop,
+    /// streams a `loop_detected` event with the call and fails the request.
+    fn check_tool_loop(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        detector: &mut LoopDetector,
+        msg: &Message,
+    ) -> KaiakResult<()> {
+        for content in &msg.content {
+            let MessageContent::ToolRequest(req) = content else {
+                continue;
+            };
+            let Ok(call) = &req.tool_call else {
+                continue;
+            };
+            let Some(repetitions) = detector.record(&call.name, call.arguments.as_ref()) else {
+                continue;
+            };
+            warn!(
+                "Request {} called {} {} times with identical arguments; stopping it as a loop",
+                request_id, call.name, repetitions
+            );
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::System,
+                serde_json::json!({
+                    "event": "loop_detected",
+                    "tool_name": call.name,
+                    "arguments": call.arguments,
+                    "repetitions": repetitions,
+                    "window": detector.window,
+                }),
+            );
+            return Err(crate::KaiakError::agent(
+                format!(
+                    "Suspected agent loop: {} was called {} times with identical arguments within the last {} tool calls",
+                    call.name, repetitions, detector.window
+                ),
+                Some(format!("request {}", request_id)),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Stream an MCP notification from a running tool. Shell output is sent as
+    /// `{id, stream, output}` chunks, up to `MAX_STREAMED_TOOL_OUTPUT_BYTES` per tool call.
+    fn send_tool_progress(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        tool_request_id: &str,
+        notification: &serde_json::Value,
+        tool_output: &mut ToolOutputBudget,
+        progress: &mut ProgressBuffer,
+    ) {
+        let payload = match tool_output_chunk(notification) {
+            Some((stream, output)) => {
+                let Some((output, truncated)) = tool_output.take(tool_request_id, output) else {
+                    return;
+                };
+                let mut payload = serde_json::json!({
+                    "id": tool_request_id,
+                    "stream": stream,
+                    "output": output,
+                });
+                if truncated {
+                    payload["truncated"] = serde_json::Value::Bool(true);
+                }
+                payload
+            }
+            None => serde_json::json!({
+                "id": tool_request_id,
+                "notification": notification,
+            }),
+        };
+        for payload in progress.push(tool_request_id, payload) {
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::ToolProgress,
+                payload,
+            );
+        }
+    }
+
+    /// Send the `tool_progress` events held back by `progress_coalesce_ms`, so they arrive
+    /// before the request's next event
+    fn flush_tool_progress(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        progress: &mut ProgressBuffer,
+    ) {
+        for payload in progress.drain() {
+            self.send_notification(
+                notifier,
+                request_id,
+                session_id,
+                GenerateFixDataKind::ToolProgress,
+                payload,
+            );
+        }
+    }
+
+    /// Process a single message from the agent stream
+    async fn process_message(
+        &self,
+        notifier: &NotificationSender,
+        request_id: &str,
+        session_id: &str,
+        agent: &goose::agents::Agent,
+        session_config: &goose::agents::SessionConfig,
+        message: &Message,
+        collector: &mut MigrationReportCollector,
+    ) {
+        for content in &message.content {
+            match content {
+                MessageContent::Text(text) => {
+                    self.send_notification(
+                        notifier,
+                        request_id,
+                        session_id,
+                        GenerateFixDataKind::AiMessage,
+                        serde_json::json!({
+                            "role": format!("{:?}", message.role),
+                            "text": text.text,
+                        }),
+                    );
+                }
+
+                MessageContent::Thinking(thinking) => {
+                    self.send_notification(
+                        notifier,
+                        request_id,
+                        session_id,
+                        GenerateFixDataKind::Thinking,
+                        serde_json::json!({
+                            "thinking": thinking.thinking,
+                        }),
+                    );
+                }
+
+                MessageContent::ToolRequest(req) => {
+                    let mut incident_id = None;
+                    if let Ok(call) = &req.tool_call {
+                        collector.record_tool_request(&req.id, &call.name, call.arguments.as_ref());
+                        if let Some(arguments) = &call.arguments {
+                            self.record_patch_target(request_id, arguments);
+                            incident_id = self.incident_for_tool(request_id, arguments).await;
+                        }
+                    }
+                    let tool_info = match &req.tool_call {
+                        Ok(call) => serde_json::json!({
+                            "id": req.id,
+                            "tool_name": call.name,
+                            "arguments": call.arguments,
+                        }),
+                        Err(e) => serde_json::json!({
+                            "id": req.id,
+                            "error": format!("{:?}", e),
+                        }),
+                    };
+                    self.send_incident_notification(
+                        notifier,
+                        request_id,
+                        session_id,
+                        GenerateFixDataKind::ToolCall,
+                        incident_id,
+                        tool_info,
+                    );
+                }
+
+                MessageContent::ToolResponse(resp) => {
+                    let success =
+                        matches!(&resp.tool_result, Ok(result) if result.is_error != Some(true));
+                    let statuses = collector.record_tool_response(&resp.id, success);
+                    let result_info = match &resp.tool_result {
+                        Ok(result) => {
+                            let mut info = serde_json::json!({
+                                "id": resp.id,
+                                "is_error": result.is_error,
+                                "content_count": result.content.len(),
+                            });
+                            self.attach_tool_result(session_id, &mut info, &result.content);
+                            info
+                        }
+                        Err(e) => serde_json::json!({
+                            "id": resp.id,
+                            "error": format!("{:?}", e),
+                        }),
                     };
                     self.send_notification(
                         notifier,
@@ -564,6 +2437,17 @@ impl GenerateFixHandler {
                         GenerateFixDataKind::ToolResponse,
                         result_info,
                     );
+                    // The report is built from the same collector state, so it agrees with these
+                    for status in statuses {
+                        self.send_incident_notification(
+                            notifier,
+                            request_id,
+                            session_id,
+                            GenerateFixDataKind::IncidentStatus,
+                            Some(status.incident_id),
+                            serde_json::json!({ "outcome": status.outcome }),
+                        );
+                    }
                 }
 
                 MessageContent::ActionRequired(action) => match &action.data {
@@ -632,9 +2516,3362 @@ impl GenerateFixHandler {
         }
     }
 
+    /// Register a request as running, refusing an ID already in use so the running
+    /// request's state is never replaced. The request stays registered until the returned
+    /// guard is dropped.
+    async fn track_active_request(
+        &self,
+        request_id: &str,
+        request: &GenerateFixRequest,
+    ) -> KaiakResult<ActiveRequestGuard> {
+        let mut active = self.active_requests.write().await;
+        match active.entry(request_id.to_string()) {
+            std::collections::hash_map::Entry::Occupied(_) => {
+                warn!(
+                    "Request ID {} is already in use by an active request",
+                    request_id
+                );
+                Err(crate::KaiakError::RequestInUse(request_id.to_string()))
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(request.clone());
+                Ok(ActiveRequestGuard {
+                    request_id: request_id.to_string(),
+                    active_requests: self.active_requests.clone(),
+                    verbosities: self.verbosities.clone(),
+                    approvals: self.approvals.clone(),
+                })
+            }
+        }
+    }
+
     /// Cancel a generate fix request
     pub async fn cancel_request(&self, request_id: &str) -> KaiakResult<bool> {
+        self.verbosities.lock().unwrap().remove(request_id);
+        self.approvals.lock().unwrap().remove(request_id);
         let mut active = self.active_requests.write().await;
         Ok(active.remove(request_id).is_some())
     }
 }
+
+/// Removes a running request's state from its handler when dropped, so a request that
+/// panics does not leave it behind
+#[derive(Debug)]
+struct ActiveRequestGuard {
+    request_id: String,
+    active_requests: ActiveRequests,
+    verbosities: Arc<std::sync::Mutex<std::collections::HashMap<String, Verbosity>>>,
+    approvals:
+        Arc<std::sync::Mutex<std::collections::HashMap<String, std::collections::HashSet<String>>>>,
+}
+
+impl Drop for ActiveRequestGuard {
+    fn drop(&mut self) {
+        // A panic while a lock was held must not keep the state from being removed
+        self.verbosities
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(&self.request_id);
+        self.approvals
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(&self.request_id);
+        match self.active_requests.try_write() {
+            Ok(mut active) => {
+                active.remove(&self.request_id);
+            }
+            // Held by a reader; remove the request once it is released
+            Err(_) => {
+                let active_requests = self.active_requests.clone();
+                let request_id = std::mem::take(&mut self.request_id);
+                tokio::spawn(async move {
+                    active_requests.write().await.remove(&request_id);
+                });
+            }
+        }
+    }
+}
+
+/// Messages in a session, counted from its `message_count` when the request started
+#[derive(Debug)]
+struct MessageLimit {
+    cap: Option<usize>,
+    count: usize,
+}
+
+impl MessageLimit {
+    fn new(cap: Option<usize>, message_count: usize) -> Self {
+        Self {
+            cap,
+            count: message_count,
+        }
+    }
+
+    /// Count one more message; returns the cap once the count exceeds it
+    fn record(&mut self) -> Option<usize> {
+        self.count += 1;
+        self.cap.filter(|cap| self.count > *cap)
+    }
+}
+
+/// The most recent tool calls of a request, as (tool name, arguments hash)
+#[derive(Debug)]
+struct LoopDetector {
+    repetitions: usize,
+    window: usize,
+    recent: std::collections::VecDeque<(String, u64)>,
+}
+
+impl LoopDetector {
+    fn new(config: &crate::models::configuration::LoopDetection) -> Self {
+        Self {
+            repetitions: config.repetitions,
+            window: config.window,
+            recent: std::collections::VecDeque::with_capacity(config.window),
+        }
+    }
+
+    /// Record one tool call; returns how often it occurs in the window once that reaches
+    /// the configured repetitions
+    fn record(
+        &mut self,
+        tool_name: &str,
+        arguments: Option<&serde_json::Map<String, serde_json::Value>>,
+    ) -> Option<usize> {
+        if self.repetitions == 0 {
+            return None;
+        }
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        serde_json::to_string(&arguments)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        let call = (tool_name.to_string(), hasher.finish());
+
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(call);
+        let call = self.recent.back()?;
+        let count = self.recent.iter().filter(|recent| *recent == call).count();
+        (count >= self.repetitions).then_some(count)
+    }
+}
+
+/// Output bytes streamed so far for each tool call of a request
+#[derive(Debug, Default)]
+struct ToolOutputBudget {
+    /// `usize::MAX` once the call's output was cut off
+    streamed: std::collections::HashMap<String, usize>,
+}
+
+impl ToolOutputBudget {
+    /// The part of `output` that still fits the tool call's budget, and whether it was
+    /// cut short. Returns None once the call's output has been cut off.
+    fn take<'a>(&mut self, tool_request_id: &str, output: &'a str) -> Option<(&'a str, bool)> {
+        let streamed = self
+            .streamed
+            .entry(tool_request_id.to_string())
+            .or_insert(0);
+        if *streamed == usize::MAX {
+            return None;
+        }
+        let remaining = MAX_STREAMED_TOOL_OUTPUT_BYTES - *streamed;
+        if output.len() <= remaining {
+            *streamed += output.len();
+            return Some((output, false));
+        }
+        let mut end = remaining;
+        while !output.is_char_boundary(end) {
+            end -= 1;
+        }
+        *streamed = usize::MAX;
+        Some((&output[..end], true))
+    }
+}
+
+/// `tool_progress` payloads held back by `progress_coalesce_ms`
+#[derive(Debug, Default)]
+struct ProgressBuffer {
+    interval: Option<std::time::Duration>,
+    /// When each tool call's progress was last sent
+    sent: std::collections::HashMap<String, std::time::Instant>,
+    /// Latest held back payload of each tool call and output stream, in arrival order
+    pending: Vec<((String, Option<String>), serde_json::Value)>,
+}
+
+impl ProgressBuffer {
+    fn new(interval_ms: Option<u64>) -> Self {
+        Self {
+            interval: interval_ms.map(std::time::Duration::from_millis),
+            ..Self::default()
+        }
+    }
+
+    /// Payloads to send for a tool call's progress: none while its window is open, else
+    /// the held back ones and this one. Each output chunk only carries new output, so
+    /// held back chunks of a stream are joined instead of replaced.
+    fn push(
+        &mut self,
+        tool_request_id: &str,
+        payload: serde_json::Value,
+    ) -> Vec<serde_json::Value> {
+        let Some(interval) = self.interval else {
+            return vec![payload];
+        };
+        let stream = payload["stream"].as_str().map(str::to_string);
+        let key = (tool_request_id.to_string(), stream);
+        match self
+            .pending
+            .iter_mut()
+            .find(|(pending_key, _)| *pending_key == key)
+        {
+            Some((_, pending)) if key.1.is_some() => {
+                let output = format!(
+                    "{}{}",
+                    pending["output"].as_str().unwrap_or_default(),
+                    payload["output"].as_str().unwrap_or_default()
+                );
+                pending["output"] = serde_json::Value::String(output);
+                if payload.get("truncated").is_some() {
+                    pending["truncated"] = serde_json::Value::Bool(true);
+                }
+            }
+            Some((_, pending)) => *pending = payload,
+            None => self.pending.push((key, payload)),
+        }
+
+        let now = std::time::Instant::now();
+        let open = self
+            .sent
+            .get(tool_request_id)
+            .is_some_and(|sent| now.duration_since(*sent) < interval);
+        if open {
+            return Vec::new();
+        }
+        self.sent.insert(tool_request_id.to_string(), now);
+        let (due, held) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|((id, _), _)| id == tool_request_id);
+        self.pending = held;
+        due.into_iter().map(|(_, payload)| payload).collect()
+    }
+
+    /// Every held back payload, to send before any other event of the request
+    fn drain(&mut self) -> Vec<serde_json::Value> {
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(_, payload)| payload)
+            .collect()
+    }
+}
+
+/// Stream name and text of an output chunk from the developer extension's shell tool,
+/// which it sends as logging notifications while the command runs
+fn tool_output_chunk(notification: &serde_json::Value) -> Option<(&str, &str)> {
+    let data = notification.pointer("/params/data")?;
+    if data.get("type").and_then(|kind| kind.as_str()) != Some("shell_output") {
+        return None;
+    }
+    let stream = data
+        .get("stream")
+        .and_then(|stream| stream.as_str())
+        .unwrap_or("stdout");
+    Some((stream, data.get("output")?.as_str()?))
+}
+
+/// Fail before any agent work when no provider or model is configured, instead of
+/// surfacing an opaque error from the provider factory
+fn check_model_provider(model: &crate::models::configuration::ModelConfig) -> KaiakResult<()> {
+    let (missing, env_var) = if model.provider.trim().is_empty() {
+        ("provider", "KAIAK_MODEL_PROVIDER")
+    } else if model.model.trim().is_empty() {
+        ("model", "KAIAK_MODEL")
+    } else {
+        return Ok(());
+    };
+    Err(crate::KaiakError::configuration(format!(
+        "No model {missing} configured. Set `base_config.model.{missing}` in the server config \
+         (`kaiak config edit`), set the {env_var} environment variable when starting the \
+         server, or pass `agent_config.override_base_config.model.{missing}` in the request",
+    )))
+}
+
+/// Run the request's field validation, flattening errors into one message, and refuse
+/// overrides of server-only settings
+fn check_request(request: &GenerateFixRequest) -> KaiakResult<()> {
+    if let Err(validation_errors) = request.validate() {
+        error!("Request validation failed: {:?}", validation_errors);
+        let error_messages: Vec<String> = validation_errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| {
+                    format!(
+                        "Field '{}': {}",
+                        field,
+                        error
+                            .message
+                            .as_ref()
+                            .map(|m| m.as_ref())
+                            .unwrap_or("validation error")
+                    )
+                })
+            })
+            .collect();
+
+        return Err(crate::KaiakError::InvalidParams(format!(
+            "Request validation failed: {}",
+            error_messages.join(", ")
+        )));
+    }
+    if let Some(config) = &request.agent_config.override_base_config {
+        config.check_override()?;
+    }
+    if request.apply_mode == ApplyMode::Patch && request.session_id.is_some() {
+        return Err(crate::KaiakError::InvalidParams(
+            "`session_id` cannot be used with `apply_mode: patch`: patch mode requests run in \
+             a new session on a copy of the workspace"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// `KaiakError::Agent` context marking a prompt that did not fit the model's context window
+const CONTEXT_LENGTH_EXCEEDED: &str = "context_length_exceeded";
+
+/// Whether a provider error message reports that the prompt exceeded the context window
+fn is_context_length_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "context_length_exceeded",
+        "context length",
+        "context window",
+        "maximum context",
+        "prompt is too long",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// `KaiakError::Agent` context marking a model call that failed in a way retrying the same
+/// model would not fix
+const MODEL_CALL_FAILED: &str = "model_call_failed";
+
+/// Whether a provider error message reports a failure that may pass on its own
+fn is_transient_model_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "rate limit",
+        "429",
+        "timeout",
+        "timed out",
+        "overloaded",
+        "temporarily",
+        "502",
+        "503",
+        "504",
+        "connection reset",
+        "try again",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Agent error for a failed model call, tagged when the context length was exceeded or the
+/// failure is not transient
+fn agent_error(message: String) -> crate::KaiakError {
+    let context = if is_context_length_error(&message) {
+        Some(CONTEXT_LENGTH_EXCEEDED.to_string())
+    } else if !is_transient_model_error(&message) {
+        Some(MODEL_CALL_FAILED.to_string())
+    } else {
+        None
+    };
+    crate::KaiakError::agent(message, context)
+}
+
+/// Whether another model might succeed where the request's model failed: its provider
+/// could not be set up, or its model call failed for a reason retrying would not fix
+fn needs_fallback_model(error: &crate::KaiakError) -> bool {
+    match error {
+        crate::KaiakError::AgentInitialization { .. } => true,
+        crate::KaiakError::Agent {
+            context: Some(context),
+            ..
+        } => context == MODEL_CALL_FAILED,
+        _ => false,
+    }
+}
+
+/// Run `attempt` with the request's model (`None`), then once with `fallback` if that failed
+/// in a way another model could avoid. Returns the outcome with the primary model's error
+/// when the fallback ran.
+async fn run_with_fallback<T, Fut>(
+    fallback: Option<&crate::models::configuration::ModelConfig>,
+    mut attempt: impl FnMut(Option<crate::models::configuration::ModelConfig>) -> Fut,
+) -> KaiakResult<(T, Option<String>)>
+where
+    Fut: std::future::Future<Output = KaiakResult<T>>,
+{
+    match (attempt(None).await, fallback) {
+        (Err(e), Some(fallback)) if needs_fallback_model(&e) => {
+            warn!(
+                "Primary model failed ({}); retrying with fallback model '{}' from '{}'",
+                e, fallback.model, fallback.provider
+            );
+            let output = attempt(Some(fallback.clone())).await?;
+            Ok((output, Some(e.to_string())))
+        }
+        (result, _) => result.map(|output| (output, None)),
+    }
+}
+
+fn exceeded_context_length(error: &crate::KaiakError) -> bool {
+    matches!(
+        error,
+        crate::KaiakError::Agent { context: Some(context), .. } if context == CONTEXT_LENGTH_EXCEEDED
+    )
+}
+
+/// Run `attempt` on the prompt, retrying once on `reduce(prompt)` if the model's context
+/// length was exceeded. Returns the outcome, the prompt it came from, and whether the
+/// prompt was reduced.
+async fn retry_on_context_length<T, Fut>(
+    prompt: FixPrompt,
+    reduce: impl FnOnce(&FixPrompt) -> FixPrompt,
+    mut attempt: impl FnMut(FixPrompt) -> Fut,
+) -> (KaiakResult<T>, FixPrompt, bool)
+where
+    Fut: std::future::Future<Output = KaiakResult<T>>,
+{
+    match attempt(prompt.clone()).await {
+        Err(e) if exceeded_context_length(&e) => {
+            let reduced = reduce(&prompt);
+            warn!(
+                "Prompt of ~{} tokens exceeded the model's context length; retrying with ~{} tokens and {} incidents dropped",
+                estimate_tokens(&prompt.text),
+                estimate_tokens(&reduced.text),
+                reduced.trimmed_incident_ids.len()
+            );
+            let result = attempt(reduced.clone()).await.map_err(|e| {
+                if exceeded_context_length(&e) {
+                    crate::KaiakError::agent(
+                        format!(
+                            "Prompt exceeds the model's context length even after reducing it to ~{} tokens: {}",
+                            estimate_tokens(&reduced.text),
+                            e
+                        ),
+                        Some(CONTEXT_LENGTH_EXCEEDED.to_string()),
+                    )
+                } else {
+                    e
+                }
+            });
+            (result, reduced, true)
+        }
+        result => (result, prompt, false),
+    }
+}
+
+/// The server's prompt template, read from disk
+fn prompt_template(
+    base_config: &crate::models::configuration::BaseConfig,
+) -> KaiakResult<Option<Arc<PromptTemplate>>> {
+    match &base_config.prompt_template_path {
+        Some(path) => Ok(Some(Arc::new(PromptTemplate::from_file(
+            std::path::Path::new(path),
+        )?))),
+        None => Ok(None),
+    }
+}
+
+/// Prompt builder for a request, with the context lines it shows around each incident
+fn prompt_builder(
+    base_config: &crate::models::configuration::BaseConfig,
+    request: &GenerateFixRequest,
+    template: Option<Arc<PromptTemplate>>,
+) -> (PromptBuilder, usize) {
+    let context_lines = request
+        .context_lines
+        .unwrap_or(base_config.incident_context_lines);
+    let builder = PromptBuilder::new()
+        .with_rule_grouping(request.group_by_rule)
+        .with_context_lines(context_lines)
+        .with_workspace(&request.agent_config.workspace)
+        .with_incident_text_limit(base_config.incident_text_limit)
+        .with_max_readable_file_bytes(base_config.max_readable_file_bytes)
+        .with_reference_links(request.reference_links)
+        .with_template(template);
+    (builder, context_lines)
+}
+
+/// Number of turns that covered each incident file, by resolved path
+fn turns_per_file(
+    workspace: &std::path::Path,
+    turns: &[Vec<MigrationIncident>],
+) -> std::collections::BTreeMap<String, usize> {
+    let mut counts = std::collections::BTreeMap::new();
+    for turn in turns {
+        let files: std::collections::BTreeSet<String> = turn
+            .iter()
+            .map(|incident| {
+                resolve_incident_path(workspace, incident)
+                    .display()
+                    .to_string()
+            })
+            .collect();
+        for file in files {
+            *counts.entry(file).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Warnings for incidents the prompt left out or cut short
+fn prompt_warnings(prompt: &FixPrompt) -> Vec<Warning> {
+    let skipped = prompt.trimmed_incident_ids.iter().map(|id| {
+        Warning::new(
+            "incident_skipped",
+            format!(
+                "Incident '{}' was left out of the prompt to fit the token budget",
+                id
+            ),
+        )
+        .with_context(serde_json::json!({ "incident_id": id }))
+    });
+    let truncated = prompt.truncated_incident_ids.iter().map(|id| {
+        Warning::new(
+            "incident_truncated",
+            format!(
+                "The message or description of incident '{}' was truncated in the prompt",
+                id
+            ),
+        )
+        .with_context(serde_json::json!({ "incident_id": id }))
+    });
+    skipped.chain(truncated).collect()
+}
+
+/// Give incidents sent without an ID their fingerprint, so events can be attributed to them
+fn assign_incident_ids(request: &mut GenerateFixRequest) {
+    let workspace = request.agent_config.workspace.clone();
+    for incident in request.incidents.iter_mut().filter(|i| i.id.is_empty()) {
+        incident.id = fingerprint::fingerprint(incident, &workspace);
+    }
+}
+
+/// Reclassify incidents whose `rule_id` matches a `severity_overrides` glob, so the
+/// prompt shows the configured severity and trimming to `max_prompt_tokens` drops by it.
+/// The longest matching glob wins.
+fn apply_severity_overrides(
+    request: &mut GenerateFixRequest,
+    overrides: &std::collections::BTreeMap<String, IncidentSeverity>,
+) {
+    if overrides.is_empty() {
+        return;
+    }
+    let patterns: Vec<(&str, WildMatch, IncidentSeverity)> = overrides
+        .iter()
+        .map(|(pattern, severity)| (pattern.as_str(), WildMatch::new(pattern), *severity))
+        .collect();
+    for incident in &mut request.incidents {
+        let Some(rule_id) = incident.rule_id.as_deref() else {
+            continue;
+        };
+        let Some((pattern, _, severity)) = patterns
+            .iter()
+            .filter(|(_, matcher, _)| matcher.matches(rule_id))
+            .max_by_key(|(pattern, _, _)| pattern.len())
+        else {
+            continue;
+        };
+        if incident.severity != Some(*severity) {
+            info!(
+                "Incident {} of rule {} reclassified from {} to {} by severity override '{}'",
+                incident.id,
+                rule_id,
+                incident
+                    .severity
+                    .map(|severity| severity.to_string())
+                    .unwrap_or_else(|| "unset".to_string()),
+                severity,
+                pattern
+            );
+            incident.severity = Some(*severity);
+        }
+    }
+}
+
+/// Drop the incidents `include_rules`/`exclude_rules` leave out, returning their IDs.
+/// Exclusions win over inclusions, and incidents without a `rule_id` only pass when
+/// `include_rules` is empty.
+fn filter_incidents_by_rule(request: &mut GenerateFixRequest) -> KaiakResult<Vec<String>> {
+    if request.include_rules.is_empty() && request.exclude_rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    let include: Vec<WildMatch> = request
+        .include_rules
+        .iter()
+        .map(|p| WildMatch::new(p))
+        .collect();
+    let exclude: Vec<WildMatch> = request
+        .exclude_rules
+        .iter()
+        .map(|p| WildMatch::new(p))
+        .collect();
+    let allowed = |rule_id: Option<&str>| match rule_id {
+        Some(rule_id) => {
+            (include.is_empty() || include.iter().any(|m| m.matches(rule_id)))
+                && !exclude.iter().any(|m| m.matches(rule_id))
+        }
+        None => include.is_empty(),
+    };
+
+    let mut filtered = Vec::new();
+    request.incidents.retain(|incident| {
+        let keep = allowed(incident.rule_id.as_deref());
+        if !keep {
+            filtered.push(incident.id.clone());
+        }
+        keep
+    });
+    if !filtered.is_empty() {
+        info!(
+            "Rule filters skipped {} incidents, {} remain",
+            filtered.len(),
+            request.incidents.len()
+        );
+    }
+    if request.incidents.is_empty() {
+        return Err(crate::KaiakError::InvalidParams(format!(
+            "include_rules/exclude_rules filtered out all {} incidents",
+            filtered.len()
+        )));
+    }
+    Ok(filtered)
+}
+
+/// Estimated (completion, input) tokens of an agent message: what the model generated,
+/// and the tool results it will read on its next turn
+fn message_tokens(message: &Message) -> (u64, u64) {
+    let (mut completion, mut input) = (0, 0);
+    for content in &message.content {
+        match content {
+            MessageContent::Text(text) => completion += estimate_tokens(&text.text),
+            MessageContent::Thinking(thinking) => completion += estimate_tokens(&thinking.thinking),
+            MessageContent::ToolRequest(req) => {
+                if let Ok(call) = &req.tool_call {
+                    let arguments = serde_json::to_string(&call.arguments).unwrap_or_default();
+                    completion += estimate_tokens(&call.name) + estimate_tokens(&arguments);
+                }
+            }
+            MessageContent::ToolResponse(response) => {
+                if let Ok(result) = &response.tool_result {
+                    let content = serde_json::to_string(&result.content).unwrap_or_default();
+                    input += estimate_tokens(&content);
+                }
+            }
+            _ => {}
+        }
+    }
+    (completion as u64, input as u64)
+}
+
+/// Whether processing the message waits on the client, for a tool confirmation or an
+/// elicitation response
+fn awaits_interaction(message: &Message) -> bool {
+    message.content.iter().any(|content| {
+        matches!(
+            content,
+            MessageContent::ActionRequired(action) if matches!(
+                action.data,
+                ActionRequiredData::ToolConfirmation { .. } | ActionRequiredData::Elicitation { .. }
+            )
+        )
+    })
+}
+
+/// Remove thinking content from a message so it is not streamed, logging it instead
+fn drop_thinking(message: &mut Message) {
+    message.content.retain(|content| match content {
+        MessageContent::Thinking(thinking) => {
+            debug!("Thinking (not streamed): {}", thinking.thinking);
+            false
+        }
+        _ => true,
+    });
+}
+
+/// Resolve an incident's file against the workspace
+pub(super) fn resolve_incident_path(
+    workspace: &std::path::Path,
+    incident: &MigrationIncident,
+) -> std::path::PathBuf {
+    let path = incident.file_path();
+    if path.is_relative() {
+        workspace.join(path)
+    } else {
+        path
+    }
+}
+
+/// Whether the ledger records `proposal` as applied to `path` as it is now
+pub(super) fn is_applied(
+    applied_edits: &Option<(std::path::PathBuf, AppliedEdits)>,
+    path: &std::path::Path,
+    proposal: &str,
+) -> bool {
+    let Some((_, applied)) = applied_edits else {
+        return false;
+    };
+    std::fs::read_to_string(path).is_ok_and(|content| applied.is_applied(path, proposal, &content))
+}
+
+/// Record in the ledger, and save it at once, that `proposal` turned `path` from `before`
+/// into `after`, so a crash right after still leaves it recorded
+pub(super) fn record_applied(
+    applied_edits: &mut Option<(std::path::PathBuf, AppliedEdits)>,
+    path: &std::path::Path,
+    proposal: &str,
+    before: &str,
+    after: &str,
+) {
+    let Some((ledger_path, applied)) = applied_edits else {
+        return;
+    };
+    applied.record(path, proposal, before, after);
+    if let Err(e) = applied.save(ledger_path) {
+        warn!("Failed to record edit applied to {}: {}", path.display(), e);
+    }
+}
+
+/// Tells the agent where temporary files go; writes anywhere else outside the workspace
+/// are refused by the file guards
+fn scratch_dir_note(dir: &std::path::Path) -> String {
+    format!(
+        "Put any temporary or scratch files in {}; it is removed when the session ends. \
+         Writes outside the project and this directory are refused.",
+        dir.display()
+    )
+}
+
+/// Copy a `patch` mode request's workspace to `kaiak-patch-<request_id>` in the system
+/// temp directory
+async fn create_workspace_copy(
+    request: &GenerateFixRequest,
+    request_id: &str,
+) -> KaiakResult<WorkspaceCopy> {
+    let workspace = &request.agent_config.workspace;
+    let root = std::env::temp_dir().join(format!("kaiak-patch-{}", request_id));
+    // Copying a whole tree would hold up the runtime's worker thread
+    let copied = {
+        let (workspace, root) = (workspace.clone(), root.clone());
+        tokio::task::spawn_blocking(move || WorkspaceCopy::create(&workspace, root))
+            .await
+            .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+    };
+    copied.map_err(|e| {
+        // Leave nothing of a partial copy behind
+        let _ = std::fs::remove_dir_all(&root);
+        crate::KaiakError::workspace(
+            format!("Failed to copy the workspace for patch mode: {}", e),
+            Some(workspace.display().to_string()),
+        )
+    })
+}
+
+/// Point a request at a copy of its workspace, including incidents with absolute paths
+/// into the workspace
+fn redirect_to_copy(request: &mut GenerateFixRequest, copy: &WorkspaceCopy) {
+    let workspace = request.agent_config.workspace.clone();
+    for incident in &mut request.incidents {
+        let path = resolve_incident_path(&workspace, incident);
+        if incident.file_path().is_absolute() {
+            let path = std::env::current_dir()
+                .map(|dir| dir.join(&path))
+                .unwrap_or(path);
+            let path = copy.to_copy(&path);
+            incident.uri = if incident.uri.starts_with("file://") {
+                format!("file://{}", path.display())
+            } else {
+                path.display().to_string()
+            };
+        }
+    }
+    request.agent_config.workspace = copy.path().to_path_buf();
+}
+
+/// Resolve a tool call's `path` argument against the workspace
+fn resolve_tool_path(
+    workspace: &std::path::Path,
+    arguments: &rmcp::model::JsonObject,
+) -> Option<std::path::PathBuf> {
+    let path = std::path::Path::new(arguments.get("path")?.as_str()?);
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        Some(workspace.join(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::tool_results::{
+        GetToolResultHandler, GetToolResultRequest, ToolResultRef,
+    };
+    use crate::models::configuration::{BackupStrategy, BaseConfig, LoopDetection, ModelConfig};
+    use crate::models::report::TextEdit;
+    use goose::session::SessionManager;
+    use std::collections::HashMap;
+
+    fn handler(allow_incident_auto_apply: bool) -> GenerateFixHandler {
+        let base_config = BaseConfig {
+            allow_incident_auto_apply,
+            ..BaseConfig::default()
+        };
+        GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+    }
+
+    fn request() -> GenerateFixRequest {
+        GenerateFixRequest {
+            session_id: None,
+            incidents: vec![
+                MigrationIncident::new(
+                    "format-1".to_string(),
+                    "file:///workspace/src/Format.java".to_string(),
+                    "Fix import ordering".to_string(),
+                    String::new(),
+                )
+                .with_auto_apply(true),
+                MigrationIncident::new(
+                    "api-1".to_string(),
+                    "file:///workspace/src/Api.java".to_string(),
+                    "Replace removed API".to_string(),
+                    String::new(),
+                ),
+            ],
+            migration_context: None,
+            agent_config: AgentConfig {
+                workspace: std::path::PathBuf::from("/workspace"),
+                ..AgentConfig::default()
+            },
+            group_by_rule: false,
+            max_prompt_tokens: None,
+            context_lines: None,
+            track_fingerprints: false,
+            stream_thinking: None,
+            validate_only: false,
+            stream_warnings: false,
+            verbosity: Verbosity::default(),
+            reference_links: false,
+            apply_mode: ApplyMode::InPlace,
+            patch_path: None,
+            include_rules: Vec::new(),
+            exclude_rules: Vec::new(),
+            approval_cache: None,
+            no_cache: false,
+        }
+    }
+
+    fn arguments(path: &str) -> rmcp::model::JsonObject {
+        serde_json::json!({ "command": "write", "path": path })
+            .as_object()
+            .cloned()
+            .unwrap()
+    }
+
+    async fn register(handler: &GenerateFixHandler, request_id: &str) {
+        handler
+            .active_requests
+            .write()
+            .await
+            .insert(request_id.to_string(), request());
+    }
+
+    #[tokio::test]
+    async fn test_auto_apply_incident_skips_approval_when_enabled() {
+        let handler = handler(true);
+        register(&handler, "req-1").await;
+
+        assert_eq!(
+            handler
+                .auto_apply_incident("req-1", &arguments("/workspace/src/Format.java"))
+                .await,
+            Some("format-1".to_string())
+        );
+        // Relative paths resolve against the workspace
+        assert_eq!(
+            handler
+                .auto_apply_incident("req-1", &arguments("src/Format.java"))
+                .await,
+            Some("format-1".to_string())
+        );
+        // Incidents without the hint still require approval
+        assert_eq!(
+            handler
+                .auto_apply_incident("req-1", &arguments("/workspace/src/Api.java"))
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_apply_matches_incident_with_relative_uri() {
+        let handler = handler(true);
+        let mut request = request();
+        request.incidents[0].uri = "src/Format.java".to_string();
+        handler
+            .active_requests
+            .write()
+            .await
+            .insert("req-1".to_string(), request);
+
+        assert_eq!(
+            handler
+                .auto_apply_incident("req-1", &arguments("/workspace/src/Format.java"))
+                .await,
+            Some("format-1".to_string())
+        );
+        assert_eq!(
+            handler
+                .auto_apply_incident("req-1", &arguments("src/Format.java"))
+                .await,
+            Some("format-1".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_apply_requires_server_flag() {
+        let handler = handler(false);
+        register(&handler, "req-1").await;
+
+        assert_eq!(
+            handler
+                .auto_apply_incident("req-1", &arguments("/workspace/src/Format.java"))
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modification_of_binary_file_is_rejected() {
+        let workspace = tempfile::tempdir().unwrap();
+        std::fs::write(
+            workspace.path().join("logo.png"),
+            [0x89, b'P', b'N', b'G', 0x00, 0x01],
+        )
+        .unwrap();
+        std::fs::write(workspace.path().join("Main.java"), "class Main {}\n").unwrap();
+
+        let handler = handler(false);
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        handler
+            .active_requests
+            .write()
+            .await
+            .insert("req-1".to_string(), request);
+
+        let violation = handler
+            .guard_tool_call("req-1", "session-1", &arguments("logo.png"))
+            .await
+            .expect("binary file modification should be refused");
+        assert_eq!(violation.code(), "binary_file");
+
+        assert_eq!(
+            handler
+                .guard_tool_call("req-1", "session-1", &arguments("Main.java"))
+                .await,
+            None
+        );
+
+        // Viewing a binary file is not a modification
+        let view = serde_json::json!({ "command": "view", "path": "logo.png" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        assert_eq!(
+            handler.guard_tool_call("req-1", "session-1", &view).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protected_path_refused_even_for_auto_apply() {
+        let base_config = BaseConfig {
+            allow_incident_auto_apply: true,
+            protected_paths: vec!["src/Format.java".to_string()],
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        register(&handler, "req-1").await;
+
+        // The incident would otherwise be approved automatically
+        assert!(handler
+            .auto_apply_incident("req-1", &arguments("/workspace/src/Format.java"))
+            .await
+            .is_some());
+
+        let violation = handler
+            .guard_tool_call(
+                "req-1",
+                "session-1",
+                &arguments("/workspace/src/Format.java"),
+            )
+            .await
+            .expect("protected path modification should be refused");
+        assert_eq!(violation.code(), "protected_path");
+    }
+
+    #[tokio::test]
+    async fn test_read_only_server_never_applies_approved_writes() {
+        let base_config = BaseConfig {
+            read_only: true,
+            allow_incident_auto_apply: true,
+            tool_permissions: HashMap::from([
+                (
+                    "developer__text_editor".to_string(),
+                    PermissionLevel::AlwaysAllow,
+                ),
+                ("developer__shell".to_string(), PermissionLevel::AlwaysAllow),
+            ]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        register(&handler, "req-1").await;
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+
+        // Approved by tool permission and by the incident's auto_apply hint
+        handler
+            .handle_tool_confirmation(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                "tool-1",
+                "developer__text_editor",
+                &arguments("/workspace/src/Format.java"),
+                &None,
+            )
+            .await;
+
+        let params = notifications.try_recv().unwrap().params.unwrap();
+        assert_eq!(params["kind"], "error");
+        assert_eq!(params["payload"]["error"], "read_only");
+        assert!(notifications.try_recv().is_err());
+
+        // A shell command names no path but may still write, so it is denied even when
+        // its tool is always allowed
+        let sed = serde_json::json!({ "command": "sed -i 's/javax/jakarta/' src/Format.java" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        handler
+            .handle_tool_confirmation(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                "tool-2",
+                "developer__shell",
+                &sed,
+                &None,
+            )
+            .await;
+
+        let params = notifications.try_recv().unwrap().params.unwrap();
+        assert_eq!(params["kind"], "error");
+        assert_eq!(params["payload"]["error"], "read_only");
+        assert_eq!(params["payload"]["tool_name"], "developer__shell");
+        assert!(notifications.try_recv().is_err());
+
+        // Reads are still allowed
+        let view = serde_json::json!({ "command": "view", "path": "src/Format.java" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        assert_eq!(
+            handler.guard_tool_call("req-1", "session-1", &view).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approval_covers_later_writes_to_same_file() {
+        let handler = handler(false);
+        let mut request = request();
+        request.approval_cache = Some(ApprovalCachePolicy::AskOncePerFile);
+        handler
+            .active_requests
+            .write()
+            .await
+            .insert("req-1".to_string(), request);
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let decide = |id: &'static str, path: &'static str| {
+            let notifier = notifier.clone();
+            let handler = &handler;
+            async move {
+                handler
+                    .decide_tool_call(
+                        &notifier,
+                        "req-1",
+                        "session-1",
+                        id,
+                        "developer__text_editor",
+                        &arguments(path),
+                        &None,
+                    )
+                    .await
+            }
+        };
+
+        // The first write is asked and approved by the client
+        let answer = async {
+            loop {
+                let submitted = handler
+                    .interaction_manager
+                    .submit_confirmation("tool-1", Permission::AllowOnce)
+                    .await;
+                if submitted.is_ok() {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        };
+        let ((confirmation, reason), _) = tokio::join!(decide("tool-1", "src/Api.java"), answer);
+        assert_eq!(confirmation.permission, Permission::AllowOnce);
+        assert_eq!(reason, "user");
+        let params = notifications.try_recv().unwrap().params.unwrap();
+        assert_eq!(params["kind"], "user_interaction");
+
+        // A second write to the same file proceeds without asking
+        let (confirmation, reason) = decide("tool-2", "/workspace/src/Api.java").await;
+        assert_eq!(confirmation.permission, Permission::AllowOnce);
+        assert_eq!(reason, "approval_cache");
+        let params = notifications.try_recv().unwrap().params.unwrap();
+        assert_eq!(params["payload"]["event"], "approval_reused");
+        assert!(notifications.try_recv().is_err());
+
+        // Other files are still asked, and nothing outlives the request
+        let pending = tokio::time::timeout(
+            Duration::from_millis(50),
+            decide("tool-3", "src/Other.java"),
+        )
+        .await;
+        assert!(pending.is_err());
+        assert_eq!(
+            notifications.try_recv().unwrap().params.unwrap()["kind"],
+            "user_interaction"
+        );
+        handler.cancel_request("req-1").await.unwrap();
+        assert!(handler.approvals.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_wide_approval_never_covers_other_files_or_shell() {
+        let handler = handler(false);
+        let mut request = request();
+        request.approval_cache = Some(ApprovalCachePolicy::AskOncePerRequest);
+        handler
+            .active_requests
+            .write()
+            .await
+            .insert("req-1".to_string(), request);
+        let view = |path: &str| {
+            serde_json::json!({ "command": "view", "path": path })
+                .as_object()
+                .cloned()
+                .unwrap()
+        };
+        let key = |tool_name: &'static str, arguments: rmcp::model::JsonObject| {
+            let handler = &handler;
+            async move { handler.approval_key("req-1", tool_name, &arguments).await }
+        };
+
+        // Reads share one approval for the whole request
+        assert_eq!(
+            key("developer__text_editor", view("src/Api.java")).await,
+            key("developer__text_editor", view("src/Other.java")).await
+        );
+        // Writes are only covered for the file that was approved
+        assert_eq!(
+            key("developer__text_editor", arguments("src/Api.java")).await,
+            key(
+                "developer__text_editor",
+                arguments("/workspace/src/Api.java")
+            )
+            .await
+        );
+        assert_ne!(
+            key("developer__text_editor", arguments("src/Api.java")).await,
+            key("developer__text_editor", arguments("src/Other.java")).await
+        );
+        // Shell commands are asked every time, under any policy
+        let command = serde_json::json!({ "command": "rm -rf src" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        assert_eq!(key("developer__shell", command.clone()).await, None);
+        handler
+            .active_requests
+            .write()
+            .await
+            .get_mut("req-1")
+            .unwrap()
+            .approval_cache = Some(ApprovalCachePolicy::AskOncePerFile);
+        assert_eq!(key("developer__shell", command).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_patch_mode_denies_shell_and_records_without_active_request() {
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(&file, "class Main {}\n").unwrap();
+        let scratch = tempfile::tempdir().unwrap();
+        let copy = WorkspaceCopy::create(workspace.path(), scratch.path().join("req-1")).unwrap();
+        // A cancelled request is no longer active but still finishes its patch
+        let handler = handler(false);
+        handler
+            .patch_recorders
+            .lock()
+            .unwrap()
+            .insert("req-1".to_string(), PatchRecorder::new(copy.path()));
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+
+        let shell = serde_json::json!({ "command": "sed -i s/Main/App/ Main.java" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        let (confirmation, reason) = handler
+            .decide_tool_call(
+                &notifier,
+                "req-1",
+                "session-1",
+                "tool-1",
+                "developer__shell",
+                &shell,
+                &None,
+            )
+            .await;
+        assert_eq!(confirmation.permission, Permission::DenyOnce);
+        assert_eq!(reason, "untracked_modification");
+        let params = notifications.try_recv().unwrap().params.unwrap();
+        assert_eq!(params["payload"]["error"], "untracked_modification");
+
+        handler.record_patch_target("req-1", &arguments("Main.java"));
+        std::fs::write(copy.path().join("Main.java"), "class App {}\n").unwrap();
+        let patch_path = workspace.path().join("fix.patch");
+        let recorder = handler
+            .patch_recorders
+            .lock()
+            .unwrap()
+            .remove("req-1")
+            .unwrap();
+        assert_eq!(recorder.finish(&patch_path).unwrap(), 1);
+        assert!(std::fs::read_to_string(&patch_path)
+            .unwrap()
+            .contains("+class App {}"));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "class Main {}\n");
+    }
+
+    #[tokio::test]
+    async fn test_identity_is_recorded_in_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+        let base_config = BaseConfig {
+            tool_permissions: HashMap::from([(
+                "developer__text_editor".to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let identity = ClientIdentity::default();
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .with_identity(identity.clone())
+        .with_audit_log(Arc::new(AuditLog::new(Some(audit_path.clone()))));
+        register(&handler, "req-1").await;
+
+        crate::handlers::IdentityHandler::new(identity)
+            .handle_set_identity(crate::handlers::SetIdentityRequest {
+                identity: "alice@tenant-a".to_string(),
+            })
+            .unwrap();
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        handler
+            .handle_tool_confirmation(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                "tool-1",
+                "developer__text_editor",
+                &arguments("/workspace/src/Format.java"),
+                &None,
+            )
+            .await;
+
+        let log = std::fs::read_to_string(&audit_path).unwrap();
+        let entries: Vec<AuditEntry> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].identity, "alice@tenant-a");
+        assert_eq!(entries[0].decision, AuditDecision::Allowed);
+        assert_eq!(entries[0].reason, "tool_permission");
+        assert_eq!(
+            entries[0].path.as_deref(),
+            Some("/workspace/src/Format.java")
+        );
+    }
+
+    /// Kinds of the notifications one agent message produces
+    async fn streamed_kinds(message: &Message) -> Vec<String> {
+        let handler = handler(false);
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let session_config = goose::agents::SessionConfig {
+            id: "session-1".to_string(),
+            schedule_id: None,
+            max_turns: None,
+            retry_config: None,
+        };
+        let mut collector = MigrationReportCollector::new("/workspace");
+
+        handler
+            .process_message(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                &session_config,
+                message,
+                &mut collector,
+            )
+            .await;
+        drop(notifier);
+
+        let mut kinds = Vec::new();
+        while let Some(notification) = notifications.recv().await {
+            let params = notification.params.unwrap();
+            kinds.push(params["kind"].as_str().unwrap().to_string());
+        }
+        kinds
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_attributed_to_incident() {
+        let handler = handler(false);
+        register(&handler, "req-1").await;
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let session_config = goose::agents::SessionConfig {
+            id: "session-1".to_string(),
+            schedule_id: None,
+            max_turns: None,
+            retry_config: None,
+        };
+        let view = serde_json::json!({ "command": "view", "path": "src/Api.java" });
+        let message = Message::assistant().with_tool_request(
+            "tool-1",
+            Ok(rmcp::model::CallToolRequestParam {
+                name: "developer__text_editor".into(),
+                arguments: view.as_object().cloned(),
+            }),
+        );
+
+        handler
+            .process_message(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                &session_config,
+                &message,
+                &mut MigrationReportCollector::new("/workspace"),
+            )
+            .await;
+        drop(notifier);
+
+        let params = notifications.recv().await.unwrap().params.unwrap();
+        assert_eq!(params["kind"], "tool_call");
+        assert_eq!(params["incident_id"], "api-1");
+    }
+
+    #[tokio::test]
+    async fn test_shell_output_streams_before_tool_result() {
+        let handler = handler(false);
+        handler
+            .verbosities
+            .lock()
+            .unwrap()
+            .insert("req-1".to_string(), Verbosity::Verbose);
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let session_config = goose::agents::SessionConfig {
+            id: "session-1".to_string(),
+            schedule_id: None,
+            max_turns: None,
+            retry_config: None,
+        };
+        let shell_output = |stream: &str, output: &str| {
+            serde_json::json!({
+                "method": "notifications/message",
+                "params": {
+                    "level": "info",
+                    "logger": "shell_tool",
+                    "data": { "type": "shell_output", "stream": stream, "output": output },
+                },
+            })
+        };
+
+        // A build that prints as it runs, then completes
+        let mut tool_output = ToolOutputBudget::default();
+        for (stream, line) in [
+            ("stdout", "Compiling kaiak v0.1.0"),
+            ("stderr", "warning: unused import"),
+            ("stdout", "Finished in 3.2s"),
+        ] {
+            handler.send_tool_progress(
+                &notifier,
+                "req-1",
+                "session-1",
+                "tool-1",
+                &shell_output(stream, line),
+                &mut tool_output,
+                &mut ProgressBuffer::default(),
+            );
+        }
+        handler
+            .process_message(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                &session_config,
+                &Message::user()
+                    .with_tool_response("tool-1", Ok(rmcp::model::CallToolResult::success(vec![]))),
+                &mut MigrationReportCollector::new("/workspace"),
+            )
+            .await;
+        drop(notifier);
+
+        let mut events = Vec::new();
+        while let Some(notification) = notifications.recv().await {
+            events.push(notification.params.unwrap());
+        }
+        let progress: Vec<(&str, &str)> = events
+            .iter()
+            .take_while(|event| event["kind"] == "tool_progress")
+            .map(|event| {
+                (
+                    event["payload"]["stream"].as_str().unwrap(),
+                    event["payload"]["output"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            progress,
+            [
+                ("stdout", "Compiling kaiak v0.1.0"),
+                ("stderr", "warning: unused import"),
+                ("stdout", "Finished in 3.2s"),
+            ]
+        );
+        assert_eq!(events[3]["kind"], "tool_response");
+    }
+
+    #[tokio::test]
+    async fn test_rapid_progress_is_coalesced_but_results_and_errors_are_not() {
+        let handler = handler(false);
+        handler
+            .verbosities
+            .lock()
+            .unwrap()
+            .insert("req-1".to_string(), Verbosity::Verbose);
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let session_config = goose::agents::SessionConfig {
+            id: "session-1".to_string(),
+            schedule_id: None,
+            max_turns: None,
+            retry_config: None,
+        };
+        let mut tool_output = ToolOutputBudget::default();
+        let mut progress = ProgressBuffer::new(Some(60_000));
+
+        // A tool reporting progress far faster than the window, plus shell output
+        for step in 1..=50 {
+            let notification = serde_json::json!({
+                "method": "notifications/progress",
+                "params": { "progress": step, "total": 50 },
+            });
+            handler.send_tool_progress(
+                &notifier,
+                "req-1",
+                "session-1",
+                "tool-1",
+                &notification,
+                &mut tool_output,
+                &mut progress,
+            );
+        }
+        for line in ["Compiling\n", "Finished\n"] {
+            let notification = serde_json::json!({
+                "params": { "data": { "type": "shell_output", "stream": "stdout", "output": line } },
+            });
+            handler.send_tool_progress(
+                &notifier,
+                "req-1",
+                "session-1",
+                "tool-2",
+                &notification,
+                &mut tool_output,
+                &mut progress,
+            );
+        }
+        handler.flush_tool_progress(&notifier, "req-1", "session-1", &mut progress);
+        handler
+            .process_message(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                &session_config,
+                &Message::user()
+                    .with_tool_response("tool-1", Ok(rmcp::model::CallToolResult::success(vec![]))),
+                &mut MigrationReportCollector::new("/workspace"),
+            )
+            .await;
+        handler.send_notification(
+            &notifier,
+            "req-1",
+            "session-1",
+            GenerateFixDataKind::Error,
+            serde_json::json!({ "error": "stream failed" }),
+        );
+        drop(notifier);
+
+        let mut events = Vec::new();
+        while let Some(notification) = notifications.recv().await {
+            events.push(notification.params.unwrap());
+        }
+        let steps: Vec<u64> = events
+            .iter()
+            .filter(|event| event["payload"]["id"] == "tool-1" && event["kind"] == "tool_progress")
+            .map(|event| {
+                event["payload"]["notification"]["params"]["progress"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        // The first update, then the latest of the window
+        assert_eq!(steps, vec![1, 50]);
+        let output: String = events
+            .iter()
+            .filter(|event| event["payload"]["id"] == "tool-2")
+            .map(|event| event["payload"]["output"].as_str().unwrap())
+            .collect();
+        assert_eq!(output, "Compiling\nFinished\n");
+
+        let kinds: Vec<&str> = events
+            .iter()
+            .map(|event| event["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(&kinds[kinds.len() - 2..], ["tool_response", "error"]);
+    }
+
+    #[tokio::test]
+    async fn test_chatty_request_stops_at_message_cap() {
+        let base_config = BaseConfig {
+            max_messages_per_session: Some(4),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+
+        // One message of history, then the prompt and an agent that never stops talking
+        let mut limit = MessageLimit::new(handler.base_config.max_messages_per_session, 1);
+        let mut processed = 0;
+        let error = loop {
+            if let Err(e) = handler.check_message_limit(&notifier, "req-1", "session-1", &mut limit)
+            {
+                break e;
+            }
+            processed += 1;
+            assert!(processed < 100, "the cap never stopped the request");
+        };
+        drop(notifier);
+
+        // The prompt and two agent messages fit under the cap of 4
+        assert_eq!(processed, 3);
+        assert!(
+            matches!(error, crate::KaiakError::ResourceExhausted(_)),
+            "{error:?}"
+        );
+        assert!(error.to_string().contains("max_messages_per_session (4)"));
+
+        let event = notifications.recv().await.unwrap().params.unwrap();
+        assert_eq!(event["kind"], "system");
+        assert_eq!(event["payload"]["event"], "message_limit_exceeded");
+        assert_eq!(event["payload"]["max_messages_per_session"], 4);
+        assert!(notifications.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_tool_call_is_stopped_as_loop() {
+        let base_config = BaseConfig {
+            loop_detection: LoopDetection {
+                repetitions: 3,
+                window: 5,
+            },
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let shell = |id: &str, command: &str| {
+            Message::assistant().with_tool_request(
+                id,
+                Ok(rmcp::model::CallToolRequestParam {
+                    name: "developer__shell".into(),
+                    arguments: serde_json::json!({ "command": command })
+                        .as_object()
+                        .cloned(),
+                }),
+            )
+        };
+
+        // Different arguments are not a loop, however often the tool is used
+        let mut detector = LoopDetector::new(&handler.base_config.loop_detection);
+        for (i, command) in ["ls", "cat pom.xml", "mvn compile", "ls"]
+            .iter()
+            .enumerate()
+        {
+            let msg = shell(&format!("tool-{i}"), command);
+            handler
+                .check_tool_loop(&notifier, "req-1", "session-1", &mut detector, &msg)
+                .unwrap();
+        }
+
+        // The agent keeps rerunning the same build
+        let mut calls = 0;
+        let error = loop {
+            let msg = shell(&format!("build-{calls}"), "mvn test");
+            if let Err(e) =
+                handler.check_tool_loop(&notifier, "req-1", "session-1", &mut detector, &msg)
+            {
+                break e;
+            }
+            calls += 1;
+            assert!(calls < 100, "the loop was never detected");
+        };
+        drop(notifier);
+
+        assert_eq!(calls, 2);
+        assert!(
+            matches!(error, crate::KaiakError::Agent { .. }),
+            "{error:?}"
+        );
+        assert!(error
+            .to_string()
+            .contains("Suspected agent loop: developer__shell"));
+
+        let event = notifications.recv().await.unwrap().params.unwrap();
+        assert_eq!(event["kind"], "system");
+        assert_eq!(event["payload"]["event"], "loop_detected");
+        assert_eq!(event["payload"]["tool_name"], "developer__shell");
+        assert_eq!(event["payload"]["arguments"]["command"], "mvn test");
+        assert_eq!(event["payload"]["repetitions"], 3);
+        assert!(notifications.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_tool_result_is_spilled_and_fetchable() {
+        let spill_dir = tempfile::tempdir().unwrap();
+        let tool_results = Arc::new(ToolResultStore::new(spill_dir.path()));
+        let base_config = BaseConfig {
+            tool_result_spill_bytes: 1024,
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .with_tool_results(tool_results.clone());
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let session_config = goose::agents::SessionConfig {
+            id: "session-1".to_string(),
+            schedule_id: None,
+            max_turns: None,
+            retry_config: None,
+        };
+        let tree = (0..200)
+            .map(|i| format!("src/main/java/com/example/Class{i}.java"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let result = |id: &str, text: &str| {
+            Message::user().with_tool_response(
+                id,
+                Ok(rmcp::model::CallToolResult::success(vec![
+                    rmcp::model::Content::text(text),
+                ])),
+            )
+        };
+
+        for message in [result("tool-1", "ok"), result("tool-2", &tree)] {
+            handler
+                .process_message(
+                    &notifier,
+                    "req-1",
+                    "session-1",
+                    &goose::agents::Agent::new(),
+                    &session_config,
+                    &message,
+                    &mut MigrationReportCollector::new("/workspace"),
+                )
+                .await;
+        }
+        drop(notifier);
+
+        // Small results stay inline
+        let small = notifications.recv().await.unwrap().params.unwrap();
+        assert_eq!(small["payload"]["content"][0]["text"], "ok");
+        assert!(small["payload"].get("result_ref").is_none());
+
+        let large = notifications.recv().await.unwrap().params.unwrap();
+        assert_eq!(large["kind"], "tool_response");
+        assert!(large["payload"].get("content").is_none());
+        let result_ref: ToolResultRef =
+            serde_json::from_value(large["payload"]["result_ref"].clone()).unwrap();
+        assert!(result_ref.size > 1024);
+        assert!(result_ref.path.starts_with(spill_dir.path()));
+        assert!(result_ref.path.exists());
+
+        let fetched = GetToolResultHandler::new(tool_results.clone())
+            .handle_get_tool_result(GetToolResultRequest {
+                result_id: result_ref.result_id.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(fetched.size, result_ref.size);
+        assert_eq!(fetched.content[0]["text"], tree);
+
+        // Deleting the session removes its files
+        tool_results.release_session("session-1");
+        assert!(!result_ref.path.exists());
+        assert!(tool_results.get(&result_ref.result_id).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spilled_tool_result_is_fetchable_after_request_returns() {
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(&file, "import javax.servlet.http.HttpServlet;\n").unwrap();
+        let spill_dir = tempfile::tempdir().unwrap();
+        let tool_results = Arc::new(ToolResultStore::new(spill_dir.path()));
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            tool_result_spill_bytes: 1024,
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .with_tool_results(tool_results.clone());
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(1)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+        let (notifier, mut notifications) =
+            tokio::sync::mpsc::unbounded_channel::<JsonRpcNotification>();
+        let tree = "src/main/java/com/example/Class.java\n".repeat(100);
+
+        // While the request waits on the client, a large tool result is spilled under it
+        let client = async {
+            let (request_id, session_id) = loop {
+                let params = notifications.recv().await.unwrap().params.unwrap();
+                if params["kind"] == "user_interaction" {
+                    break (
+                        params["request_id"].as_str().unwrap().to_string(),
+                        params["session_id"].as_str().unwrap().to_string(),
+                    );
+                }
+            };
+            let session_config = goose::agents::SessionConfig {
+                id: session_id.clone(),
+                schedule_id: None,
+                max_turns: None,
+                retry_config: None,
+            };
+            let message = Message::user().with_tool_response(
+                "tool-1",
+                Ok(rmcp::model::CallToolResult::success(vec![
+                    rmcp::model::Content::text(&tree),
+                ])),
+            );
+            handler
+                .process_message(
+                    &notifier,
+                    &request_id,
+                    &session_id,
+                    &goose::agents::Agent::new(),
+                    &session_config,
+                    &message,
+                    &mut MigrationReportCollector::new(workspace.path()),
+                )
+                .await;
+            let params = notifications.recv().await.unwrap().params.unwrap();
+            let result_ref: ToolResultRef =
+                serde_json::from_value(params["payload"]["result_ref"].clone()).unwrap();
+            handler
+                .interaction_manager
+                .submit_confirmation("suggested-javax-1", Permission::AllowOnce)
+                .await
+                .unwrap();
+            (result_ref, session_id)
+        };
+        let (response, (result_ref, session_id)) = tokio::join!(
+            handler.handle_generate_fix(request.clone(), notifier.clone()),
+            client
+        );
+        response.unwrap();
+
+        // The request has returned and its result can still be fetched
+        let fetched = GetToolResultHandler::new(tool_results.clone())
+            .handle_get_tool_result(GetToolResultRequest {
+                result_id: result_ref.result_id.clone(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(fetched.content[0]["text"], tree);
+
+        // Until its session is deleted
+        crate::handlers::DeleteSessionHandler::new(handler.agent_manager.clone())
+            .with_tool_results(tool_results.clone())
+            .handle_delete_session(serde_json::from_value(serde_json::json!({
+                "session_id": session_id,
+                "cleanup_options": { "force": true, "cleanup_temp_files": false, "preserve_logs": true },
+            })).unwrap())
+            .await
+            .unwrap();
+        assert!(!result_ref.path.exists());
+        assert!(tool_results.get(&result_ref.result_id).is_err());
+    }
+
+    #[test]
+    fn test_streamed_tool_output_is_bounded() {
+        let mut tool_output = ToolOutputBudget::default();
+        let line = "x".repeat(MAX_STREAMED_TOOL_OUTPUT_BYTES - 10);
+
+        assert_eq!(
+            tool_output.take("tool-1", &line),
+            Some((line.as_str(), false))
+        );
+        assert_eq!(
+            tool_output.take("tool-1", "0123456789abc"),
+            Some(("0123456789", true))
+        );
+        assert_eq!(tool_output.take("tool-1", "more"), None);
+        // Budgets are per tool call
+        assert_eq!(tool_output.take("tool-2", "more"), Some(("more", false)));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_incident_statuses_match_report() {
+        let handler = handler(false);
+        register(&handler, "req-1").await;
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let session_config = goose::agents::SessionConfig {
+            id: "session-1".to_string(),
+            schedule_id: None,
+            max_turns: None,
+            retry_config: None,
+        };
+        let mut incidents = request().incidents;
+        incidents.push(MigrationIncident::new(
+            "trimmed-1".to_string(),
+            "file:///workspace/src/Format.java".to_string(),
+            "Left out of the prompt".to_string(),
+            String::new(),
+        ));
+        let mut collector = MigrationReportCollector::new("/workspace")
+            .with_incidents(&incidents, &["trimmed-1".to_string()]);
+
+        let write = |id: &str, path: &str| {
+            Message::assistant().with_tool_request(
+                id,
+                Ok(rmcp::model::CallToolRequestParam {
+                    name: "developer__text_editor".into(),
+                    arguments: Some(arguments(path)),
+                }),
+            )
+        };
+        let result = |id: &str, success: bool| {
+            let result = if success {
+                rmcp::model::CallToolResult::success(vec![])
+            } else {
+                rmcp::model::CallToolResult::error(vec![])
+            };
+            Message::user().with_tool_response(id, Ok(result))
+        };
+        // Format.java fails, then succeeds; Api.java only fails
+        let messages = [
+            write("tool-1", "src/Format.java"),
+            result("tool-1", false),
+            write("tool-2", "src/Api.java"),
+            result("tool-2", false),
+            write("tool-3", "src/Format.java"),
+            result("tool-3", true),
+        ];
+        for message in &messages {
+            handler
+                .process_message(
+                    &notifier,
+                    "req-1",
+                    "session-1",
+                    &goose::agents::Agent::new(),
+                    &session_config,
+                    message,
+                    &mut collector,
+                )
+                .await;
+        }
+        drop(notifier);
+
+        let mut streamed: HashMap<String, String> = HashMap::new();
+        while let Some(notification) = notifications.recv().await {
+            let params = notification.params.unwrap();
+            if params["kind"] == "incident_status" {
+                streamed.insert(
+                    params["incident_id"].as_str().unwrap().to_string(),
+                    params["payload"]["outcome"].as_str().unwrap().to_string(),
+                );
+            }
+        }
+        let report = collector.finish(0);
+
+        let tally = |outcome: &str| streamed.values().filter(|o| o.as_str() == outcome).count();
+        assert_eq!(report.incidents_fixed, tally("fixed"));
+        assert_eq!(report.incidents_failed, tally("failed"));
+        assert_eq!(
+            report.incidents_fixed + report.incidents_failed,
+            streamed.len()
+        );
+        for result in &report.incidents {
+            let outcome = serde_json::to_value(result.outcome).unwrap();
+            match streamed.get(&result.incident_id) {
+                Some(streamed) => assert_eq!(outcome, streamed.as_str()),
+                None => assert_eq!(result.outcome, crate::models::IncidentOutcome::Skipped),
+            }
+        }
+        assert_eq!(streamed["format-1"], "fixed");
+        assert_eq!(streamed["api-1"], "failed");
+        assert!(!streamed.contains_key("trimmed-1"));
+    }
+
+    #[tokio::test]
+    async fn test_suggested_replacement_is_proposed_without_calling_the_model() {
+        #[derive(Default)]
+        struct CountingInterceptor(std::sync::atomic::AtomicUsize);
+        impl crate::agent::ModelInterceptor for CountingInterceptor {
+            fn before_call(&self, _call: &ModelCall<'_>) {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(
+            &file,
+            "package app;\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n",
+        )
+        .unwrap();
+
+        let interceptor = Arc::new(CountingInterceptor::default());
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new().with_model_interceptor(interceptor.clone())),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        assert_eq!(interceptor.0.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "package app;\nimport jakarta.servlet.http.HttpServlet;\nclass Main {}\n"
+        );
+        let result = &response.report.incidents[0];
+        assert_eq!(result.outcome, crate::models::IncidentOutcome::Fixed);
+        assert_eq!(result.fixed_by, Some(FixSource::SuggestedReplacement));
+
+        // The edit was proposed like one of the model's
+        let mut proposal = None;
+        while let Ok(notification) = notifications.try_recv() {
+            let params = notification.params.unwrap();
+            if params["kind"] == "tool_call" {
+                proposal = Some(params);
+            }
+        }
+        let proposal = proposal.unwrap();
+        assert_eq!(proposal["incident_id"], "javax-1");
+        assert_eq!(proposal["payload"]["source"], "suggested_replacement");
+        let arguments = &proposal["payload"]["arguments"];
+        assert_eq!(
+            arguments["old_str"],
+            "import javax.servlet.http.HttpServlet;"
+        );
+        assert_eq!(
+            arguments["new_str"],
+            "import jakarta.servlet.http.HttpServlet;"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rerun_skips_suggestions_applied_by_an_earlier_run() {
+        let workspace = tempfile::tempdir().unwrap();
+        let ledger_dir = tempfile::tempdir().unwrap();
+        let first = workspace.path().join("First.java");
+        let second = workspace.path().join("Second.java");
+        let original = "package app;\nimport javax.servlet.http.HttpServlet;\n";
+        std::fs::write(&first, original).unwrap();
+        std::fs::write(&second, original).unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            applied_edits_dir: Some(ledger_dir.path().to_path_buf()),
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let incident = |id: &str, file: &std::path::Path| {
+            MigrationIncident::new(
+                id.to_string(),
+                format!("file://{}", file.display()),
+                "Replace javax.servlet import".to_string(),
+                String::new(),
+            )
+            .with_line_number(2)
+            .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)
+        };
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![incident("first-1", &first), incident("second-1", &second)];
+        let fixed = "package app;\nimport jakarta.servlet.http.HttpServlet;\n";
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request.clone(), notifier)
+            .await
+            .unwrap();
+        assert_eq!(response.report.incidents_fixed, 2);
+        assert!(response.report.already_applied.is_empty());
+
+        // As if the run had stopped before writing the second file
+        std::fs::write(&second, original).unwrap();
+
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), fixed);
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), fixed);
+        assert_eq!(response.report.incidents_fixed, 2);
+        assert_eq!(response.report.already_applied, vec!["first-1".to_string()]);
+        assert_eq!(response.report.file_changes.len(), 1);
+        assert_eq!(
+            response.report.file_changes[0].path,
+            second.display().to_string()
+        );
+        let mut proposed = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            let params = notification.params.unwrap();
+            if params["kind"] == "tool_call" {
+                proposed.push(params["incident_id"].as_str().unwrap().to_string());
+            }
+        }
+        assert_eq!(proposed, vec!["second-1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_patch_mode_fixes_a_copy_and_leaves_workspace_untouched() {
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        let original = "package app;\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n";
+        std::fs::write(&file, original).unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.apply_mode = ApplyMode::Patch;
+        request.patch_path = Some(workspace.path().join("fix.patch"));
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), original);
+        let patch = std::fs::read_to_string(workspace.path().join("fix.patch")).unwrap();
+        assert!(
+            patch.contains("+import jakarta.servlet.http.HttpServlet;"),
+            "{}",
+            patch
+        );
+        assert_eq!(
+            response.report.file_changes[0].path,
+            file.display().to_string()
+        );
+        let copy = std::env::temp_dir().join(format!("kaiak-patch-{}", response.request_id));
+        assert!(!copy.exists());
+        // The session worked in the copy and ended with the request
+        assert!(
+            !handler
+                .agent_manager
+                .session_exists(&response.session_id)
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_patch_mode_refuses_an_existing_session() {
+        let mut request = request();
+        request.apply_mode = ApplyMode::Patch;
+        request.session_id = Some("session-1".to_string());
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let error = handler(false)
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, crate::KaiakError::InvalidParams(_)),
+            "{:?}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_suggested_replacements_in_one_file_use_original_line_numbers() {
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(
+            &file,
+            "package app;\nimport javax.servlet.Servlet;\nclass Main {\n    javax.servlet.Filter filter;\n}\n",
+        )
+        .unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let incident = |id: &str, line: usize, replacement: &str| {
+            MigrationIncident::new(
+                id.to_string(),
+                format!("file://{}", file.display()),
+                "Replace javax.servlet".to_string(),
+                String::new(),
+            )
+            .with_line_number(line)
+            .with_suggested_replacement(replacement, None)
+        };
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![
+            // Adds a line above the second incident's
+            incident(
+                "import-1",
+                2,
+                "import jakarta.servlet.Servlet;\nimport jakarta.servlet.Filter;",
+            ),
+            incident("field-1", 4, "    jakarta.servlet.Filter filter;"),
+        ];
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "package app;\nimport jakarta.servlet.Servlet;\nimport jakarta.servlet.Filter;\nclass Main {\n    jakarta.servlet.Filter filter;\n}\n"
+        );
+        assert_eq!(response.report.incidents_fixed, 2);
+
+        // Line 0 names no line to replace
+        assert!(incident("file-1", 0, "package app;")
+            .affected_lines()
+            .is_none());
+        let range = crate::models::LineRange {
+            start_line: 0,
+            end_line: 1,
+        };
+        assert!(
+            super::super::suggestions::replace_lines("package app;\n", range, "package app;")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_request_id_is_rejected_across_handlers() {
+        let active_requests = ActiveRequests::default();
+        let first = handler(false).with_active_requests(active_requests.clone());
+        let second = handler(false).with_active_requests(active_requests.clone());
+
+        let running = first
+            .track_active_request("req-1", &request())
+            .await
+            .unwrap();
+        let err = second
+            .track_active_request("req-1", &request())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, crate::KaiakError::RequestInUse(_)));
+        assert!(err.to_string().contains("req-1"));
+        assert_eq!(
+            crate::jsonrpc::JsonRpcError::from(err).code,
+            crate::jsonrpc::protocol::error_codes::REQUEST_IN_USE
+        );
+        assert_eq!(active_requests.read().await.len(), 1);
+
+        // The ID is free again once its request ends
+        drop(running);
+        assert!(active_requests.read().await.is_empty());
+        let _running = second
+            .track_active_request("req-1", &request())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_panicking_request_leaves_no_state_behind() {
+        let handler = Arc::new(handler(false));
+        let task = {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let _running = handler
+                    .track_active_request("req-1", &request())
+                    .await
+                    .unwrap();
+                handler
+                    .verbosities
+                    .lock()
+                    .unwrap()
+                    .insert("req-1".to_string(), Verbosity::Verbose);
+                handler
+                    .approvals
+                    .lock()
+                    .unwrap()
+                    .insert("req-1".to_string(), Default::default());
+                panic!("request failed");
+            })
+        };
+
+        assert!(task.await.unwrap_err().is_panic());
+        assert!(handler.active_requests.read().await.is_empty());
+        assert!(handler.verbosities.lock().unwrap().is_empty());
+        assert!(handler.approvals.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_incident_is_fixed_from_cache_without_calling_the_model() {
+        #[derive(Default)]
+        struct CountingInterceptor(std::sync::atomic::AtomicUsize);
+        impl crate::agent::ModelInterceptor for CountingInterceptor {
+            fn before_call(&self, _call: &ModelCall<'_>) {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        }
+
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        let original = "package app;\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n";
+        let fixed = "package app;\nimport jakarta.servlet.http.HttpServlet;\nclass Main {}\n";
+        std::fs::write(&file, original).unwrap();
+
+        let interceptor = Arc::new(CountingInterceptor::default());
+        let agent_manager =
+            Arc::new(GooseAgentManager::new().with_model_interceptor(interceptor.clone()));
+        let fix_cache = Arc::new(FixCache::default());
+        let handler = |apply_suggested_replacements: bool| {
+            let base_config = BaseConfig {
+                apply_suggested_replacements,
+                fix_cache_ttl_secs: Some(3600),
+                tool_permissions: HashMap::from([(
+                    SUGGESTION_TOOL.to_string(),
+                    PermissionLevel::AlwaysAllow,
+                )]),
+                ..BaseConfig::default()
+            };
+            GenerateFixHandler::new(
+                agent_manager.clone(),
+                Arc::new(InteractionManager::new()),
+                Arc::new(base_config),
+            )
+            .with_fix_cache(fix_cache.clone())
+        };
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let first = handler(true)
+            .handle_generate_fix(request.clone(), notifier.clone())
+            .await
+            .unwrap();
+        assert!(first.cache_hits.is_empty());
+        assert_eq!(
+            first.report.incidents[0].fixed_by,
+            Some(FixSource::SuggestedReplacement)
+        );
+
+        // The same incident in the same code, as when the migration is run again; with
+        // suggestions off only the cache can fix it without the model
+        std::fs::write(&file, original).unwrap();
+        let second = handler(false)
+            .handle_generate_fix(request.clone(), notifier.clone())
+            .await
+            .unwrap();
+        assert_eq!(second.cache_hits, vec!["javax-1".to_string()]);
+        assert_eq!(
+            second.report.incidents[0].outcome,
+            crate::models::IncidentOutcome::Fixed
+        );
+        assert_eq!(second.report.incidents[0].fixed_by, Some(FixSource::Cache));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), fixed);
+        assert_eq!(interceptor.0.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        // `no_cache` skips the lookup
+        std::fs::write(&file, original).unwrap();
+        request.no_cache = true;
+        let third = handler(true)
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+        assert!(third.cache_hits.is_empty());
+        assert_eq!(
+            third.report.incidents[0].fixed_by,
+            Some(FixSource::SuggestedReplacement)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_denied_cached_edit_leaves_file_untouched() {
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(
+            &file,
+            "package app;\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n",
+        )
+        .unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            fix_cache_ttl_secs: Some(3600),
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AskBefore,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+        let edit = |old_str: &str, new_str: &str| TextEdit {
+            old_str: old_str.to_string(),
+            new_str: new_str.to_string(),
+        };
+        let (fingerprints, _) = handler.fingerprint_request(&request);
+        handler.fix_cache.record(
+            handler.primary_model(&request),
+            &fingerprints[0].fingerprint,
+            vec![
+                edit("package app;", "package app.web;"),
+                edit("class Main {}", "class Main extends HttpServlet {}"),
+            ],
+        );
+
+        // The first cached edit is approved and the second denied; the suggestion then
+        // fixes the incident instead
+        let client = async {
+            for (id, permission) in [
+                ("cached-javax-1-0", Permission::AllowOnce),
+                ("cached-javax-1-1", Permission::DenyOnce),
+                ("suggested-javax-1", Permission::AllowOnce),
+            ] {
+                while handler
+                    .interaction_manager
+                    .submit_confirmation(id, permission.clone())
+                    .await
+                    .is_err()
+                {
+                    tokio::task::yield_now().await;
+                }
+            }
+        };
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let (response, _) = tokio::join!(handler.handle_generate_fix(request, notifier), client);
+        let response = response.unwrap();
+
+        assert!(response.cache_hits.is_empty());
+        assert_eq!(
+            response.report.incidents[0].fixed_by,
+            Some(FixSource::SuggestedReplacement)
+        );
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "package app;\nimport jakarta.servlet.http.HttpServlet;\nclass Main {}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cached_fixes_in_one_file_are_attributed_to_each_incident() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(
+            &file,
+            "package app;\nimport javax.servlet.http.HttpServlet;\nimport javax.inject.Inject;\nclass Main {}\n",
+        )
+        .unwrap();
+
+        let base_config = BaseConfig {
+            fix_cache_ttl_secs: Some(3600),
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .with_audit_log(Arc::new(AuditLog::new(Some(audit_path.clone()))));
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        let incident = |id: &str, message: &str, line: usize| {
+            MigrationIncident::new(
+                id.to_string(),
+                format!("file://{}", file.display()),
+                message.to_string(),
+                String::new(),
+            )
+            .with_rule_id(id)
+            .with_line_number(line)
+        };
+        request.incidents = vec![
+            incident("servlet-1", "Replace javax.servlet", 2),
+            incident("inject-1", "Replace javax.inject", 3),
+        ];
+        let (fingerprints, _) = handler.fingerprint_request(&request);
+        for (fingerprint, (old_str, new_str)) in fingerprints.iter().zip([
+            ("javax.servlet", "jakarta.servlet"),
+            ("javax.inject", "jakarta.inject"),
+        ]) {
+            handler.fix_cache.record(
+                handler.primary_model(&request),
+                &fingerprint.fingerprint,
+                vec![TextEdit {
+                    old_str: old_str.to_string(),
+                    new_str: new_str.to_string(),
+                }],
+            );
+        }
+
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "package app;\nimport jakarta.servlet.http.HttpServlet;\nimport jakarta.inject.Inject;\nclass Main {}\n"
+        );
+        assert_eq!(response.cache_hits, vec!["servlet-1", "inject-1"]);
+        for result in &response.report.incidents {
+            assert_eq!(result.outcome, crate::models::IncidentOutcome::Fixed);
+            assert_eq!(result.fixed_by, Some(FixSource::Cache));
+        }
+
+        // Each edit is proposed under the incident it fixed
+        let mut proposals = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            let params = notification.params.unwrap();
+            if params["kind"] == "tool_call" {
+                proposals.push((
+                    params["incident_id"].as_str().unwrap().to_string(),
+                    params["payload"]["arguments"]["old_str"]
+                        .as_str()
+                        .unwrap()
+                        .to_string(),
+                ));
+            }
+        }
+        assert_eq!(
+            proposals,
+            vec![
+                ("servlet-1".to_string(), "javax.servlet".to_string()),
+                ("inject-1".to_string(), "javax.inject".to_string()),
+            ]
+        );
+        let tool_call_ids: Vec<String> = std::fs::read_to_string(&audit_path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<AuditEntry>(line)
+                    .unwrap()
+                    .tool_call_id
+            })
+            .collect();
+        assert_eq!(
+            tool_call_ids,
+            vec!["cached-servlet-1-0", "cached-inject-1-0"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_backup_keeps_original_of_applied_modification() {
+        let workspace = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        std::fs::create_dir(workspace.path().join("src")).unwrap();
+        let file = workspace.path().join("src/Main.java");
+        let original = "package app;\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n";
+        std::fs::write(&file, original).unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            backup_strategy: Some(BackupStrategy::Copy),
+            backup_dir: Some(backups.path().to_path_buf()),
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        assert!(std::fs::read_to_string(&file).unwrap().contains("jakarta"));
+        let backup_dir = std::path::PathBuf::from(response.report.backup_dir.unwrap());
+        assert!(backup_dir.starts_with(backups.path()));
+        assert!(backup_dir
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .ends_with(&response.request_id));
+        assert_eq!(
+            std::fs::read_to_string(backup_dir.join("src/Main.java")).unwrap(),
+            original
+        );
+        assert!(response.report.backup_commit.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rollback_restores_files_from_backups() {
+        use crate::handlers::rollback::{RollbackHandler, RollbackRequest};
+
+        let workspace = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        let original = "package app;\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n";
+        std::fs::write(&file, original).unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            backup_strategy: Some(BackupStrategy::Copy),
+            backup_dir: Some(backups.path().to_path_buf()),
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let completed_backups = Arc::new(CompletedBackups::default());
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .with_completed_backups(completed_backups.clone());
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+        assert_ne!(std::fs::read_to_string(&file).unwrap(), original);
+
+        let rollback = RollbackHandler::new(completed_backups);
+        let request = RollbackRequest {
+            request_id: response.request_id.clone(),
+        };
+        let rolled_back = rollback.handle_rollback(request.clone()).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), original);
+        assert_eq!(rolled_back.rolled_back, vec![file.display().to_string()]);
+        assert!(rolled_back.not_rolled_back.is_empty());
+        // Nothing is left to roll back once every file is
+        assert!(rollback.handle_rollback(request).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_rollback_keep_legacy_encoding() {
+        use crate::handlers::rollback::{RollbackHandler, RollbackRequest};
+
+        let workspace = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        let (original, _, _) = encoding_rs::WINDOWS_1252
+            .encode("// Café\nimport javax.servlet.http.HttpServlet;\nclass Main {}\n");
+        std::fs::write(&file, &original).unwrap();
+
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            transcode_legacy_encodings: true,
+            backup_strategy: Some(BackupStrategy::Copy),
+            backup_dir: Some(backups.path().to_path_buf()),
+            tool_permissions: HashMap::from([(
+                SUGGESTION_TOOL.to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let completed_backups = Arc::new(CompletedBackups::default());
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .with_completed_backups(completed_backups.clone());
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        request.incidents = vec![MigrationIncident::new(
+            "javax-1".to_string(),
+            format!("file://{}", file.display()),
+            "Replace javax.servlet import".to_string(),
+            String::new(),
+        )
+        .with_line_number(2)
+        .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)];
+
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        let response = handler
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap();
+
+        // The edit is written back in the file's encoding, and the backup holds the
+        // original bytes rather than the UTF-8 the agent worked on
+        let (fixed, _, _) = encoding_rs::WINDOWS_1252
+            .encode("// Café\nimport jakarta.servlet.http.HttpServlet;\nclass Main {}\n");
+        assert_eq!(std::fs::read(&file).unwrap(), fixed.as_ref());
+        let backup_dir = std::path::PathBuf::from(response.report.backup_dir.unwrap());
+        assert_eq!(
+            std::fs::read(backup_dir.join("Main.java")).unwrap(),
+            original.as_ref()
+        );
+
+        let rolled_back = RollbackHandler::new(completed_backups)
+            .handle_rollback(RollbackRequest {
+                request_id: response.request_id,
+            })
+            .unwrap();
+        assert_eq!(rolled_back.rolled_back, vec![file.display().to_string()]);
+        assert_eq!(std::fs::read(&file).unwrap(), original.as_ref());
+    }
+
+    #[test]
+    fn test_missing_incident_ids_get_stable_fingerprints() {
+        let mut first = request();
+        first.incidents[1].id = String::new();
+        let mut second = first.clone();
+
+        assign_incident_ids(&mut first);
+        assign_incident_ids(&mut second);
+
+        assert_eq!(first.incidents[0].id, "format-1");
+        assert!(!first.incidents[1].id.is_empty());
+        assert_eq!(first.incidents[1].id, second.incidents[1].id);
+    }
+
+    #[tokio::test]
+    async fn test_context_length_error_retries_with_reduced_prompt() {
+        let prompt = |text: &str| FixPrompt {
+            text: text.to_string(),
+            trimmed_incident_ids: Vec::new(),
+            truncated_incident_ids: Vec::new(),
+        };
+        let mut attempts = Vec::new();
+
+        let (result, used, reduced) = retry_on_context_length(
+            prompt("full prompt with every incident"),
+            |_| prompt("reduced prompt"),
+            |prompt| {
+                attempts.push(prompt.text.clone());
+                let result = if attempts.len() == 1 {
+                    Err(agent_error(
+                        "This model's maximum context length is 128000 tokens".to_string(),
+                    ))
+                } else {
+                    Ok(prompt.text)
+                };
+                async move { result }
+            },
+        )
+        .await;
+
+        assert!(reduced);
+        assert_eq!(used.text, "reduced prompt");
+        assert_eq!(result.unwrap(), "reduced prompt");
+        assert_eq!(
+            attempts,
+            vec!["full prompt with every incident", "reduced prompt"]
+        );
+
+        // Other failures are not retried
+        let (result, _, reduced) = retry_on_context_length(
+            prompt("full prompt"),
+            |_| unreachable!(),
+            |_| async { Err::<(), _>(agent_error("rate limited".to_string())) },
+        )
+        .await;
+        assert!(!reduced);
+        assert!(!exceeded_context_length(&result.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn test_context_length_retry_reuses_the_failed_attempts_session() {
+        let workspace = tempfile::tempdir().unwrap();
+        let handler = handler(false);
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        let prompt = |text: &str| FixPrompt {
+            text: text.to_string(),
+            trimmed_incident_ids: Vec::new(),
+            truncated_incident_ids: Vec::new(),
+        };
+        let attempt_session = std::sync::Mutex::new(None);
+
+        let (result, _, reduced) = retry_on_context_length(
+            prompt("full prompt"),
+            |_| prompt("reduced prompt"),
+            |fix_prompt| {
+                let (handler, request, attempt_session) = (&handler, &request, &attempt_session);
+                async move {
+                    let session = handler
+                        .attempt_session(request, &request.agent_config, attempt_session)
+                        .await?
+                        .session;
+                    SessionManager::add_message(
+                        &session.id,
+                        &Message::user().with_text(&fix_prompt.text),
+                    )
+                    .await
+                    .unwrap();
+                    if fix_prompt.text == "full prompt" {
+                        return Err(agent_error(
+                            "This model's maximum context length is 128000 tokens".to_string(),
+                        ));
+                    }
+                    Ok(session.id)
+                }
+            },
+        )
+        .await;
+
+        // One session, holding only the reduced prompt
+        let session_id = result.unwrap();
+        let manager = &handler.agent_manager;
+        assert!(reduced);
+        assert_eq!(manager.stats().snapshot(false).sessions_created, 1);
+        let messages = manager.session_wrapper().messages(&session_id).await;
+        assert_eq!(messages.unwrap().len(), 1);
+
+        manager.delete_session(&session_id).await.unwrap();
+    }
+
+    #[test]
+    fn test_rule_filters_keep_included_and_not_excluded_incidents() {
+        let mut request = request();
+        request.incidents = [
+            ("a", Some("javax-to-jakarta-import")),
+            ("b", Some("javax-to-jakarta-annotations")),
+            ("c", Some("javax-to-jakarta-noisy")),
+            ("d", Some("jaxb-removed")),
+            ("e", None),
+        ]
+        .into_iter()
+        .map(|(id, rule_id)| {
+            let incident = MigrationIncident::new(
+                id.to_string(),
+                format!("file:///workspace/src/{}.java", id),
+                "Fix it".to_string(),
+                String::new(),
+            );
+            match rule_id {
+                Some(rule_id) => incident.with_rule_id(rule_id),
+                None => incident,
+            }
+        })
+        .collect();
+        request.include_rules = vec!["javax-to-jakarta-*".to_string()];
+        request.exclude_rules = vec!["*-noisy".to_string()];
+
+        let filtered = filter_incidents_by_rule(&mut request).unwrap();
+
+        let kept: Vec<&str> = request.incidents.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(kept, vec!["a", "b"]);
+        assert_eq!(filtered, vec!["c", "d", "e"]);
+
+        // Filtering out everything fails the request instead of running an empty prompt
+        request.exclude_rules = vec!["*".to_string()];
+        let error = filter_incidents_by_rule(&mut request).unwrap_err();
+        assert!(
+            matches!(error, crate::KaiakError::InvalidParams(_)),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_preview_prompt_shows_incident_files_and_rules() {
+        let mut request = request();
+        request.group_by_rule = true;
+        request.incidents[0].rule_id = Some("javax-to-jakarta-import".to_string());
+        request.incidents[1].rule_id = Some("jaxb-removed".to_string());
+
+        let preview = GenerateFixHandler::preview_prompt(&request, &BaseConfig::default()).unwrap();
+
+        assert_eq!(preview.turns.len(), 1);
+        let turn = &preview.turns[0];
+        for incident in &request.incidents {
+            assert!(turn.prompt.contains(&incident.uri), "{}", turn.prompt);
+            assert!(turn.incident_ids.contains(&incident.id));
+        }
+        assert!(turn.prompt.contains("[javax-to-jakarta-import]"));
+        assert!(turn.prompt.contains("[jaxb-removed]"));
+        assert_eq!(turn.estimated_tokens, estimate_tokens(&turn.prompt));
+        assert_eq!(preview.estimated_tokens, turn.estimated_tokens);
+
+        // A turn budget previews each turn separately
+        let base_config = BaseConfig {
+            incident_turn_budget: crate::models::configuration::IncidentTurnBudget {
+                max_incidents: 1,
+                max_chars: 0,
+            },
+            ..BaseConfig::default()
+        };
+        let preview = GenerateFixHandler::preview_prompt(&request, &base_config).unwrap();
+        assert_eq!(preview.turns.len(), 2);
+    }
+
+    #[test]
+    fn test_severity_override_reclassifies_incident_before_trimming() {
+        let mut request = request();
+        request.max_prompt_tokens = Some(1);
+        request.incidents[0].rule_id = Some("javax-to-jakarta-import".to_string());
+        request.incidents[0].severity = Some(IncidentSeverity::Info);
+        request.incidents[1].rule_id = Some("jaxb-removed".to_string());
+        request.incidents[1].severity = Some(IncidentSeverity::Warning);
+
+        // Only one incident fits; the lowest severity goes first
+        let preview = GenerateFixHandler::preview_prompt(&request, &BaseConfig::default()).unwrap();
+        assert_eq!(preview.trimmed_incidents, vec!["format-1".to_string()]);
+
+        let base_config = BaseConfig {
+            severity_overrides: std::collections::BTreeMap::from([
+                ("javax-*".to_string(), IncidentSeverity::Critical),
+                ("javax-to-jakarta-*".to_string(), IncidentSeverity::Error),
+            ]),
+            ..BaseConfig::default()
+        };
+        let normalized = GenerateFixHandler::validate_only(&request, &base_config).unwrap();
+        let severity = |id: &str| {
+            normalized
+                .incidents
+                .iter()
+                .find(|normalized| normalized.incident.id == id)
+                .and_then(|normalized| normalized.incident.severity)
+        };
+        // The longest matching glob wins; unmatched rules keep their severity
+        assert_eq!(severity("format-1"), Some(IncidentSeverity::Error));
+        assert_eq!(severity("api-1"), Some(IncidentSeverity::Warning));
+
+        let preview = GenerateFixHandler::preview_prompt(&request, &base_config).unwrap();
+        assert_eq!(preview.trimmed_incidents, vec!["api-1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_primary_model_completes_on_fallback() {
+        let fallback = ModelConfig {
+            provider: "ollama".to_string(),
+            model: "qwen2.5-coder".to_string(),
+            ..ModelConfig::default()
+        };
+        let mut attempts = Vec::new();
+
+        let result = run_with_fallback(Some(&fallback), |model| {
+            attempts.push(model.as_ref().map(|model| model.model.clone()));
+            let result = match model {
+                None => Err(crate::KaiakError::agent_initialization(
+                    "Failed to create provider 'openai' with model 'gpt-4o': OPENAI_API_KEY is not set",
+                )),
+                Some(model) => Ok(format!("fixed with {}", model.model)),
+            };
+            async move { result }
+        })
+        .await;
+
+        let (output, fallback_reason) = result.unwrap();
+        assert_eq!(output, "fixed with qwen2.5-coder");
+        assert!(fallback_reason
+            .unwrap()
+            .contains("OPENAI_API_KEY is not set"));
+        assert_eq!(attempts, vec![None, Some("qwen2.5-coder".to_string())]);
+
+        // The fallback attempt runs on the fallback model, with no further fallback
+        let handler = handler(false);
+        let config = handler.with_model(&request().agent_config, &fallback);
+        let override_config = config.override_base_config.unwrap();
+        assert_eq!(override_config.model.provider, "ollama");
+        assert!(override_config.fallback_model.is_none());
+
+        // Transient failures are left to the caller to retry
+        let result = run_with_fallback(Some(&fallback), |model| {
+            assert!(model.is_none(), "a rate limit should not switch models");
+            async {
+                Err::<(), _>(agent_error(
+                    "429 Too Many Requests: rate limit exceeded".to_string(),
+                ))
+            }
+        })
+        .await;
+        assert!(result.is_err());
+
+        // Without a fallback the primary error is returned as is
+        let result = run_with_fallback(None, |_| async {
+            Err::<(), _>(agent_error("model 'gpt-5-mini' does not exist".to_string()))
+        })
+        .await;
+        assert!(needs_fallback_model(&result.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn test_fallback_runs_in_the_primary_attempts_session() {
+        let workspace = tempfile::tempdir().unwrap();
+        let handler = handler(false);
+        let mut request = request();
+        request.agent_config.workspace = workspace.path().to_path_buf();
+        let fallback = ModelConfig {
+            provider: "ollama".to_string(),
+            model: "qwen2.5-coder".to_string(),
+            ..ModelConfig::default()
+        };
+        let attempt_session = std::sync::Mutex::new(None);
+
+        let result = run_with_fallback(Some(&fallback), |model| {
+            let (handler, request, attempt_session) = (&handler, &request, &attempt_session);
+            async move {
+                let session = handler
+                    .attempt_session(request, &request.agent_config, attempt_session)
+                    .await?
+                    .session;
+                SessionManager::add_message(&session.id, &Message::user().with_text("fix"))
+                    .await
+                    .unwrap();
+                match model {
+                    None => Err(crate::KaiakError::agent_initialization(
+                        "Failed to create provider 'openai' with model 'gpt-4o'",
+                    )),
+                    Some(_) => Ok(session.id),
+                }
+            }
+        })
+        .await;
+
+        // One session, holding only the fallback's prompt
+        let (session_id, fallback_reason) = result.unwrap();
+        let manager = &handler.agent_manager;
+        assert!(fallback_reason.is_some());
+        assert_eq!(manager.stats().snapshot(false).sessions_created, 1);
+        let messages = manager.session_wrapper().messages(&session_id).await;
+        assert_eq!(messages.unwrap().len(), 1);
+
+        manager.delete_session(&session_id).await.unwrap();
+    }
+
+    #[test]
+    fn test_skipped_and_truncated_incidents_are_distinct_warnings() {
+        let mut request = request();
+        request.incidents[0].description = "Reorder imports. ".repeat(50);
+        request.incidents[1].severity = Some(crate::models::IncidentSeverity::Info);
+        let prompt = PromptBuilder::new()
+            .with_incident_text_limit(100)
+            .with_max_prompt_tokens(Some(1))
+            .build(&request.incidents);
+
+        let warnings = prompt_warnings(&prompt);
+        let codes: Vec<(&str, &serde_json::Value)> = warnings
+            .iter()
+            .map(|w| (w.code.as_str(), &w.context.as_ref().unwrap()["incident_id"]))
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ("incident_skipped", &serde_json::json!("api-1")),
+                ("incident_truncated", &serde_json::json!("format-1")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_minimal_verbosity_streams_only_errors_and_interactions() {
+        let handler = handler(false);
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+        handler
+            .verbosities
+            .lock()
+            .unwrap()
+            .insert("req-1".to_string(), Verbosity::Minimal);
+
+        for kind in [
+            GenerateFixDataKind::Thinking,
+            GenerateFixDataKind::ToolProgress,
+            GenerateFixDataKind::AiMessage,
+            GenerateFixDataKind::ToolCall,
+            GenerateFixDataKind::Error,
+            GenerateFixDataKind::UserInteraction,
+        ] {
+            handler.send_notification(&notifier, "req-1", "session-1", kind, serde_json::json!({}));
+        }
+        drop(notifier);
+
+        let mut kinds = Vec::new();
+        while let Some(notification) = notifications.recv().await {
+            kinds.push(
+                notification.params.unwrap()["kind"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+            );
+        }
+        assert_eq!(kinds, vec!["error", "user_interaction"]);
+
+        // Progress is only streamed at verbose
+        assert!(!Verbosity::Normal.streams(&GenerateFixDataKind::ToolProgress));
+        assert!(Verbosity::Verbose.streams(&GenerateFixDataKind::ToolProgress));
+    }
+
+    #[tokio::test]
+    async fn test_thinking_suppressed_when_stream_thinking_off() {
+        let message = Message::assistant()
+            .with_thinking("Consider javax usages first", "signature")
+            .with_text("Replaced javax.xml.bind with jakarta.xml.bind");
+
+        assert_eq!(
+            streamed_kinds(&message).await,
+            vec!["thinking", "ai_message"]
+        );
+
+        let mut suppressed = message.clone();
+        drop_thinking(&mut suppressed);
+        assert_eq!(streamed_kinds(&suppressed).await, vec!["ai_message"]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_provider_is_a_configuration_error() {
+        let mut request = request();
+        let mut base_config = BaseConfig::default();
+        base_config.model.provider = String::new();
+        request.agent_config.override_base_config = Some(base_config);
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+
+        let error = handler(false)
+            .handle_generate_fix(request, notifier)
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(error, crate::KaiakError::Configuration { .. }),
+            "{error:?}"
+        );
+        let message = error.to_string();
+        assert!(
+            message.contains("No model provider configured"),
+            "{message}"
+        );
+        assert!(message.contains("KAIAK_MODEL_PROVIDER"), "{message}");
+        assert!(
+            message.contains("override_base_config.model.provider"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_incident_metadata_is_echoed_in_report() {
+        let mut request = request();
+        let metadata = serde_json::json!({
+            "source": "kantra",
+            "links": [{ "title": "Rule docs", "url": "https://example.com/rules/api-1" }],
+        });
+        request.incidents[1].metadata = metadata.clone();
+        request.reference_links = true;
+        let request: GenerateFixRequest =
+            serde_json::from_value(serde_json::to_value(&request).unwrap()).unwrap();
+        assert!(request.validate().is_ok());
+
+        let prompt = PromptBuilder::new()
+            .with_reference_links(request.reference_links)
+            .build(&request.incidents);
+        assert!(prompt.text.contains("See: https://example.com/rules/api-1"));
+
+        let report = MigrationReportCollector::new("/workspace")
+            .with_incidents(&request.incidents, &[])
+            .finish(0);
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!(report["incidents"][1]["incident_id"], "api-1");
+        assert_eq!(report["incidents"][1]["metadata"], metadata);
+        assert!(report["incidents"][0].get("metadata").is_none());
+
+        let notes = "x".repeat(crate::models::incidents::MAX_INCIDENT_METADATA_BYTES);
+        let mut oversized = request.clone();
+        oversized.incidents[0].metadata = serde_json::json!({ "notes": notes });
+        assert!(oversized.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_only_normalizes_incidents() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path();
+        std::fs::create_dir(workspace.join("src")).unwrap();
+        std::fs::write(workspace.join("src/A.java"), "class A {}\n").unwrap();
+
+        let incident = |id: &str, uri: &str, line: usize| {
+            MigrationIncident::new(
+                id.to_string(),
+                uri.to_string(),
+                "msg".to_string(),
+                String::new(),
+            )
+            .with_line_number(line)
+        };
+        let request = GenerateFixRequest {
+            incidents: vec![
+                incident("b", "src/A.java", 9),
+                incident("missing", "src/Gone.java", 1),
+                incident("a", "src/A.java", 3),
+                incident("b", "src/A.java", 9),
+            ],
+            agent_config: AgentConfig {
+                workspace: workspace.to_path_buf(),
+                ..AgentConfig::default()
+            },
+            validate_only: true,
+            ..request()
+        };
+
+        // An associated function: there is no agent manager for it to touch
+        let response = GenerateFixHandler::validate_only(&request, &BaseConfig::default()).unwrap();
+
+        let ids: Vec<&str> = response
+            .incidents
+            .iter()
+            .map(|i| i.incident.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "b", "missing"]);
+        assert_eq!(
+            response.incidents[0].resolved_path,
+            workspace.join("src/A.java")
+        );
+        assert_eq!(response.warnings.len(), 2);
+        assert!(response.warnings[0].contains("'missing' file not found"));
+        assert!(response.warnings[1].contains("Duplicate incident ID 'b'"));
+    }
+
+    #[tokio::test]
+    async fn test_unlisted_tool_follows_default_permission() {
+        let base_config = BaseConfig {
+            tool_permissions: HashMap::from([(
+                "developer__text_editor".to_string(),
+                PermissionLevel::AskBefore,
+            )]),
+            default_tool_permission: PermissionLevel::NeverAllow,
+            ..BaseConfig::default()
+        };
+        let handler = GenerateFixHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        );
+        register(&handler, "req-1").await;
+        let (notifier, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+
+        handler
+            .handle_tool_confirmation(
+                &notifier,
+                "req-1",
+                "session-1",
+                &goose::agents::Agent::new(),
+                "tool-1",
+                "developer__shell",
+                &serde_json::json!({ "command": "rm -rf build" })
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+                &None,
+            )
+            .await;
+
+        let params = notifications.try_recv().unwrap().params.unwrap();
+        assert_eq!(params["kind"], "error");
+        assert_eq!(params["payload"]["error"], "tool_not_permitted");
+        assert_eq!(params["payload"]["tool_name"], "developer__shell");
+        // Denied outright: no confirmation was registered or requested from the client
+        assert!(notifications.try_recv().is_err());
+    }
+}
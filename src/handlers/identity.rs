@@ -0,0 +1,103 @@
+//! Identity of the client driving the server, for multi-tenant deployments.
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tracing::info;
+use validator::Validate;
+
+use crate::{KaiakError, KaiakResult};
+
+/// Identity used until one is configured or set by the client
+pub const ANONYMOUS_IDENTITY: &str = "anonymous";
+
+/// Request type for kaiak/set_identity endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SetIdentityRequest {
+    #[validate(length(min = 1, max = 256, message = "Identity must be 1-256 characters"))]
+    pub identity: String,
+}
+
+/// Response type for kaiak/set_identity and kaiak/whoami endpoints
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdentityResponse {
+    pub identity: String,
+}
+
+/// Identity of the current client connection, shared by the handlers that log or audit
+/// on its behalf. The server serves one connection at a time, so there is a single
+/// identity: the one read from the environment at startup until a client sets its own,
+/// and again once that client disconnects.
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    current: Arc<RwLock<String>>,
+    /// Identity a connection starts with
+    initial: Arc<str>,
+}
+
+impl ClientIdentity {
+    pub fn new(identity: impl Into<String>) -> Self {
+        let identity = identity.into();
+        Self {
+            initial: identity.as_str().into(),
+            current: Arc::new(RwLock::new(identity)),
+        }
+    }
+
+    /// Identity from the environment variable `var`, or anonymous when it is unset or blank
+    pub fn from_env(var: &str) -> Self {
+        match std::env::var(var) {
+            Ok(identity) if !identity.trim().is_empty() => Self::new(identity.trim()),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn get(&self) -> String {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn set(&self, identity: impl Into<String>) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = identity.into();
+    }
+
+    /// Forget the identity a client set, going back to the one connections start with
+    pub fn reset(&self) {
+        self.set(self.initial.as_ref());
+    }
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        Self::new(ANONYMOUS_IDENTITY)
+    }
+}
+
+/// Handler for kaiak/set_identity and kaiak/whoami
+pub struct IdentityHandler {
+    identity: ClientIdentity,
+}
+
+impl IdentityHandler {
+    pub fn new(identity: ClientIdentity) -> Self {
+        Self { identity }
+    }
+
+    pub fn handle_set_identity(&self, request: SetIdentityRequest) -> KaiakResult<IdentityResponse> {
+        request
+            .validate()
+            .map_err(|e| KaiakError::InvalidParams(format!("Invalid set_identity request: {}", e)))?;
+
+        let identity = request.identity.trim();
+        if identity.is_empty() {
+            return Err(KaiakError::InvalidParams("Identity cannot be blank".to_string()));
+        }
+        info!("Client identity set to {}", identity);
+        self.identity.set(identity);
+        Ok(self.handle_whoami())
+    }
+
+    pub fn handle_whoami(&self) -> IdentityResponse {
+        IdentityResponse {
+            identity: self.identity.get(),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! List models handler for discovering the models the configured provider offers.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use goose::providers::base::Provider;
+
+use crate::agent::GooseAgentManager;
+use crate::models::configuration::BaseConfig;
+use crate::{KaiakError, KaiakResult};
+
+/// How long a provider's model catalog is reused before it is fetched again
+const MODEL_CATALOG_TTL: Duration = Duration::from_secs(600);
+
+/// Request type for kaiak/list_models endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListModelsRequest {
+    /// Ignore any cached catalog and query the provider again
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Response type for kaiak/list_models endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ListModelsResponse {
+    pub provider: String,
+    pub models: Vec<String>,
+    /// Model the server uses unless a request overrides it
+    pub default_model: String,
+    /// Whether the models came from the cache rather than the provider
+    pub cached: bool,
+    /// Set when the provider has no catalog and only the configured model is listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Something that can report the models a provider offers
+#[async_trait]
+pub trait ModelSource: Send + Sync {
+    /// Available models, or None when the provider has no catalog API
+    async fn supported_models(&self) -> KaiakResult<Option<Vec<String>>>;
+}
+
+#[async_trait]
+impl ModelSource for Arc<dyn Provider> {
+    async fn supported_models(&self) -> KaiakResult<Option<Vec<String>>> {
+        self.fetch_supported_models().await.map_err(|e| {
+            KaiakError::agent(format!("Failed to fetch supported models: {}", e), None)
+        })
+    }
+}
+
+/// Model catalogs per provider, shared across requests
+#[derive(Default)]
+pub struct ModelCatalogCache {
+    entries: Mutex<HashMap<String, CachedCatalog>>,
+}
+
+struct CachedCatalog {
+    fetched_at: Instant,
+    models: Option<Vec<String>>,
+}
+
+impl ModelCatalogCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, provider: &str) -> Option<Option<Vec<String>>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(provider)
+            .filter(|entry| entry.fetched_at.elapsed() < MODEL_CATALOG_TTL)
+            .map(|entry| entry.models.clone())
+    }
+
+    fn insert(&self, provider: &str, models: Option<Vec<String>>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(
+            provider.to_string(),
+            CachedCatalog {
+                fetched_at: Instant::now(),
+                models,
+            },
+        );
+    }
+}
+
+/// Handler for kaiak/list_models endpoint
+pub struct ListModelsHandler {
+    base_config: Arc<BaseConfig>,
+    cache: Arc<ModelCatalogCache>,
+}
+
+impl ListModelsHandler {
+    pub fn new(base_config: Arc<BaseConfig>, cache: Arc<ModelCatalogCache>) -> Self {
+        Self { base_config, cache }
+    }
+
+    /// Handle list models request for the server's configured provider
+    pub async fn handle_list_models(
+        &self,
+        request: ListModelsRequest,
+    ) -> KaiakResult<ListModelsResponse> {
+        let model = &self.base_config.model;
+        info!(
+            "Processing list_models request for provider: {}",
+            model.provider
+        );
+
+        if !request.refresh {
+            if let Some(models) = self.cache.get(&model.provider) {
+                debug!("Using cached model catalog for {}", model.provider);
+                return Ok(self.response(models, true));
+            }
+        }
+
+        let provider = GooseAgentManager::create_provider(model).await?;
+        self.list_from(&provider).await
+    }
+
+    /// Query a model source and cache its catalog
+    pub async fn list_from(&self, source: &dyn ModelSource) -> KaiakResult<ListModelsResponse> {
+        let models = source.supported_models().await?;
+        self.cache
+            .insert(&self.base_config.model.provider, models.clone());
+        Ok(self.response(models, false))
+    }
+
+    fn response(&self, models: Option<Vec<String>>, cached: bool) -> ListModelsResponse {
+        let model = &self.base_config.model;
+        let (models, note) = match models {
+            Some(models) => (models, None),
+            None => (
+                vec![model.model.clone()],
+                Some(format!(
+                    "Provider '{}' does not publish a model catalog; only the configured model is listed",
+                    model.provider
+                )),
+            ),
+        };
+        ListModelsResponse {
+            provider: model.provider.clone(),
+            models,
+            default_model: model.model.clone(),
+            cached,
+            note,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct MockProvider {
+        models: Option<Vec<String>>,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ModelSource for MockProvider {
+        async fn supported_models(&self) -> KaiakResult<Option<Vec<String>>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.models.clone())
+        }
+    }
+
+    fn handler() -> ListModelsHandler {
+        ListModelsHandler::new(
+            Arc::new(BaseConfig::default()),
+            Arc::new(ModelCatalogCache::new()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_advertised_models_are_returned_and_cached() {
+        let handler = handler();
+        let provider = MockProvider {
+            models: Some(vec!["model-large".to_string(), "model-small".to_string()]),
+            calls: AtomicUsize::new(0),
+        };
+
+        let response = handler.list_from(&provider).await.unwrap();
+        assert_eq!(response.models, vec!["model-large", "model-small"]);
+        assert!(!response.cached);
+        assert!(response.note.is_none());
+
+        // Served from the cache without querying the provider again
+        let cached = handler
+            .handle_list_models(ListModelsRequest::default())
+            .await
+            .unwrap();
+        assert_eq!(cached.models, response.models);
+        assert!(cached.cached);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_provider_without_catalog_lists_default_model() {
+        let handler = handler();
+        let provider = MockProvider {
+            models: None,
+            calls: AtomicUsize::new(0),
+        };
+
+        let response = handler.list_from(&provider).await.unwrap();
+        assert_eq!(response.models, vec![response.default_model.clone()]);
+        assert!(response.note.is_some());
+    }
+}
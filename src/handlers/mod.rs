@@ -1,16 +1,56 @@
 /// JSON-RPC procedure handlers
 pub mod generate_fix;
 pub mod delete_session;
+pub mod reset_session;
+pub mod export_session;
+pub mod update_session_config;
+pub mod tool_results;
+pub mod retry_failed;
+pub mod fix_cache;
+pub mod suggestions;
+pub mod rollback;
+pub mod ready;
+pub mod stats;
+pub mod stream_logs;
+pub mod list_models;
 pub mod client_notifications;
 pub mod interaction_manager;
+pub mod file_guard;
+pub mod identity;
+pub mod audit;
 
 pub use generate_fix::{
-    GenerateFixHandler, GenerateFixRequest, GenerateFixResponse,
+    ActiveRequests, GenerateFixHandler, GenerateFixRequest, GenerateFixResponse,
     GenerateFixData, GenerateFixDataKind, UserInteractionPayload,
+    ValidateOnlyResponse, NormalizedIncident, PromptPreview, PromptPreviewTurn,
 };
 pub use delete_session::{DeleteSessionHandler, DeleteSessionRequest, DeleteSessionResponse};
+pub use reset_session::{
+    ResetSessionContextHandler, ResetSessionContextRequest, ResetSessionContextResponse,
+};
+pub use export_session::{ExportSessionHandler, ExportSessionRequest};
+pub use tool_results::{
+    GetToolResultHandler, GetToolResultRequest, GetToolResultResponse, ToolResultRef,
+    ToolResultStore,
+};
+pub use retry_failed::{CompletedFixes, RetryFailedRequest};
+pub use fix_cache::FixCache;
+pub use rollback::{CompletedBackups, RollbackHandler, RollbackRequest, RollbackResponse};
+pub use ready::{ReadyHandler, ReadyResponse};
+pub use stats::{StatsHandler, StatsRequest, StatsResponse};
+pub use stream_logs::{StreamLogsHandler, StreamLogsRequest, StreamLogsResponse};
+pub use update_session_config::{
+    UpdateSessionConfigHandler, UpdateSessionConfigRequest, UpdateSessionConfigResponse,
+};
+pub use list_models::{
+    ListModelsHandler, ListModelsRequest, ListModelsResponse, ModelCatalogCache, ModelSource,
+};
 pub use client_notifications::{
     ClientNotificationHandler, ClientNotificationRequest, ClientNotificationResponse,
     ClientNotificationKind, ToolConfirmationPayload, ElicitationResponsePayload,
 };
-pub use interaction_manager::InteractionManager;
\ No newline at end of file
+pub use interaction_manager::InteractionManager;
+pub use identity::{
+    ClientIdentity, IdentityHandler, IdentityResponse, SetIdentityRequest, ANONYMOUS_IDENTITY,
+};
+pub use audit::{AuditDecision, AuditEntry, AuditLog};
\ No newline at end of file
@@ -0,0 +1,128 @@
+//! Readiness of the server to serve generate_fix requests, for orchestrators that only
+//! route traffic to ready instances.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use validator::Validate;
+
+use crate::agent::credentials::missing_credentials;
+use crate::agent::GooseAgentManager;
+use crate::jsonrpc::ServerStatus;
+use crate::models::configuration::BaseConfig;
+
+/// Response type for kaiak/ready endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    /// Why the server is not ready; empty when it is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<String>,
+}
+
+/// Handler for kaiak/ready endpoint
+pub struct ReadyHandler {
+    agent_manager: Arc<GooseAgentManager>,
+    base_config: Arc<BaseConfig>,
+    status: ServerStatus,
+}
+
+impl ReadyHandler {
+    pub fn new(
+        agent_manager: Arc<GooseAgentManager>,
+        base_config: Arc<BaseConfig>,
+        status: ServerStatus,
+    ) -> Self {
+        Self {
+            agent_manager,
+            base_config,
+            status,
+        }
+    }
+
+    /// Ready when the configuration is valid, its provider is allowed and its credentials
+    /// resolve, and the server is accepting connections
+    pub async fn handle_ready(&self) -> ReadyResponse {
+        let mut reasons = Vec::new();
+
+        if let Err(e) = self.base_config.validate() {
+            reasons.push(format!("Configuration is invalid: {}", e));
+        }
+
+        let provider = &self.base_config.model.provider;
+        if let Err(e) = self.agent_manager.check_provider_allowed(provider) {
+            reasons.push(e.to_string());
+        }
+        // Providers with unknown credential keys are only checked when a session creates them
+        if let Some(missing) = missing_credentials(provider) {
+            if !missing.is_empty() {
+                reasons.push(format!(
+                    "Credentials for provider '{}' do not resolve: missing {}",
+                    provider,
+                    missing.join(", ")
+                ));
+            }
+        }
+
+        if !self.status.is_running().await {
+            reasons.push("Server is not accepting connections".to_string());
+        }
+
+        ReadyResponse {
+            ready: reasons.is_empty(),
+            reasons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{JsonRpcServer, TransportConfig};
+    use crate::models::configuration::{Framing, ModelConfig};
+
+    #[tokio::test]
+    async fn test_ready_only_after_provider_setup() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = JsonRpcServer::new(TransportConfig::UnixSocket {
+            path: dir.path().join("kaiak.sock").display().to_string(),
+            framing: Framing::default(),
+        })
+        .await
+        .unwrap();
+        let base_config = BaseConfig {
+            model: ModelConfig {
+                provider: "groq".to_string(),
+                model: "llama-3.3-70b-versatile".to_string(),
+                ..ModelConfig::default()
+            },
+            ..BaseConfig::default()
+        };
+        let handler = ReadyHandler::new(
+            Arc::new(GooseAgentManager::new()),
+            Arc::new(base_config),
+            server.status(),
+        );
+
+        std::env::remove_var("GROQ_API_KEY");
+        let response = handler.handle_ready().await;
+        assert!(!response.ready);
+        assert_eq!(response.reasons.len(), 2, "{:?}", response.reasons);
+        assert!(response.reasons[0].contains("GROQ_API_KEY"));
+
+        std::env::set_var("GROQ_API_KEY", "test-key");
+        // The server only runs while `start` is polled
+        let response = tokio::select! {
+            result = server.start() => panic!("server stopped: {:?}", result),
+            response = async {
+                while !handler.status.is_running().await {
+                    tokio::task::yield_now().await;
+                }
+                handler.handle_ready().await
+            } => response,
+        };
+        std::env::remove_var("GROQ_API_KEY");
+
+        assert!(response.ready, "{:?}", response.reasons);
+        assert!(response.reasons.is_empty());
+    }
+}
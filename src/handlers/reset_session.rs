@@ -0,0 +1,102 @@
+//! Reset session context handler for clearing a session's conversation history.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::generate_fix::{GenerateFixData, GenerateFixDataKind};
+use crate::agent::GooseAgentManager;
+use crate::jsonrpc::{methods::GENERATE_FIX_DATA, JsonRpcNotification, NotificationSender};
+use crate::{KaiakError, KaiakResult};
+
+/// Request type for kaiak/reset_session_context endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ResetSessionContextRequest {
+    /// Session whose conversation history should be cleared
+    #[validate(length(min = 1, message = "Session ID cannot be empty"))]
+    pub session_id: String,
+}
+
+/// Response type for kaiak/reset_session_context endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResetSessionContextResponse {
+    pub session_id: String,
+    /// Number of messages in the session before the reset
+    pub cleared_messages: usize,
+    /// Number of messages remaining after the reset (always zero on success)
+    pub message_count: usize,
+    pub reset_at: String,
+}
+
+/// Handler for kaiak/reset_session_context endpoint
+/// Clears conversation history while keeping the agent, provider, and configuration
+pub struct ResetSessionContextHandler {
+    agent_manager: Arc<GooseAgentManager>,
+}
+
+impl ResetSessionContextHandler {
+    pub fn new(agent_manager: Arc<GooseAgentManager>) -> Self {
+        Self { agent_manager }
+    }
+
+    /// Handle reset session context request
+    pub async fn handle_reset_session_context(
+        &self,
+        request: ResetSessionContextRequest,
+        notifier: NotificationSender,
+    ) -> KaiakResult<ResetSessionContextResponse> {
+        info!("Processing reset_session_context request for: {}", request.session_id);
+
+        if let Err(validation_errors) = request.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        let cleared_messages = self
+            .agent_manager
+            .session_wrapper()
+            .get_session(&request.session_id)
+            .await?
+            .map(|info| info.session.message_count)
+            .ok_or_else(|| KaiakError::SessionNotFound(request.session_id.clone()))?;
+
+        let session_info = self
+            .agent_manager
+            .reset_session_context(&request.session_id)
+            .await?;
+
+        let data = GenerateFixData {
+            request_id: Uuid::new_v4().to_string(),
+            session_id: request.session_id.clone(),
+            kind: GenerateFixDataKind::System,
+            incident_id: None,
+            payload: serde_json::json!({
+                "event": "context_reset",
+                "cleared_messages": cleared_messages,
+            }),
+        };
+        let notification = JsonRpcNotification::new(
+            GENERATE_FIX_DATA,
+            Some(serde_json::to_value(&data).unwrap_or_default()),
+        );
+        if let Err(e) = notifier.send(notification) {
+            warn!("Failed to send notification: {}", e);
+        }
+
+        info!(
+            "Cleared {} messages from session {}",
+            cleared_messages, request.session_id
+        );
+
+        Ok(ResetSessionContextResponse {
+            session_id: session_info.session.id,
+            cleared_messages,
+            message_count: session_info.session.message_count,
+            reset_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
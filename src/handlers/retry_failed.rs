@@ -0,0 +1,227 @@
+//! Finished generate_fix requests, kept so their failed incidents can be retried.
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use tracing::info;
+use validator::Validate;
+
+use super::generate_fix::GenerateFixRequest;
+use crate::models::configuration::BaseConfig;
+use crate::models::patch::ApplyMode;
+use crate::models::report::{IncidentOutcome, MigrationReport};
+use crate::{KaiakError, KaiakResult};
+
+/// Finished requests kept for `kaiak/retry_failed`; older ones are forgotten first
+pub const MAX_COMPLETED_FIXES: usize = 100;
+
+/// A finished request and the outcome of each of its incidents
+#[derive(Debug, Clone)]
+struct CompletedFix {
+    /// The request as it ran, with its session ID
+    request: GenerateFixRequest,
+    outcomes: HashMap<String, IncidentOutcome>,
+}
+
+/// Recently finished generate_fix requests, by request ID
+#[derive(Debug)]
+pub struct CompletedFixes {
+    fixes: Mutex<LruCache<String, CompletedFix>>,
+}
+
+impl Default for CompletedFixes {
+    fn default() -> Self {
+        Self::new(MAX_COMPLETED_FIXES)
+    }
+}
+
+impl CompletedFixes {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            fixes: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Remember a finished request with the session it ran in and its report's outcomes
+    pub fn record(
+        &self,
+        request_id: &str,
+        request: &GenerateFixRequest,
+        session_id: &str,
+        report: &MigrationReport,
+    ) {
+        let mut request = request.clone();
+        // A patch mode request's session ends with it
+        if request.apply_mode != ApplyMode::Patch {
+            request.session_id = Some(session_id.to_string());
+        }
+        let outcomes = report
+            .incidents
+            .iter()
+            .map(|result| (result.incident_id.clone(), result.outcome))
+            .collect();
+        self.fixes
+            .lock()
+            .unwrap()
+            .put(request_id.to_string(), CompletedFix { request, outcomes });
+    }
+
+    /// Build a request for the incidents of an earlier request that failed or were
+    /// skipped, in the same session
+    pub fn retry_request(&self, retry: &RetryFailedRequest) -> KaiakResult<GenerateFixRequest> {
+        if let Err(validation_errors) = retry.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        let fix = self
+            .fixes
+            .lock()
+            .unwrap()
+            .get(&retry.request_id)
+            .cloned()
+            .ok_or_else(|| {
+                KaiakError::configuration(format!(
+                    "No finished request {}; only the last {} successful requests can be retried",
+                    retry.request_id, MAX_COMPLETED_FIXES
+                ))
+            })?;
+
+        let mut request = fix.request;
+        request.incidents.retain(|incident| {
+            fix.outcomes.get(&incident.id) != Some(&IncidentOutcome::Fixed)
+        });
+        if request.incidents.is_empty() {
+            return Err(KaiakError::configuration(format!(
+                "Request {} has no failed or skipped incidents to retry",
+                retry.request_id
+            )));
+        }
+        if let Some(config) = &retry.override_base_config {
+            request.agent_config.override_base_config = Some(config.clone());
+        }
+
+        info!(
+            "Retrying {} incidents of request {}",
+            request.incidents.len(),
+            retry.request_id
+        );
+        Ok(request)
+    }
+}
+
+/// Request type for kaiak/retry_failed endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RetryFailedRequest {
+    /// `request_id` of a finished generate_fix request
+    #[validate(length(min = 1, message = "Request ID cannot be empty"))]
+    pub request_id: String,
+    /// Replaces the earlier request's `agent_config.override_base_config`, e.g. to retry
+    /// on another model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(nested)]
+    pub override_base_config: Option<BaseConfig>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::configuration::{AgentConfig, ModelConfig};
+    use crate::models::report::IncidentResult;
+    use crate::models::incidents::MigrationIncident;
+
+    fn request(ids: &[&str]) -> GenerateFixRequest {
+        serde_json::from_value(serde_json::json!({
+            "incidents": ids
+                .iter()
+                .map(|id| MigrationIncident::new(
+                    id.to_string(),
+                    format!("file:///workspace/src/{}.java", id),
+                    "Fix it".to_string(),
+                    String::new(),
+                ))
+                .collect::<Vec<_>>(),
+            "agent_config": AgentConfig {
+                workspace: std::path::PathBuf::from("/workspace"),
+                ..AgentConfig::default()
+            },
+        }))
+        .unwrap()
+    }
+
+    fn report(outcomes: &[(&str, IncidentOutcome)]) -> MigrationReport {
+        MigrationReport {
+            incidents: outcomes
+                .iter()
+                .map(|(id, outcome)| IncidentResult {
+                    incident_id: id.to_string(),
+                    outcome: *outcome,
+                    fixed_by: None,
+                    metadata: serde_json::Value::Null,
+                })
+                .collect(),
+            ..MigrationReport::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_only_reruns_failed_and_skipped_incidents() {
+        let fixes = CompletedFixes::default();
+        fixes.record(
+            "req-1",
+            &request(&["a", "b", "c", "d"]),
+            "session-1",
+            &report(&[
+                ("a", IncidentOutcome::Fixed),
+                ("b", IncidentOutcome::Failed),
+                ("c", IncidentOutcome::Fixed),
+                ("d", IncidentOutcome::Skipped),
+            ]),
+        );
+
+        let model = ModelConfig {
+            provider: "anthropic".to_string(),
+            model: "claude-sonnet-4".to_string(),
+            ..ModelConfig::default()
+        };
+        let retry = fixes
+            .retry_request(&RetryFailedRequest {
+                request_id: "req-1".to_string(),
+                override_base_config: Some(BaseConfig {
+                    model,
+                    ..BaseConfig::default()
+                }),
+            })
+            .unwrap();
+
+        let ids: Vec<&str> = retry.incidents.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "d"]);
+        assert_eq!(retry.session_id.as_deref(), Some("session-1"));
+        let config = retry.agent_config.override_base_config.unwrap();
+        assert_eq!(config.model.provider, "anthropic");
+
+        // A retry that fixed everything leaves nothing to retry
+        fixes.record(
+            "req-2",
+            &request(&["b", "d"]),
+            "session-1",
+            &report(&[("b", IncidentOutcome::Fixed), ("d", IncidentOutcome::Fixed)]),
+        );
+        let request = RetryFailedRequest {
+            request_id: "req-2".to_string(),
+            override_base_config: None,
+        };
+        assert!(fixes.retry_request(&request).is_err());
+
+        let unknown = RetryFailedRequest {
+            request_id: "missing".to_string(),
+            override_base_config: None,
+        };
+        assert!(fixes.retry_request(&unknown).is_err());
+    }
+}
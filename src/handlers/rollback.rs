@@ -0,0 +1,185 @@
+//! Backups of finished generate_fix requests, kept so their modifications can be undone.
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+use validator::Validate;
+
+use crate::models::backup::{RollbackFailure, WorkspaceBackup};
+use crate::{KaiakError, KaiakResult};
+
+/// Finished requests kept for `kaiak/rollback_request`; older ones are forgotten first
+pub const MAX_COMPLETED_BACKUPS: usize = 100;
+
+/// Backups of recently finished generate_fix requests, by request ID; `None` for a
+/// request that ran without backups
+#[derive(Debug)]
+pub struct CompletedBackups {
+    backups: Mutex<LruCache<String, Option<WorkspaceBackup>>>,
+}
+
+impl Default for CompletedBackups {
+    fn default() -> Self {
+        Self::new(MAX_COMPLETED_BACKUPS)
+    }
+}
+
+impl CompletedBackups {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            backups: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Remember a finished request's backup, sealed with the files as it left them
+    pub fn record(&self, request_id: &str, backup: Option<WorkspaceBackup>) {
+        let backup = backup.map(|mut backup| {
+            backup.seal();
+            backup
+        });
+        self.backups
+            .lock()
+            .unwrap()
+            .put(request_id.to_string(), backup);
+    }
+
+    /// Take a request's backup, so two rollbacks of a request never run at once
+    fn take(&self, request_id: &str) -> KaiakResult<WorkspaceBackup> {
+        let mut backups = self.backups.lock().unwrap();
+        match backups.pop(request_id) {
+            Some(Some(backup)) => Ok(backup),
+            Some(None) => {
+                backups.put(request_id.to_string(), None);
+                Err(KaiakError::InvalidParams(format!(
+                    "Request {} ran without backups; set base_config.backup_strategy to enable them",
+                    request_id
+                )))
+            }
+            None => Err(KaiakError::InvalidParams(format!(
+                "No finished request {} to roll back; only the last {} requests can be rolled back, until all their files are",
+                request_id, MAX_COMPLETED_BACKUPS
+            ))),
+        }
+    }
+
+    /// Return a backup taken for a rollback that left files as they are, so it can be
+    /// retried for them
+    fn put_back(&self, request_id: &str, backup: WorkspaceBackup) {
+        self.backups
+            .lock()
+            .unwrap()
+            .put(request_id.to_string(), Some(backup));
+    }
+}
+
+/// Request type for kaiak/rollback_request endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RollbackRequest {
+    /// `request_id` of a finished generate_fix request
+    #[validate(length(min = 1, message = "Request ID cannot be empty"))]
+    pub request_id: String,
+}
+
+/// Response type for kaiak/rollback_request endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackResponse {
+    pub request_id: String,
+    /// Files restored to their content before the request, or removed when it created them
+    pub rolled_back: Vec<String>,
+    /// Files left as they are, with the reason
+    pub not_rolled_back: Vec<RollbackFailure>,
+}
+
+/// Handler for kaiak/rollback_request endpoint
+pub struct RollbackHandler {
+    completed_backups: Arc<CompletedBackups>,
+}
+
+impl RollbackHandler {
+    pub fn new(completed_backups: Arc<CompletedBackups>) -> Self {
+        Self { completed_backups }
+    }
+
+    pub fn handle_rollback(&self, request: RollbackRequest) -> KaiakResult<RollbackResponse> {
+        if let Err(validation_errors) = request.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        let mut backup = self.completed_backups.take(&request.request_id)?;
+        let outcome = backup.rollback();
+        info!(
+            "Rolled back {} files of request {}; {} left as they are",
+            outcome.rolled_back.len(),
+            request.request_id,
+            outcome.not_rolled_back.len()
+        );
+        if !outcome.not_rolled_back.is_empty() {
+            self.completed_backups.put_back(&request.request_id, backup);
+        }
+        Ok(RollbackResponse {
+            request_id: request.request_id,
+            rolled_back: outcome
+                .rolled_back
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect(),
+            not_rolled_back: outcome.not_rolled_back,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::configuration::BackupStrategy;
+
+    #[test]
+    fn test_partial_rollback_can_be_retried() {
+        let workspace = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let first = workspace.path().join("First.java");
+        let second = workspace.path().join("Second.java");
+        std::fs::write(&first, "javax").unwrap();
+        std::fs::write(&second, "javax").unwrap();
+
+        let mut backup = WorkspaceBackup::new(
+            BackupStrategy::Copy,
+            workspace.path(),
+            backup_dir.path(),
+            "req-1",
+        );
+        for path in [&first, &second] {
+            backup.backup(path).unwrap();
+            std::fs::write(path, "jakarta").unwrap();
+        }
+        let completed_backups = Arc::new(CompletedBackups::default());
+        completed_backups.record("req-1", Some(backup));
+        let handler = RollbackHandler::new(completed_backups);
+        let request = RollbackRequest {
+            request_id: "req-1".to_string(),
+        };
+
+        // Edited after the request finished
+        std::fs::write(&second, "jakarta, edited").unwrap();
+        let partial = handler.handle_rollback(request.clone()).unwrap();
+        assert_eq!(partial.rolled_back, vec![first.display().to_string()]);
+        assert_eq!(partial.not_rolled_back.len(), 1);
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "javax");
+
+        // Once the edit is undone, the rest of the request rolls back
+        std::fs::write(&second, "jakarta").unwrap();
+        let rest = handler.handle_rollback(request.clone()).unwrap();
+        assert_eq!(rest.rolled_back, vec![second.display().to_string()]);
+        assert!(rest.not_rolled_back.is_empty());
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), "javax");
+
+        let error = handler.handle_rollback(request).unwrap_err();
+        assert!(matches!(error, KaiakError::InvalidParams(_)), "{:?}", error);
+    }
+}
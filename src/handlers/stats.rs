@@ -0,0 +1,131 @@
+//! Server-wide totals in one call, for dashboards.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::agent::{GooseAgentManager, StatsSnapshot};
+
+/// Request type for kaiak/stats endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StatsRequest {
+    /// Start the totals over from zero once they are read
+    #[serde(default)]
+    pub reset: bool,
+}
+
+/// Response type for kaiak/stats endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsResponse {
+    #[serde(flatten)]
+    pub totals: StatsSnapshot,
+    /// Agents currently loaded for sessions
+    pub active_agents: usize,
+    /// Resident memory of the server process; absent where the platform does not tell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resident_memory_bytes: Option<u64>,
+}
+
+/// Handler for kaiak/stats endpoint
+pub struct StatsHandler {
+    agent_manager: Arc<GooseAgentManager>,
+}
+
+impl StatsHandler {
+    pub fn new(agent_manager: Arc<GooseAgentManager>) -> Self {
+        Self { agent_manager }
+    }
+
+    pub async fn handle_stats(&self, request: StatsRequest) -> StatsResponse {
+        StatsResponse {
+            totals: self.agent_manager.stats().snapshot(request.reset),
+            active_agents: self.agent_manager.active_agent_count().await,
+            resident_memory_bytes: resident_memory_bytes(),
+        }
+    }
+}
+
+/// Resident set size from `/proc/self/status`; Linux only
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::generate_fix::{GenerateFixHandler, GenerateFixRequest};
+    use crate::handlers::InteractionManager;
+    use crate::models::configuration::{AgentConfig, BaseConfig, PermissionLevel};
+    use crate::models::incidents::MigrationIncident;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_counters_increment_after_completed_request() {
+        let workspace = tempfile::tempdir().unwrap();
+        let file = workspace.path().join("Main.java");
+        std::fs::write(&file, "import javax.servlet.http.HttpServlet;\n").unwrap();
+
+        // A suggested replacement fixes the incident without a model call
+        let base_config = BaseConfig {
+            apply_suggested_replacements: true,
+            tool_permissions: HashMap::from([(
+                "developer__text_editor".to_string(),
+                PermissionLevel::AlwaysAllow,
+            )]),
+            ..BaseConfig::default()
+        };
+        let agent_manager = Arc::new(GooseAgentManager::new());
+        let handler = StatsHandler::new(agent_manager.clone());
+        let before = handler.handle_stats(StatsRequest::default()).await;
+        assert_eq!(before.totals.requests_processed, 0);
+
+        let request: GenerateFixRequest = serde_json::from_value(serde_json::json!({
+            "incidents": [MigrationIncident::new(
+                "javax-1".to_string(),
+                format!("file://{}", file.display()),
+                "Replace javax.servlet import".to_string(),
+                String::new(),
+            )
+            .with_line_number(1)
+            .with_suggested_replacement("import jakarta.servlet.http.HttpServlet;", None)],
+            "agent_config": AgentConfig {
+                workspace: workspace.path().to_path_buf(),
+                ..AgentConfig::default()
+            },
+        }))
+        .unwrap();
+        let (notifier, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        GenerateFixHandler::new(
+            agent_manager.clone(),
+            Arc::new(InteractionManager::new()),
+            Arc::new(base_config),
+        )
+        .handle_generate_fix(request, notifier)
+        .await
+        .unwrap();
+
+        let after = handler.handle_stats(StatsRequest { reset: true }).await;
+        assert_eq!(after.totals.sessions_created, 1);
+        assert_eq!(after.totals.sessions_active, 1);
+        assert_eq!(after.totals.requests_processed, 1);
+        assert_eq!(after.totals.requests_succeeded, 1);
+        assert_eq!(after.totals.requests_failed, 0);
+        assert_eq!(after.totals.requests_running, 0);
+        assert_eq!(after.totals.files_modified, 1);
+        assert!(after.totals.tool_calls >= 1);
+
+        // Reading with `reset` starts the totals over but keeps the gauges
+        let reset = handler.handle_stats(StatsRequest::default()).await;
+        assert_eq!(reset.totals.requests_processed, 0);
+        assert_eq!(reset.totals.sessions_created, 0);
+        assert_eq!(reset.totals.sessions_active, 1);
+    }
+}
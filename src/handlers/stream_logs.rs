@@ -0,0 +1,103 @@
+//! Forwarding of server log lines to a subscribed client.
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, Level};
+use validator::Validate;
+
+use crate::logging::LogStream;
+use crate::{KaiakError, KaiakResult};
+
+/// Request type for kaiak/stream_logs endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct StreamLogsRequest {
+    /// Least severe level to forward: trace, debug, info, warn or error
+    #[serde(default = "default_level")]
+    #[validate(custom(function = "validate_level"))]
+    pub level: String,
+    /// Only forward lines logged while handling this session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 1, message = "Session ID cannot be empty"))]
+    pub session_id: Option<String>,
+    /// Stop forwarding instead of subscribing
+    #[serde(default)]
+    pub unsubscribe: bool,
+}
+
+fn default_level() -> String {
+    "info".to_string()
+}
+
+fn validate_level(level: &str) -> Result<(), validator::ValidationError> {
+    level
+        .parse::<Level>()
+        .map(|_| ())
+        .map_err(|_| validator::ValidationError::new("Invalid log level"))
+}
+
+/// Response type for kaiak/stream_logs endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamLogsResponse {
+    /// Whether log lines are now being forwarded
+    pub subscribed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+/// Handler for kaiak/stream_logs endpoint
+pub struct StreamLogsHandler {
+    /// `init_config.allow_log_streaming`
+    allowed: bool,
+    stream: LogStream,
+}
+
+impl StreamLogsHandler {
+    pub fn new(allowed: bool, stream: LogStream) -> Self {
+        Self { allowed, stream }
+    }
+
+    pub fn handle_stream_logs(&self, request: StreamLogsRequest) -> KaiakResult<StreamLogsResponse> {
+        if !self.allowed {
+            return Err(KaiakError::configuration(
+                "Log streaming is disabled; set init_config.allow_log_streaming to enable it",
+            ));
+        }
+
+        if let Err(validation_errors) = request.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        if request.unsubscribe {
+            if self.stream.unsubscribe() {
+                info!("Client stopped streaming logs");
+            }
+            return Ok(StreamLogsResponse {
+                subscribed: false,
+                level: None,
+                session_id: None,
+            });
+        }
+
+        // Validated above
+        let level: Level = request.level.parse().unwrap_or(Level::INFO);
+        info!(
+            "Client streaming logs at {} and above{}",
+            level,
+            request
+                .session_id
+                .as_ref()
+                .map(|id| format!(" for session {}", id))
+                .unwrap_or_default()
+        );
+        self.stream.subscribe(level, request.session_id.clone());
+        Ok(StreamLogsResponse {
+            subscribed: true,
+            level: Some(level.to_string().to_lowercase()),
+            session_id: request.session_id,
+        })
+    }
+}
@@ -0,0 +1,229 @@
+//! Incidents' `suggested_replacement` hints, proposed and applied like the model's edits
+//! but without a model call.
+
+use tracing::{info, warn};
+
+use super::generate_fix::{
+    is_applied, record_applied, resolve_incident_path, GenerateFixDataKind, GenerateFixHandler,
+    GenerateFixRequest, SUGGESTION_TOOL,
+};
+use crate::jsonrpc::NotificationSender;
+use crate::models::applied_edits;
+use crate::models::incidents::MigrationIncident;
+use crate::models::report::{FixSource, MigrationReport, MigrationReportCollector, TextEdit};
+
+impl GenerateFixHandler {
+    /// Propose the `suggested_replacement` of each of `incidents` that has one as a text
+    /// editor edit, decided like the model's edits but without a model call, and apply the
+    /// approved ones in `session_id`, or the request's session. Returns the session with
+    /// the report of the applied suggestions, and adds their edits to `fixed_edits`;
+    /// incidents not in the report are left to the model.
+    pub(super) async fn apply_suggested_replacements(
+        &self,
+        request_id: &str,
+        request: &GenerateFixRequest,
+        incidents: &[MigrationIncident],
+        session_id: Option<&str>,
+        fixed_edits: &mut Vec<(String, Vec<TextEdit>)>,
+        notifier: &NotificationSender,
+        started_at: std::time::Instant,
+    ) -> Option<(String, MigrationReport)> {
+        let workspace = &request.agent_config.workspace;
+        let mut suggested: Vec<_> = incidents
+            .iter()
+            .filter_map(|incident| {
+                let replacement = incident.suggested_replacement.as_ref()?;
+                let range = incident.affected_lines()?;
+                Some((
+                    resolve_incident_path(workspace, incident),
+                    range,
+                    replacement,
+                    incident,
+                ))
+            })
+            .collect();
+        if suggested.is_empty() {
+            return None;
+        }
+        // Bottom-up within each file, so a replacement that changes the number of lines
+        // leaves the lines of the suggestions above it where their incidents say they are
+        suggested.sort_by(|(a_path, a_range, ..), (b_path, b_range, ..)| {
+            a_path
+                .cmp(b_path)
+                .then(b_range.start_line.cmp(&a_range.start_line))
+        });
+
+        let session_id = self.suggestion_session(request, session_id).await?;
+        info!(
+            "Proposing suggested replacements for {} incidents",
+            suggested.len()
+        );
+
+        let mut applied_edits = self.applied_edits(request);
+        let mut report = MigrationReport::default();
+        // First line replaced so far in the file being worked on
+        let mut replaced_from: Option<(std::path::PathBuf, usize)> = None;
+        for (path, range, replacement, incident) in suggested {
+            let overlaps = matches!(
+                &replaced_from,
+                Some((replaced, start)) if *replaced == path && range.end_line >= *start
+            );
+            if overlaps {
+                warn!(
+                    "Lines {}-{} of {} overlap another suggested replacement; leaving incident {} to the model",
+                    range.start_line,
+                    range.end_line,
+                    path.display(),
+                    incident.id
+                );
+                continue;
+            }
+            let proposal = applied_edits::proposal_id(
+                &path,
+                &format!("{}-{}\0{}", range.start_line, range.end_line, replacement),
+            );
+            if is_applied(&applied_edits, &path, &proposal) {
+                info!(
+                    "Suggested replacement for incident {} was applied by an earlier run",
+                    incident.id
+                );
+                replaced_from = Some((path, range.start_line));
+                report.merge(self.already_applied(
+                    notifier,
+                    request_id,
+                    &session_id,
+                    std::slice::from_ref(incident),
+                    FixSource::SuggestedReplacement,
+                ));
+                continue;
+            }
+            let Some((old_text, _)) = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| replace_lines(&content, range, replacement))
+            else {
+                warn!(
+                    "Lines {}-{} of {} can't be read; leaving incident {} to the model",
+                    range.start_line,
+                    range.end_line,
+                    path.display(),
+                    incident.id
+                );
+                continue;
+            };
+
+            let id = format!("suggested-{}", incident.id);
+            let arguments = serde_json::json!({
+                "command": "str_replace",
+                "path": path.display().to_string(),
+                "old_str": old_text,
+                "new_str": replacement,
+            })
+            .as_object()
+            .cloned()
+            .unwrap_or_default();
+            let allowed = self
+                .propose_edit(
+                    notifier,
+                    request_id,
+                    &session_id,
+                    &incident.id,
+                    &id,
+                    &arguments,
+                    "suggested_replacement",
+                )
+                .await;
+            if !allowed {
+                info!(
+                    "Suggested replacement for incident {} was not allowed; leaving it to the model",
+                    incident.id
+                );
+                continue;
+            }
+
+            // The file may have changed while the client decided
+            let written = std::fs::read_to_string(&path).ok().and_then(|content| {
+                let (current, updated) = replace_lines(&content, range, replacement)?;
+                (current == old_text && std::fs::write(&path, &updated).is_ok())
+                    .then_some((content, updated))
+            });
+            let success = written.is_some();
+            if !success {
+                warn!(
+                    "Failed to apply suggested replacement to {}; leaving incident {} to the model",
+                    path.display(),
+                    incident.id
+                );
+            }
+            self.send_notification(
+                notifier,
+                request_id,
+                &session_id,
+                GenerateFixDataKind::ToolResponse,
+                serde_json::json!({ "id": id, "is_error": !success }),
+            );
+            let Some((before, after)) = written else {
+                continue;
+            };
+            record_applied(&mut applied_edits, &path, &proposal, &before, &after);
+            replaced_from = Some((path, range.start_line));
+
+            let mut collector = MigrationReportCollector::new(workspace)
+                .with_incidents(std::slice::from_ref(incident), &[])
+                .with_fix_source(FixSource::SuggestedReplacement);
+            collector.record_tool_request(&id, SUGGESTION_TOOL, Some(&arguments));
+            let statuses = collector.record_tool_response(&id, true);
+            fixed_edits.extend(collector.fixed_edits());
+            for status in statuses {
+                self.send_incident_notification(
+                    notifier,
+                    request_id,
+                    &session_id,
+                    GenerateFixDataKind::IncidentStatus,
+                    Some(status.incident_id),
+                    serde_json::json!({ "outcome": status.outcome }),
+                );
+            }
+            report.merge(collector.finish(started_at.elapsed().as_millis() as u64));
+        }
+
+        info!(
+            "Applied suggested replacements for {} incidents without the model",
+            report.incidents_fixed
+        );
+        Some((session_id, report))
+    }
+}
+
+/// Replace the lines of `range` in `content` with `replacement`, keeping the line ending of
+/// the last replaced line. Returns the replaced text and the new content, or None if the
+/// range is not 1-based or the file has fewer lines.
+pub(super) fn replace_lines(
+    content: &str,
+    range: crate::models::LineRange,
+    replacement: &str,
+) -> Option<(String, String)> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    if range.start_line == 0 || range.end_line < range.start_line || range.end_line > lines.len() {
+        return None;
+    }
+    let replaced = &lines[range.start_line - 1..range.end_line];
+    let replaced_text = replaced.concat();
+    let ending = if replaced_text.ends_with("\r\n") {
+        "\r\n"
+    } else if replaced_text.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    };
+
+    let mut updated = lines[..range.start_line - 1].concat();
+    if !replacement.is_empty() {
+        updated.push_str(replacement.trim_end_matches(['\r', '\n']));
+        updated.push_str(ending);
+    }
+    updated.push_str(&lines[range.end_line..].concat());
+    Some((
+        replaced_text.trim_end_matches(['\r', '\n']).to_string(),
+        updated,
+    ))
+}
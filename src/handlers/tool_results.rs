@@ -0,0 +1,188 @@
+//! Tool results too large to stream, kept on disk until their session is deleted.
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{KaiakError, KaiakResult};
+
+/// Spilled results kept for `kaiak/get_tool_result`; older ones are removed first
+pub const MAX_SPILLED_TOOL_RESULTS: usize = 100;
+
+/// Where a spilled tool result can be fetched, streamed in place of its content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolResultRef {
+    /// ID to pass to `kaiak/get_tool_result`
+    pub result_id: String,
+    pub path: PathBuf,
+    /// Size of the serialized content in bytes
+    pub size: usize,
+}
+
+/// Spilled results, each in its own file. They outlive their request, since a client can
+/// only fetch them once the request has returned, and are removed when their session is
+/// deleted or they are evicted.
+#[derive(Debug)]
+pub struct ToolResultStore {
+    dir: PathBuf,
+    /// Spilled results by ID, with the session that produced them
+    spilled: Mutex<LruCache<String, (String, ToolResultRef)>>,
+}
+
+impl Default for ToolResultStore {
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join(format!("kaiak-tool-results-{}", std::process::id())))
+    }
+}
+
+impl ToolResultStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_capacity(dir, MAX_SPILLED_TOOL_RESULTS)
+    }
+
+    pub fn with_capacity(dir: impl Into<PathBuf>, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            dir: dir.into(),
+            spilled: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Write a tool result's serialized content to its own file
+    pub fn spill(&self, session_id: &str, content: &str) -> KaiakResult<ToolResultRef> {
+        let result_id = Uuid::new_v4().to_string();
+        let path = self.dir.join(format!("{}.json", result_id));
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&path, content))
+            .map_err(|e| KaiakError::Io {
+                message: format!("Failed to spill tool result to {}: {}", path.display(), e),
+            })?;
+
+        let result = ToolResultRef {
+            result_id: result_id.clone(),
+            path,
+            size: content.len(),
+        };
+        debug!(
+            "Spilled {} byte tool result to {}",
+            result.size,
+            result.path.display()
+        );
+        let evicted = self
+            .spilled
+            .lock()
+            .unwrap()
+            .push(result_id, (session_id.to_string(), result.clone()));
+        if let Some((evicted_id, (_, evicted))) = evicted {
+            if evicted_id != result.result_id {
+                remove(&evicted);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Content of a spilled result
+    pub fn get(&self, result_id: &str) -> KaiakResult<(ToolResultRef, serde_json::Value)> {
+        let result = self
+            .spilled
+            .lock()
+            .unwrap()
+            .get(result_id)
+            .map(|(_, result)| result.clone())
+            .ok_or_else(|| KaiakError::Io {
+                message: format!(
+                    "Tool result {} not found; results are removed when their session is deleted, \
+                     and only the last {} are kept",
+                    result_id, MAX_SPILLED_TOOL_RESULTS
+                ),
+            })?;
+        let content = std::fs::read_to_string(&result.path).map_err(|e| KaiakError::Io {
+            message: format!("Failed to read tool result {}: {}", result_id, e),
+        })?;
+        let content = serde_json::from_str(&content).map_err(|e| KaiakError::Serialization {
+            message: format!("Tool result {} is not valid JSON: {}", result_id, e),
+        })?;
+        Ok((result, content))
+    }
+
+    /// Remove the files spilled in a session
+    pub fn release_session(&self, session_id: &str) {
+        let mut spilled = self.spilled.lock().unwrap();
+        let owned: Vec<String> = spilled
+            .iter()
+            .filter(|(_, (owner, _))| owner == session_id)
+            .map(|(result_id, _)| result_id.clone())
+            .collect();
+        for result_id in owned {
+            if let Some((_, result)) = spilled.pop(&result_id) {
+                remove(&result);
+            }
+        }
+    }
+}
+
+fn remove(result: &ToolResultRef) {
+    if let Err(e) = std::fs::remove_file(&result.path) {
+        warn!(
+            "Failed to remove spilled tool result {}: {}",
+            result.path.display(),
+            e
+        );
+    }
+}
+
+/// Request type for kaiak/get_tool_result endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct GetToolResultRequest {
+    #[validate(length(min = 1, message = "Result ID cannot be empty"))]
+    pub result_id: String,
+}
+
+/// Response type for kaiak/get_tool_result endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetToolResultResponse {
+    pub result_id: String,
+    pub size: usize,
+    /// The tool result's content, as it would have been streamed
+    pub content: serde_json::Value,
+}
+
+/// Handler for kaiak/get_tool_result endpoint
+pub struct GetToolResultHandler {
+    tool_results: Arc<ToolResultStore>,
+}
+
+impl GetToolResultHandler {
+    pub fn new(tool_results: Arc<ToolResultStore>) -> Self {
+        Self { tool_results }
+    }
+
+    pub async fn handle_get_tool_result(
+        &self,
+        request: GetToolResultRequest,
+    ) -> KaiakResult<GetToolResultResponse> {
+        info!(
+            "Processing get_tool_result request for: {}",
+            request.result_id
+        );
+
+        if let Err(validation_errors) = request.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        let (result, content) = self.tool_results.get(&request.result_id)?;
+        Ok(GetToolResultResponse {
+            result_id: result.result_id,
+            size: result.size,
+            content,
+        })
+    }
+}
@@ -0,0 +1,107 @@
+//! Update session config handler for changing a live session's settings between requests.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::generate_fix::{GenerateFixData, GenerateFixDataKind};
+use crate::agent::GooseAgentManager;
+use crate::jsonrpc::{methods::GENERATE_FIX_DATA, JsonRpcNotification, NotificationSender};
+use crate::models::configuration::{permission_strictness, BaseConfig, SessionConfigUpdate};
+use crate::{KaiakError, KaiakResult};
+
+/// Request type for kaiak/update_session_config endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct UpdateSessionConfigRequest {
+    /// Session whose configuration should change
+    #[validate(length(min = 1, message = "Session ID cannot be empty"))]
+    pub session_id: String,
+    /// Settings to change; fields that need a new session are rejected
+    #[validate(nested)]
+    pub config: SessionConfigUpdate,
+}
+
+/// Response type for kaiak/update_session_config endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateSessionConfigResponse {
+    pub session_id: String,
+    /// Every setting changed on the session so far, including this update
+    pub config: SessionConfigUpdate,
+    pub updated_at: String,
+}
+
+/// Handler for kaiak/update_session_config endpoint
+/// Changes take effect from the session's next generate_fix request
+pub struct UpdateSessionConfigHandler {
+    agent_manager: Arc<GooseAgentManager>,
+    base_config: Arc<BaseConfig>,
+}
+
+impl UpdateSessionConfigHandler {
+    pub fn new(agent_manager: Arc<GooseAgentManager>, base_config: Arc<BaseConfig>) -> Self {
+        Self {
+            agent_manager,
+            base_config,
+        }
+    }
+
+    /// Handle update session config request
+    pub async fn handle_update_session_config(
+        &self,
+        request: UpdateSessionConfigRequest,
+        notifier: NotificationSender,
+    ) -> KaiakResult<UpdateSessionConfigResponse> {
+        info!("Processing update_session_config request for: {}", request.session_id);
+
+        if let Err(validation_errors) = request.validate() {
+            return Err(KaiakError::InvalidParams(format!(
+                "Request validation failed: {}",
+                validation_errors
+            )));
+        }
+
+        // A session may restrict tools further, never loosen what the server allows
+        for (tool_name, level) in &request.config.tool_permissions {
+            let server = self.base_config.tool_permission(tool_name);
+            if permission_strictness(level) < permission_strictness(server) {
+                return Err(KaiakError::configuration(format!(
+                    "Tool permission for '{}' cannot be looser than the server's ({:?})",
+                    tool_name, server
+                )));
+            }
+        }
+
+        let config = self
+            .agent_manager
+            .update_session_config(&request.session_id, request.config.clone())
+            .await?;
+
+        let data = GenerateFixData {
+            request_id: Uuid::new_v4().to_string(),
+            session_id: request.session_id.clone(),
+            kind: GenerateFixDataKind::System,
+            incident_id: None,
+            payload: serde_json::json!({
+                "event": "config_updated",
+                "changed": request.config,
+            }),
+        };
+        let notification = JsonRpcNotification::new(
+            GENERATE_FIX_DATA,
+            Some(serde_json::to_value(&data).unwrap_or_default()),
+        );
+        if let Err(e) = notifier.send(notification) {
+            warn!("Failed to send notification: {}", e);
+        }
+
+        info!("Updated configuration of session {}", request.session_id);
+
+        Ok(UpdateSessionConfigResponse {
+            session_id: request.session_id,
+            config,
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
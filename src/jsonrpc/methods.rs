@@ -14,10 +14,50 @@ use super::core::{KaiakRequest, KaiakResponse};
 pub const GENERATE_FIX: &str = "kaiak/generate_fix";
 pub const GENERATE_FIX_DATA: &str = "kaiak/generate_fix/data";
 pub const DELETE_SESSION: &str = "kaiak/delete_session";
+pub const RESET_SESSION_CONTEXT: &str = "kaiak/reset_session_context";
+pub const EXPORT_SESSION: &str = "kaiak/export_session";
+pub const UPDATE_SESSION_CONFIG: &str = "kaiak/update_session_config";
+pub const GET_TOOL_RESULT: &str = "kaiak/get_tool_result";
+pub const PREVIEW_PROMPT: &str = "kaiak/preview_prompt";
+pub const RETRY_FAILED: &str = "kaiak/retry_failed";
+pub const ROLLBACK_REQUEST: &str = "kaiak/rollback_request";
 pub const CLIENT_USER_MESSAGE: &str = "kaiak/client/user_message";
+pub const SERVER_INFO: &str = "kaiak/server_info";
+pub const READY: &str = "kaiak/ready";
+pub const STATS: &str = "kaiak/stats";
+pub const LIST_MODELS: &str = "kaiak/list_models";
+pub const SET_IDENTITY: &str = "kaiak/set_identity";
+pub const WHOAMI: &str = "kaiak/whoami";
+pub const STREAM_LOGS: &str = "kaiak/stream_logs";
+/// Server-to-client notification carrying a non-fatal issue
+pub const WARNING: &str = "kaiak/warning";
+/// Server-to-client notification carrying the token usage of one model turn
+pub const USAGE: &str = "kaiak/usage";
+/// Server-to-client notification sent before the server closes an idle connection
+pub const CONNECTION_CLOSING: &str = "kaiak/connection_closing";
+/// Server-to-client notification carrying one server log line, after `kaiak/stream_logs`
+pub const LOG: &str = "kaiak/log";
 
 /// All supported Kaiak JSON-RPC methods
-pub const ALL_METHODS: &[&str] = &[GENERATE_FIX, DELETE_SESSION, CLIENT_USER_MESSAGE];
+pub const ALL_METHODS: &[&str] = &[
+    GENERATE_FIX,
+    DELETE_SESSION,
+    RESET_SESSION_CONTEXT,
+    EXPORT_SESSION,
+    UPDATE_SESSION_CONFIG,
+    GET_TOOL_RESULT,
+    PREVIEW_PROMPT,
+    RETRY_FAILED,
+    ROLLBACK_REQUEST,
+    CLIENT_USER_MESSAGE,
+    SERVER_INFO,
+    READY,
+    STATS,
+    LIST_MODELS,
+    SET_IDENTITY,
+    WHOAMI,
+    STREAM_LOGS,
+];
 
 /// Kaiak JSON-RPC trait definition for server implementations
 /// Provides type-safe method definitions for all Kaiak procedures
@@ -12,21 +12,25 @@
 
 // Core JSON-RPC implementation
 pub mod protocol;
-pub mod transport;
 pub mod server;
+pub mod transport;
 
-pub mod methods;
 pub mod core;
+pub mod methods;
 
-pub use protocol::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, JsonRpcError};
-pub use transport::{Transport, TransportConfig, StdioTransport, IpcTransport};
+pub use protocol::{JsonRpcError, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse};
 pub use server::{
-    JsonRpcServer, ServerBuilder, MethodHandler, 
-    StreamingMethodHandler, NotificationSender, NotificationReceiver,
+    JsonRpcServer, MethodCatalog, MethodHandler, MethodNamespace, NotificationReceiver,
+    NotificationSender, ServerBuilder, ServerStatus, StreamingMethodHandler,
 };
+pub use transport::{IpcTransport, StdioTransport, Transport, TransportConfig};
 
-pub use methods::{GENERATE_FIX, DELETE_SESSION, CLIENT_USER_MESSAGE};
 pub use core::{KaiakRequest, KaiakResponse, ResponseMetadata};
+pub use methods::{
+    CLIENT_USER_MESSAGE, DELETE_SESSION, EXPORT_SESSION, GENERATE_FIX, GET_TOOL_RESULT,
+    LIST_MODELS, PREVIEW_PROMPT, READY, RESET_SESSION_CONTEXT, RETRY_FAILED, ROLLBACK_REQUEST,
+    SERVER_INFO, SET_IDENTITY, STATS, STREAM_LOGS, UPDATE_SESSION_CONFIG, WHOAMI,
+};
 
 pub const JSONRPC_VERSION: &str = "2.0";
 pub const DEFAULT_REQUEST_TIMEOUT: u64 = 300;
@@ -39,23 +43,67 @@ pub async fn create_kaiak_server(
     use crate::jsonrpc::transport::TransportConfig;
 
     // Determine transport from config
+    let framing = server_config.init_config.framing;
     let transport_config = match server_config.init_config.transport.as_str() {
-        "stdio" => TransportConfig::Stdio,
+        "stdio" => TransportConfig::Stdio { framing },
         "socket" => {
-            let socket_path = server_config.init_config.socket_path
+            let socket_path = server_config
+                .init_config
+                .socket_path
                 .clone()
                 .unwrap_or_else(|| "/tmp/kaiak.sock".to_string());
-            TransportConfig::UnixSocket { path: socket_path }
+            TransportConfig::UnixSocket {
+                path: socket_path,
+                framing,
+            }
         }
         _ => {
-            tracing::warn!("Unknown transport '{}', defaulting to stdio", server_config.init_config.transport);
-            TransportConfig::Stdio
+            tracing::warn!(
+                "Unknown transport '{}', defaulting to stdio",
+                server_config.init_config.transport
+            );
+            TransportConfig::Stdio { framing }
         }
     };
 
-    let server = JsonRpcServer::new(transport_config).await?;
+    let mut server = match transport_config {
+        TransportConfig::UnixSocket { path, framing } => {
+            let mode = server_config.init_config.socket_mode()?;
+            let idle_timeout = server_config
+                .init_config
+                .connection_idle_timeout_secs
+                .map(std::time::Duration::from_secs);
+            let transport = transport::IpcServerTransport::bind(&path)
+                .await?
+                .with_idle_timeout(idle_timeout)
+                .with_framing(framing);
+            transport.apply_socket_permissions(mode, server_config.init_config.socket_group)?;
+            JsonRpcServer::with_transport(Box::new(transport))
+        }
+        TransportConfig::Stdio { framing } => JsonRpcServer::with_transport(Box::new(
+            transport::StdioTransport::new().with_framing(framing),
+        )),
+    };
 
-    register_kaiak_methods(&server, agent_manager, std::sync::Arc::new(server_config.base_config.clone())).await?;
+    let init_config = &server_config.init_config;
+    let identity = crate::handlers::ClientIdentity::from_env(&init_config.identity_env);
+    let audit_log =
+        crate::handlers::AuditLog::new(init_config.audit_log_path.as_ref().map(Into::into));
+    register_kaiak_methods(
+        &server,
+        agent_manager,
+        std::sync::Arc::new(server_config.base_config.clone()),
+        identity.clone(),
+        std::sync::Arc::new(audit_log),
+        init_config.allow_log_streaming,
+    )
+    .await?;
+    server.forward_logs(crate::logging::log_stream().clone());
+    // Log lines and the identity a client set must not carry over to whoever connects next
+    server.on_disconnect(move || {
+        crate::logging::log_stream().unsubscribe();
+        identity.reset();
+    });
 
     Ok(server)
 }
@@ -75,11 +123,26 @@ pub async fn register_kaiak_methods(
     server: &JsonRpcServer,
     agent_manager: std::sync::Arc<crate::agent::GooseAgentManager>,
     base_config: std::sync::Arc<crate::models::configuration::BaseConfig>,
+    identity: crate::handlers::ClientIdentity,
+    audit_log: std::sync::Arc<crate::handlers::AuditLog>,
+    allow_log_streaming: bool,
 ) -> anyhow::Result<()> {
     use crate::handlers::{
-        generate_fix::{GenerateFixRequest, GenerateFixHandler},
-        delete_session::{DeleteSessionRequest, DeleteSessionHandler},
-        client_notifications::{ClientNotificationRequest, ClientNotificationHandler},
+        client_notifications::{ClientNotificationHandler, ClientNotificationRequest},
+        delete_session::{DeleteSessionHandler, DeleteSessionRequest},
+        export_session::{ExportSessionHandler, ExportSessionRequest},
+        fix_cache::FixCache,
+        generate_fix::{ActiveRequests, GenerateFixHandler, GenerateFixRequest},
+        identity::{IdentityHandler, SetIdentityRequest},
+        list_models::{ListModelsHandler, ListModelsRequest, ModelCatalogCache},
+        ready::ReadyHandler,
+        reset_session::{ResetSessionContextHandler, ResetSessionContextRequest},
+        retry_failed::{CompletedFixes, RetryFailedRequest},
+        rollback::{CompletedBackups, RollbackHandler, RollbackRequest},
+        stats::{StatsHandler, StatsRequest},
+        stream_logs::{StreamLogsHandler, StreamLogsRequest},
+        tool_results::{GetToolResultHandler, GetToolResultRequest, ToolResultStore},
+        update_session_config::{UpdateSessionConfigHandler, UpdateSessionConfigRequest},
         InteractionManager,
     };
 
@@ -87,17 +150,42 @@ pub async fn register_kaiak_methods(
     // This is shared between GenerateFixHandler (which waits for responses)
     // and ClientNotificationHandler (which receives responses from clients)
     let interaction_manager = std::sync::Arc::new(InteractionManager::new());
+    // Tool results spilled by generate_fix requests, served by get_tool_result
+    let tool_results = std::sync::Arc::new(ToolResultStore::default());
+    // Finished generate_fix requests, whose failures retry_failed runs again
+    let completed_fixes = std::sync::Arc::new(CompletedFixes::default());
+    // Backups of finished generate_fix requests, which rollback_request restores
+    let completed_backups = std::sync::Arc::new(CompletedBackups::default());
+    // Edits that fixed earlier incidents, replayed by generate_fix when the fix cache is on
+    let fix_cache = std::sync::Arc::new(FixCache::default());
+    // Running generate_fix and retry_failed requests, so request IDs are unique across them
+    let active_requests = ActiveRequests::default();
     
     // Register generate_fix method (streaming - sends notifications during execution)
     {
         let agent_manager = agent_manager.clone();
         let interaction_manager = interaction_manager.clone();
+        let base_config = base_config.clone();
+        let identity = identity.clone();
+        let audit_log = audit_log.clone();
+        let tool_results = tool_results.clone();
+        let completed_fixes = completed_fixes.clone();
+        let completed_backups = completed_backups.clone();
+        let fix_cache = fix_cache.clone();
+        let active_requests = active_requests.clone();
         server.register_streaming_method(
             GENERATE_FIX.to_string(),
             move |params, notifier| {
                 let agent_manager = agent_manager.clone();
                 let interaction_manager = interaction_manager.clone();
                 let base_config = base_config.clone();
+                let identity = identity.clone();
+                let audit_log = audit_log.clone();
+                let tool_results = tool_results.clone();
+                let completed_fixes = completed_fixes.clone();
+                let completed_backups = completed_backups.clone();
+                let fix_cache = fix_cache.clone();
+                let active_requests = active_requests.clone();
                 async move {
                     let params_value = params.unwrap_or(serde_json::Value::Null);
                     
@@ -107,52 +195,333 @@ pub async fn register_kaiak_methods(
                             create_parse_error::<GenerateFixRequest>(&e, &params_value)
                         })?;
 
-                    let handler = GenerateFixHandler::new(agent_manager, interaction_manager, base_config.clone());
-                    let response = handler.handle_generate_fix(request, notifier).await
-                        .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+                    // Validate-only requests never reach the agent manager
+                    let response = if request.validate_only {
+                        let response = GenerateFixHandler::validate_only(&request, &base_config)
+                            .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+                        serde_json::to_value(response)
+                    } else {
+                        let handler = GenerateFixHandler::new(
+                            agent_manager,
+                            interaction_manager,
+                            base_config.clone(),
+                        )
+                        .with_identity(identity)
+                        .with_audit_log(audit_log)
+                        .with_tool_results(tool_results)
+                        .with_completed_fixes(completed_fixes)
+                        .with_completed_backups(completed_backups)
+                        .with_fix_cache(fix_cache)
+                        .with_active_requests(active_requests);
+                        let response = handler
+                            .handle_generate_fix(request, notifier)
+                            .await
+                            .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+                        serde_json::to_value(response)
+                    };
 
                     // Return raw response (no wrapper)
-                    serde_json::to_value(response)
-                        .map_err(|e| crate::jsonrpc::JsonRpcError::custom(
+                    response.map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register retry_failed method (streaming - runs generate_fix on the failed incidents)
+    {
+        let agent_manager = agent_manager.clone();
+        let interaction_manager = interaction_manager.clone();
+        let base_config = base_config.clone();
+        let identity = identity.clone();
+        let audit_log = audit_log.clone();
+        let tool_results = tool_results.clone();
+        let completed_fixes = completed_fixes.clone();
+        let completed_backups = completed_backups.clone();
+        let fix_cache = fix_cache.clone();
+        let active_requests = active_requests.clone();
+        server
+            .register_streaming_method(RETRY_FAILED.to_string(), move |params, notifier| {
+                let agent_manager = agent_manager.clone();
+                let interaction_manager = interaction_manager.clone();
+                let base_config = base_config.clone();
+                let identity = identity.clone();
+                let audit_log = audit_log.clone();
+                let tool_results = tool_results.clone();
+                let completed_fixes = completed_fixes.clone();
+                let completed_backups = completed_backups.clone();
+                let fix_cache = fix_cache.clone();
+                let active_requests = active_requests.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                    let retry: RetryFailedRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| create_parse_error::<RetryFailedRequest>(&e, &params_value))?;
+                    let request = completed_fixes
+                        .retry_request(&retry)
+                        .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                    let handler =
+                        GenerateFixHandler::new(agent_manager, interaction_manager, base_config)
+                            .with_identity(identity)
+                            .with_audit_log(audit_log)
+                            .with_tool_results(tool_results)
+                            .with_completed_fixes(completed_fixes)
+                            .with_completed_backups(completed_backups)
+                            .with_fix_cache(fix_cache)
+                            .with_active_requests(active_requests);
+                    let response = handler
+                        .handle_generate_fix(request, notifier)
+                        .await
+                        .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register preview_prompt method (non-streaming - never reaches the agent manager)
+    {
+        let base_config = base_config.clone();
+        server
+            .register_async_method(PREVIEW_PROMPT.to_string(), move |params| {
+                let base_config = base_config.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                    let request: GenerateFixRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| create_parse_error::<GenerateFixRequest>(&e, &params_value))?;
+
+                    let response = GenerateFixHandler::preview_prompt(&request, &base_config)
+                        .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
                             crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
                             format!("Failed to serialize response: {}", e),
                             None,
-                        ))
+                        )
+                    })
                 }
-            },
-        ).await?;
+            })
+            .await?;
     }
 
     // Register delete_session method (non-streaming)
     {
         let agent_manager = agent_manager.clone();
+        let tool_results = tool_results.clone();
         server.register_async_method(
             DELETE_SESSION.to_string(),
             move |params| {
                 let agent_manager = agent_manager.clone();
+                let tool_results = tool_results.clone();
                 async move {
                     let params_value = params.unwrap_or(serde_json::Value::Null);
-                    
+
                     // Parse directly as DeleteSessionRequest (no wrapper)
-                    let request: DeleteSessionRequest = serde_json::from_value(params_value.clone())
-                        .map_err(|e| {
+                    let request: DeleteSessionRequest =
+                        serde_json::from_value(params_value.clone()).map_err(|e| {
                             create_parse_error::<DeleteSessionRequest>(&e, &params_value)
                         })?;
 
-                    let handler = DeleteSessionHandler::new(agent_manager);
-                    let response = handler.handle_delete_session(request).await
+                    let handler =
+                        DeleteSessionHandler::new(agent_manager).with_tool_results(tool_results);
+                    let response = handler
+                        .handle_delete_session(request)
+                        .await
                         .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
 
                     // Return raw response (no wrapper)
-                    serde_json::to_value(response)
-                        .map_err(|e| crate::jsonrpc::JsonRpcError::custom(
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register reset_session_context method (streaming - emits a context_reset system event)
+    {
+        let agent_manager = agent_manager.clone();
+        server
+            .register_streaming_method(
+                RESET_SESSION_CONTEXT.to_string(),
+                move |params, notifier| {
+                    let agent_manager = agent_manager.clone();
+                    async move {
+                        let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                        // Parse directly as ResetSessionContextRequest (no wrapper)
+                        let request: ResetSessionContextRequest =
+                            serde_json::from_value(params_value.clone()).map_err(|e| {
+                                create_parse_error::<ResetSessionContextRequest>(&e, &params_value)
+                            })?;
+
+                        let handler = ResetSessionContextHandler::new(agent_manager);
+                        let response = handler
+                            .handle_reset_session_context(request, notifier)
+                            .await
+                            .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+
+                        // Return raw response (no wrapper)
+                        serde_json::to_value(response).map_err(|e| {
+                            crate::jsonrpc::JsonRpcError::custom(
+                                crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                                format!("Failed to serialize response: {}", e),
+                                None,
+                            )
+                        })
+                    }
+                },
+            )
+            .await?;
+    }
+
+    // Register update_session_config method (streaming - emits a config_updated system event)
+    {
+        let agent_manager = agent_manager.clone();
+        let base_config = base_config.clone();
+        server
+            .register_streaming_method(
+                UPDATE_SESSION_CONFIG.to_string(),
+                move |params, notifier| {
+                    let agent_manager = agent_manager.clone();
+                    let base_config = base_config.clone();
+                    async move {
+                        let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                        let request: UpdateSessionConfigRequest =
+                            serde_json::from_value(params_value.clone()).map_err(|e| {
+                                create_parse_error::<UpdateSessionConfigRequest>(&e, &params_value)
+                            })?;
+
+                        let handler = UpdateSessionConfigHandler::new(agent_manager, base_config);
+                        let response = handler
+                            .handle_update_session_config(request, notifier)
+                            .await
+                            .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                        serde_json::to_value(response).map_err(|e| {
+                            crate::jsonrpc::JsonRpcError::custom(
+                                crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                                format!("Failed to serialize response: {}", e),
+                                None,
+                            )
+                        })
+                    }
+                },
+            )
+            .await?;
+    }
+
+    // Register rollback_request method (non-streaming - restores files from backups)
+    {
+        let completed_backups = completed_backups.clone();
+        server
+            .register_async_method(ROLLBACK_REQUEST.to_string(), move |params| {
+                let completed_backups = completed_backups.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                    let request: RollbackRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| create_parse_error::<RollbackRequest>(&e, &params_value))?;
+
+                    let handler = RollbackHandler::new(completed_backups);
+                    let response = handler
+                        .handle_rollback(request)
+                        .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register get_tool_result method (non-streaming)
+    {
+        let tool_results = tool_results.clone();
+        server
+            .register_async_method(GET_TOOL_RESULT.to_string(), move |params| {
+                let tool_results = tool_results.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                    let request: GetToolResultRequest =
+                        serde_json::from_value(params_value.clone()).map_err(|e| {
+                            create_parse_error::<GetToolResultRequest>(&e, &params_value)
+                        })?;
+
+                    let handler = GetToolResultHandler::new(tool_results);
+                    let response = handler
+                        .handle_get_tool_result(request)
+                        .await
+                        .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register export_session method (non-streaming)
+    {
+        let agent_manager = agent_manager.clone();
+        server
+            .register_async_method(EXPORT_SESSION.to_string(), move |params| {
+                let agent_manager = agent_manager.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                    let request: ExportSessionRequest =
+                        serde_json::from_value(params_value.clone()).map_err(|e| {
+                            create_parse_error::<ExportSessionRequest>(&e, &params_value)
+                        })?;
+
+                    let handler = ExportSessionHandler::new(agent_manager);
+                    let response = handler
+                        .handle_export_session(request)
+                        .await
+                        .map_err(crate::jsonrpc::JsonRpcError::from)?;
+
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
                             crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
                             format!("Failed to serialize response: {}", e),
                             None,
-                        ))
+                        )
+                    })
                 }
-            },
-        ).await?;
+            })
+            .await?;
     }
 
     // Register client_user_message method (non-streaming, for client notifications)
@@ -169,28 +538,227 @@ pub async fn register_kaiak_methods(
                     let params_value = params.unwrap_or(serde_json::Value::Null);
 
                     // Parse directly as ClientNotificationRequest (no wrapper)
-                    let request: ClientNotificationRequest = serde_json::from_value(params_value.clone())
-                        .map_err(|e| {
+                    let request: ClientNotificationRequest =
+                        serde_json::from_value(params_value.clone()).map_err(|e| {
                             create_parse_error::<ClientNotificationRequest>(&e, &params_value)
                         })?;
 
-                    let handler = ClientNotificationHandler::new(agent_manager, interaction_manager);
-                    let response = handler.handle_notification(request).await
+                    let handler =
+                        ClientNotificationHandler::new(agent_manager, interaction_manager);
+                    let response = handler
+                        .handle_notification(request)
+                        .await
+                        .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+
+                    // Return raw response (no wrapper)
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register list_models method (non-streaming, catalogs cached across requests)
+    {
+        let base_config = base_config.clone();
+        let cache = std::sync::Arc::new(ModelCatalogCache::new());
+        server
+            .register_async_method(LIST_MODELS.to_string(), move |params| {
+                let base_config = base_config.clone();
+                let cache = cache.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::json!({}));
+
+                    // Parse directly as ListModelsRequest (no wrapper)
+                    let request: ListModelsRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| {
+                        create_parse_error::<ListModelsRequest>(&e, &params_value)
+                    })?;
+
+                    let handler = ListModelsHandler::new(base_config, cache);
+                    let response = handler
+                        .handle_list_models(request)
+                        .await
+                        .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+
+                    // Return raw response (no wrapper)
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register set_identity method (non-streaming, identity applies to later requests)
+    {
+        let identity = identity.clone();
+        server
+            .register_async_method(SET_IDENTITY.to_string(), move |params| {
+                let identity = identity.clone();
+                async move {
+                    let params_value = params.unwrap_or(serde_json::Value::Null);
+
+                    // Parse directly as SetIdentityRequest (no wrapper)
+                    let request: SetIdentityRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| create_parse_error::<SetIdentityRequest>(&e, &params_value))?;
+
+                    let response = IdentityHandler::new(identity)
+                        .handle_set_identity(request)
                         .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
 
                     // Return raw response (no wrapper)
-                    serde_json::to_value(response)
-                        .map_err(|e| crate::jsonrpc::JsonRpcError::custom(
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register whoami method (non-streaming)
+    {
+        let identity = identity.clone();
+        server
+            .register_async_method(WHOAMI.to_string(), move |_params| {
+                let identity = identity.clone();
+                async move {
+                    let response = IdentityHandler::new(identity).handle_whoami();
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register stream_logs method (non-streaming, the server writes the log lines alongside
+    // other notifications for as long as the subscription lasts)
+    {
+        server
+            .register_async_method(STREAM_LOGS.to_string(), move |params| {
+                async move {
+                    let params_value = params.unwrap_or(serde_json::json!({}));
+
+                    // Parse directly as StreamLogsRequest (no wrapper)
+                    let request: StreamLogsRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| {
+                        create_parse_error::<StreamLogsRequest>(&e, &params_value)
+                    })?;
+
+                    let handler = StreamLogsHandler::new(
+                        allow_log_streaming,
+                        crate::logging::log_stream().clone(),
+                    );
+                    let response = handler
+                        .handle_stream_logs(request)
+                        .map_err(|e| crate::jsonrpc::JsonRpcError::from(e))?;
+
+                    // Return raw response (no wrapper)
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register server_info method (non-streaming, lists methods and plugin namespaces)
+    {
+        let catalog = server.method_catalog();
+        let read_only = base_config.read_only;
+        server
+            .register_async_method(SERVER_INFO.to_string(), move |_params| {
+                let catalog = catalog.clone();
+                async move {
+                    Ok::<_, JsonRpcError>(serde_json::json!({
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "methods": catalog.methods().await,
+                        "namespaces": catalog.namespaces().await,
+                        "capabilities": {
+                            "read_only": read_only,
+                            "log_streaming": allow_log_streaming,
+                        },
+                    }))
+                }
+            })
+            .await?;
+    }
+
+    // Register ready method (non-streaming)
+    {
+        let agent_manager = agent_manager.clone();
+        let base_config = base_config.clone();
+        let status = server.status();
+        server
+            .register_async_method(READY.to_string(), move |_params| {
+                let handler =
+                    ReadyHandler::new(agent_manager.clone(), base_config.clone(), status.clone());
+                async move {
+                    let response = handler.handle_ready().await;
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
+                            crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
+                            format!("Failed to serialize response: {}", e),
+                            None,
+                        )
+                    })
+                }
+            })
+            .await?;
+    }
+
+    // Register stats method (non-streaming, server-wide totals)
+    {
+        let agent_manager = agent_manager.clone();
+        server
+            .register_async_method(STATS.to_string(), move |params| {
+                let handler = StatsHandler::new(agent_manager.clone());
+                async move {
+                    let params_value = params.unwrap_or_else(|| serde_json::json!({}));
+
+                    let request: StatsRequest = serde_json::from_value(params_value.clone())
+                        .map_err(|e| create_parse_error::<StatsRequest>(&e, &params_value))?;
+
+                    let response = handler.handle_stats(request).await;
+                    serde_json::to_value(response).map_err(|e| {
+                        crate::jsonrpc::JsonRpcError::custom(
                             crate::jsonrpc::protocol::error_codes::INTERNAL_ERROR,
                             format!("Failed to serialize response: {}", e),
                             None,
-                        ))
+                        )
+                    })
                 }
-            },
-        ).await?;
+            })
+            .await?;
     }
 
-    tracing::info!("Registered {} Kaiak JSON-RPC methods", 3);
+    tracing::info!(
+        "Registered {} Kaiak JSON-RPC methods",
+        methods::ALL_METHODS.len()
+    );
     Ok(())
 }
 
@@ -47,6 +47,31 @@ pub struct JsonRpcBatch(pub Vec<JsonRpcRequest>);
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRpcBatchResponse(pub Vec<JsonRpcResponse>);
 
+/// A single message read from a transport: one request or a batch
+#[derive(Debug, Clone)]
+pub enum JsonRpcMessage {
+    Single(JsonRpcRequest),
+    /// Raw batch entries; each is parsed and validated individually when dispatched
+    Batch(Vec<serde_json::Value>),
+}
+
+impl JsonRpcMessage {
+    /// Parse message content, accepting either a request object or a batch array
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(content)?;
+        match value {
+            serde_json::Value::Array(entries) => Ok(JsonRpcMessage::Batch(entries)),
+            value => {
+                let request: JsonRpcRequest = serde_json::from_value(value)?;
+                request
+                    .validate()
+                    .map_err(|e| anyhow::anyhow!("Invalid request: {}", e.message))?;
+                Ok(JsonRpcMessage::Single(request))
+            }
+        }
+    }
+}
+
 /// JSON-RPC 2.0 Notification (server-to-client, no response expected)
 /// 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,6 +103,21 @@ impl JsonRpcNotification {
             })),
         )
     }
+
+    /// Attach a trace ID to the notification params (object params only)
+    pub fn with_trace_id(mut self, trace_id: &str) -> Self {
+        let params = self.params.get_or_insert_with(|| serde_json::json!({}));
+        insert_trace_id(params, trace_id);
+        self
+    }
+}
+
+/// Insert `trace_id` into a JSON object unless one is already present
+fn insert_trace_id(value: &mut serde_json::Value, trace_id: &str) {
+    if let Some(map) = value.as_object_mut() {
+        map.entry("trace_id")
+            .or_insert_with(|| serde_json::Value::String(trace_id.to_string()));
+    }
 }
 
 /// Standard JSON-RPC 2.0 error codes
@@ -89,6 +129,8 @@ pub mod error_codes {
     pub const INTERNAL_ERROR: i32 = -32603;
     pub const SERVER_ERROR_START: i32 = -32099;
     pub const SERVER_ERROR_END: i32 = -32000;
+    /// A request ID that a running request already uses
+    pub const REQUEST_IN_USE: i32 = -32018;
 }
 
 impl JsonRpcRequest {
@@ -112,6 +154,16 @@ impl JsonRpcRequest {
         self.id.is_none()
     }
 
+    /// Get the client-supplied trace ID from the params object, if any
+    pub fn trace_id(&self) -> Option<String> {
+        self.params
+            .as_ref()?
+            .get("trace_id")?
+            .as_str()
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_string())
+    }
+
     /// Validate the request structure
     pub fn validate(&self) -> Result<(), JsonRpcError> {
         if self.jsonrpc != "2.0" {
@@ -222,6 +274,18 @@ impl JsonRpcResponse {
             id,
         )
     }
+
+    /// Echo a trace ID in the result object, or in the error data
+    pub fn with_trace_id(mut self, trace_id: &str) -> Self {
+        if let Some(result) = self.result.as_mut() {
+            insert_trace_id(result, trace_id);
+        }
+        if let Some(error) = self.error.as_mut() {
+            let data = error.data.get_or_insert_with(|| serde_json::json!({}));
+            insert_trace_id(data, trace_id);
+        }
+        self
+    }
 }
 
 impl JsonRpcError {
@@ -231,6 +295,14 @@ impl JsonRpcError {
     }
 }
 
+impl std::fmt::Display for JsonRpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JSON-RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for JsonRpcError {}
+
 /// Convert our KaiakError to JSON-RPC error
 impl From<crate::KaiakError> for JsonRpcError {
     fn from(error: crate::KaiakError) -> Self {
@@ -252,6 +324,8 @@ impl From<crate::KaiakError> for JsonRpcError {
             crate::KaiakError::ToolExecution { .. } => -32013,
             crate::KaiakError::InteractionTimeout { .. } => -32014,
             crate::KaiakError::FileOperation { .. } => -32012,
+            crate::KaiakError::InvalidParams(_) => error_codes::INVALID_PARAMS,
+            crate::KaiakError::RequestInUse(_) => error_codes::REQUEST_IN_USE,
         };
 
         JsonRpcError {
@@ -342,4 +416,18 @@ mod tests {
         assert_eq!(req.method, parsed.method);
         assert_eq!(req.id, parsed.id);
     }
+
+    #[test]
+    fn test_message_parsing() {
+        let single = JsonRpcMessage::parse(r#"{"jsonrpc":"2.0","method":"ping","id":1}"#).unwrap();
+        assert!(matches!(single, JsonRpcMessage::Single(req) if req.method == "ping"));
+
+        let batch = JsonRpcMessage::parse(
+            r#"[{"jsonrpc":"2.0","method":"ping","id":1},{"jsonrpc":"2.0","method":"health","id":2}]"#,
+        )
+        .unwrap();
+        assert!(matches!(batch, JsonRpcMessage::Batch(entries) if entries.len() == 2));
+
+        assert!(JsonRpcMessage::parse(r#"{"jsonrpc":"1.0","method":"ping","id":1}"#).is_err());
+    }
 }
\ No newline at end of file
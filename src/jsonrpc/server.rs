@@ -4,16 +4,22 @@
 //! and dispatch them to registered method handlers.
 
 use crate::jsonrpc::{
-    protocol::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse, JsonRpcError},
-    transport::{Transport, TransportConfig},
+    protocol::{
+        JsonRpcBatchResponse, JsonRpcError, JsonRpcMessage, JsonRpcNotification, JsonRpcRequest,
+        JsonRpcResponse,
+    },
+    transport::{lsp_format, Transport, TransportConfig},
 };
+use crate::logging::LogStream;
+use crate::models::configuration::Framing;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use futures::future::BoxFuture;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex};
-use tracing::{debug, error, info, instrument, trace, warn};
+use tracing::{debug, error, info, instrument, trace, warn, Instrument};
 
 /// Sender for streaming notifications from handlers
 pub type NotificationSender = mpsc::UnboundedSender<JsonRpcNotification>;
@@ -50,25 +56,57 @@ pub struct JsonRpcServer {
     running: Arc<Mutex<bool>>,
     /// Sender for notifications - clone and pass to handlers
     notification_tx: NotificationSender,
+    /// Notifications sent outside any request, written to the client between and
+    /// alongside request notifications
+    notification_rx: NotificationReceiver,
+    /// Transport connection the last message arrived on
+    connection_id: u64,
+    /// Run when the client the last message came from is gone
+    disconnect_hooks: Vec<Box<dyn Fn() + Send + Sync>>,
+    /// Log lines written to the client while it is subscribed
+    log_stream: Option<LogStream>,
 }
 
 impl JsonRpcServer {
     /// Create a new JSON-RPC server with the specified transport
     pub async fn new(transport_config: TransportConfig) -> Result<Self> {
         let transport = transport_config.create_transport().await?;
-        let (notification_tx, _notification_rx) = mpsc::unbounded_channel();
+        Ok(Self::with_transport(transport))
+    }
+
+    /// Create a new JSON-RPC server over an already constructed transport
+    pub fn with_transport(transport: Box<dyn Transport>) -> Self {
+        let (notification_tx, notification_rx) = mpsc::unbounded_channel();
 
-        Ok(Self {
+        Self {
             transport,
             methods: Arc::new(Mutex::new(HashMap::new())),
             running: Arc::new(Mutex::new(false)),
             notification_tx,
-        })
+            notification_rx,
+            connection_id: 0,
+            disconnect_hooks: Vec::new(),
+            log_stream: None,
+        }
+    }
+
+    /// Run `hook` whenever a message arrives on a new transport connection, so state
+    /// tied to the previous client can be dropped
+    pub fn on_disconnect(&mut self, hook: impl Fn() + Send + Sync + 'static) {
+        self.disconnect_hooks.push(Box::new(hook));
+    }
+
+    /// Write the lines buffered in `stream` to the client alongside other notifications.
+    /// The subscription ends when a line cannot be written or the client's input ends.
+    pub fn forward_logs(&mut self, stream: LogStream) {
+        self.log_stream = Some(stream);
     }
 
     /// Get a clone of the notification sender
     /// 
-    /// Pass this to handlers that need to stream notifications back to the client.
+    /// Pass this to handlers that need to stream notifications back to the client
+    /// outside their own request. Notifications are written while the server handles
+    /// requests, so ones sent while it waits for the next message are delayed until then.
     pub fn notification_sender(&self) -> NotificationSender {
         self.notification_tx.clone()
     }
@@ -87,8 +125,7 @@ impl JsonRpcServer {
             Box::pin(handler(params))
         });
 
-        let mut methods = self.methods.lock().await;
-        methods.insert(method_name.clone(), HandlerType::NonStreaming(wrapped_handler));
+        self.insert_handler(&method_name, HandlerType::NonStreaming(wrapped_handler)).await?;
 
         debug!("Registered method: {}", method_name);
         Ok(())
@@ -115,8 +152,7 @@ impl JsonRpcServer {
             })
         });
 
-        let mut methods = self.methods.lock().await;
-        methods.insert(method_name.clone(), HandlerType::NonStreaming(wrapped_handler));
+        self.insert_handler(&method_name, HandlerType::NonStreaming(wrapped_handler)).await?;
 
         debug!("Registered async method: {}", method_name);
         Ok(())
@@ -143,13 +179,57 @@ impl JsonRpcServer {
             })
         });
 
-        let mut methods = self.methods.lock().await;
-        methods.insert(method_name.clone(), HandlerType::Streaming(wrapped_handler));
+        self.insert_handler(&method_name, HandlerType::Streaming(wrapped_handler)).await?;
 
         debug!("Registered streaming method: {}", method_name);
         Ok(())
     }
 
+    /// Store a handler, refusing to replace one already registered under the same name
+    async fn insert_handler(&self, method_name: &str, handler: HandlerType) -> Result<()> {
+        let mut methods = self.methods.lock().await;
+        match methods.entry(method_name.to_string()) {
+            Entry::Occupied(_) => Err(anyhow!(
+                "Method '{}' is already registered; each method name can only have one handler",
+                method_name
+            )),
+            Entry::Vacant(entry) => {
+                entry.insert(handler);
+                Ok(())
+            }
+        }
+    }
+
+    /// Start a group of methods registered under `namespace/`, e.g. `myplugin/doThing`
+    pub fn register_namespace(&self, namespace: &str) -> Result<MethodNamespace<'_>> {
+        if namespace.is_empty() || namespace.contains('/') || namespace.starts_with("rpc.") {
+            return Err(anyhow!(
+                "Invalid namespace '{}': must be non-empty, contain no '/', and not start with 'rpc.'",
+                namespace
+            ));
+        }
+
+        Ok(MethodNamespace {
+            server: self,
+            namespace: namespace.to_string(),
+        })
+    }
+
+    /// Read-only view of the method registry that can be moved into handlers
+    pub fn method_catalog(&self) -> MethodCatalog {
+        MethodCatalog {
+            methods: self.methods.clone(),
+        }
+    }
+
+    /// Read-only view of whether the server is accepting connections, that can be moved
+    /// into handlers
+    pub fn status(&self) -> ServerStatus {
+        ServerStatus {
+            running: self.running.clone(),
+        }
+    }
+
     /// Check if the server is running
     pub async fn is_running(&self) -> bool {
         let running = self.running.lock().await;
@@ -188,14 +268,24 @@ impl JsonRpcServer {
         Ok(())
     }
     
-    /// Handle a single request while streaming notifications concurrently
+    /// Handle a single request or batch while streaming notifications concurrently
     async fn handle_single_request_with_streaming(&mut self) -> Result<()> {
         // Read request from transport
-        let request = match self.transport.read_request().await {
-            Ok(req) => req,
+        let message = match self.transport.read_message().await {
+            Ok(message) => message,
             Err(e) => {
                 error!("Failed to read request: {}", e);
-                let response = JsonRpcResponse::parse_error();
+                // Nobody is left to read log lines once the input has ended
+                if !lsp_format::is_framing_error(&e) {
+                    self.stop_forwarding_logs();
+                }
+                let mut response = JsonRpcResponse::parse_error();
+                // Tell the client what was wrong with the framing it sent
+                if lsp_format::is_framing_error(&e) {
+                    if let Some(error) = response.error.as_mut() {
+                        error.data = Some(serde_json::json!({ "framing_error": e.to_string() }));
+                    }
+                }
                 if let Err(write_err) = self.transport.write_response(response).await {
                     error!("Failed to send error response: {}", write_err);
                 }
@@ -203,7 +293,89 @@ impl JsonRpcServer {
             }
         };
 
-        debug!("Received request: method={}, id={:?}", request.method, request.id);
+        let connection_id = self.transport.connection_id();
+        if connection_id != self.connection_id {
+            debug!("Message on new connection {}, dropping state of the previous one", connection_id);
+            self.connection_id = connection_id;
+            while self.notification_rx.try_recv().is_ok() {}
+            for hook in &self.disconnect_hooks {
+                hook();
+            }
+        }
+
+        match message {
+            JsonRpcMessage::Single(request) => {
+                if let Some(response) = self.dispatch_with_streaming(request).await {
+                    if let Err(e) = self.transport.write_response(response).await {
+                        error!("Failed to send response: {}", e);
+                    }
+                }
+            }
+            JsonRpcMessage::Batch(entries) => self.handle_batch(entries).await,
+        }
+
+        Ok(())
+    }
+
+    /// Dispatch each batch entry in order and reply with one array of responses.
+    /// Notifications produce no entry; an all-notification batch produces no reply.
+    async fn handle_batch(&mut self, entries: Vec<serde_json::Value>) {
+        if entries.is_empty() {
+            if let Err(e) = self.transport.write_response(JsonRpcResponse::invalid_request(None)).await {
+                error!("Failed to send error response: {}", e);
+            }
+            return;
+        }
+
+        debug!("Received batch of {} requests", entries.len());
+
+        let mut responses = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let id = entry.get("id").cloned();
+            match serde_json::from_value::<JsonRpcRequest>(entry) {
+                // Held to the same rules as a request sent on its own
+                Ok(request) => match request.validate() {
+                    Ok(()) => {
+                        if let Some(response) = self.dispatch_with_streaming(request).await {
+                            responses.push(response);
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Invalid batch entry: {}", e.message);
+                        responses.push(JsonRpcResponse::error(e, id));
+                    }
+                },
+                Err(e) => {
+                    warn!("Invalid batch entry: {}", e);
+                    responses.push(JsonRpcResponse::invalid_request(id));
+                }
+            }
+        }
+
+        if !responses.is_empty() {
+            if let Err(e) = self
+                .transport
+                .write_batch_response(JsonRpcBatchResponse(responses))
+                .await
+            {
+                error!("Failed to send batch response: {}", e);
+            }
+        }
+    }
+
+    /// Run one request, streaming its notifications, and return its response (if any)
+    async fn dispatch_with_streaming(&mut self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        // Use the client's trace ID when provided so its logs and ours correlate
+        let trace_id = request
+            .trace_id()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        debug!(
+            "Received request: method={}, id={:?}, trace_id={}",
+            request.method, request.id, trace_id
+        );
+
+        self.write_connection_notifications().await;
 
         // Create a fresh notification channel for this request
         let (notification_tx, mut notification_rx) = mpsc::unbounded_channel::<JsonRpcNotification>();
@@ -212,15 +384,29 @@ impl JsonRpcServer {
         let methods = self.methods.clone();
         let request_id = request.id.clone();
         let is_notification = request.is_notification();
+        // Handlers that assign their own request ID record it in `request_id`, the session
+        // they run in in `session_id`, and the client identity they act for in `identity`
+        let span = tracing::info_span!(
+            "request",
+            method = %request.method,
+            trace_id = %trace_id,
+            request_id = tracing::field::Empty,
+            session_id = tracing::field::Empty,
+            identity = tracing::field::Empty,
+        );
         
         // Spawn the request processing as a task so we can stream notifications concurrently
-        let mut process_handle = tokio::spawn(async move {
-            Self::process_request_static(methods, request, notification_tx).await
-        });
+        let mut process_handle = tokio::spawn(
+            async move { Self::process_request_static(methods, request, notification_tx).await }
+                .instrument(span),
+        );
 
+        let log_stream = self.log_stream.clone();
         // Track if client is still connected
         let mut client_connected = true;
         let mut response: Option<JsonRpcResponse> = None;
+        // Notification requests finish without a response, so track completion separately
+        let mut handler_done = false;
 
         // Track if notification channel is still open
         let mut channel_open = true;
@@ -235,6 +421,7 @@ impl JsonRpcServer {
                     match notification {
                         Some(notification) => {
                             trace!("Streaming notification: {}", notification.method);
+                            let notification = notification.with_trace_id(&trace_id);
                             if let Err(e) = self.transport.write_notification(notification).await {
                                 let is_broken_pipe = e.to_string().contains("Broken pipe") 
                                     || e.to_string().contains("os error 32");
@@ -254,9 +441,23 @@ impl JsonRpcServer {
                         }
                     }
                 }
+
+                // Forward notifications sent outside any request
+                Some(notification) = self.notification_rx.recv(), if client_connected => {
+                    let span = tracing::info_span!(crate::logging::UNSTREAMED_SPAN);
+                    if let Err(e) = self.transport.write_notification(notification).instrument(span).await {
+                        debug!("Failed to send notification: {}", e);
+                    }
+                }
+
+                // Forward log lines as they are buffered
+                _ = Self::log_lines_ready(&log_stream), if client_connected => {
+                    self.write_log_lines().await;
+                }
                 
                 // Check if request processing is complete
-                result = &mut process_handle, if response.is_none() => {
+                result = &mut process_handle, if !handler_done => {
+                    handler_done = true;
                     match result {
                         Ok(resp) => {
                             response = resp;
@@ -276,19 +477,17 @@ impl JsonRpcServer {
             }
             
             // Exit when we have the response and notification channel is closed
-            if response.is_some() && !channel_open {
+            if handler_done && !channel_open {
                 break;
             }
         }
 
-        // Send response (if not a notification request)
-        if let Some(response) = response {
-            if let Err(e) = self.transport.write_response(response).await {
-                error!("Failed to send response: {}", e);
-            }
+        if client_connected {
+            self.write_connection_notifications().await;
         }
 
-        Ok(())
+        // Notification requests produce no response
+        response.map(|response| response.with_trace_id(&trace_id))
     }
     
     /// Process a request (static version for spawning)
@@ -347,6 +546,57 @@ impl JsonRpcServer {
         }
     }
 
+    /// Write the notifications queued on the server's own channel
+    async fn write_connection_notifications(&mut self) {
+        let span = tracing::info_span!(crate::logging::UNSTREAMED_SPAN);
+        while let Ok(notification) = self.notification_rx.try_recv() {
+            if let Err(e) = self
+                .transport
+                .write_notification(notification)
+                .instrument(span.clone())
+                .await
+            {
+                debug!("Failed to send notification: {}", e);
+                return;
+            }
+        }
+        self.write_log_lines().await;
+    }
+
+    /// Write the buffered log lines, ending the subscription if one cannot be written
+    async fn write_log_lines(&mut self) {
+        let Some(stream) = &self.log_stream else { return };
+        let span = tracing::info_span!(crate::logging::UNSTREAMED_SPAN);
+        for line in stream.take_pending() {
+            if let Err(e) = self
+                .transport
+                .write_notification(line)
+                .instrument(span.clone())
+                .await
+            {
+                debug!("Failed to send log line: {}", e);
+                self.stop_forwarding_logs();
+                return;
+            }
+        }
+    }
+
+    /// Wait for a log line to be buffered; never completes without a stream
+    async fn log_lines_ready(stream: &Option<LogStream>) {
+        match stream {
+            Some(stream) => stream.ready().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn stop_forwarding_logs(&self) {
+        if let Some(stream) = &self.log_stream {
+            if stream.unsubscribe() {
+                info!("Stopped streaming logs to the client");
+            }
+        }
+    }
+
     /// Send a notification immediately (for use outside request handlers)
     pub async fn send_notification(&mut self, notification: JsonRpcNotification) -> Result<()> {
         self.transport.write_notification(notification).await
@@ -370,16 +620,100 @@ impl JsonRpcServer {
         methods.keys().cloned().collect()
     }
 
+    /// Get the namespaces of registered methods
+    pub async fn get_registered_namespaces(&self) -> Vec<String> {
+        self.method_catalog().namespaces().await
+    }
+
     /// Get transport description
     pub fn transport_description(&self) -> &'static str {
         self.transport.description()
     }
 }
 
+/// Methods registered under a common `namespace/` prefix
+pub struct MethodNamespace<'a> {
+    server: &'a JsonRpcServer,
+    namespace: String,
+}
+
+impl MethodNamespace<'_> {
+    /// Fully qualified name of a method in this namespace
+    pub fn method_name(&self, name: &str) -> String {
+        format!("{}/{}", self.namespace, name)
+    }
+
+    /// Register an async method as `namespace/name`
+    pub async fn register_async_method<F, Fut, E>(&self, name: &str, handler: F) -> Result<()>
+    where
+        F: Fn(Option<serde_json::Value>) -> Fut + Send + Sync + 'static + Clone,
+        Fut: std::future::Future<Output = Result<serde_json::Value, E>> + Send + 'static,
+        E: Into<JsonRpcError> + Send + 'static,
+    {
+        self.server
+            .register_async_method(self.method_name(name), handler)
+            .await
+    }
+
+    /// Register a streaming method as `namespace/name`
+    pub async fn register_streaming_method<F, Fut, E>(&self, name: &str, handler: F) -> Result<()>
+    where
+        F: Fn(Option<serde_json::Value>, NotificationSender) -> Fut + Send + Sync + 'static + Clone,
+        Fut: std::future::Future<Output = Result<serde_json::Value, E>> + Send + 'static,
+        E: Into<JsonRpcError> + Send + 'static,
+    {
+        self.server
+            .register_streaming_method(self.method_name(name), handler)
+            .await
+    }
+}
+
+/// Shared read-only handle to the registered method names
+#[derive(Clone)]
+pub struct MethodCatalog {
+    methods: Arc<Mutex<HashMap<String, HandlerType>>>,
+}
+
+impl MethodCatalog {
+    /// Registered method names, sorted
+    pub async fn methods(&self) -> Vec<String> {
+        let methods = self.methods.lock().await;
+        let mut names: Vec<String> = methods.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Distinct namespaces (the part before the first `/`) of registered methods, sorted
+    pub async fn namespaces(&self) -> Vec<String> {
+        let methods = self.methods.lock().await;
+        methods
+            .keys()
+            .filter_map(|name| name.split_once('/').map(|(namespace, _)| namespace.to_string()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Shared read-only handle to whether the server is running
+#[derive(Clone)]
+pub struct ServerStatus {
+    running: Arc<Mutex<bool>>,
+}
+
+impl ServerStatus {
+    /// Whether the server has started and not yet stopped
+    pub async fn is_running(&self) -> bool {
+        *self.running.lock().await
+    }
+}
+
 /// Builder for JSON-RPC server
 pub struct ServerBuilder {
     transport_config: Option<TransportConfig>,
     methods: HashMap<String, HandlerType>,
+    /// Names registered more than once; reported by `build`
+    duplicates: Vec<String>,
 }
 
 impl ServerBuilder {
@@ -388,6 +722,16 @@ impl ServerBuilder {
         Self {
             transport_config: None,
             methods: HashMap::new(),
+            duplicates: Vec::new(),
+        }
+    }
+
+    fn insert_handler(&mut self, method_name: String, handler: HandlerType) {
+        match self.methods.entry(method_name) {
+            Entry::Occupied(entry) => self.duplicates.push(entry.key().clone()),
+            Entry::Vacant(entry) => {
+                entry.insert(handler);
+            }
         }
     }
 
@@ -397,14 +741,19 @@ impl ServerBuilder {
         self
     }
 
-    /// Set stdio transport
+    /// Set stdio transport with the default framing
     pub fn with_stdio(self) -> Self {
-        self.with_transport(TransportConfig::Stdio)
+        self.with_transport(TransportConfig::Stdio {
+            framing: Framing::default(),
+        })
     }
 
-    /// Set Unix socket transport
+    /// Set Unix socket transport with the default framing
     pub fn with_unix_socket(self, path: String) -> Self {
-        self.with_transport(TransportConfig::UnixSocket { path })
+        self.with_transport(TransportConfig::UnixSocket {
+            path,
+            framing: Framing::default(),
+        })
     }
 
     /// Register a method during building
@@ -421,7 +770,7 @@ impl ServerBuilder {
             Box::pin(handler(params))
         });
 
-        self.methods.insert(method_name, HandlerType::NonStreaming(wrapped_handler));
+        self.insert_handler(method_name, HandlerType::NonStreaming(wrapped_handler));
         self
     }
 
@@ -446,7 +795,7 @@ impl ServerBuilder {
             })
         });
 
-        self.methods.insert(method_name, HandlerType::Streaming(wrapped_handler));
+        self.insert_handler(method_name, HandlerType::Streaming(wrapped_handler));
         self
     }
 
@@ -455,6 +804,13 @@ impl ServerBuilder {
         let transport_config = self.transport_config
             .ok_or_else(|| anyhow!("Transport configuration not specified"))?;
 
+        if !self.duplicates.is_empty() {
+            return Err(anyhow!(
+                "Methods registered more than once: {}",
+                self.duplicates.join(", ")
+            ));
+        }
+
         let server = JsonRpcServer::new(transport_config).await?;
 
         // Register pre-configured methods
@@ -524,3 +880,326 @@ where
         })
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// In-memory transport that replays queued requests and records everything written
+    #[derive(Default)]
+    struct MockTransport {
+        messages: VecDeque<JsonRpcMessage>,
+        responses: Arc<std::sync::Mutex<Vec<JsonRpcResponse>>>,
+        batch_responses: Arc<std::sync::Mutex<Vec<JsonRpcBatchResponse>>>,
+        notifications: Arc<std::sync::Mutex<Vec<JsonRpcNotification>>>,
+    }
+
+    #[async_trait]
+    impl Transport for MockTransport {
+        async fn read_request(&mut self) -> Result<JsonRpcRequest> {
+            match self.read_message().await? {
+                JsonRpcMessage::Single(request) => Ok(request),
+                JsonRpcMessage::Batch(_) => Err(anyhow!("Unexpected batch")),
+            }
+        }
+
+        async fn read_message(&mut self) -> Result<JsonRpcMessage> {
+            self.messages
+                .pop_front()
+                .ok_or_else(|| anyhow!("Connection closed"))
+        }
+
+        async fn write_response(&mut self, response: JsonRpcResponse) -> Result<()> {
+            self.responses.lock().unwrap().push(response);
+            Ok(())
+        }
+
+        async fn write_batch_response(&mut self, responses: JsonRpcBatchResponse) -> Result<()> {
+            self.batch_responses.lock().unwrap().push(responses);
+            Ok(())
+        }
+
+        async fn write_notification(&mut self, notification: JsonRpcNotification) -> Result<()> {
+            self.notifications.lock().unwrap().push(notification);
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn description(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    async fn server_with_echo_method(
+        request: JsonRpcRequest,
+    ) -> (
+        JsonRpcServer,
+        Arc<std::sync::Mutex<Vec<JsonRpcResponse>>>,
+        Arc<std::sync::Mutex<Vec<JsonRpcNotification>>>,
+    ) {
+        let transport = MockTransport {
+            messages: VecDeque::from(vec![JsonRpcMessage::Single(request)]),
+            ..Default::default()
+        };
+        let responses = transport.responses.clone();
+        let notifications = transport.notifications.clone();
+
+        let server = JsonRpcServer::with_transport(Box::new(transport));
+        server
+            .register_streaming_method("test/echo".to_string(), |params, notifier| async move {
+                let _ = notifier.send(JsonRpcNotification::new(
+                    "test/echo/data",
+                    Some(serde_json::json!({ "step": 1 })),
+                ));
+                Ok::<_, JsonRpcError>(serde_json::json!({ "echo": params }))
+            })
+            .await
+            .unwrap();
+
+        (server, responses, notifications)
+    }
+
+    #[tokio::test]
+    async fn test_trace_id_echoed_in_response_and_notifications() {
+        let request = JsonRpcRequest::new(
+            "test/echo".to_string(),
+            Some(serde_json::json!({ "trace_id": "trace-abc" })),
+            Some(serde_json::json!(1)),
+        );
+        let (mut server, responses, notifications) = server_with_echo_method(request).await;
+
+        server.handle_single_request_with_streaming().await.unwrap();
+
+        let responses = responses.lock().unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(
+            responses[0].result.as_ref().unwrap()["trace_id"],
+            "trace-abc"
+        );
+
+        let notifications = notifications.lock().unwrap();
+        assert!(!notifications.is_empty());
+        assert_eq!(
+            notifications[0].params.as_ref().unwrap()["trace_id"],
+            "trace-abc"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_id_generated_when_absent() {
+        let request = JsonRpcRequest::new(
+            "test/echo".to_string(),
+            Some(serde_json::json!({})),
+            Some(serde_json::json!(1)),
+        );
+        let (mut server, responses, notifications) = server_with_echo_method(request).await;
+
+        server.handle_single_request_with_streaming().await.unwrap();
+
+        let responses = responses.lock().unwrap();
+        let trace_id = responses[0].result.as_ref().unwrap()["trace_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(!trace_id.is_empty());
+
+        // Notifications for the same request share the generated trace ID
+        let notifications = notifications.lock().unwrap();
+        assert_eq!(
+            notifications[0].params.as_ref().unwrap()["trace_id"],
+            trace_id.as_str()
+        );
+    }
+
+    async fn server_with_batch(
+        entries: Vec<serde_json::Value>,
+    ) -> (
+        JsonRpcServer,
+        Arc<std::sync::Mutex<Vec<JsonRpcResponse>>>,
+        Arc<std::sync::Mutex<Vec<JsonRpcBatchResponse>>>,
+    ) {
+        let transport = MockTransport {
+            messages: VecDeque::from(vec![JsonRpcMessage::Batch(entries)]),
+            ..Default::default()
+        };
+        let responses = transport.responses.clone();
+        let batch_responses = transport.batch_responses.clone();
+
+        let server = JsonRpcServer::with_transport(Box::new(transport));
+        server
+            .register_method("ping".to_string(), |_| async { Ok(serde_json::json!("pong")) })
+            .await
+            .unwrap();
+        server
+            .register_method("health".to_string(), |_| async {
+                Ok(serde_json::json!({ "status": "ok" }))
+            })
+            .await
+            .unwrap();
+
+        (server, responses, batch_responses)
+    }
+
+    #[tokio::test]
+    async fn test_batch_returns_ordered_responses() {
+        let (mut server, responses, batch_responses) = server_with_batch(vec![
+            serde_json::json!({ "jsonrpc": "2.0", "method": "ping", "id": 1 }),
+            serde_json::json!({ "jsonrpc": "2.0", "method": "ping" }),
+            serde_json::json!({ "jsonrpc": "2.0", "method": "health", "id": 2 }),
+            // Rejected as they would be on their own
+            serde_json::json!({ "jsonrpc": "1.0", "method": "ping", "id": 3 }),
+            serde_json::json!({ "jsonrpc": "2.0", "method": "", "id": 4 }),
+            serde_json::json!({ "jsonrpc": "2.0", "method": "rpc.ping", "id": 5 }),
+        ])
+        .await;
+
+        server.handle_single_request_with_streaming().await.unwrap();
+
+        assert!(responses.lock().unwrap().is_empty());
+        let batch_responses = batch_responses.lock().unwrap();
+        assert_eq!(batch_responses.len(), 1);
+
+        // The notification entry gets no response
+        let batch = &batch_responses[0].0;
+        assert_eq!(batch.len(), 5);
+        assert_eq!(batch[0].id, Some(serde_json::json!(1)));
+        assert_eq!(batch[0].result.as_ref().unwrap(), &serde_json::json!("pong"));
+        assert_eq!(batch[1].id, Some(serde_json::json!(2)));
+        assert_eq!(batch[1].result.as_ref().unwrap()["status"], "ok");
+        for (response, id) in batch[2..].iter().zip(3..) {
+            assert_eq!(response.id, Some(serde_json::json!(id)));
+            assert!(response.result.is_none());
+            assert_eq!(
+                response.error.as_ref().unwrap().code,
+                crate::jsonrpc::protocol::error_codes::INVALID_REQUEST
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_batch_is_invalid_request() {
+        let (mut server, responses, batch_responses) = server_with_batch(vec![]).await;
+
+        server.handle_single_request_with_streaming().await.unwrap();
+
+        assert!(batch_responses.lock().unwrap().is_empty());
+        let responses = responses.lock().unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(
+            responses[0].error.as_ref().unwrap().code,
+            crate::jsonrpc::protocol::error_codes::INVALID_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribed_log_lines_are_streamed_during_requests() {
+        use crate::logging::{LogStream, LogStreamLayer};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let stream = LogStream::default();
+        let subscriber = tracing_subscriber::registry().with(LogStreamLayer::new(stream.clone()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let transport = MockTransport {
+            messages: VecDeque::from(vec![JsonRpcMessage::Single(JsonRpcRequest::new(
+                "test/work".to_string(),
+                None,
+                Some(serde_json::json!(1)),
+            ))]),
+            ..Default::default()
+        };
+        let notifications = transport.notifications.clone();
+        let mut server = JsonRpcServer::with_transport(Box::new(transport));
+        server
+            .register_method("test/work".to_string(), |_| async {
+                tracing::info!(session_id = "s-1", "fixing s-1");
+                tracing::debug!(session_id = "s-1", "too verbose");
+                tracing::info!(session_id = "s-2", "fixing s-2");
+                Ok(serde_json::json!("done"))
+            })
+            .await
+            .unwrap();
+        stream.subscribe(tracing::Level::INFO, Some("s-1".to_string()));
+        server.forward_logs(stream.clone());
+
+        server.handle_single_request_with_streaming().await.unwrap();
+
+        let notifications = notifications.lock().unwrap();
+        let lines: Vec<&serde_json::Value> = notifications
+            .iter()
+            .filter(|n| n.method == crate::jsonrpc::methods::LOG)
+            .filter_map(|n| n.params.as_ref())
+            .collect();
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["session_id"], "s-1");
+        assert!(lines[0]["message"].as_str().unwrap().starts_with("fixing s-1"));
+    }
+
+    #[tokio::test]
+    async fn test_log_stream_ends_when_input_ends() {
+        let stream = crate::logging::LogStream::default();
+        stream.subscribe(tracing::Level::INFO, None);
+        let mut server = JsonRpcServer::with_transport(Box::new(MockTransport::default()));
+        server.forward_logs(stream.clone());
+
+        // No more messages: the mock reports the connection closed
+        server.handle_single_request_with_streaming().await.unwrap();
+
+        assert!(!stream.is_subscribed());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_registration_fails() {
+        let server = JsonRpcServer::with_transport(Box::new(MockTransport::default()));
+        server
+            .register_method("ping".to_string(), |_| async { Ok(serde_json::json!("pong")) })
+            .await
+            .unwrap();
+
+        let err = server
+            .register_method("ping".to_string(), |_| async { Ok(serde_json::json!("again")) })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Method 'ping' is already registered"));
+
+        let plugin = server.register_namespace("myplugin").unwrap();
+        plugin
+            .register_async_method("doThing", |_| async { Ok::<_, JsonRpcError>(serde_json::json!(1)) })
+            .await
+            .unwrap();
+        let err = plugin
+            .register_async_method("doThing", |_| async { Ok::<_, JsonRpcError>(serde_json::json!(2)) })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("'myplugin/doThing'"));
+    }
+
+    #[tokio::test]
+    async fn test_namespaced_methods() {
+        let server = JsonRpcServer::with_transport(Box::new(MockTransport::default()));
+        assert!(server.register_namespace("").is_err());
+        assert!(server.register_namespace("a/b").is_err());
+
+        let plugin = server.register_namespace("myplugin").unwrap();
+        assert_eq!(plugin.method_name("doThing"), "myplugin/doThing");
+        plugin
+            .register_async_method("doThing", |_| async { Ok::<_, JsonRpcError>(serde_json::json!(1)) })
+            .await
+            .unwrap();
+        server
+            .register_method("ping".to_string(), |_| async { Ok(serde_json::json!("pong")) })
+            .await
+            .unwrap();
+
+        assert_eq!(server.get_registered_namespaces().await, vec!["myplugin"]);
+        assert_eq!(
+            server.method_catalog().methods().await,
+            vec!["myplugin/doThing", "ping"]
+        );
+    }
+}
@@ -1,14 +1,20 @@
 //! Transport layer for JSON-RPC communication
 //!
-//! Implements LSP-style message framing with Content-Length headers
-//! and support for different transport types (stdio, IPC, HTTP).
-
-use crate::jsonrpc::protocol::{JsonRpcNotification, JsonRpcRequest, JsonRpcResponse};
+//! Implements LSP-style message framing with Content-Length headers, or newline-delimited
+//! JSON, and support for different transport types (stdio, IPC, HTTP).
+
+use crate::jsonrpc::methods::CONNECTION_CLOSING;
+use crate::jsonrpc::protocol::{
+    JsonRpcBatchResponse, JsonRpcMessage, JsonRpcNotification, JsonRpcRequest, JsonRpcResponse,
+};
+use crate::models::configuration::Framing;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use std::future::Future;
 use std::path::Path;
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tracing::{debug, trace};
+use tracing::{debug, info, trace, warn};
 
 /// Transport trait for different communication methods
 #[async_trait]
@@ -16,9 +22,17 @@ pub trait Transport: Send {
     /// Read a JSON-RPC request from the transport
     async fn read_request(&mut self) -> Result<JsonRpcRequest>;
 
+    /// Read a JSON-RPC request or batch from the transport
+    async fn read_message(&mut self) -> Result<JsonRpcMessage> {
+        Ok(JsonRpcMessage::Single(self.read_request().await?))
+    }
+
     /// Write a JSON-RPC response to the transport
     async fn write_response(&mut self, response: JsonRpcResponse) -> Result<()>;
 
+    /// Write the responses to a batch request as a single array
+    async fn write_batch_response(&mut self, responses: JsonRpcBatchResponse) -> Result<()>;
+
     /// Write a JSON-RPC notification to the transport (server-to-client)
     async fn write_notification(&mut self, notification: JsonRpcNotification) -> Result<()>;
 
@@ -27,6 +41,12 @@ pub trait Transport: Send {
 
     /// Get transport description for logging
     fn description(&self) -> &'static str;
+
+    /// Identifies the client connection the last message was read from; transports
+    /// that only ever have one connection keep it at 0
+    fn connection_id(&self) -> u64 {
+        0
+    }
 }
 
 /// Trait for the write-half of a transport (for sharing with notification senders)
@@ -39,10 +59,11 @@ pub trait TransportWriter: Send + Sync {
     async fn write_response(&mut self, response: JsonRpcResponse) -> Result<()>;
 }
 
-/// Stdio transport using LSP-style Content-Length headers
+/// Stdio transport using LSP-style Content-Length headers, or newline-delimited JSON
 pub struct StdioTransport {
     reader: BufReader<tokio::io::Stdin>,
     writer: tokio::io::Stdout,
+    framing: Framing,
 }
 
 impl StdioTransport {
@@ -51,70 +72,25 @@ impl StdioTransport {
         Self {
             reader: BufReader::new(tokio::io::stdin()),
             writer: tokio::io::stdout(),
+            framing: Framing::default(),
         }
     }
 
-    /// Read LSP-style message with Content-Length header
-    async fn read_lsp_message(&mut self) -> Result<String> {
-        let mut content_length = None;
-
-        // Read headers
-        loop {
-            let mut line = String::new();
-            let bytes_read = self.reader.read_line(&mut line).await?;
-
-            if bytes_read == 0 {
-                return Err(anyhow!("Connection closed"));
-            }
-
-            // Remove trailing \r\n or \n
-            let line = line.trim_end();
-
-            // Empty line indicates end of headers
-            if line.is_empty() {
-                break;
-            }
-
-            // Parse Content-Length header
-            if let Some(length_str) = line.strip_prefix("Content-Length: ") {
-                content_length = Some(length_str.parse::<usize>()?);
-            }
-
-            // Ignore other headers (Content-Type, etc.)
-            trace!("Received header: {}", line);
-        }
-
-        let content_length = content_length
-            .ok_or_else(|| anyhow!("Missing Content-Length header"))?;
-
-        // Read the JSON content
-        let mut buffer = vec![0u8; content_length];
-        self.reader.read_exact(&mut buffer).await?;
-
-        let content = String::from_utf8(buffer)?;
-        debug!("Received message: {} bytes", content_length);
-        trace!("Message content: {}", content);
-
-        Ok(content)
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
     }
 
-    /// Write LSP-style message with Content-Length header
-    async fn write_lsp_message(&mut self, content: &str) -> Result<()> {
-        let content_bytes = content.as_bytes();
-        let content_length = content_bytes.len();
-
-        // Write headers
-        self.writer
-            .write_all(format!("Content-Length: {}\r\n\r\n", content_length).as_bytes())
-            .await?;
+    /// Read one message in the transport's framing
+    async fn read_framed(&mut self) -> Result<String> {
+        read_framed(&mut self.reader, self.framing).await
+    }
 
-        // Write content
-        self.writer.write_all(content_bytes).await?;
+    /// Write one message in the transport's framing
+    async fn write_framed(&mut self, content: &str) -> Result<()> {
+        write_framed(&mut self.writer, self.framing, content).await?;
         self.writer.flush().await?;
-
-        debug!("Sent message: {} bytes", content_length);
         trace!("Message content: {}", content);
-
         Ok(())
     }
 }
@@ -122,20 +98,30 @@ impl StdioTransport {
 #[async_trait]
 impl Transport for StdioTransport {
     async fn read_request(&mut self) -> Result<JsonRpcRequest> {
-        let content = self.read_lsp_message().await?;
+        let content = self.read_framed().await?;
         let request: JsonRpcRequest = serde_json::from_str(&content)?;
         request.validate().map_err(|e| anyhow!("Invalid request: {}", e.message))?;
         Ok(request)
     }
 
+    async fn read_message(&mut self) -> Result<JsonRpcMessage> {
+        let content = self.read_framed().await?;
+        JsonRpcMessage::parse(&content)
+    }
+
     async fn write_response(&mut self, response: JsonRpcResponse) -> Result<()> {
         let content = serde_json::to_string(&response)?;
-        self.write_lsp_message(&content).await
+        self.write_framed(&content).await
+    }
+
+    async fn write_batch_response(&mut self, responses: JsonRpcBatchResponse) -> Result<()> {
+        let content = serde_json::to_string(&responses)?;
+        self.write_framed(&content).await
     }
 
     async fn write_notification(&mut self, notification: JsonRpcNotification) -> Result<()> {
         let content = serde_json::to_string(&notification)?;
-        self.write_lsp_message(&content).await
+        self.write_framed(&content).await
     }
 
     async fn close(&mut self) -> Result<()> {
@@ -159,6 +145,7 @@ impl Default for StdioTransport {
 pub struct IpcTransport {
     reader: BufReader<tokio::net::unix::OwnedReadHalf>,
     writer: tokio::net::unix::OwnedWriteHalf,
+    framing: Framing,
 }
 
 impl IpcTransport {
@@ -170,6 +157,7 @@ impl IpcTransport {
         Ok(Self {
             reader: BufReader::new(read_half),
             writer: write_half,
+            framing: Framing::default(),
         })
     }
 
@@ -180,81 +168,53 @@ impl IpcTransport {
         Self {
             reader: BufReader::new(read_half),
             writer: write_half,
+            framing: Framing::default(),
         }
     }
 
-    /// Read LSP-style message over Unix socket
-    async fn read_lsp_message(&mut self) -> Result<String> {
-        let mut content_length = None;
-
-        // Read headers
-        loop {
-            let mut line = String::new();
-            let bytes_read = self.reader.read_line(&mut line).await?;
-
-            if bytes_read == 0 {
-                return Err(anyhow!("Connection closed"));
-            }
-
-            let line = line.trim_end();
-
-            if line.is_empty() {
-                break;
-            }
-
-            if let Some(length_str) = line.strip_prefix("Content-Length: ") {
-                content_length = Some(length_str.parse::<usize>()?);
-            }
-        }
-
-        let content_length = content_length
-            .ok_or_else(|| anyhow!("Missing Content-Length header"))?;
-
-        // Read the JSON content
-        let mut buffer = vec![0u8; content_length];
-        self.reader.read_exact(&mut buffer).await?;
-
-        let content = String::from_utf8(buffer)?;
-        debug!("Received IPC message: {} bytes", content_length);
-
-        Ok(content)
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
     }
 
-    /// Write LSP-style message over Unix socket
-    async fn write_lsp_message(&mut self, content: &str) -> Result<()> {
-        let content_bytes = content.as_bytes();
-        let content_length = content_bytes.len();
-
-        // Write headers
-        self.writer
-            .write_all(format!("Content-Length: {}\r\n\r\n", content_length).as_bytes())
-            .await?;
-
-        // Write content
-        self.writer.write_all(content_bytes).await?;
+    /// Read one message over the Unix socket in the transport's framing
+    async fn read_framed(&mut self) -> Result<String> {
+        read_framed(&mut self.reader, self.framing).await
+    }
 
-        debug!("Sent IPC message: {} bytes", content_length);
-        Ok(())
+    /// Write one message over the Unix socket in the transport's framing
+    async fn write_framed(&mut self, content: &str) -> Result<()> {
+        write_framed(&mut self.writer, self.framing, content).await
     }
 }
 
 #[async_trait]
 impl Transport for IpcTransport {
     async fn read_request(&mut self) -> Result<JsonRpcRequest> {
-        let content = self.read_lsp_message().await?;
+        let content = self.read_framed().await?;
         let request: JsonRpcRequest = serde_json::from_str(&content)?;
         request.validate().map_err(|e| anyhow!("Invalid request: {}", e.message))?;
         Ok(request)
     }
 
+    async fn read_message(&mut self) -> Result<JsonRpcMessage> {
+        let content = self.read_framed().await?;
+        JsonRpcMessage::parse(&content)
+    }
+
     async fn write_response(&mut self, response: JsonRpcResponse) -> Result<()> {
         let content = serde_json::to_string(&response)?;
-        self.write_lsp_message(&content).await
+        self.write_framed(&content).await
+    }
+
+    async fn write_batch_response(&mut self, responses: JsonRpcBatchResponse) -> Result<()> {
+        let content = serde_json::to_string(&responses)?;
+        self.write_framed(&content).await
     }
 
     async fn write_notification(&mut self, notification: JsonRpcNotification) -> Result<()> {
         let content = serde_json::to_string(&notification)?;
-        self.write_lsp_message(&content).await
+        self.write_framed(&content).await
     }
 
     async fn close(&mut self) -> Result<()> {
@@ -276,6 +236,12 @@ pub struct IpcServerTransport {
     listener: tokio::net::UnixListener,
     socket_path: String,
     current_connection: Option<IpcTransport>,
+    /// Close a connection that sends nothing for this long between requests
+    idle_timeout: Option<Duration>,
+    /// Framing of accepted connections
+    framing: Framing,
+    /// Connections accepted so far
+    connections: u64,
 }
 
 impl IpcServerTransport {
@@ -299,9 +265,40 @@ impl IpcServerTransport {
             listener,
             socket_path,
             current_connection: None,
+            idle_timeout: None,
+            framing: Framing::default(),
+            connections: 0,
         })
     }
+
+    /// Close connections that send no message for `timeout` while no request is running,
+    /// so a client that stays connected without working doesn't keep others waiting
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
     
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Set the socket file mode and, optionally, its group
+    pub fn apply_socket_permissions(&self, mode: u32, group: Option<u32>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| anyhow!("Failed to set permissions on socket {}: {}", self.socket_path, e))?;
+
+        if let Some(gid) = group {
+            std::os::unix::fs::chown(&self.socket_path, None, Some(gid))
+                .map_err(|e| anyhow!("Failed to set group {} on socket {}: {}", gid, self.socket_path, e))?;
+        }
+
+        debug!("Socket {} permissions set to {:o}", self.socket_path, mode);
+        Ok(())
+    }
+
     /// Accept a new connection (blocks until a client connects)
     async fn accept_connection(&mut self) -> Result<()> {
         debug!("Waiting for client connection on {}", self.socket_path);
@@ -310,11 +307,35 @@ impl IpcServerTransport {
             .map_err(|e| anyhow!("Failed to accept connection: {}", e))?;
         
         debug!("Client connected to {}", self.socket_path);
-        self.current_connection = Some(IpcTransport::from_stream(stream));
+        self.current_connection = Some(IpcTransport::from_stream(stream).with_framing(self.framing));
+        self.connections += 1;
         
         Ok(())
     }
     
+    /// Tell the idle client why it is being disconnected and drop its connection
+    async fn close_idle_connection(&mut self) {
+        let Some(mut transport) = self.current_connection.take() else {
+            return;
+        };
+        let idle_secs = self.idle_timeout.unwrap_or_default().as_secs_f64();
+        info!("Closing connection idle for {}s on {}", idle_secs, self.socket_path);
+
+        let notification = JsonRpcNotification::new(
+            CONNECTION_CLOSING,
+            Some(serde_json::json!({
+                "reason": "idle_timeout",
+                "idle_timeout_secs": idle_secs,
+            })),
+        );
+        if let Err(e) = transport.write_notification(notification).await {
+            debug!("Failed to notify idle client: {}", e);
+        }
+        if let Err(e) = transport.close().await {
+            debug!("Failed to close idle connection: {}", e);
+        }
+    }
+
     /// Ensure we have an active connection, accepting one if needed
     async fn ensure_connection(&mut self) -> Result<&mut IpcTransport> {
         if self.current_connection.is_none() {
@@ -329,16 +350,37 @@ impl IpcServerTransport {
 impl Transport for IpcServerTransport {
     async fn read_request(&mut self) -> Result<JsonRpcRequest> {
         loop {
+            let idle_timeout = self.idle_timeout;
             let transport = self.ensure_connection().await?;
             
-            match transport.read_request().await {
-                Ok(request) => return Ok(request),
-                Err(e) => {
+            match with_idle_timeout(idle_timeout, transport.read_request()).await {
+                Some(Ok(request)) => return Ok(request),
+                // The reader has resynced, so the connection stays usable
+                Some(Err(e)) if lsp_format::is_framing_error(&e) => return Err(e),
+                Some(Err(e)) => {
                     // Connection closed or error - drop this connection and wait for a new one
                     debug!("Connection error (will accept new connection): {}", e);
                     self.current_connection = None;
                     // Continue loop to accept next connection
                 }
+                None => self.close_idle_connection().await,
+            }
+        }
+    }
+
+    async fn read_message(&mut self) -> Result<JsonRpcMessage> {
+        loop {
+            let idle_timeout = self.idle_timeout;
+            let transport = self.ensure_connection().await?;
+
+            match with_idle_timeout(idle_timeout, transport.read_message()).await {
+                Some(Ok(message)) => return Ok(message),
+                Some(Err(e)) if lsp_format::is_framing_error(&e) => return Err(e),
+                Some(Err(e)) => {
+                    debug!("Connection error (will accept new connection): {}", e);
+                    self.current_connection = None;
+                }
+                None => self.close_idle_connection().await,
             }
         }
     }
@@ -349,6 +391,12 @@ impl Transport for IpcServerTransport {
         transport.write_response(response).await
     }
 
+    async fn write_batch_response(&mut self, responses: JsonRpcBatchResponse) -> Result<()> {
+        let transport = self.current_connection.as_mut()
+            .ok_or_else(|| anyhow!("No active connection"))?;
+        transport.write_batch_response(responses).await
+    }
+
     async fn write_notification(&mut self, notification: JsonRpcNotification) -> Result<()> {
         let transport = self.current_connection.as_mut()
             .ok_or_else(|| anyhow!("No active connection"))?;
@@ -372,30 +420,76 @@ impl Transport for IpcServerTransport {
     fn description(&self) -> &'static str {
         "JSON-RPC server over Unix domain socket (LSP-style)"
     }
+
+    fn connection_id(&self) -> u64 {
+        self.connections
+    }
+}
+
+/// Read one message from `reader` in the given framing
+async fn read_framed<R>(reader: &mut R, framing: Framing) -> Result<String>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    match framing {
+        Framing::ContentLength => lsp_format::read_message(reader).await,
+        Framing::Ndjson => ndjson_format::read_message(reader).await,
+    }
+}
+
+/// Write one message to `writer` in the given framing
+async fn write_framed<W>(writer: &mut W, framing: Framing, content: &str) -> Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let message = match framing {
+        Framing::ContentLength => lsp_format::format_message(content),
+        Framing::Ndjson => ndjson_format::format_message(content),
+    };
+    writer.write_all(message.as_bytes()).await?;
+    debug!("Sent message: {} bytes", content.len());
+    Ok(())
+}
+
+/// Await `read`, or return `None` once `timeout` passes without it completing
+async fn with_idle_timeout<T>(timeout: Option<Duration>, read: impl Future<Output = T>) -> Option<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, read).await.ok(),
+        None => Some(read.await),
+    }
 }
 
 /// Transport configuration
 #[derive(Debug, Clone)]
 pub enum TransportConfig {
-    /// Standard input/output with LSP message framing
-    Stdio,
+    /// Standard input/output
+    Stdio { framing: Framing },
     /// Unix domain socket with specified path
-    UnixSocket { path: String },
+    UnixSocket { path: String, framing: Framing },
 }
 
 impl TransportConfig {
+    /// Message framing of the transport
+    pub fn framing(&self) -> Framing {
+        match self {
+            TransportConfig::Stdio { framing } | TransportConfig::UnixSocket { framing, .. } => {
+                *framing
+            }
+        }
+    }
+
     /// Create a server-side transport from configuration
     /// 
     /// For Unix sockets, this binds and listens (server mode).
     /// For stdio, this creates a standard input/output transport.
     pub async fn create_transport(&self) -> Result<Box<dyn Transport>> {
         match self {
-            TransportConfig::Stdio => {
-                Ok(Box::new(StdioTransport::new()))
+            TransportConfig::Stdio { framing } => {
+                Ok(Box::new(StdioTransport::new().with_framing(*framing)))
             }
-            TransportConfig::UnixSocket { path } => {
+            TransportConfig::UnixSocket { path, framing } => {
                 // Server mode: bind and listen
-                let transport = IpcServerTransport::bind(path).await?;
+                let transport = IpcServerTransport::bind(path).await?.with_framing(*framing);
                 Ok(Box::new(transport))
             }
         }
@@ -406,12 +500,12 @@ impl TransportConfig {
     /// For Unix sockets, this connects to an existing server.
     pub async fn create_client_transport(&self) -> Result<Box<dyn Transport>> {
         match self {
-            TransportConfig::Stdio => {
-                Ok(Box::new(StdioTransport::new()))
+            TransportConfig::Stdio { framing } => {
+                Ok(Box::new(StdioTransport::new().with_framing(*framing)))
             }
-            TransportConfig::UnixSocket { path } => {
+            TransportConfig::UnixSocket { path, framing } => {
                 // Client mode: connect to existing server
-                let transport = IpcTransport::connect(path).await?;
+                let transport = IpcTransport::connect(path).await?.with_framing(*framing);
                 Ok(Box::new(transport))
             }
         }
@@ -419,13 +513,14 @@ impl TransportConfig {
 
     /// Create transport configuration from InitConfig
     pub fn from_init_config(init_config: &crate::models::configuration::InitConfig) -> Result<Self> {
+        let framing = init_config.framing;
         match init_config.transport.as_str() {
-            "stdio" => Ok(TransportConfig::Stdio),
+            "stdio" => Ok(TransportConfig::Stdio { framing }),
             "socket" => {
                 let path = init_config.socket_path
                     .clone()
                     .ok_or_else(|| anyhow!("Socket path is required when using socket transport"))?;
-                Ok(TransportConfig::UnixSocket { path })
+                Ok(TransportConfig::UnixSocket { path, framing })
             }
             other => Err(anyhow!("Unsupported transport type: {}", other)),
         }
@@ -434,8 +529,8 @@ impl TransportConfig {
     /// Get transport description
     pub fn description(&self) -> String {
         match self {
-            TransportConfig::Stdio => "stdin/stdout".to_string(),
-            TransportConfig::UnixSocket { path } => format!("Unix socket ({})", path),
+            TransportConfig::Stdio { .. } => "stdin/stdout".to_string(),
+            TransportConfig::UnixSocket { path, .. } => format!("Unix socket ({})", path),
         }
     }
 }
@@ -443,16 +538,166 @@ impl TransportConfig {
 /// Helper functions for working with LSP message format
 pub mod lsp_format {
     use super::*;
+    use crate::KaiakError;
+
+    /// Largest message body accepted, in bytes
+    pub const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+    /// Longest header line accepted, in bytes; longer input outside a header block is
+    /// skipped in pieces of this size
+    pub const MAX_HEADER_LINE: usize = 8 * 1024;
+
+    const CONTENT_LENGTH: &str = "Content-Length";
 
     /// Parse LSP message headers from a string
     pub fn parse_headers(headers: &str) -> Result<usize> {
-        for line in headers.lines() {
-            let line = line.trim();
-            if let Some(length_str) = line.strip_prefix("Content-Length: ") {
-                return Ok(length_str.parse()?);
+        let mut content_length = None;
+        for line in headers.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            add_header(&mut content_length, line)?;
+        }
+        Ok(check_content_length(content_length)?)
+    }
+
+    /// Read one LSP-style message from `reader`.
+    ///
+    /// Malformed framing fails with a `KaiakError::Transport` and leaves the reader ready
+    /// for the next message: input before a header block is skipped up to the next
+    /// `Content-Length` header, so the body of a rejected message is not read as headers.
+    pub async fn read_message<R>(reader: &mut R) -> Result<String>
+    where
+        R: tokio::io::AsyncBufRead + Unpin,
+    {
+        let mut content_length = None;
+        let mut in_headers = false;
+        let mut skipped = 0;
+        let mut error = None;
+
+        loop {
+            let mut bytes = Vec::new();
+            if read_header_line(reader, &mut bytes).await? == 0 {
+                return Err(anyhow!("Connection closed"));
+            }
+            if in_headers && bytes.len() == MAX_HEADER_LINE && !bytes.ends_with(b"\n") {
+                error.get_or_insert(KaiakError::transport(format!(
+                    "Header line exceeds the {} byte limit",
+                    MAX_HEADER_LINE
+                )));
+                // Discard the rest of the line without holding it
+                while !bytes.ends_with(b"\n") {
+                    bytes.clear();
+                    if read_header_line(reader, &mut bytes).await? == 0 {
+                        return Err(anyhow!("Connection closed"));
+                    }
+                }
+                continue;
+            }
+            let line = String::from_utf8_lossy(&bytes);
+            let mut line = line.trim_end();
+
+            // Empty line ends the headers; before them it is leftover framing
+            if line.is_empty() {
+                if in_headers {
+                    break;
+                }
+                continue;
+            }
+
+            if !in_headers && !is_header_line(line) {
+                // Resync: a message may follow the body of a rejected one on the same line
+                match find_content_length(line) {
+                    Some(start) => {
+                        skipped += start;
+                        line = &line[start..];
+                    }
+                    None => {
+                        skipped += bytes.len();
+                        continue;
+                    }
+                }
+            }
+            in_headers = true;
+            trace!("Received header: {}", line);
+            if let Err(e) = add_header(&mut content_length, line) {
+                error.get_or_insert(e);
             }
         }
-        Err(anyhow!("Content-Length header not found"))
+
+        if skipped > 0 {
+            warn!("Skipped {} bytes of input outside message framing", skipped);
+        }
+        if let Some(e) = error {
+            return Err(e.into());
+        }
+        let content_length = check_content_length(content_length)?;
+
+        // Read the JSON content
+        let mut buffer = vec![0u8; content_length];
+        reader.read_exact(&mut buffer).await?;
+
+        let content = String::from_utf8(buffer).map_err(|e| {
+            KaiakError::transport(format!("Message body is not valid UTF-8: {}", e))
+        })?;
+        debug!("Received message: {} bytes", content_length);
+        trace!("Message content: {}", content);
+
+        Ok(content)
+    }
+
+    /// Read up to a newline, or `MAX_HEADER_LINE` bytes if none comes before
+    async fn read_header_line<R>(reader: &mut R, bytes: &mut Vec<u8>) -> std::io::Result<usize>
+    where
+        R: tokio::io::AsyncBufRead + Unpin,
+    {
+        reader.take(MAX_HEADER_LINE as u64).read_until(b'\n', bytes).await
+    }
+
+    /// Whether `error` is a framing problem the reader recovered from, as opposed to a
+    /// closed or broken connection
+    pub fn is_framing_error(error: &anyhow::Error) -> bool {
+        matches!(error.downcast_ref::<KaiakError>(), Some(KaiakError::Transport { .. }))
+    }
+
+    /// Record one header line, rejecting malformed lines and invalid or repeated lengths.
+    /// Headers other than Content-Length (Content-Type, etc.) are ignored.
+    fn add_header(content_length: &mut Option<usize>, line: &str) -> Result<(), KaiakError> {
+        if !is_header_line(line) {
+            return Err(KaiakError::transport(format!("Malformed header line: {:?}", line)));
+        }
+        let (name, value) = line.split_once(':').unwrap_or_default();
+        if !name.eq_ignore_ascii_case(CONTENT_LENGTH) {
+            return Ok(());
+        }
+        let value = value.trim();
+        let length = value.parse::<usize>().map_err(|_| {
+            KaiakError::transport(format!("Invalid Content-Length: {:?}", value))
+        })?;
+        if content_length.replace(length).is_some() {
+            return Err(KaiakError::transport("Duplicate Content-Length header"));
+        }
+        Ok(())
+    }
+
+    fn check_content_length(content_length: Option<usize>) -> Result<usize, KaiakError> {
+        match content_length {
+            None => Err(KaiakError::transport("Missing Content-Length header")),
+            Some(length) if length > MAX_CONTENT_LENGTH => Err(KaiakError::transport(format!(
+                "Content-Length {} exceeds the {} byte limit",
+                length, MAX_CONTENT_LENGTH
+            ))),
+            Some(length) => Ok(length),
+        }
+    }
+
+    /// `Name: value` with a name of letters, digits and dashes
+    fn is_header_line(line: &str) -> bool {
+        line.split_once(':').is_some_and(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+    }
+
+    fn find_content_length(line: &str) -> Option<usize> {
+        line.to_ascii_lowercase()
+            .find(&format!("{}:", CONTENT_LENGTH.to_ascii_lowercase()))
     }
 
     /// Format message with LSP headers
@@ -484,6 +729,57 @@ pub mod lsp_format {
     }
 }
 
+/// Helper functions for newline-delimited JSON framing: one message per line
+pub mod ndjson_format {
+    use super::*;
+    use crate::KaiakError;
+
+    /// Read the next non-empty line from `reader` as one message
+    pub async fn read_message<R>(reader: &mut R) -> Result<String>
+    where
+        R: tokio::io::AsyncBufRead + Unpin,
+    {
+        // One byte past the limit tells an oversized line from one that just fits
+        let limit = lsp_format::MAX_CONTENT_LENGTH as u64 + 1;
+        loop {
+            let mut bytes = Vec::new();
+            if reader.take(limit).read_until(b'\n', &mut bytes).await? == 0 {
+                return Err(anyhow!("Connection closed"));
+            }
+            if bytes.len() as u64 == limit && !bytes.ends_with(b"\n") {
+                // Discard the rest of the line so the next one is read as a message
+                while !bytes.ends_with(b"\n") {
+                    bytes.clear();
+                    if reader.take(limit).read_until(b'\n', &mut bytes).await? == 0 {
+                        break;
+                    }
+                }
+                return Err(KaiakError::transport(format!(
+                    "Message exceeds the {} byte limit",
+                    lsp_format::MAX_CONTENT_LENGTH
+                ))
+                .into());
+            }
+            let line = String::from_utf8(bytes).map_err(|e| {
+                KaiakError::transport(format!("Message is not valid UTF-8: {}", e))
+            })?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            debug!("Received message: {} bytes", line.len());
+            trace!("Message content: {}", line);
+            return Ok(line.to_string());
+        }
+    }
+
+    /// Terminate a message with a newline. Serialized JSON-RPC messages never contain
+    /// one, since serde_json escapes newlines inside strings.
+    pub fn format_message(content: &str) -> String {
+        format!("{}\n", content)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -511,14 +807,177 @@ mod tests {
         assert_eq!(content, r#"{"jsonrpc":"2.0","method":"test","id":1}"#);
     }
 
+    #[tokio::test]
+    async fn test_malformed_headers_are_rejected_and_resynced() {
+        let valid = r#"{"jsonrpc":"2.0","method":"kaiak/whoami","id":1}"#;
+        let cases = [
+            ("non-numeric", "Content-Length: abc\r\n\r\n{}".to_string()),
+            ("negative", "Content-Length: -5\r\n\r\n{}".to_string()),
+            ("missing", "Content-Type: application/json\r\n\r\n{}".to_string()),
+            ("duplicate", "Content-Length: 2\r\nContent-Length: 2\r\n\r\n{}".to_string()),
+            ("malformed line", "Content-Length: 2\r\nnot a header\r\n\r\n{}".to_string()),
+            ("oversized", format!("Content-Length: {}\r\n\r\n{{}}", usize::MAX)),
+            (
+                "overlong line",
+                format!(
+                    "Content-Length: 2\r\nX-Padding: {}\r\n\r\n{{}}",
+                    "a".repeat(lsp_format::MAX_HEADER_LINE * 3)
+                ),
+            ),
+        ];
+
+        for (name, malformed) in cases {
+            let input = format!("{}{}", malformed, lsp_format::format_message(valid));
+            let mut reader = BufReader::new(input.as_bytes());
+
+            let error = lsp_format::read_message(&mut reader).await.unwrap_err();
+            assert!(lsp_format::is_framing_error(&error), "{}: {}", name, error);
+            // The next message is read intact after the rejected one
+            assert_eq!(lsp_format::read_message(&mut reader).await.unwrap(), valid, "{}", name);
+            let closed = lsp_format::read_message(&mut reader).await.unwrap_err();
+            assert!(!lsp_format::is_framing_error(&closed), "{}", name);
+        }
+
+        // Unframed garbage before a message is skipped
+        let input = format!("garbage\n\r\n{}", lsp_format::format_message(valid));
+        let mut reader = BufReader::new(input.as_bytes());
+        assert_eq!(lsp_format::read_message(&mut reader).await.unwrap(), valid);
+        let garbage = "a".repeat(lsp_format::MAX_HEADER_LINE * 3);
+        let input = format!("{}\n{}", garbage, lsp_format::format_message(valid));
+        let mut reader = BufReader::new(input.as_bytes());
+        assert_eq!(lsp_format::read_message(&mut reader).await.unwrap(), valid);
+
+        // Header names are case-insensitive
+        let input = format!("content-length: {}\r\n\r\n{}", valid.len(), valid);
+        let mut reader = BufReader::new(input.as_bytes());
+        assert_eq!(lsp_format::read_message(&mut reader).await.unwrap(), valid);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_ndjson_line_is_rejected_and_skipped() {
+        let valid = r#"{"jsonrpc":"2.0","method":"ping","id":1}"#;
+        let oversized = "a".repeat(lsp_format::MAX_CONTENT_LENGTH + 10);
+        let input = format!("{}\n{}", oversized, ndjson_format::format_message(valid));
+        let mut reader = BufReader::new(input.as_bytes());
+
+        let error = ndjson_format::read_message(&mut reader).await.unwrap_err();
+        assert!(lsp_format::is_framing_error(&error), "{}", error);
+        assert_eq!(ndjson_format::read_message(&mut reader).await.unwrap(), valid);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ping_round_trip_over_ndjson() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kaiak.sock");
+        let transport = IpcServerTransport::bind(&path)
+            .await
+            .unwrap()
+            .with_framing(Framing::Ndjson);
+        let mut server = crate::jsonrpc::JsonRpcServer::with_transport(Box::new(transport));
+        server
+            .register_method("ping".to_string(), |_| async { Ok(serde_json::json!("pong")) })
+            .await
+            .unwrap();
+        let client = async {
+            let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            write_half
+                .write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"ping\",\"id\":1}\n")
+                .await
+                .unwrap();
+
+            let mut line = String::new();
+            tokio::time::timeout(
+                Duration::from_secs(2),
+                BufReader::new(read_half).read_line(&mut line),
+            )
+            .await
+            .expect("server should answer the ping")
+            .unwrap();
+            // One JSON object on one line, with no Content-Length header
+            assert!(line.ends_with('\n'));
+            let response: serde_json::Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(response["result"], "pong");
+            assert_eq!(response["id"], 1);
+        };
+
+        // The server never stops on its own, so it only runs until the client is done
+        tokio::select! {
+            result = server.start() => panic!("server stopped: {:?}", result),
+            () = client => {}
+        }
+    }
+
     #[test]
     fn test_transport_config() {
-        let config = TransportConfig::Stdio;
+        let config = TransportConfig::Stdio {
+            framing: Framing::Ndjson,
+        };
         assert_eq!(config.description(), "stdin/stdout");
+        assert_eq!(config.framing(), Framing::Ndjson);
 
         let config = TransportConfig::UnixSocket {
             path: "/tmp/test.sock".to_string(),
+            framing: Framing::default(),
         };
         assert!(config.description().contains("/tmp/test.sock"));
+        assert_eq!(config.framing(), Framing::ContentLength);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_socket_permissions_applied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kaiak.sock");
+
+        let transport = IpcServerTransport::bind(&path).await.unwrap();
+        transport.apply_socket_permissions(0o600, None).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_idle_connection_is_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kaiak.sock");
+        let mut server = IpcServerTransport::bind(&path)
+            .await
+            .unwrap()
+            .with_idle_timeout(Some(Duration::from_millis(200)));
+        let (first_tx, first_rx) = tokio::sync::oneshot::channel();
+        let server_task = tokio::spawn(async move {
+            let _ = first_tx.send(server.read_message().await.unwrap());
+            // Waits for the next client once the idle connection is closed
+            let _ = server.read_message().await;
+        });
+
+        let mut client = IpcTransport::connect(&path).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        let request = r#"{"jsonrpc":"2.0","method":"kaiak/whoami","id":1}"#;
+        client.write_framed(request).await.unwrap();
+        assert!(matches!(first_rx.await.unwrap(), JsonRpcMessage::Single(_)));
+
+        // The message reset the idle timer, so the connection outlives 200ms from connecting
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(30), client.read_framed())
+                .await
+                .is_err()
+        );
+
+        let closing = tokio::time::timeout(Duration::from_secs(2), client.read_framed())
+            .await
+            .expect("idle connection should be closed")
+            .unwrap();
+        let closing: JsonRpcNotification = serde_json::from_str(&closing).unwrap();
+        assert_eq!(closing.method, CONNECTION_CLOSING);
+        assert_eq!(closing.params.unwrap()["reason"], "idle_timeout");
+        assert!(client.read_framed().await.is_err());
+        server_task.abort();
     }
-}
\ No newline at end of file
+}
@@ -75,6 +75,14 @@ pub enum KaiakError {
 
     #[error("File operation error: {message}")]
     FileOperation { message: String, file_path: Option<String> },
+
+    /// Request parameters that parsed but failed validation
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+
+    /// A request ID already taken by a running request
+    #[error("Request ID already in use: {0}")]
+    RequestInUse(String),
 }
 
 impl KaiakError {
@@ -182,6 +190,8 @@ impl KaiakError {
             KaiakError::Internal(_) => -32603,
             KaiakError::Io { .. } => -32603,
             KaiakError::Serialization { .. } => -32700,
+            KaiakError::InvalidParams(_) => crate::jsonrpc::protocol::error_codes::INVALID_PARAMS,
+            KaiakError::RequestInUse(_) => crate::jsonrpc::protocol::error_codes::REQUEST_IN_USE,
         }
     }
 
@@ -263,6 +273,12 @@ impl KaiakError {
                     format!("File operation error: {}", message)
                 }
             }
+            KaiakError::InvalidParams(message) => {
+                format!("Invalid params: {}", message)
+            }
+            KaiakError::RequestInUse(request_id) => {
+                format!("Request ID {} is already in use by an active request", request_id)
+            }
         }
     }
 }
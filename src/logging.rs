@@ -1,4 +1,30 @@
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, fmt, EnvFilter};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, fmt, EnvFilter, Layer};
+
+use crate::jsonrpc::methods::LOG;
+use crate::jsonrpc::JsonRpcNotification;
+
+/// Span field whose value names a request's log file
+const REQUEST_ID_FIELD: &str = "request_id";
+/// Span field naming the session a request runs in, matched by `kaiak/stream_logs` filters
+const SESSION_ID_FIELD: &str = "session_id";
+/// Name of spans whose events are not streamed, so writing a streamed line cannot log
+/// more lines to stream
+pub const UNSTREAMED_SPAN: &str = "unstreamed";
 
 /// Initialize logging system with structured output and environment-based level filtering
 pub fn init_logging() -> anyhow::Result<()> {
@@ -18,12 +44,414 @@ pub fn init_logging() -> anyhow::Result<()> {
                 .with_level(true)
                 .compact(),
         )
+        .with(RequestLogLayer::new(request_log_dir().clone()))
+        .with(LogStreamLayer::new(log_stream().clone()))
         .try_init()?;
 
     tracing::info!("Structured logging initialized");
     Ok(())
 }
 
+/// Directory for per-request log files, shared between the logging layer and the server
+/// that configures it once its config is loaded. Per-request logging is off while unset.
+#[derive(Debug, Clone, Default)]
+pub struct RequestLogDir(Arc<RwLock<Option<PathBuf>>>);
+
+impl RequestLogDir {
+    pub fn set(&self, dir: Option<PathBuf>) {
+        *self.0.write().unwrap() = dir;
+    }
+
+    fn get(&self) -> Option<PathBuf> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// The directory used by the layer installed in `init_logging`
+pub fn request_log_dir() -> &'static RequestLogDir {
+    static DIR: OnceLock<RequestLogDir> = OnceLock::new();
+    DIR.get_or_init(RequestLogDir::default)
+}
+
+/// Copies events inside a span with a `request_id` field to `<dir>/<request_id>.log`,
+/// in addition to the main log. The field may be recorded after the span is created.
+pub struct RequestLogLayer {
+    dir: RequestLogDir,
+}
+
+/// Open log file of a request, stored in its span's extensions
+struct RequestLog {
+    request_id: String,
+    file: Mutex<File>,
+}
+
+impl RequestLogLayer {
+    pub fn new(dir: RequestLogDir) -> Self {
+        Self { dir }
+    }
+
+    fn open<S>(&self, id: &Id, values: &Record<'_>, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        let (Some(request_id), Some(dir), Some(span)) =
+            (visitor.request_id, self.dir.get(), ctx.span(id))
+        else {
+            return;
+        };
+
+        // Request IDs are server-generated UUIDs; keep anything else from escaping the directory
+        let name: String = request_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = dir.join(format!("{}.log", name));
+        let file = std::fs::create_dir_all(&dir).and_then(|_| {
+            File::options().create(true).append(true).open(&path)
+        });
+        match file {
+            Ok(file) => span.extensions_mut().insert(RequestLog {
+                request_id,
+                file: Mutex::new(file),
+            }),
+            // Logging from inside the layer would recurse; report on stderr instead
+            Err(e) => eprintln!("Failed to open request log {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl<S> Layer<S> for RequestLogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.open(id, &Record::new(attrs.values()), &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        self.open(id, values, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else { return };
+        for span in scope {
+            let extensions = span.extensions();
+            let Some(log) = extensions.get::<RequestLog>() else { continue };
+
+            let mut visitor = FieldVisitor::default();
+            event.record(&mut visitor);
+            let metadata = event.metadata();
+            let line = format!(
+                "{} {} [{}] {}: {}{}\n",
+                chrono::Utc::now().to_rfc3339(),
+                metadata.level(),
+                log.request_id,
+                metadata.target(),
+                visitor.message,
+                visitor.fields
+            );
+            let _ = log.file.lock().unwrap().write_all(line.as_bytes());
+            return;
+        }
+    }
+}
+
+/// Server log line sent to a `kaiak/stream_logs` subscriber as a `kaiak/log` notification
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    /// The message followed by the event's other fields, as in the main log
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+}
+
+/// Log lines held for a subscriber until the server writes them; past this the oldest
+/// are dropped, so a client that stops reading cannot grow the server's memory
+pub const MAX_BUFFERED_LOG_LINES: usize = 1000;
+
+/// A client's `kaiak/stream_logs` subscription
+#[derive(Debug)]
+struct LogSubscription {
+    /// Least severe level forwarded
+    level: Level,
+    session_id: Option<String>,
+}
+
+/// Lines waiting to be written to the subscriber, oldest first
+#[derive(Debug, Default)]
+struct PendingLines {
+    lines: VecDeque<LogLine>,
+    /// Lines dropped to make room since the last ones were taken
+    dropped: usize,
+}
+
+/// The connection subscribed to the server's logs, if any, shared between the logging
+/// layer, the `kaiak/stream_logs` handler and the server that writes the lines
+#[derive(Debug, Clone, Default)]
+pub struct LogStream {
+    subscription: Arc<RwLock<Option<LogSubscription>>>,
+    pending: Arc<Mutex<PendingLines>>,
+    /// Woken when a line is buffered
+    ready: Arc<Notify>,
+}
+
+impl LogStream {
+    /// Forward lines at `level` or more severe, only from `session_id` when set, replacing
+    /// any earlier subscription
+    pub fn subscribe(&self, level: Level, session_id: Option<String>) {
+        *self.subscription.write().unwrap() = Some(LogSubscription { level, session_id });
+    }
+
+    /// Stop forwarding and drop the lines not yet written; returns whether there was a
+    /// subscription
+    pub fn unsubscribe(&self) -> bool {
+        let subscribed = self.subscription.write().unwrap().take().is_some();
+        *self.pending.lock().unwrap() = PendingLines::default();
+        subscribed
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription.read().unwrap().is_some()
+    }
+
+    fn push(&self, line: LogLine) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.lines.len() >= MAX_BUFFERED_LOG_LINES {
+                pending.lines.pop_front();
+                pending.dropped += 1;
+            }
+            pending.lines.push_back(line);
+        }
+        self.ready.notify_one();
+    }
+
+    /// Take the buffered lines as `kaiak/log` notifications, oldest first, preceded by a
+    /// warning line when some were dropped
+    pub fn take_pending(&self) -> Vec<JsonRpcNotification> {
+        let PendingLines { lines, dropped } = std::mem::take(&mut *self.pending.lock().unwrap());
+        let dropped = (dropped > 0).then(|| LogLine {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            level: Level::WARN.to_string(),
+            target: module_path!().to_string(),
+            message: format!("{} log lines dropped because the client did not read them", dropped),
+            request_id: None,
+            session_id: None,
+        });
+        dropped
+            .into_iter()
+            .chain(lines)
+            .map(|line| JsonRpcNotification::new(LOG, serde_json::to_value(line).ok()))
+            .collect()
+    }
+
+    /// Wait until a line is buffered
+    pub async fn ready(&self) {
+        self.ready.notified().await
+    }
+}
+
+/// The stream fed by the layer installed in `init_logging`
+pub fn log_stream() -> &'static LogStream {
+    static STREAM: OnceLock<LogStream> = OnceLock::new();
+    STREAM.get_or_init(LogStream::default)
+}
+
+/// Forwards events to the `LogStream` subscriber, with the request and session of the
+/// innermost span that records them. Only sees events the main log filter lets through.
+pub struct LogStreamLayer {
+    stream: LogStream,
+}
+
+/// `request_id` and `session_id` recorded on a span, stored in its extensions
+#[derive(Default)]
+struct SpanIds {
+    request_id: Option<String>,
+    session_id: Option<String>,
+}
+
+impl LogStreamLayer {
+    pub fn new(stream: LogStream) -> Self {
+        Self { stream }
+    }
+
+    fn record<S>(&self, id: &Id, values: &Record<'_>, ctx: &Context<'_, S>)
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        if visitor.request_id.is_none() && visitor.session_id.is_none() {
+            return;
+        }
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        if extensions.get_mut::<SpanIds>().is_none() {
+            extensions.insert(SpanIds::default());
+        }
+        let ids = extensions.get_mut::<SpanIds>().unwrap();
+        ids.request_id = visitor.request_id.or(ids.request_id.take());
+        ids.session_id = visitor.session_id.or(ids.session_id.take());
+    }
+}
+
+impl<S> Layer<S> for LogStreamLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.record(id, &Record::new(attrs.values()), &ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        self.record(id, values, &ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let subscription = self.stream.subscription.read().unwrap();
+        let Some(subscription) = subscription.as_ref() else { return };
+        let metadata = event.metadata();
+        if *metadata.level() > subscription.level {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let (mut request_id, mut session_id) = (visitor.request_id, visitor.session_id);
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                if span.name() == UNSTREAMED_SPAN {
+                    return;
+                }
+                if let Some(ids) = span.extensions().get::<SpanIds>() {
+                    request_id = request_id.or_else(|| ids.request_id.clone());
+                    session_id = session_id.or_else(|| ids.session_id.clone());
+                }
+            }
+        }
+        if subscription.session_id.is_some() && subscription.session_id != session_id {
+            return;
+        }
+
+        let line = LogLine {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: format!("{}{}", visitor.message, visitor.fields),
+            request_id,
+            session_id,
+        };
+        self.stream.push(line);
+    }
+}
+
+/// Collects an event's message, its other fields, and `request_id` and `session_id`
+/// fields if present. `session_id` is also kept in the other fields.
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: String,
+    request_id: Option<String>,
+    session_id: Option<String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            REQUEST_ID_FIELD => {
+                self.request_id = Some(value.to_string());
+                return;
+            }
+            SESSION_ID_FIELD => self.session_id = Some(value.to_string()),
+            _ => {}
+        }
+        self.record_debug(field, &value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            REQUEST_ID_FIELD if self.request_id.is_none() => {
+                self.request_id = Some(format!("{:?}", value));
+            }
+            name => {
+                if name == SESSION_ID_FIELD && self.session_id.is_none() {
+                    self.session_id = Some(format!("{:?}", value));
+                }
+                let _ = write!(self.fields, " {}={:?}", name, value);
+            }
+        }
+    }
+}
+
+/// Delete per-request log files in `dir` last modified more than `retention` ago.
+/// Only `<request_id>.log` files are per-request logs; request IDs are UUIDs, so other logs
+/// kept in the same directory are left alone. Returns how many were removed.
+pub fn cleanup_request_logs(dir: &Path, retention: Duration) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    let now = SystemTime::now();
+    entries
+        .flatten()
+        .filter(|entry| is_request_log(&entry.path()))
+        .filter(|entry| {
+            entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified).unwrap_or_default() > retention
+                })
+        })
+        .filter(|entry| std::fs::remove_file(entry.path()).is_ok())
+        .count()
+}
+
+/// Whether `path` is named like the log `RequestLogLayer` writes for a request
+fn is_request_log(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "log")
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| uuid::Uuid::parse_str(stem).is_ok())
+}
+
+/// How often a running server deletes per-request logs past their retention
+pub const REQUEST_LOG_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Retention of per-request logs kept for `days`, saturating for absurdly large values
+pub fn request_log_retention(days: u64) -> Duration {
+    Duration::from_secs(days.saturating_mul(24 * 60 * 60))
+}
+
+/// Delete per-request logs in `dir` older than `retention` right away and then every
+/// `interval`, for as long as the server runs
+pub fn spawn_request_log_cleanup(
+    dir: PathBuf,
+    retention: Duration,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            let dir = dir.clone();
+            let removed =
+                tokio::task::spawn_blocking(move || cleanup_request_logs(&dir, retention))
+                    .await
+                    .unwrap_or(0);
+            if removed > 0 {
+                tracing::info!("Removed {} per-request logs past their retention", removed);
+            }
+        }
+    })
+}
+
 /// Initialize logging for testing with reduced verbosity
 pub fn init_test_logging() -> anyhow::Result<()> {
     let env_filter = EnvFilter::new("kaiak=debug");
@@ -69,4 +497,114 @@ mod tests {
         // Test should not panic
         let _ = init_logging();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_request_log_file_contains_request_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_dir = RequestLogDir::default();
+        log_dir.set(Some(dir.path().to_path_buf()));
+        let subscriber = tracing_subscriber::registry().with(RequestLogLayer::new(log_dir));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", request_id = tracing::field::Empty);
+            let _entered = span.enter();
+            tracing::info!("before the request ID is known");
+            span.record("request_id", "req-42");
+            tracing::info!(incidents = 2, "processing request");
+        });
+        tracing::info!("outside any request");
+
+        let path = dir.path().join("req-42.log");
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(log.lines().count(), 1, "{log}");
+        assert!(log.contains("[req-42]"), "{log}");
+        assert!(log.contains("processing request incidents=2"), "{log}");
+    }
+
+    #[test]
+    fn test_cleanup_removes_only_old_request_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = SystemTime::now() - Duration::from_secs(8 * 24 * 60 * 60);
+        let write = |name: &str, modified: Option<SystemTime>| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "log").unwrap();
+            if let Some(modified) = modified {
+                let file = File::options().write(true).open(&path).unwrap();
+                file.set_modified(modified).unwrap();
+            }
+            path
+        };
+        let expired = write("0f8fad5b-d9cb-469f-a165-70867728950e.log", Some(old));
+        let recent = write("7c9e6679-7425-40de-944b-e07fc1f90ae7.log", None);
+        // The main log or another tool's, sharing the directory
+        let other = write("kaiak.log", Some(old));
+
+        assert_eq!(cleanup_request_logs(dir.path(), Duration::from_secs(7 * 24 * 60 * 60)), 1);
+        assert!(!expired.exists());
+        assert!(recent.exists());
+        assert!(other.exists());
+    }
+
+    #[tokio::test]
+    async fn test_request_logs_are_cleaned_up_while_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = SystemTime::now() - Duration::from_secs(8 * 24 * 60 * 60);
+        let write_old = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, "old").unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(old).unwrap();
+            path
+        };
+        let wait_for_removal = |path: PathBuf| async move {
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while path.exists() {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            })
+            .await
+            .expect("old log removed");
+        };
+
+        let first = write_old("0f8fad5b-d9cb-469f-a165-70867728950e.log");
+        let cleanup = spawn_request_log_cleanup(
+            dir.path().to_path_buf(),
+            request_log_retention(7),
+            Duration::from_millis(10),
+        );
+        wait_for_removal(first).await;
+
+        // Logs that age past retention later go too, without a restart
+        wait_for_removal(write_old("7c9e6679-7425-40de-944b-e07fc1f90ae7.log")).await;
+        cleanup.abort();
+
+        assert_eq!(request_log_retention(u64::MAX), Duration::from_secs(u64::MAX));
+    }
+
+    #[test]
+    fn test_log_stream_drops_oldest_lines_when_full() {
+        let stream = LogStream::default();
+        let subscriber = tracing_subscriber::registry().with(LogStreamLayer::new(stream.clone()));
+        stream.subscribe(Level::INFO, None);
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..MAX_BUFFERED_LOG_LINES + 5 {
+                tracing::info!("line {}", i);
+            }
+            let lines = stream.take_pending();
+            assert_eq!(lines.len(), MAX_BUFFERED_LOG_LINES + 1);
+            let message = |i: usize| lines[i].params.as_ref().unwrap()["message"].clone();
+            assert_eq!(message(0), "5 log lines dropped because the client did not read them");
+            assert_eq!(message(1), "line 5");
+            assert_eq!(
+                message(MAX_BUFFERED_LOG_LINES),
+                format!("line {}", MAX_BUFFERED_LOG_LINES + 4)
+            );
+            assert!(stream.take_pending().is_empty());
+
+            // Unsubscribing drops whatever was not written yet
+            tracing::info!("never written");
+            assert!(stream.unsubscribe());
+            assert!(stream.take_pending().is_empty());
+        });
+    }
+}
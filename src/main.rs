@@ -1,13 +1,23 @@
 //! Kaiak - Goose AI agent orchestrator for code migration workflows.
 
-use anyhow::Result;
-use kaiak::cli::Cli;
+use std::process::ExitCode;
+
+use kaiak::cli::{exit_code, Cli, EXIT_FAILURE};
 use kaiak::logging::init_logging;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    init_logging()?;
+async fn main() -> ExitCode {
+    if let Err(e) = init_logging() {
+        eprintln!("Error: {:?}", e);
+        return ExitCode::from(EXIT_FAILURE);
+    }
 
     let cli = Cli::parse_args();
-    cli.run().await
+    match cli.run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
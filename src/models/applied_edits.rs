@@ -0,0 +1,124 @@
+//! Edits the server applied itself, per workspace, so a re-run after a crash can skip
+//! the ones already in the files instead of proposing them again.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use super::fingerprint::fnv1a;
+use crate::{KaiakError, KaiakResult};
+
+/// Proposals applied to one file since it last changed by other means
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AppliedFile {
+    /// Hash of the file's content after the last recorded proposal
+    pub hash: String,
+    pub proposals: BTreeSet<String>,
+}
+
+/// Proposals the server applied in a workspace, by file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppliedEdits {
+    pub files: BTreeMap<String, AppliedFile>,
+}
+
+/// Stable hash of a file's content
+pub fn content_hash(content: &str) -> String {
+    format!("{:016x}", fnv1a(content.as_bytes()))
+}
+
+/// Stable id of a proposal to change `path`, from a description of the change
+pub fn proposal_id(path: &Path, change: &str) -> String {
+    let key = format!("{}\0{}", path.display(), change);
+    format!("{:016x}", fnv1a(key.as_bytes()))
+}
+
+impl AppliedEdits {
+    /// Ledger file for a workspace in `dir`
+    pub fn path_in(dir: &Path, workspace: &Path) -> PathBuf {
+        let workspace_key = fnv1a(workspace.to_string_lossy().as_bytes());
+        dir.join(format!("{:016x}.json", workspace_key))
+    }
+
+    /// Load a ledger, treating a missing file as an empty one
+    pub fn load(path: &Path) -> KaiakResult<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| {
+                KaiakError::configuration(format!(
+                    "Invalid applied edits ledger {}: {}",
+                    path.display(),
+                    e
+                ))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error(path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> KaiakResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+        }
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| KaiakError::Serialization {
+                message: format!("Failed to serialize applied edits: {}", e),
+            })?;
+        std::fs::write(path, content).map_err(|e| io_error(path, e))
+    }
+
+    /// Whether `proposal` was applied to `file` and the file has not changed since
+    pub fn is_applied(&self, file: &Path, proposal: &str, content: &str) -> bool {
+        self.files
+            .get(&file.display().to_string())
+            .is_some_and(|applied| {
+                applied.hash == content_hash(content) && applied.proposals.contains(proposal)
+            })
+    }
+
+    /// Record that applying `proposal` turned `file` from `before` into `after`. Proposals
+    /// recorded before the file last changed by other means are forgotten.
+    pub fn record(&mut self, file: &Path, proposal: &str, before: &str, after: &str) {
+        let applied = self.files.entry(file.display().to_string()).or_default();
+        if applied.hash != content_hash(before) {
+            applied.proposals.clear();
+        }
+        applied.proposals.insert(proposal.to_string());
+        applied.hash = content_hash(after);
+    }
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> KaiakError {
+    KaiakError::Io {
+        message: format!("{}: {}", path.display(), error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proposals_stay_applied_until_the_file_changes_otherwise() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger_path = AppliedEdits::path_in(dir.path(), Path::new("/ws"));
+        let file = Path::new("/ws/Main.java");
+
+        let mut applied = AppliedEdits::default();
+        applied.record(file, "first", "a\nb\n", "A\nb\n");
+        applied.record(file, "second", "A\nb\n", "A\nB\n");
+        applied.save(&ledger_path).unwrap();
+
+        let applied = AppliedEdits::load(&ledger_path).unwrap();
+        assert!(applied.is_applied(file, "first", "A\nB\n"));
+        assert!(applied.is_applied(file, "second", "A\nB\n"));
+        assert!(!applied.is_applied(file, "third", "A\nB\n"));
+        // Changed since, e.g. reverted or edited by hand
+        assert!(!applied.is_applied(file, "first", "a\nB\n"));
+
+        // A proposal applied after such a change starts the file's record over
+        let mut applied = applied;
+        applied.record(file, "third", "a\nB\n", "a\nC\n");
+        assert!(!applied.is_applied(file, "first", "a\nC\n"));
+        assert!(applied.is_applied(file, "third", "a\nC\n"));
+    }
+}
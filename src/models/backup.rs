@@ -0,0 +1,320 @@
+//! Backups of the files a request modifies, taken before each modification is applied.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, info, warn};
+
+use crate::{KaiakError, KaiakResult};
+
+/// How files are backed up before an approved modification is applied
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupStrategy {
+    /// Copy each file into a timestamped directory
+    Copy,
+    /// Save the workspace's uncommitted changes as a git stash entry; files git does not
+    /// track are copied. Falls back to `copy` outside a git repository.
+    Git,
+}
+
+/// Files a request has backed up so far
+#[derive(Debug)]
+pub struct WorkspaceBackup {
+    strategy: BackupStrategy,
+    workspace: PathBuf,
+    /// Directory the copies go to; created with the first copy
+    dir: PathBuf,
+    /// Whether each backed up file existed; a file created by the request has no copy
+    files: BTreeMap<PathBuf, bool>,
+    /// Stash entry, or HEAD for a clean workspace, holding the tracked files (`git`)
+    commit: Option<String>,
+    /// Hash of each backed up file as the request left it, `None` when it was removed;
+    /// set by `seal`
+    left: BTreeMap<PathBuf, Option<u64>>,
+    /// Content of files the server rewrote before the request could modify them, such as
+    /// legacy encodings transcoded to UTF-8; backed up instead of what is on disk
+    originals: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+/// A file `rollback` did not restore, and why
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollbackFailure {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Files restored by `rollback` and those it left alone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RollbackOutcome {
+    pub rolled_back: Vec<PathBuf>,
+    pub not_rolled_back: Vec<RollbackFailure>,
+}
+
+impl WorkspaceBackup {
+    /// Backups for a request go to `<backup_dir>/<timestamp>-<request_id>`
+    pub fn new(
+        strategy: BackupStrategy,
+        workspace: impl Into<PathBuf>,
+        backup_dir: &Path,
+        request_id: &str,
+    ) -> Self {
+        let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+        Self {
+            strategy,
+            workspace: workspace.into(),
+            dir: backup_dir.join(format!("{}-{}", timestamp, request_id)),
+            files: BTreeMap::new(),
+            commit: None,
+            left: BTreeMap::new(),
+            originals: BTreeMap::new(),
+        }
+    }
+
+    /// Back up these contents for their files rather than the files as they will be on
+    /// disk, for files rewritten before the request starts
+    pub fn with_originals(
+        mut self,
+        originals: impl IntoIterator<Item = (PathBuf, Vec<u8>)>,
+    ) -> Self {
+        self.originals.extend(originals);
+        self
+    }
+
+    /// Back up a file before it is modified, unless it already was in this request
+    pub fn backup(&mut self, path: &Path) -> KaiakResult<()> {
+        if self.files.contains_key(path) {
+            return Ok(());
+        }
+        if let Some(original) = self.originals.get(path) {
+            // Neither the file nor a git snapshot taken now holds this content any more
+            self.write_copy(path, original)?;
+            self.files.insert(path.to_path_buf(), true);
+            return Ok(());
+        }
+        let exists = path.exists();
+        if exists {
+            let copy = self.strategy == BackupStrategy::Copy
+                || !self.snapshot_git().unwrap_or_else(|e| {
+                    warn!("Git backup failed, copying instead: {}", e);
+                    false
+                })
+                || !self.git_tracks(path);
+            if copy {
+                self.copy(path)?;
+            }
+        }
+        self.files.insert(path.to_path_buf(), exists);
+        Ok(())
+    }
+
+    /// Directory holding copies, when any file was copied
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.exists().then_some(self.dir.as_path())
+    }
+
+    /// Commit holding the tracked files, for the `git` strategy
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    /// Remember the backed up files as the request left them, so `rollback` can tell
+    /// which were changed since
+    pub fn seal(&mut self) {
+        self.left = self
+            .files
+            .keys()
+            .map(|path| (path.clone(), content_hash(path)))
+            .collect();
+    }
+
+    /// Restore every backed up file to its content before the request, and remove the
+    /// files it created. Files changed since the request finished are left alone, and
+    /// stay backed up for a later rollback; restored ones are forgotten.
+    pub fn rollback(&mut self) -> RollbackOutcome {
+        let mut outcome = RollbackOutcome::default();
+        for (path, existed) in &self.files {
+            let result = if self.left.get(path).copied() != Some(content_hash(path)) {
+                Err("changed since the request finished".to_string())
+            } else if *existed {
+                self.original(path)
+                    .and_then(|original| std::fs::write(path, original).map_err(|e| e.to_string()))
+            } else {
+                match std::fs::remove_file(path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+                    _ => Ok(()),
+                }
+            };
+            match result {
+                Ok(()) => {
+                    info!("Rolled back {}", path.display());
+                    outcome.rolled_back.push(path.clone());
+                }
+                Err(reason) => {
+                    warn!("Not rolling back {}: {}", path.display(), reason);
+                    outcome.not_rolled_back.push(RollbackFailure {
+                        path: path.clone(),
+                        reason,
+                    });
+                }
+            }
+        }
+        for path in &outcome.rolled_back {
+            self.files.remove(path);
+            self.left.remove(path);
+        }
+        outcome
+    }
+
+    /// Content of a file before the request, from its copy or the git backup commit
+    fn original(&self, path: &Path) -> Result<Vec<u8>, String> {
+        let relative = self.relative(path);
+        let copy = self.dir.join(&relative);
+        if copy.exists() {
+            return std::fs::read(&copy).map_err(|e| format!("backup unreadable: {}", e));
+        }
+        let Some(commit) = &self.commit else {
+            return Err("no backup of the file".to_string());
+        };
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.workspace)
+            .args(["show", &format!("{}:./{}", commit, relative)])
+            .output()
+            .map_err(|e| format!("failed to run git: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "git backup unreadable: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+
+    fn copy(&self, path: &Path) -> KaiakResult<()> {
+        let target = self.copy_target(path)?;
+        std::fs::copy(path, &target).map_err(|e| KaiakError::Io {
+            message: format!(
+                "Failed to back up {} to {}: {}",
+                path.display(),
+                target.display(),
+                e
+            ),
+        })?;
+        debug!("Backed up {} to {}", path.display(), target.display());
+        Ok(())
+    }
+
+    fn write_copy(&self, path: &Path, content: &[u8]) -> KaiakResult<()> {
+        let target = self.copy_target(path)?;
+        std::fs::write(&target, content).map_err(|e| KaiakError::Io {
+            message: format!(
+                "Failed to back up {} to {}: {}",
+                path.display(),
+                target.display(),
+                e
+            ),
+        })?;
+        debug!(
+            "Backed up original content of {} to {}",
+            path.display(),
+            target.display()
+        );
+        Ok(())
+    }
+
+    /// Where a file's copy goes, with its parent directory created
+    fn copy_target(&self, path: &Path) -> KaiakResult<PathBuf> {
+        let target = self.dir.join(self.relative(path));
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| KaiakError::Io {
+                message: format!(
+                    "Failed to create backup directory {}: {}",
+                    parent.display(),
+                    e
+                ),
+            })?;
+        }
+        Ok(target)
+    }
+
+    /// Path of a backup copy below the backup directory
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.workspace)
+            .unwrap_or(path)
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect()
+    }
+
+    /// Record the workspace's tracked files in git once per request. Returns false
+    /// outside a git repository.
+    fn snapshot_git(&mut self) -> KaiakResult<bool> {
+        if self.commit.is_some() {
+            return Ok(true);
+        }
+        if self.git(&["rev-parse", "--is-inside-work-tree"]).is_err() {
+            return Ok(false);
+        }
+
+        // `stash create` leaves the working tree alone and prints nothing when it is clean
+        let stash = self.git(&["stash", "create", "kaiak backup"])?;
+        let commit = if stash.is_empty() {
+            self.git(&["rev-parse", "HEAD"])?
+        } else {
+            let dir = self.dir.display().to_string();
+            let message = format!("kaiak backup {}", dir);
+            self.git(&["stash", "store", "-m", &message, &stash])?;
+            stash
+        };
+        info!(
+            "Backed up workspace {} at git commit {}",
+            self.workspace.display(),
+            commit
+        );
+        self.commit = Some(commit);
+        Ok(true)
+    }
+
+    fn git_tracks(&self, path: &Path) -> bool {
+        let path = path.display().to_string();
+        self.git(&["ls-files", "--error-unmatch", "--", &path])
+            .is_ok()
+    }
+
+    fn git(&self, args: &[&str]) -> KaiakResult<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.workspace)
+            .args(args)
+            .output()
+            .map_err(|e| KaiakError::Io {
+                message: format!("Failed to run git: {}", e),
+            })?;
+        if !output.status.success() {
+            return Err(KaiakError::Io {
+                message: format!(
+                    "git {} failed: {}",
+                    args.join(" "),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// Hash of a file's content; `None` when it does not exist or cannot be read
+fn content_hash(path: &Path) -> Option<u64> {
+    let content = std::fs::read(path).ok()?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.hash(&mut hasher);
+    Some(hasher.finish())
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use validator::Validate;
 
@@ -9,9 +9,19 @@ pub use goose::agents::{ExtensionConfig, SessionConfig as GooseSessionConfig};
 pub use goose::config::permission::PermissionLevel;
 pub use goose::session::SessionType;
 
+pub use super::backup::BackupStrategy;
+use super::incidents::IncidentSeverity;
+
+/// Version of the configuration file format this binary reads and writes
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
 /// Unified server configuration for Kaiak server initialization and runtime settings
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct ServerConfig {
+    /// Format version of the configuration file; older files are migrated on load
+    #[serde(default = "current_schema_version")]
+    pub schema_version: u32,
+
     /// This is set at runtime and cannot be changed until server restart
     #[validate(nested)]
     pub init_config: InitConfig,
@@ -33,6 +43,19 @@ pub struct InitConfig {
     /// Unix socket path (required when transport = "socket")
     pub socket_path: Option<String>,
 
+    /// Octal file mode applied to the Unix socket after binding, e.g. "0600"
+    #[serde(default = "default_socket_permissions")]
+    #[validate(custom(function = "validate_socket_permissions"))]
+    pub socket_permissions: String,
+
+    /// Numeric group ID to assign to the Unix socket
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket_group: Option<u32>,
+
+    /// How messages are framed on the transport
+    #[serde(default)]
+    pub framing: Framing,
+
     /// Logging level: trace, debug, info, warn, error
     #[validate(custom(function = "validate_log_level"))]
     pub log_level: String,
@@ -40,16 +63,250 @@ pub struct InitConfig {
     /// Maximum concurrent agent sessions
     #[validate(range(min = 1, max = 100))]
     pub max_concurrent_sessions: u32,
+
+    /// Maximum model calls running at once across all sessions, for providers that
+    /// rate-limit aggressively; further calls wait for a slot. Unbounded when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "max_concurrent_model_calls must be positive"))]
+    pub max_concurrent_model_calls: Option<u32>,
+
+    /// Directory where redacted prompts and responses are recorded, one JSONL file per
+    /// session. Recording is off when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_recording_dir: Option<String>,
+
+    /// Directory where each generate_fix request also logs to `<request_id>.log`.
+    /// Per-request logging is off when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_log_dir: Option<String>,
+
+    /// Days to keep per-request log files; older ones are deleted at startup and hourly
+    /// while the server runs. Other files in `request_log_dir` are never deleted.
+    #[serde(default = "default_request_log_retention_days")]
+    pub request_log_retention_days: u64,
+
+    /// Seconds a socket connection may stay silent between requests before the server
+    /// closes it so the next client can connect; unrelated to session lifetimes.
+    /// Idle connections are kept open when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "connection_idle_timeout_secs must be positive"))]
+    pub connection_idle_timeout_secs: Option<u64>,
+
+    /// Environment variable holding the client identity, for deployments that start a
+    /// server per tenant. Clients may also set it with `kaiak/set_identity`; it is
+    /// `anonymous` otherwise.
+    #[serde(default = "default_identity_env")]
+    pub identity_env: String,
+
+    /// File where decisions on file-modifying tool calls are appended as JSON lines,
+    /// with the client identity. They are always logged under the `kaiak::audit` target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log_path: Option<String>,
+
+    /// Whether clients may subscribe to the server's logs with `kaiak/stream_logs`.
+    /// Off by default, since logs can include file contents and tool arguments.
+    #[serde(default)]
+    pub allow_log_streaming: bool,
+}
+
+/// Message framing of a transport
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// LSP-style `Content-Length` header before each message
+    #[default]
+    ContentLength,
+    /// Newline-delimited JSON: one message per line
+    Ndjson,
 }
 
-/// Runtime server base configuration that can be overridden per session
+/// Runtime server base configuration that can be overridden per session with
+/// `agent_config.override_base_config`. Fields marked server-only are only read from the
+/// server's own configuration, so an override setting one to anything but its default is
+/// rejected rather than ignored.
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct BaseConfig {
+    #[validate(nested)]
     pub model: ModelConfig,
+    /// Model a request switches to when `model`'s provider cannot be set up or its model
+    /// call fails with an error that retrying would not fix
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(nested)]
+    pub fallback_model: Option<ModelConfig>,
     // We maintain a map of tool names to their permission levels
     // TODO (pgaikwad): Deep dive into smart permission settings
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub tool_permissions: HashMap<String, PermissionLevel>,
+    /// Permission for tools without an entry in `tool_permissions`
+    #[serde(default = "default_tool_permission")]
+    pub default_tool_permission: PermissionLevel,
+    /// Allow incidents marked `auto_apply` to skip tool approval. Server-only.
+    #[serde(default)]
+    pub allow_incident_auto_apply: bool,
+    /// Propose each incident's `suggested_replacement` as an edit without calling the
+    /// model; otherwise it is only given to the model as a hint. Server-only.
+    #[serde(default)]
+    pub apply_suggested_replacements: bool,
+    /// Glob patterns (relative to the workspace) of paths the agent may never modify,
+    /// even with approval. A trailing `/` protects everything under a directory.
+    /// Server-only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub protected_paths: Vec<String>,
+    /// Severity given to incidents whose `rule_id` matches a glob (`*` and `?`), replacing
+    /// the one the analysis reported; the longest matching glob wins. Server-only.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub severity_overrides: BTreeMap<String, IncidentSeverity>,
+    /// Lines of surrounding code included in the prompt around each incident line.
+    /// Requests may override this; values above the maximum are clamped.
+    #[serde(default = "default_incident_context_lines")]
+    pub incident_context_lines: usize,
+    /// Maximum characters of an incident's message or description in the prompt; longer
+    /// text keeps its head and tail around a truncation marker. 0 disables truncation.
+    #[serde(default = "default_incident_text_limit")]
+    pub incident_text_limit: usize,
+    /// Largest source file, in bytes, read whole for prompt context; larger files are
+    /// refused rather than loaded into memory. Server-only.
+    #[serde(default = "default_max_readable_file_bytes")]
+    #[validate(range(min = 1, message = "max_readable_file_bytes must be positive"))]
+    pub max_readable_file_bytes: u64,
+    /// Agents pre-initialized at startup with the server's model so new sessions skip
+    /// provider creation and extension loading; 0 disables the pool. Server-only.
+    #[serde(default)]
+    #[validate(range(max = 32, message = "agent_pool_size must be at most 32"))]
+    pub agent_pool_size: usize,
+    /// Convert non-UTF-8 incident files (e.g. Latin-1, Shift-JIS) to UTF-8 on disk while the
+    /// agent works on them and back to their original encoding afterwards. Off by default:
+    /// a server that dies mid-request leaves the files as UTF-8. Never applies to
+    /// `read_only` servers, `patch` mode requests, or files over `max_readable_file_bytes`.
+    #[serde(default = "default_transcode_legacy_encodings")]
+    pub transcode_legacy_encodings: bool,
+    /// Stream the model's thinking to clients; requests may turn it off with `stream_thinking`
+    #[serde(default = "default_stream_thinking")]
+    pub stream_thinking: bool,
+    /// How long each agent extension's tool calls may run. Server-only.
+    #[serde(default)]
+    #[validate(nested)]
+    pub extension_timeouts: ExtensionTimeouts,
+    /// Providers sessions may use, e.g. only an internal gateway; empty allows any.
+    /// Server-only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_providers: Vec<String>,
+    /// Refuse every file modification server-wide, whatever the user approves, for
+    /// analysis-only deployments. Server-only.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Back up each file before an approved modification is applied, for rolling the
+    /// request back; unset disables backups. Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_strategy: Option<BackupStrategy>,
+    /// Directory the `copy` backups of each request go to, in a timestamped
+    /// subdirectory; defaults to `kaiak-backups` in the system temp directory. Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_dir: Option<PathBuf>,
+    /// Directory each session's scratch directory for tool temporary files is created in,
+    /// removed with the session; relative paths are resolved against the session's
+    /// workspace, and it defaults to `kaiak-scratch` in the system temp directory.
+    /// Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scratch_dir: Option<PathBuf>,
+    /// Seconds the edits that fixed an incident are kept, keyed by the incident's
+    /// fingerprint and model, so the same incident seen again is fixed by replaying them
+    /// instead of calling the model; unset disables the fix cache. Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "fix_cache_ttl_secs must be positive"))]
+    pub fix_cache_ttl_secs: Option<u64>,
+    /// Directory recording, per workspace, the suggested replacements and cached fixes the
+    /// server applied itself, so a request re-run after a crash skips those still in their
+    /// files; unset disables the record. Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied_edits_dir: Option<PathBuf>,
+    /// minijinja template file laying out the fix prompt; see `DEFAULT_PROMPT_TEMPLATE`
+    /// for the variables. Checked when the config is validated. Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(function = "validate_prompt_template_path"))]
+    pub prompt_template_path: Option<String>,
+    /// Messages a session may hold, counted with its existing history, before the request
+    /// that passes the cap is stopped; guards against runaway agent loops. Unlimited when unset.
+    /// Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "max_messages_per_session must be positive"))]
+    pub max_messages_per_session: Option<usize>,
+    /// When a request is stopped as a suspected agent loop. Server-only.
+    #[serde(default)]
+    #[validate(nested)]
+    pub loop_detection: LoopDetection,
+    /// Serialized size in bytes above which a tool result's content is written to a file and
+    /// streamed as a reference for `kaiak/get_tool_result`; 0 always streams the content.
+    /// Server-only.
+    #[serde(default = "default_tool_result_spill_bytes")]
+    pub tool_result_spill_bytes: usize,
+    /// Milliseconds within which a tool call's `tool_progress` events are coalesced into
+    /// one, for clients that cannot keep up with every update; unset sends each event.
+    /// Server-only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, message = "progress_coalesce_ms must be positive"))]
+    pub progress_coalesce_ms: Option<u64>,
+    /// Most incidents sent to the model in one turn; larger requests run as several turns
+    /// in the same session. Server-only.
+    #[serde(default)]
+    pub incident_turn_budget: IncidentTurnBudget,
+    /// Which later tool calls of a request a client's approval also covers; requests may
+    /// choose their own with `approval_cache`
+    #[serde(default)]
+    pub approval_cache: ApprovalCachePolicy,
+}
+
+/// Limits on the incidents of a single model turn; 0 leaves a limit off
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, PartialEq)]
+pub struct IncidentTurnBudget {
+    /// Incidents per turn
+    #[serde(default)]
+    pub max_incidents: usize,
+    /// Characters of incident messages and descriptions per turn
+    #[serde(default)]
+    pub max_chars: usize,
+}
+
+/// Tool calls a client's approval also covers for the rest of the same request.
+/// Approvals are never remembered across requests.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalCachePolicy {
+    /// Every call that needs approval is sent to the client
+    #[default]
+    AlwaysAsk,
+    /// Later calls of the same tool doing the same kind of access (read or modify) to the
+    /// same file; calls without a file need an identical earlier call
+    AskOncePerFile,
+    /// Later reads by the same tool, whatever their arguments; modifications are still
+    /// only covered for the same file
+    AskOncePerRequest,
+}
+
+/// Detection of agents stuck repeating the same tool call
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, PartialEq)]
+pub struct LoopDetection {
+    /// Identical tool calls (same name and arguments) that mark a loop; 0 disables detection
+    #[serde(default = "default_loop_repetitions")]
+    #[validate(custom(function = "validate_loop_repetitions"))]
+    pub repetitions: usize,
+    /// Most recent tool calls of a request in which the repetitions are counted
+    #[serde(default = "default_loop_window")]
+    #[validate(range(min = 1, message = "loop_detection.window must be positive"))]
+    pub window: usize,
+}
+
+/// Tool call timeouts, in seconds, for agent extensions
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, PartialEq)]
+pub struct ExtensionTimeouts {
+    /// Timeout for extensions without an entry in `extensions`
+    #[serde(default = "default_extension_timeout")]
+    #[validate(range(min = 1, message = "extension timeouts must be positive"))]
+    pub default: u64,
+    /// Timeout by extension name, e.g. `developer`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    #[validate(custom(function = "validate_extension_timeouts"))]
+    pub extensions: HashMap<String, u64>,
 }
 
 /// Per-session agent configuration sent by clients for individual agent sessions in the generate_fix request
@@ -66,17 +323,74 @@ pub struct AgentConfig {
     pub override_base_config: Option<BaseConfig>,
 }
 
+/// Settings a client may change on a live session with `kaiak/update_session_config`.
+/// Anything else (model, workspace, extensions) needs a new session, so unknown fields
+/// are rejected.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SessionConfigUpdate {
+    /// Turn limit for the session's later requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, max = 10000, message = "max_turns must be between 1 and 10000"))]
+    pub max_turns: Option<u32>,
+    /// Per-tool permissions for the session. They can only make the server's permissions
+    /// stricter; the stricter of the two applies.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tool_permissions: HashMap<String, PermissionLevel>,
+}
+
+impl SessionConfigUpdate {
+    /// Fold a later update into this one; its fields win
+    pub fn merge(&mut self, later: SessionConfigUpdate) {
+        if later.max_turns.is_some() {
+            self.max_turns = later.max_turns;
+        }
+        self.tool_permissions.extend(later.tool_permissions);
+    }
+
+    /// Apply the update to a session's agent configuration
+    pub fn apply(&self, config: &mut AgentConfig) {
+        if let Some(max_turns) = self.max_turns {
+            match config.session.as_mut() {
+                Some(session) => session.max_turns = Some(max_turns),
+                None => {
+                    config.session = Some(GooseSessionConfig {
+                        id: String::new(),
+                        schedule_id: None,
+                        max_turns: Some(max_turns),
+                        retry_config: None,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// How restrictive a permission is, from `AlwaysAllow` (0) to `NeverAllow` (2)
+pub fn permission_strictness(level: &PermissionLevel) -> u8 {
+    match level {
+        PermissionLevel::AlwaysAllow => 0,
+        PermissionLevel::AskBefore => 1,
+        PermissionLevel::NeverAllow => 2,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct ModelConfig {
     pub provider: String,
     pub model: String,
+    /// Sampling temperature passed to the provider
+    #[validate(range(min = 0.0, max = 2.0, message = "temperature must be between 0 and 2"))]
     pub temperature: Option<f32>,
+    /// Upper bound on tokens generated per response
+    #[validate(range(min = 1, message = "max_tokens must be positive"))]
     pub max_tokens: Option<u32>,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
+            schema_version: CONFIG_SCHEMA_VERSION,
             init_config: InitConfig::default(),
             base_config: BaseConfig::default(),
         }
@@ -88,12 +402,35 @@ impl Default for InitConfig {
         Self {
             transport: "stdio".to_string(),
             socket_path: None,
+            socket_permissions: default_socket_permissions(),
+            socket_group: None,
+            framing: Framing::default(),
             log_level: "info".to_string(),
             max_concurrent_sessions: 10,
+            max_concurrent_model_calls: None,
+            model_recording_dir: None,
+            request_log_dir: None,
+            request_log_retention_days: default_request_log_retention_days(),
+            connection_idle_timeout_secs: None,
+            identity_env: default_identity_env(),
+            audit_log_path: None,
+            allow_log_streaming: false,
         }
     }
 }
 
+impl InitConfig {
+    /// Parse `socket_permissions` into a file mode
+    pub fn socket_mode(&self) -> crate::KaiakResult<u32> {
+        parse_socket_permissions(&self.socket_permissions).ok_or_else(|| {
+            crate::KaiakError::configuration(format!(
+                "Invalid socket_permissions '{}': expected an octal mode such as 0600",
+                self.socket_permissions
+            ))
+        })
+    }
+}
+
 impl Default for AgentConfig {
     fn default() -> Self {
         Self {
@@ -104,16 +441,143 @@ impl Default for AgentConfig {
     }
 }
 
+impl AgentConfig {
+    /// The base configuration the session runs with: the request's override when it has
+    /// one, else the server's
+    pub fn effective_base_config<'a>(&'a self, server: &'a BaseConfig) -> &'a BaseConfig {
+        self.override_base_config.as_ref().unwrap_or(server)
+    }
+}
+
+impl BaseConfig {
+    /// Server-only fields this configuration sets to something other than their default
+    pub fn server_only_fields_set(&self) -> Vec<&'static str> {
+        let default = BaseConfig::default();
+        [
+            (
+                "allow_incident_auto_apply",
+                self.allow_incident_auto_apply != default.allow_incident_auto_apply,
+            ),
+            (
+                "apply_suggested_replacements",
+                self.apply_suggested_replacements != default.apply_suggested_replacements,
+            ),
+            ("protected_paths", self.protected_paths != default.protected_paths),
+            ("severity_overrides", self.severity_overrides != default.severity_overrides),
+            (
+                "max_readable_file_bytes",
+                self.max_readable_file_bytes != default.max_readable_file_bytes,
+            ),
+            ("agent_pool_size", self.agent_pool_size != default.agent_pool_size),
+            ("extension_timeouts", self.extension_timeouts != default.extension_timeouts),
+            ("allowed_providers", self.allowed_providers != default.allowed_providers),
+            ("read_only", self.read_only != default.read_only),
+            ("backup_strategy", self.backup_strategy != default.backup_strategy),
+            ("backup_dir", self.backup_dir != default.backup_dir),
+            ("scratch_dir", self.scratch_dir != default.scratch_dir),
+            ("fix_cache_ttl_secs", self.fix_cache_ttl_secs != default.fix_cache_ttl_secs),
+            ("applied_edits_dir", self.applied_edits_dir != default.applied_edits_dir),
+            ("prompt_template_path", self.prompt_template_path != default.prompt_template_path),
+            (
+                "max_messages_per_session",
+                self.max_messages_per_session != default.max_messages_per_session,
+            ),
+            ("loop_detection", self.loop_detection != default.loop_detection),
+            (
+                "tool_result_spill_bytes",
+                self.tool_result_spill_bytes != default.tool_result_spill_bytes,
+            ),
+            ("progress_coalesce_ms", self.progress_coalesce_ms != default.progress_coalesce_ms),
+            ("incident_turn_budget", self.incident_turn_budget != default.incident_turn_budget),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(field, _)| field)
+        .collect()
+    }
+
+    /// Reject a request override that sets server-only fields
+    pub fn check_override(&self) -> crate::KaiakResult<()> {
+        let fields = self.server_only_fields_set();
+        if fields.is_empty() {
+            return Ok(());
+        }
+        Err(crate::KaiakError::configuration(format!(
+            "override_base_config cannot set {}: only the server's own configuration can",
+            fields.join(", ")
+        )))
+    }
+
+    /// Permission for a tool: its `tool_permissions` entry, else `default_tool_permission`
+    pub fn tool_permission(&self, tool_name: &str) -> &PermissionLevel {
+        self.tool_permissions
+            .get(tool_name)
+            .unwrap_or(&self.default_tool_permission)
+    }
+}
+
 impl Default for BaseConfig {
     fn default() -> Self {
         Self {
             model: ModelConfig::default(),
+            fallback_model: None,
             // TODO (pgaikwad): revisit this
             tool_permissions: HashMap::new(),
+            default_tool_permission: default_tool_permission(),
+            allow_incident_auto_apply: false,
+            apply_suggested_replacements: false,
+            protected_paths: Vec::new(),
+            severity_overrides: BTreeMap::new(),
+            incident_context_lines: default_incident_context_lines(),
+            incident_text_limit: default_incident_text_limit(),
+            max_readable_file_bytes: default_max_readable_file_bytes(),
+            agent_pool_size: 0,
+            transcode_legacy_encodings: default_transcode_legacy_encodings(),
+            stream_thinking: default_stream_thinking(),
+            extension_timeouts: ExtensionTimeouts::default(),
+            allowed_providers: Vec::new(),
+            read_only: false,
+            backup_strategy: None,
+            backup_dir: None,
+            scratch_dir: None,
+            fix_cache_ttl_secs: None,
+            applied_edits_dir: None,
+            prompt_template_path: None,
+            max_messages_per_session: None,
+            loop_detection: LoopDetection::default(),
+            tool_result_spill_bytes: default_tool_result_spill_bytes(),
+            progress_coalesce_ms: None,
+            incident_turn_budget: IncidentTurnBudget::default(),
+            approval_cache: ApprovalCachePolicy::default(),
         }
     }
 }
 
+impl Default for LoopDetection {
+    fn default() -> Self {
+        Self {
+            repetitions: default_loop_repetitions(),
+            window: default_loop_window(),
+        }
+    }
+}
+
+impl Default for ExtensionTimeouts {
+    fn default() -> Self {
+        Self {
+            default: default_extension_timeout(),
+            extensions: HashMap::new(),
+        }
+    }
+}
+
+impl ExtensionTimeouts {
+    /// Timeout for an extension: its `extensions` entry, else `default`
+    pub fn for_extension(&self, name: &str) -> u64 {
+        self.extensions.get(name).copied().unwrap_or(self.default)
+    }
+}
+
 impl Default for ModelConfig {
     fn default() -> Self {
         Self {
@@ -224,6 +688,14 @@ impl ConfigurationHierarchy {
             self.resolved.init_config.log_level = val.clone();
             env_overrides.push(format!("KAIAK_LOG_LEVEL={}", val));
         }
+        if let Ok(val) = std::env::var("KAIAK_MODEL_PROVIDER") {
+            self.resolved.base_config.model.provider = val.clone();
+            env_overrides.push(format!("KAIAK_MODEL_PROVIDER={}", val));
+        }
+        if let Ok(val) = std::env::var("KAIAK_MODEL") {
+            self.resolved.base_config.model.model = val.clone();
+            env_overrides.push(format!("KAIAK_MODEL={}", val));
+        }
         if !env_overrides.is_empty() {
             self.sources.push(ConfigSource {
                 name: "Environment variables".to_string(),
@@ -245,8 +717,7 @@ impl ConfigurationHierarchy {
     /// Load configuration from a TOML file
     fn load_config_file(path: &PathBuf) -> Result<ServerConfig> {
         let content = std::fs::read_to_string(path)?;
-        let config: ServerConfig = toml::from_str(&content)?;
-        Ok(config)
+        ServerConfig::from_toml(&content)
     }
 
     /// Merge two configurations (second takes precedence)
@@ -279,13 +750,20 @@ impl ServerConfig {
         
         if config_path.exists() {
             let content = std::fs::read_to_string(&config_path)?;
-            let config: ServerConfig = toml::from_str(&content)?;
-            Ok(config)
+            Self::from_toml(&content)
         } else {
             Ok(Self::default())
         }
     }
 
+    /// Parse a TOML configuration, upgrading files written with an older schema version.
+    /// Files newer than this binary supports are rejected.
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let mut value: toml::Value = toml::from_str(content)?;
+        migrate_config(&mut value)?;
+        Ok(value.try_into()?)
+    }
+
     /// Get the default config path (delegates to ConfigurationHierarchy)
     pub fn config_path() -> Result<PathBuf> {
         ConfigurationHierarchy::default_user_config_path()
@@ -310,6 +788,80 @@ impl ServerConfig {
     }
 }
 
+fn current_schema_version() -> u32 {
+    CONFIG_SCHEMA_VERSION
+}
+
+/// Upgrade a parsed configuration file to `CONFIG_SCHEMA_VERSION` in place.
+/// Files without `schema_version` predate versioning and are treated as version 1.
+fn migrate_config(value: &mut toml::Value) -> Result<()> {
+    let table = value
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("Configuration must be a TOML table"))?;
+    let version = match table.get("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v > 0)
+            .ok_or_else(|| anyhow::anyhow!("Invalid schema_version: {}", version))?,
+    };
+    if version > CONFIG_SCHEMA_VERSION {
+        anyhow::bail!(
+            "Configuration schema version {} is newer than this kaiak supports ({}); upgrade kaiak",
+            version,
+            CONFIG_SCHEMA_VERSION
+        );
+    }
+
+    for from in version..CONFIG_SCHEMA_VERSION {
+        match from {
+            1 => migrate_v1_to_v2(table)?,
+            _ => unreachable!("no migration from schema version {}", from),
+        }
+        tracing::info!("Migrated configuration from schema version {} to {}", from, from + 1);
+    }
+    table.insert(
+        "schema_version".to_string(),
+        toml::Value::Integer(CONFIG_SCHEMA_VERSION.into()),
+    );
+    Ok(())
+}
+
+/// Version 1 named the model `model_id` and could omit settings that are now required
+fn migrate_v1_to_v2(table: &mut toml::value::Table) -> Result<()> {
+    if let Some(model) = table
+        .get_mut("base_config")
+        .and_then(|base| base.get_mut("model"))
+        .and_then(|model| model.as_table_mut())
+    {
+        if let Some(model_id) = model.remove("model_id") {
+            model.entry("model").or_insert(model_id);
+        }
+    }
+
+    let defaults = toml::Value::try_from(ServerConfig::default())?;
+    if let toml::Value::Table(defaults) = defaults {
+        fill_missing(table, defaults);
+    }
+    Ok(())
+}
+
+/// Copy keys from `defaults` that `table` lacks, recursing into nested tables
+fn fill_missing(table: &mut toml::value::Table, defaults: toml::value::Table) {
+    for (key, default) in defaults {
+        match (table.get_mut(&key), default) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(default)) => {
+                fill_missing(existing, default)
+            }
+            (Some(_), _) => {}
+            (None, default) => {
+                table.insert(key, default);
+            }
+        }
+    }
+}
+
 // Validation functions for InitConfig
 fn validate_transport_type(transport: &str) -> Result<(), validator::ValidationError> {
     match transport {
@@ -320,6 +872,96 @@ fn validate_transport_type(transport: &str) -> Result<(), validator::ValidationE
     }
 }
 
+fn default_socket_permissions() -> String {
+    "0600".to_string()
+}
+
+fn default_request_log_retention_days() -> u64 {
+    7
+}
+
+fn default_identity_env() -> String {
+    "KAIAK_IDENTITY".to_string()
+}
+
+fn default_tool_permission() -> PermissionLevel {
+    PermissionLevel::AskBefore
+}
+
+fn default_incident_context_lines() -> usize {
+    5
+}
+
+fn default_incident_text_limit() -> usize {
+    4000
+}
+
+fn default_max_readable_file_bytes() -> u64 {
+    super::text_encoding::DEFAULT_MAX_READABLE_FILE_BYTES
+}
+
+fn default_transcode_legacy_encodings() -> bool {
+    false
+}
+
+fn default_stream_thinking() -> bool {
+    true
+}
+
+/// Parse an octal permission string ("0600", "660", "0o640") into a mode no wider than 0o777
+fn parse_socket_permissions(permissions: &str) -> Option<u32> {
+    let digits = permissions.strip_prefix("0o").unwrap_or(permissions);
+    if digits.is_empty() || digits.len() > 4 {
+        return None;
+    }
+    u32::from_str_radix(digits, 8).ok().filter(|mode| *mode <= 0o777)
+}
+
+fn default_extension_timeout() -> u64 {
+    300
+}
+
+fn default_tool_result_spill_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_loop_repetitions() -> usize {
+    3
+}
+
+fn default_loop_window() -> usize {
+    10
+}
+
+fn validate_loop_repetitions(repetitions: usize) -> Result<(), validator::ValidationError> {
+    if repetitions == 1 {
+        return Err(validator::ValidationError::new(
+            "loop_detection.repetitions must be 0 (disabled) or at least 2",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_extension_timeouts(
+    timeouts: &HashMap<String, u64>,
+) -> Result<(), validator::ValidationError> {
+    if timeouts.values().any(|timeout| *timeout == 0) {
+        return Err(validator::ValidationError::new(
+            "extension timeouts must be positive",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_socket_permissions(permissions: &str) -> Result<(), validator::ValidationError> {
+    match parse_socket_permissions(permissions) {
+        Some(_) => Ok(()),
+        None => Err(validator::ValidationError::new(
+            "socket_permissions must be an octal mode such as 0600",
+        )),
+    }
+}
+
 fn validate_log_level(level: &str) -> Result<(), validator::ValidationError> {
     match level {
         "trace" | "debug" | "info" | "warn" | "error" => Ok(()),
@@ -327,6 +969,18 @@ fn validate_log_level(level: &str) -> Result<(), validator::ValidationError> {
     }
 }
 
+/// The prompt template must exist and compile
+fn validate_prompt_template_path(path: &str) -> Result<(), validator::ValidationError> {
+    match crate::agent::PromptTemplate::from_file(std::path::Path::new(path)) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let mut error = validator::ValidationError::new("invalid_prompt_template");
+            error.message = Some(e.to_string().into());
+            Err(error)
+        }
+    }
+}
+
 /// Custom validation function for workspace path
 fn validate_workspace_path(path: &PathBuf) -> Result<(), validator::ValidationError> {
     // Check if path is not empty
@@ -350,3 +1004,66 @@ fn validate_workspace_path(path: &PathBuf) -> Result<(), validator::ValidationEr
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socket_permissions_parsing() {
+        let mut config = InitConfig::default();
+        assert_eq!(config.socket_mode().unwrap(), 0o600);
+
+        config.socket_permissions = "0o660".to_string();
+        assert_eq!(config.socket_mode().unwrap(), 0o660);
+
+        for invalid in ["", "0800", "rw-------", "01777"] {
+            config.socket_permissions = invalid.to_string();
+            assert!(config.socket_mode().is_err(), "accepted {:?}", invalid);
+            assert!(Validate::validate(&config).is_err());
+        }
+    }
+
+    const V1_CONFIG: &str = r#"
+[init_config]
+transport = "socket"
+socket_path = "/run/kaiak.sock"
+
+[base_config.model]
+provider = "anthropic"
+model_id = "claude-sonnet-4"
+"#;
+
+    #[test]
+    fn test_v1_config_migrates_to_current_schema() {
+        let config = ServerConfig::from_toml(V1_CONFIG).unwrap();
+
+        assert_eq!(config.schema_version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.init_config.socket_path.as_deref(), Some("/run/kaiak.sock"));
+        assert_eq!(config.base_config.model.model, "claude-sonnet-4");
+        // Settings v1 files could omit come from the defaults
+        assert_eq!(config.init_config.log_level, "info");
+        assert_eq!(config.init_config.max_concurrent_sessions, 10);
+        assert_eq!(config.base_config.model.temperature, Some(0.01));
+        assert!(config.validate().is_ok());
+
+        let newer = format!("schema_version = {}\n{}", CONFIG_SCHEMA_VERSION + 1, V1_CONFIG);
+        let err = ServerConfig::from_toml(&newer).unwrap_err();
+        assert!(err.to_string().contains("newer than this kaiak supports"), "{}", err);
+    }
+
+    #[test]
+    fn test_override_rejects_server_only_fields() {
+        let mut config = BaseConfig::default();
+        config.model.model = "gpt-5-mini".to_string();
+        config.incident_context_lines = 2;
+        assert!(config.check_override().is_ok());
+
+        config.read_only = true;
+        config.protected_paths = vec![".git/".to_string()];
+        assert_eq!(config.server_only_fields_set(), vec!["protected_paths", "read_only"]);
+        let err = config.check_override().unwrap_err();
+        assert!(matches!(err, crate::KaiakError::Configuration { .. }), "{err:?}");
+        assert!(err.to_string().contains("protected_paths, read_only"), "{err}");
+    }
+}
@@ -0,0 +1,264 @@
+//! Stable incident fingerprints and per-workspace history for correlating runs.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use super::incidents::MigrationIncident;
+use super::text_encoding::{read_text, DEFAULT_MAX_READABLE_FILE_BYTES};
+use crate::{KaiakError, KaiakResult};
+
+/// Lines on each side of the incident line hashed into the fingerprint
+const FINGERPRINT_CONTEXT_LINES: usize = 2;
+
+/// 64-bit FNV-1a; unlike `DefaultHasher`, its output is guaranteed stable across releases
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Incident path relative to the workspace, with `/` separators
+fn normalized_path(incident: &MigrationIncident, workspace: &Path) -> String {
+    let path = incident.file_path();
+    let relative = path.strip_prefix(workspace).unwrap_or(&path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Whitespace-trimmed source lines around the incident line, if the file can be read
+fn code_context(incident: &MigrationIncident, workspace: &Path) -> Option<String> {
+    let line_number = incident.line_number.filter(|line| *line > 0)?;
+    let path = incident.file_path();
+    let path = if path.is_relative() {
+        workspace.join(path)
+    } else {
+        path
+    };
+    let content = read_text(&path, DEFAULT_MAX_READABLE_FILE_BYTES).ok()?.text;
+
+    let first = line_number.saturating_sub(FINGERPRINT_CONTEXT_LINES).max(1);
+    let lines: Vec<&str> = content
+        .lines()
+        .skip(first - 1)
+        .take(line_number + FINGERPRINT_CONTEXT_LINES + 1 - first)
+        .map(str::trim)
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// Fingerprint an incident from its rule, workspace-relative path, and surrounding code.
+/// The raw line number is left out so the fingerprint survives edits elsewhere in the file;
+/// the message stands in for the code when the file cannot be read.
+pub fn fingerprint(incident: &MigrationIncident, workspace: &Path) -> String {
+    let context = code_context(incident, workspace).unwrap_or_else(|| incident.message.clone());
+    let key = format!(
+        "{}\0{}\0{}",
+        incident.rule_id.as_deref().unwrap_or_default(),
+        normalized_path(incident, workspace),
+        context
+    );
+    format!("{:016x}", fnv1a(key.as_bytes()))
+}
+
+/// Whether an incident was seen in the previous run of the same workspace
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FingerprintStatus {
+    New,
+    Recurring,
+}
+
+/// Fingerprint of one incident in a generate_fix request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IncidentFingerprint {
+    pub incident_id: String,
+    pub fingerprint: String,
+    /// Set when fingerprint history is tracked for the workspace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<FingerprintStatus>,
+}
+
+/// Fingerprints seen in the last tracked run of a workspace
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FingerprintHistory {
+    pub fingerprints: BTreeSet<String>,
+}
+
+impl FingerprintHistory {
+    /// History file for a workspace under ~/.kaiak/fingerprints
+    pub fn default_path(workspace: &Path) -> KaiakResult<PathBuf> {
+        let home_dir = dirs::home_dir().ok_or_else(|| {
+            KaiakError::configuration("Could not determine home directory".to_string())
+        })?;
+        let workspace_key = fnv1a(workspace.to_string_lossy().as_bytes());
+        Ok(home_dir
+            .join(".kaiak")
+            .join("fingerprints")
+            .join(format!("{:016x}.json", workspace_key)))
+    }
+
+    /// Load history, treating a missing file as an empty history
+    pub fn load(path: &Path) -> KaiakResult<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|e| {
+                KaiakError::configuration(format!(
+                    "Invalid fingerprint history {}: {}",
+                    path.display(),
+                    e
+                ))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(io_error(path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> KaiakResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+        }
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| KaiakError::Serialization {
+                message: format!("Failed to serialize fingerprint history: {}", e),
+            })?;
+        std::fs::write(path, content).map_err(|e| io_error(path, e))
+    }
+
+    /// Tag current incidents as new or recurring, returning the resolved fingerprints
+    /// (seen last run, absent now) and replacing the history with the current run
+    pub fn update(&mut self, current: &mut [IncidentFingerprint]) -> Vec<String> {
+        let mut seen = BTreeSet::new();
+        for incident in current.iter_mut() {
+            incident.status = Some(if self.fingerprints.contains(&incident.fingerprint) {
+                FingerprintStatus::Recurring
+            } else {
+                FingerprintStatus::New
+            });
+            seen.insert(incident.fingerprint.clone());
+        }
+
+        let resolved = self.fingerprints.difference(&seen).cloned().collect();
+        self.fingerprints = seen;
+        resolved
+    }
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> KaiakError {
+    KaiakError::Io {
+        message: format!("{}: {}", path.display(), error),
+    }
+}
+
+/// Fingerprint every incident of a request, preserving order
+pub fn fingerprint_incidents(
+    incidents: &[MigrationIncident],
+    workspace: &Path,
+) -> Vec<IncidentFingerprint> {
+    incidents
+        .iter()
+        .map(|incident| IncidentFingerprint {
+            incident_id: incident.id.clone(),
+            fingerprint: fingerprint(incident, workspace),
+            status: None,
+        })
+        .collect()
+}
+
+/// Count fingerprints per status, for logging
+pub fn status_counts(fingerprints: &[IncidentFingerprint]) -> BTreeMap<&'static str, usize> {
+    let mut counts = BTreeMap::new();
+    for status in fingerprints.iter().filter_map(|f| f.status) {
+        let key = match status {
+            FingerprintStatus::New => "new",
+            FingerprintStatus::Recurring => "recurring",
+        };
+        *counts.entry(key).or_default() += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incident(line_number: usize) -> MigrationIncident {
+        MigrationIncident::new(
+            "inc-1".to_string(),
+            "file:///ws/src/Main.java".to_string(),
+            "Replace javax.xml.bind".to_string(),
+            String::new(),
+        )
+        .with_rule_id("javax-to-jakarta")
+        .with_line_number(line_number)
+    }
+
+    #[test]
+    fn test_fingerprint_survives_line_shift() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path();
+        let file = workspace.join("Main.java");
+        let uri = format!("file://{}", file.display());
+        let body = "class Main {\n    void encode() {\n        javax.xml.bind.DatatypeConverter.printBase64Binary(data);\n    }\n}\n";
+
+        std::fs::write(&file, format!("package app;\n\n{}", body)).unwrap();
+        let before = fingerprint(
+            &MigrationIncident {
+                uri: uri.clone(),
+                ..incident(5)
+            },
+            workspace,
+        );
+
+        // Imports added above the incident move it down three lines
+        std::fs::write(
+            &file,
+            format!(
+                "package app;\n\nimport a.A;\nimport b.B;\nimport c.C;\n{}",
+                body
+            ),
+        )
+        .unwrap();
+        let after = fingerprint(
+            &MigrationIncident {
+                uri: uri.clone(),
+                ..incident(8)
+            },
+            workspace,
+        );
+        assert_eq!(before, after);
+
+        let other_rule = MigrationIncident { uri, ..incident(8) }.with_rule_id("other-rule");
+        assert_ne!(after, fingerprint(&other_rule, workspace));
+    }
+
+    #[test]
+    fn test_history_tags_new_recurring_and_resolved() {
+        let mut history = FingerprintHistory::default();
+        let entry = |fingerprint: &str| IncidentFingerprint {
+            incident_id: fingerprint.to_string(),
+            fingerprint: fingerprint.to_string(),
+            status: None,
+        };
+
+        let mut first = vec![entry("a"), entry("b")];
+        assert!(history.update(&mut first).is_empty());
+        assert!(first
+            .iter()
+            .all(|f| f.status == Some(FingerprintStatus::New)));
+
+        let mut second = vec![entry("b"), entry("c")];
+        let resolved = history.update(&mut second);
+        assert_eq!(second[0].status, Some(FingerprintStatus::Recurring));
+        assert_eq!(second[1].status, Some(FingerprintStatus::New));
+        assert_eq!(resolved, vec!["a".to_string()]);
+    }
+}
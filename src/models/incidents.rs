@@ -1,19 +1,89 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
 use validator::Validate;
 
+use crate::{KaiakError, KaiakResult};
+
+/// Upper bound on the serialized size of an incident's `metadata`
+pub const MAX_INCIDENT_METADATA_BYTES: usize = 16 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Validate)]
 pub struct MigrationIncident {
+    /// Identifier used to attribute streamed events; defaults to the incident's fingerprint
+    #[serde(default)]
     pub id: String,
+    /// Identifier of the static analysis rule that produced this incident
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_id: Option<String>,
     pub uri: String,
+    /// 1-based line of the incident within the file at `uri`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_number: Option<usize>,
     pub message: String,
     pub description: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub effort: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub severity: Option<IncidentSeverity>,
+    /// Hint that fixes for this incident are low-risk and may skip tool approval
+    #[serde(default)]
+    pub auto_apply: bool,
+    /// Text known to fix the incident, replacing the lines of `affected_range`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggested_replacement: Option<String>,
+    /// Lines `suggested_replacement` replaces; defaults to the line at `line_number`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(nested)]
+    pub affected_range: Option<LineRange>,
+    /// Opaque data from the analysis tooling (rule docs, links), echoed back in the report
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    #[validate(custom(function = "validate_metadata_size"))]
+    pub metadata: serde_json::Value,
+}
+
+/// 1-based, inclusive range of lines in an incident's file
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Validate)]
+#[validate(schema(function = "validate_line_range"))]
+pub struct LineRange {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+fn validate_line_range(range: &LineRange) -> Result<(), validator::ValidationError> {
+    if range.start_line == 0 || range.end_line < range.start_line {
+        let mut error = validator::ValidationError::new("invalid_line_range");
+        error.message = Some(
+            format!(
+                "Line range {}-{} must start at line 1 or later and not end before it starts",
+                range.start_line, range.end_line
+            )
+            .into(),
+        );
+        return Err(error);
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Keep tooling metadata small enough that echoing it back stays cheap
+fn validate_metadata_size(metadata: &serde_json::Value) -> Result<(), validator::ValidationError> {
+    let size = serde_json::to_vec(metadata).map(|bytes| bytes.len()).unwrap_or(0);
+    if size > MAX_INCIDENT_METADATA_BYTES {
+        let mut error = validator::ValidationError::new("metadata_too_large");
+        error.message = Some(
+            format!(
+                "Incident metadata is {} bytes; the limit is {} bytes",
+                size, MAX_INCIDENT_METADATA_BYTES
+            )
+            .into(),
+        );
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Incident severity, ordered from least to most severe
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum IncidentSeverity {
     Info,
@@ -55,11 +125,17 @@ impl MigrationIncident {
     ) -> Self {
         Self {
             id,
+            rule_id: None,
             uri,
+            line_number: None,
             message,
             description,
             effort: None,
             severity: None,
+            auto_apply: false,
+            suggested_replacement: None,
+            affected_range: None,
+            metadata: serde_json::Value::Null,
         }
     }
 
@@ -74,18 +150,396 @@ impl MigrationIncident {
     ) -> Self {
         Self {
             id,
+            rule_id: None,
             uri,
+            line_number: None,
             message,
             description,
             effort,
             severity,
+            auto_apply: false,
+            suggested_replacement: None,
+            affected_range: None,
+            metadata: serde_json::Value::Null,
         }
     }
+
+    /// Set the rule that produced this incident
+    pub fn with_rule_id(mut self, rule_id: impl Into<String>) -> Self {
+        self.rule_id = Some(rule_id.into());
+        self
+    }
+
+    /// Set the 1-based line where the incident occurs
+    pub fn with_line_number(mut self, line_number: usize) -> Self {
+        self.line_number = Some(line_number);
+        self
+    }
+
+    /// Mark this incident as safe to apply without user approval
+    pub fn with_auto_apply(mut self, auto_apply: bool) -> Self {
+        self.auto_apply = auto_apply;
+        self
+    }
+
+    /// Set the text known to fix the incident and the lines it replaces
+    pub fn with_suggested_replacement(
+        mut self,
+        replacement: impl Into<String>,
+        affected_range: Option<LineRange>,
+    ) -> Self {
+        self.suggested_replacement = Some(replacement.into());
+        self.affected_range = affected_range;
+        self
+    }
+
+    /// Lines a `suggested_replacement` replaces: `affected_range`, else the `line_number` line.
+    /// A `line_number` of 0, as some tools report for file-level incidents, names no line.
+    pub fn affected_lines(&self) -> Option<LineRange> {
+        self.affected_range.or_else(|| {
+            self.line_number.filter(|line| *line > 0).map(|line| LineRange {
+                start_line: line,
+                end_line: line,
+            })
+        })
+    }
+
+    /// Attach tooling metadata such as rule documentation links
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// HTTP(S) URLs found anywhere in `metadata`, in a stable order
+    pub fn reference_links(&self) -> Vec<&str> {
+        fn collect<'a>(value: &'a serde_json::Value, links: &mut Vec<&'a str>) {
+            match value {
+                serde_json::Value::String(text)
+                    if text.starts_with("https://") || text.starts_with("http://") =>
+                {
+                    links.push(text)
+                }
+                serde_json::Value::Array(items) => items.iter().for_each(|item| collect(item, links)),
+                serde_json::Value::Object(map) => map.values().for_each(|item| collect(item, links)),
+                _ => {}
+            }
+        }
+
+        let mut links = Vec::new();
+        collect(&self.metadata, &mut links);
+        links
+    }
+
+    /// Stable identity of this incident across runs; see `fingerprint::fingerprint`
+    pub fn fingerprint(&self, workspace: &std::path::Path) -> String {
+        super::fingerprint::fingerprint(self, workspace)
+    }
+
+    /// Filesystem path of the affected file, with any `file://` scheme removed
+    pub fn file_path(&self) -> std::path::PathBuf {
+        std::path::PathBuf::from(self.uri.strip_prefix("file://").unwrap_or(&self.uri))
+    }
 }
 
 // Compatibility with existing codebase - alias for the old Incident type
 pub type Incident = MigrationIncident;
 
+/// Turns one analysis report format into incidents
+pub trait IncidentParser: Send + Sync {
+    /// Short name of the format, used in logs and errors
+    fn name(&self) -> &'static str;
+
+    /// Whether `document` looks like this parser's format
+    fn can_parse(&self, document: &Value) -> bool;
+
+    fn parse(&self, document: &Value) -> KaiakResult<Vec<MigrationIncident>>;
+}
+
+/// Parsers tried in registration order; the first whose `can_parse` matches wins
+pub struct IncidentParserRegistry {
+    parsers: Vec<Box<dyn IncidentParser>>,
+}
+
+impl IncidentParserRegistry {
+    /// A registry with no parsers
+    pub fn empty() -> Self {
+        Self { parsers: Vec::new() }
+    }
+
+    /// Add a parser, tried after the ones already registered
+    pub fn with_parser(mut self, parser: impl IncidentParser + 'static) -> Self {
+        self.parsers.push(Box::new(parser));
+        self
+    }
+
+    /// The first registered parser that recognizes `document`
+    pub fn detect(&self, document: &Value) -> Option<&dyn IncidentParser> {
+        self.parsers
+            .iter()
+            .find(|parser| parser.can_parse(document))
+            .map(|parser| parser.as_ref())
+    }
+
+    /// Parse `document` with the parser that recognizes it
+    pub fn parse(&self, document: &Value) -> KaiakResult<Vec<MigrationIncident>> {
+        let parser = self.detect(document).ok_or_else(|| {
+            let formats: Vec<&str> = self.parsers.iter().map(|parser| parser.name()).collect();
+            KaiakError::configuration(format!(
+                "Unrecognized incident report format; expected one of: {}",
+                formats.join(", ")
+            ))
+        })?;
+        parser.parse(document)
+    }
+}
+
+impl Default for IncidentParserRegistry {
+    /// Konveyor analyzer output, then SARIF, then Kaiak's own incident JSON
+    fn default() -> Self {
+        Self::empty()
+            .with_parser(KonveyorParser)
+            .with_parser(SarifParser)
+            .with_parser(KaiakParser)
+    }
+}
+
+fn format_error(format: &str, message: impl std::fmt::Display) -> KaiakError {
+    KaiakError::configuration(format!("Invalid {} incident report: {}", format, message))
+}
+
+/// Konveyor analyzer output: a list of rulesets whose `violations` map rule IDs to
+/// incidents
+pub struct KonveyorParser;
+
+impl KonveyorParser {
+    fn severity(category: Option<&str>) -> Option<IncidentSeverity> {
+        match category? {
+            "mandatory" => Some(IncidentSeverity::Error),
+            "optional" => Some(IncidentSeverity::Warning),
+            "potential" => Some(IncidentSeverity::Info),
+            _ => None,
+        }
+    }
+}
+
+impl IncidentParser for KonveyorParser {
+    fn name(&self) -> &'static str {
+        "konveyor"
+    }
+
+    fn can_parse(&self, document: &Value) -> bool {
+        match document {
+            Value::Array(rulesets) => {
+                !rulesets.is_empty()
+                    && rulesets.iter().all(|ruleset| ruleset.get("violations").is_some_and(Value::is_object))
+            }
+            _ => false,
+        }
+    }
+
+    fn parse(&self, document: &Value) -> KaiakResult<Vec<MigrationIncident>> {
+        let mut incidents = Vec::new();
+        for ruleset in document.as_array().into_iter().flatten() {
+            let Some(violations) = ruleset.get("violations").and_then(Value::as_object) else {
+                continue;
+            };
+            for (rule_id, violation) in violations {
+                let description = violation
+                    .get("description")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                let effort = violation.get("effort").and_then(|effort| match effort {
+                    Value::String(text) => Some(text.clone()),
+                    Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                });
+                let severity = Self::severity(violation.get("category").and_then(Value::as_str));
+                let links = violation.get("links").cloned().unwrap_or(Value::Null);
+
+                for incident in violation.get("incidents").and_then(Value::as_array).into_iter().flatten() {
+                    let uri = incident
+                        .get("uri")
+                        .and_then(Value::as_str)
+                        .ok_or_else(|| format_error(self.name(), format!("incident of {} has no uri", rule_id)))?;
+                    let message = incident
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or(description);
+
+                    let mut parsed = MigrationIncident::with_details(
+                        String::new(),
+                        uri.to_string(),
+                        message.to_string(),
+                        description.to_string(),
+                        effort.clone(),
+                        severity,
+                    )
+                    .with_rule_id(rule_id.as_str());
+                    if let Some(line) = incident.get("lineNumber").and_then(Value::as_u64) {
+                        parsed = parsed.with_line_number(line as usize);
+                    }
+                    if !links.is_null() {
+                        parsed = parsed.with_metadata(serde_json::json!({ "links": links }));
+                    }
+                    incidents.push(parsed);
+                }
+            }
+        }
+        Ok(incidents)
+    }
+}
+
+/// SARIF 2.x static analysis results. Each physical location of a result becomes one
+/// incident; results without one are skipped with a warning.
+pub struct SarifParser;
+
+impl SarifParser {
+    /// SARIF `level`, which defaults to `warning` when neither the result nor its rule
+    /// sets one
+    fn severity(level: Option<&str>) -> Option<IncidentSeverity> {
+        match level.unwrap_or("warning") {
+            "error" => Some(IncidentSeverity::Error),
+            "warning" => Some(IncidentSeverity::Warning),
+            "note" => Some(IncidentSeverity::Info),
+            _ => None,
+        }
+    }
+
+    /// The `tool.driver.rules` entry a result refers to, by index or by ID
+    fn rule<'a>(run: &'a Value, result: &Value) -> Option<&'a Value> {
+        let rules = run.pointer("/tool/driver/rules")?.as_array()?;
+        if let Some(index) = result.get("ruleIndex").and_then(Value::as_u64) {
+            return rules.get(index as usize);
+        }
+        let rule_id = result.get("ruleId").and_then(Value::as_str)?;
+        rules
+            .iter()
+            .find(|rule| rule.get("id").and_then(Value::as_str) == Some(rule_id))
+    }
+}
+
+impl IncidentParser for SarifParser {
+    fn name(&self) -> &'static str {
+        "sarif"
+    }
+
+    fn can_parse(&self, document: &Value) -> bool {
+        let is_sarif = document
+            .get("$schema")
+            .and_then(Value::as_str)
+            .is_some_and(|schema| schema.contains("sarif"))
+            || document
+                .get("version")
+                .and_then(Value::as_str)
+                .is_some_and(|version| version.starts_with("2."));
+        is_sarif && document.get("runs").is_some_and(Value::is_array)
+    }
+
+    fn parse(&self, document: &Value) -> KaiakResult<Vec<MigrationIncident>> {
+        let mut incidents = Vec::new();
+        for run in document["runs"].as_array().into_iter().flatten() {
+            for result in run.get("results").and_then(Value::as_array).into_iter().flatten() {
+                let rule = Self::rule(run, result);
+                let rule_id = result
+                    .get("ruleId")
+                    .or_else(|| rule.and_then(|rule| rule.get("id")))
+                    .and_then(Value::as_str);
+                let message = result
+                    .pointer("/message/text")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| {
+                        format_error(
+                            self.name(),
+                            format!("result for {} has no message text", rule_id.unwrap_or("unknown rule")),
+                        )
+                    })?;
+                let description = rule
+                    .and_then(|rule| {
+                        rule.pointer("/fullDescription/text")
+                            .or_else(|| rule.pointer("/shortDescription/text"))
+                    })
+                    .and_then(Value::as_str)
+                    .unwrap_or(message);
+                let level = result
+                    .get("level")
+                    .or_else(|| rule.and_then(|rule| rule.pointer("/defaultConfiguration/level")))
+                    .and_then(Value::as_str);
+                let help_uri = rule.and_then(|rule| rule.get("helpUri")).and_then(Value::as_str);
+
+                let locations: Vec<&Value> = result
+                    .get("locations")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|location| location.get("physicalLocation"))
+                    .filter(|location| location.pointer("/artifactLocation/uri").is_some_and(Value::is_string))
+                    .collect();
+                if locations.is_empty() {
+                    warn!(
+                        "Skipping SARIF result for {} without a physical location: {}",
+                        rule_id.unwrap_or("unknown rule"),
+                        message
+                    );
+                    continue;
+                }
+
+                for location in locations {
+                    let uri = location["artifactLocation"]["uri"].as_str().unwrap_or_default();
+                    let mut incident = MigrationIncident::with_details(
+                        String::new(),
+                        uri.to_string(),
+                        message.to_string(),
+                        description.to_string(),
+                        None,
+                        Self::severity(level),
+                    );
+                    if let Some(rule_id) = rule_id {
+                        incident = incident.with_rule_id(rule_id);
+                    }
+                    if let Some(line) = location.pointer("/region/startLine").and_then(Value::as_u64) {
+                        incident = incident.with_line_number(line as usize);
+                    }
+                    if let Some(help_uri) = help_uri {
+                        incident = incident.with_metadata(serde_json::json!({ "help_uri": help_uri }));
+                    }
+                    incidents.push(incident);
+                }
+            }
+        }
+        Ok(incidents)
+    }
+}
+
+/// Kaiak's own incident JSON: a list of incidents, or an object with an `incidents` list
+pub struct KaiakParser;
+
+impl IncidentParser for KaiakParser {
+    fn name(&self) -> &'static str {
+        "kaiak"
+    }
+
+    fn can_parse(&self, document: &Value) -> bool {
+        let incidents = match document {
+            Value::Array(_) => document,
+            Value::Object(map) => match map.get("incidents") {
+                Some(incidents) => incidents,
+                None => return false,
+            },
+            _ => return false,
+        };
+        incidents.as_array().is_some_and(|incidents| {
+            incidents
+                .iter()
+                .all(|incident| incident.get("uri").is_some() && incident.get("message").is_some())
+        })
+    }
+
+    fn parse(&self, document: &Value) -> KaiakResult<Vec<MigrationIncident>> {
+        let incidents = document.get("incidents").unwrap_or(document);
+        serde_json::from_value(incidents.clone()).map_err(|e| format_error(self.name(), e))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,6 +591,13 @@ mod tests {
         assert_eq!(IncidentSeverity::Error.to_string(), "error");
     }
 
+    #[test]
+    fn test_severity_ordering() {
+        assert!(IncidentSeverity::Info < IncidentSeverity::Warning);
+        assert!(IncidentSeverity::Warning < IncidentSeverity::Error);
+        assert!(IncidentSeverity::Error < IncidentSeverity::Critical);
+    }
+
     #[test]
     fn test_incident_display() {
         let incident = MigrationIncident::with_details(
@@ -162,4 +623,119 @@ mod tests {
 
         assert_eq!(incident.to_string(), "[unknown] id1 - Test message");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_incident_file_path() {
+        let incident = MigrationIncident::new(
+            "id1".to_string(),
+            "file:///path/to/file.java".to_string(),
+            "Test message".to_string(),
+            "Description".to_string(),
+        );
+        assert_eq!(incident.file_path(), std::path::PathBuf::from("/path/to/file.java"));
+        assert!(!incident.auto_apply);
+    }
+
+    #[test]
+    fn test_registry_detects_and_parses_sarif() {
+        let sarif = serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": { "driver": { "name": "semgrep" } },
+                "results": [{
+                    "ruleId": "javax-to-jakarta",
+                    "level": "error",
+                    "message": { "text": "Replace javax.servlet with jakarta.servlet" },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": "src/Main.java" },
+                            "region": { "startLine": 3 }
+                        }
+                    }]
+                }]
+            }]
+        });
+
+        let registry = IncidentParserRegistry::default();
+        assert_eq!(registry.detect(&sarif).map(|parser| parser.name()), Some("sarif"));
+
+        let incidents = registry.parse(&sarif).unwrap();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].rule_id.as_deref(), Some("javax-to-jakarta"));
+        assert_eq!(incidents[0].uri, "src/Main.java");
+        assert_eq!(incidents[0].line_number, Some(3));
+        assert_eq!(incidents[0].message, "Replace javax.servlet with jakarta.servlet");
+    }
+
+    #[test]
+    fn test_registry_rejects_unknown_format() {
+        let error = IncidentParserRegistry::default()
+            .parse(&serde_json::json!({ "findings": [] }))
+            .unwrap_err();
+        assert!(error.to_string().contains("konveyor, sarif, kaiak"));
+    }
+
+    #[test]
+    fn test_sarif_results_map_to_incidents() {
+        let sarif = serde_json::json!({
+            "version": "2.1.0",
+            "runs": [{
+                "tool": { "driver": {
+                    "name": "lint",
+                    "rules": [
+                        {
+                            "id": "no-javax",
+                            "shortDescription": { "text": "javax is replaced by jakarta" },
+                            "helpUri": "https://example.com/rules/no-javax",
+                            "defaultConfiguration": { "level": "error" }
+                        },
+                        { "id": "style" }
+                    ]
+                }},
+                "results": [
+                    {
+                        "ruleId": "no-javax",
+                        "message": { "text": "javax.servlet import" },
+                        "locations": [
+                            { "physicalLocation": {
+                                "artifactLocation": { "uri": "src/A.java" },
+                                "region": { "startLine": 1 }
+                            }},
+                            { "physicalLocation": {
+                                "artifactLocation": { "uri": "src/B.java" },
+                                "region": { "startLine": 4 }
+                            }}
+                        ]
+                    },
+                    {
+                        "ruleIndex": 1,
+                        "level": "note",
+                        "message": { "text": "Prefer final fields" },
+                        "locations": [{ "physicalLocation": { "artifactLocation": { "uri": "src/C.java" } } }]
+                    },
+                    {
+                        "ruleId": "style",
+                        "message": { "text": "Applies to the whole project" },
+                        "locations": [{ "logicalLocations": [{ "name": "project" }] }]
+                    }
+                ]
+            }]
+        });
+
+        let incidents = SarifParser.parse(&sarif).unwrap();
+        assert_eq!(incidents.len(), 3);
+
+        let severities: Vec<_> = incidents.iter().map(|incident| incident.severity).collect();
+        assert_eq!(
+            severities,
+            [Some(IncidentSeverity::Error), Some(IncidentSeverity::Error), Some(IncidentSeverity::Info)]
+        );
+        assert_eq!(incidents[1].uri, "src/B.java");
+        assert_eq!(incidents[1].line_number, Some(4));
+        assert_eq!(incidents[1].description, "javax is replaced by jakarta");
+        assert_eq!(incidents[1].reference_links(), ["https://example.com/rules/no-javax"]);
+        assert_eq!(incidents[2].rule_id.as_deref(), Some("style"));
+        assert_eq!(incidents[2].line_number, None);
+    }
+}
@@ -0,0 +1,65 @@
+//! Line-level edit scripts between two versions of a file.
+
+/// Largest line-pair table computed for one file before falling back to replacing the
+/// whole changed region
+const MAX_DIFF_CELLS: usize = 16 * 1024 * 1024;
+
+/// One line of an edit script
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    /// Line `.0` of the old version is line `.1` of the new one
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script from `old` to `new`, by longest common subsequence
+pub fn edit_script<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_mid, new_mid) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if (n + 1) * (m + 1) > MAX_DIFF_CELLS {
+        edits.extend((0..n).map(|i| Edit::Delete(prefix + i)));
+        edits.extend((0..m).map(|j| Edit::Insert(prefix + j)));
+    } else {
+        // lcs[i][j]: common subsequence length of old_mid[i..] and new_mid[j..]
+        let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i][j] = if old_mid[i] == new_mid[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                edits.push(Edit::Equal(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                edits.push(Edit::Delete(prefix + i));
+                i += 1;
+            } else {
+                edits.push(Edit::Insert(prefix + j));
+                j += 1;
+            }
+        }
+    }
+    let (old_tail, new_tail) = (old.len() - suffix, new.len() - suffix);
+    edits.extend((0..suffix).map(|k| Edit::Equal(old_tail + k, new_tail + k)));
+    edits
+}
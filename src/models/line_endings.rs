@@ -0,0 +1,192 @@
+//! Keeping a file's dominant line ending across agent edits.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use super::line_diff::{edit_script, Edit};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::CrLf => "crlf",
+        }
+    }
+}
+
+/// The more common line ending in `text`, or None if it has no line breaks.
+/// Ties go to LF.
+pub fn detect(text: &str) -> Option<LineEnding> {
+    let line_breaks = text.matches('\n').count();
+    if line_breaks == 0 {
+        return None;
+    }
+    let crlf = text.matches("\r\n").count();
+    if crlf * 2 > line_breaks {
+        Some(LineEnding::CrLf)
+    } else {
+        Some(LineEnding::Lf)
+    }
+}
+
+/// Line ending and content of a file before the agent worked on it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileLineEnding {
+    pub path: PathBuf,
+    pub line_ending: LineEnding,
+    /// Content before the agent's edits, to tell the lines it changed
+    #[serde(skip)]
+    pub original: String,
+}
+
+/// Record the dominant line ending of each readable UTF-8 text file
+pub fn snapshot<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Vec<FileLineEnding> {
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let text = std::fs::read_to_string(path).ok()?;
+            Some(FileLineEnding {
+                path: path.to_path_buf(),
+                line_ending: detect(&text)?,
+                original: text,
+            })
+        })
+        .collect()
+}
+
+/// Give the lines of `current` that differ from `original` CRLF endings. Lines left as
+/// they were keep the ending they had in `original`, so a file with mixed endings only
+/// changes where it was edited.
+pub fn restore_changed_lines(original: &str, current: &str) -> String {
+    let original_lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let current_lines: Vec<&str> = current.split_inclusive('\n').collect();
+    let original_text: Vec<&str> = original_lines.iter().map(|line| line_text(line)).collect();
+    let current_text: Vec<&str> = current_lines.iter().map(|line| line_text(line)).collect();
+
+    let mut restored = String::with_capacity(current.len() + current_lines.len());
+    for edit in edit_script(&original_text, &current_text) {
+        let (j, ending) = match edit {
+            Edit::Equal(i, j) if original_lines[i].ends_with("\r\n") => (j, "\r\n"),
+            Edit::Equal(i, j) if original_lines[i].ends_with('\n') => (j, "\n"),
+            Edit::Equal(_, j) | Edit::Insert(j) => (j, "\r\n"),
+            Edit::Delete(_) => continue,
+        };
+        restored.push_str(current_text[j]);
+        if current_lines[j].ends_with('\n') {
+            restored.push_str(ending);
+        }
+    }
+    restored
+}
+
+/// A line without its line break
+fn line_text(line: &str) -> &str {
+    match line.strip_suffix('\n') {
+        Some(line) => line.strip_suffix('\r').unwrap_or(line),
+        None => line,
+    }
+}
+
+/// Convert the line breaks the agent wrote in each CRLF file it modified back to CRLF,
+/// only on the lines it changed. An LF file that gained CRLF lines is left alone.
+pub fn restore_line_endings(files: &[FileLineEnding]) {
+    for file in files {
+        if file.line_ending != LineEnding::CrLf {
+            continue;
+        }
+        let Ok(text) = std::fs::read_to_string(&file.path) else {
+            continue;
+        };
+        if text == file.original {
+            continue;
+        }
+        let restored = restore_changed_lines(&file.original, &text);
+        if restored == text {
+            continue;
+        }
+        match std::fs::write(&file.path, restored) {
+            Ok(()) => info!("Restored CRLF line endings in {}", file.path.display()),
+            Err(e) => warn!(
+                "Failed to restore line endings of {}: {}",
+                file.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crlf_file_keeps_crlf_after_lf_edit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Program.cs");
+        std::fs::write(&path, "class Program\r\n{\r\n    void Old() {}\r\n}\r\n").unwrap();
+
+        let snapshots = snapshot([path.as_path()]);
+        assert_eq!(snapshots[0].line_ending, LineEnding::CrLf);
+
+        // Editor tools typically write LF regardless of the file's convention
+        std::fs::write(
+            &path,
+            "class Program\n{\n    void New() {}\n    void Extra() {}\n}\n",
+        )
+        .unwrap();
+        restore_line_endings(&snapshots);
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "class Program\r\n{\r\n    void New() {}\r\n    void Extra() {}\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn test_only_changed_lines_of_mixed_file_become_crlf() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Program.cs");
+        let original = "class Program\r\n{\r\n    // generated\n    void Old() {}\r\n}\r\n";
+        std::fs::write(&path, original).unwrap();
+        let untouched = dir.path().join("Other.cs");
+        let untouched_original = "class Other\r\n{\r\n    // generated\n}\r\n";
+        std::fs::write(&untouched, untouched_original).unwrap();
+
+        let snapshots = snapshot([path.as_path(), untouched.as_path()]);
+        std::fs::write(
+            &path,
+            "class Program\n{\n    // generated\n    void New() {}\n}\n",
+        )
+        .unwrap();
+        restore_line_endings(&snapshots);
+
+        // The LF line the agent did not touch stays LF; files it did not modify are not rewritten
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "class Program\r\n{\r\n    // generated\n    void New() {}\r\n}\r\n"
+        );
+        assert_eq!(std::fs::read_to_string(&untouched).unwrap(), untouched_original);
+    }
+
+    #[test]
+    fn test_restore_changed_lines_keeps_missing_final_newline() {
+        assert_eq!(restore_changed_lines("a\r\nb", "a\nc"), "a\r\nc");
+        assert_eq!(restore_changed_lines("a\r\nb", "a\nb\nc\n"), "a\r\nb\r\nc\r\n");
+    }
+
+    #[test]
+    fn test_detect_dominant_ending() {
+        assert_eq!(detect("a\r\nb\r\nc\n"), Some(LineEnding::CrLf));
+        assert_eq!(detect("a\nb\nc\r\n"), Some(LineEnding::Lf));
+        assert_eq!(detect("a\r\nb\n"), Some(LineEnding::Lf));
+        assert_eq!(detect("no newline"), None);
+    }
+}
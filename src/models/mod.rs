@@ -1,8 +1,30 @@
 // Public exports for data models
 
+pub mod applied_edits;
+pub mod backup;
 pub mod configuration;
+pub mod fingerprint;
 pub mod incidents;
+pub mod line_diff;
+pub mod line_endings;
+pub mod patch;
+pub mod report;
+pub mod text_encoding;
+pub mod usage;
+pub mod warning;
 
+pub use applied_edits::AppliedEdits;
+pub use backup::{BackupStrategy, RollbackFailure, RollbackOutcome, WorkspaceBackup};
 pub use configuration::AgentConfig;
-pub use incidents::{MigrationIncident, IncidentSeverity};
+pub use fingerprint::{FingerprintHistory, FingerprintStatus, IncidentFingerprint};
+pub use incidents::{
+    IncidentParser, IncidentParserRegistry, IncidentSeverity, LineRange, MigrationIncident,
+};
+pub use patch::{ApplyMode, PatchRecorder, WorkspaceCopy};
+pub use report::{
+    FixSource, IncidentOutcome, IncidentResult, IncidentStatus, MigrationReport, MigrationReportCollector,
+    TextEdit,
+};
+pub use usage::{TokenUsage, UsageTracker};
+pub use warning::Warning;
 
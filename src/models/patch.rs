@@ -0,0 +1,477 @@
+//! Running the agent on a copy of the workspace and collecting its edits as a unified diff.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use super::line_diff::{edit_script, Edit};
+use super::line_endings::{self, LineEnding};
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// How approved file modifications reach the workspace
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyMode {
+    /// The agent's edits stay in the workspace
+    #[default]
+    InPlace,
+    /// The agent works on a copy of the workspace and its edits are written to a patch
+    /// file; the workspace itself is never modified
+    Patch,
+}
+
+/// A copy of a workspace for a `patch` mode request to work on. The copy is removed when
+/// this is dropped, so edits never reach the workspace even if the request fails.
+#[derive(Debug)]
+pub struct WorkspaceCopy {
+    workspace: PathBuf,
+    path: PathBuf,
+    /// Directory holding the copy, removed with it
+    root: PathBuf,
+}
+
+impl WorkspaceCopy {
+    /// Copy `workspace` into a directory of the same name under `root`, leaving out `.git`.
+    /// Symbolic links never lead out of the copy, see `copy_symlink`.
+    pub fn create(workspace: &Path, root: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let workspace = std::env::current_dir()?.join(workspace);
+        let root = root.into();
+        let name = workspace
+            .file_name()
+            .unwrap_or_else(|| "workspace".as_ref());
+        let copy = Self {
+            path: root.join(name),
+            workspace,
+            root,
+        };
+        let tree = CopiedTree {
+            workspace: std::fs::canonicalize(&copy.workspace)?,
+            copy: &copy.path,
+            exclude: &copy.root,
+        };
+        copy_tree(&copy.workspace, &copy.path, &tree)?;
+        info!(
+            "Copied {} to {}",
+            copy.workspace.display(),
+            copy.path.display()
+        );
+        Ok(copy)
+    }
+
+    /// Root of the copy, where the agent works
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where a workspace path is in the copy; paths outside the workspace are unchanged
+    pub fn to_copy(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.workspace) {
+            Ok(relative) => self.path.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    /// Where a path in the copy is in the workspace; other paths are unchanged
+    pub fn to_workspace(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.path) {
+            Ok(relative) => self.workspace.join(relative),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for WorkspaceCopy {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.root) {
+            warn!(
+                "Failed to remove workspace copy {}: {}",
+                self.root.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Where a workspace is being copied
+struct CopiedTree<'a> {
+    /// The workspace with symbolic links resolved
+    workspace: PathBuf,
+    /// Root of the copy
+    copy: &'a Path,
+    /// Where the copy goes, left out in case it is inside the workspace
+    exclude: &'a Path,
+}
+
+/// Copy the tree at `from` to `to`, skipping `.git` and the copy itself
+fn copy_tree(from: &Path, to: &Path, tree: &CopiedTree) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let (source, target) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            copy_symlink(&source, &target, tree)?;
+        } else if file_type.is_dir() {
+            if entry.file_name() != ".git" && source != tree.exclude {
+                copy_tree(&source, &target, tree)?;
+            }
+        } else {
+            std::fs::copy(&source, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Recreate the link at `source` in the copy so edits through it stay in the copy. A link
+/// into the workspace points at the same place in the copy. A link out of it would reach
+/// files outside the copy, so the file it points to is copied instead, and a directory or
+/// a dangling link is left out.
+fn copy_symlink(source: &Path, target: &Path, tree: &CopiedTree) -> std::io::Result<()> {
+    let Ok(resolved) = std::fs::canonicalize(source) else {
+        warn!(
+            "Leaving dangling link {} out of the workspace copy",
+            source.display()
+        );
+        return Ok(());
+    };
+    if let Ok(relative) = resolved.strip_prefix(&tree.workspace) {
+        return link_in_copy(&resolved, &tree.copy.join(relative), target);
+    }
+    if resolved.is_file() {
+        return std::fs::copy(&resolved, target).map(|_| ());
+    }
+    warn!(
+        "Leaving {} out of the workspace copy: it links to {} outside the workspace",
+        source.display(),
+        resolved.display()
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link_in_copy(_resolved: &Path, in_copy: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(in_copy, target)
+}
+
+#[cfg(not(unix))]
+fn link_in_copy(resolved: &Path, _in_copy: &Path, target: &Path) -> std::io::Result<()> {
+    if resolved.is_dir() {
+        Ok(())
+    } else {
+        std::fs::copy(resolved, target).map(|_| ())
+    }
+}
+
+/// Contents of the files an agent may modify in a workspace copy, as they were before it
+/// started
+#[derive(Debug, Default)]
+pub struct PatchRecorder {
+    workspace: PathBuf,
+    /// Original text by path; `None` for files that did not exist yet
+    originals: BTreeMap<PathBuf, Option<String>>,
+}
+
+impl PatchRecorder {
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        Self {
+            workspace: workspace.into(),
+            originals: BTreeMap::new(),
+        }
+    }
+
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// Remember a file's current contents unless it was already recorded.
+    /// Files that are not UTF-8 text cannot be diffed and are left out.
+    pub fn record(&mut self, path: &Path) {
+        if self.originals.contains_key(path) {
+            return;
+        }
+        let original = match std::fs::read_to_string(path) {
+            Ok(text) => Some(text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!("Not recording {} for the patch: {}", path.display(), e);
+                return;
+            }
+        };
+        self.originals.insert(path.to_path_buf(), original);
+    }
+
+    /// Diff every recorded file against its original and write the diff to `patch_path`.
+    /// Returns the number of files in the patch.
+    pub fn finish(self, patch_path: &Path) -> std::io::Result<usize> {
+        let mut patch = String::new();
+        let mut changed = 0;
+        for (path, original) in &self.originals {
+            let current = match std::fs::read_to_string(path) {
+                Ok(text) => Some(text),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    warn!("Leaving {} out of the patch: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if current == *original {
+                continue;
+            }
+
+            // Editor tools write LF; keep the diff to real changes in CRLF files
+            let current = match (original.as_deref(), current) {
+                (Some(original), Some(text))
+                    if line_endings::detect(original) == Some(LineEnding::CrLf) =>
+                {
+                    Some(line_endings::restore_changed_lines(original, &text))
+                }
+                (_, current) => current,
+            };
+            patch.push_str(&unified_diff(
+                &self.display_path(path),
+                original.as_deref(),
+                current.as_deref(),
+            ));
+            changed += 1;
+        }
+
+        if let Some(parent) = patch_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(patch_path, patch)?;
+        info!(
+            "Wrote patch for {} files to {}",
+            changed,
+            patch_path.display()
+        );
+        Ok(changed)
+    }
+
+    /// Path relative to the workspace, with `/` separators
+    fn display_path(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.workspace).unwrap_or(path);
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Git-style unified diff of one file. `None` stands for a missing file, so creations
+/// and deletions diff against `/dev/null`.
+pub fn unified_diff(path: &str, old: Option<&str>, new: Option<&str>) -> String {
+    let old_lines: Vec<&str> = old.unwrap_or("").split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.unwrap_or("").split_inclusive('\n').collect();
+    let edits = edit_script(&old_lines, &new_lines);
+
+    let mut out = format!("diff --git a/{path} b/{path}\n");
+    match (old, new) {
+        (None, _) => out.push_str("new file mode 100644\n"),
+        (_, None) => out.push_str("deleted file mode 100644\n"),
+        _ => {}
+    }
+    let old_name = old.map_or("/dev/null".to_string(), |_| format!("a/{}", path));
+    let new_name = new.map_or("/dev/null".to_string(), |_| format!("b/{}", path));
+    out.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+
+    for hunk in hunks(&edits) {
+        let (mut old_start, mut old_count, mut new_start, mut new_count) = (None, 0, None, 0);
+        for edit in hunk {
+            match *edit {
+                Edit::Equal(i, j) => {
+                    old_start.get_or_insert(i);
+                    new_start.get_or_insert(j);
+                    old_count += 1;
+                    new_count += 1;
+                }
+                Edit::Delete(i) => {
+                    old_start.get_or_insert(i);
+                    old_count += 1;
+                }
+                Edit::Insert(j) => {
+                    new_start.get_or_insert(j);
+                    new_count += 1;
+                }
+            }
+        }
+        // Context lines keep both sides non-empty unless that side's file is empty
+        let old_start = old_start.map_or(0, |i| i + 1);
+        let new_start = new_start.map_or(0, |j| j + 1);
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_count, new_start, new_count
+        ));
+
+        for edit in hunk {
+            let (marker, line) = match *edit {
+                Edit::Equal(i, _) => (' ', old_lines[i]),
+                Edit::Delete(i) => ('-', old_lines[i]),
+                Edit::Insert(j) => ('+', new_lines[j]),
+            };
+            out.push(marker);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// Group changes with up to `CONTEXT_LINES` of surrounding equal lines, merging groups
+/// whose context would overlap
+fn hunks(edits: &[Edit]) -> Vec<&[Edit]> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(..)))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut hunks = Vec::new();
+    let mut changes = changes.into_iter().peekable();
+    while let Some(first) = changes.next() {
+        let mut last = first;
+        while let Some(&next) = changes.peek() {
+            if next - last > 2 * CONTEXT_LINES {
+                break;
+            }
+            last = next;
+            changes.next();
+        }
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES + 1).min(edits.len());
+        hunks.push(&edits[start..end]);
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_mode_writes_diff_and_leaves_workspace_untouched() {
+        let workspace = tempfile::tempdir().unwrap();
+        let main = workspace.path().join("src/Main.java");
+        std::fs::create_dir_all(main.parent().unwrap()).unwrap();
+        let original = "import javax.servlet.Servlet;\n\nclass Main {\n    void run() {}\n}\n";
+        std::fs::write(&main, original).unwrap();
+
+        let scratch = tempfile::tempdir().unwrap();
+        let copy = WorkspaceCopy::create(workspace.path(), scratch.path().join("req-1")).unwrap();
+        let copied_main = copy.to_copy(&main);
+        let copied_added = copy.path().join("src/Added.java");
+        assert_eq!(
+            copy.to_workspace(&copied_added),
+            workspace.path().join("src/Added.java")
+        );
+        let mut recorder = PatchRecorder::new(copy.path());
+        recorder.record(&copied_main);
+        recorder.record(&copied_added);
+
+        // The agent's edits
+        std::fs::write(
+            &copied_main,
+            "import jakarta.servlet.Servlet;\n\nclass Main {\n    void run() {}\n}\n",
+        )
+        .unwrap();
+        std::fs::write(&copied_added, "class Added {}").unwrap();
+
+        let patch_path = workspace.path().join("out/fix.patch");
+        assert_eq!(recorder.finish(&patch_path).unwrap(), 2);
+
+        assert_eq!(
+            std::fs::read_to_string(&patch_path).unwrap(),
+            "diff --git a/src/Added.java b/src/Added.java\n\
+             new file mode 100644\n\
+             --- /dev/null\n\
+             +++ b/src/Added.java\n\
+             @@ -0,0 +1,1 @@\n\
+             +class Added {}\n\
+             \\ No newline at end of file\n\
+             diff --git a/src/Main.java b/src/Main.java\n\
+             --- a/src/Main.java\n\
+             +++ b/src/Main.java\n\
+             @@ -1,4 +1,4 @@\n\
+             -import javax.servlet.Servlet;\n\
+             +import jakarta.servlet.Servlet;\n\
+             \x20\n\
+             \x20class Main {\n\
+             \x20    void run() {}\n"
+        );
+        assert_eq!(std::fs::read_to_string(&main).unwrap(), original);
+        assert!(!workspace.path().join("src/Added.java").exists());
+    }
+
+    #[test]
+    fn test_workspace_copy_skips_git_and_is_removed_when_dropped() {
+        let workspace = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(workspace.path().join(".git")).unwrap();
+        std::fs::write(workspace.path().join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(workspace.path().join("Main.java"), "class Main {}\n").unwrap();
+
+        let scratch = tempfile::tempdir().unwrap();
+        let root = scratch.path().join("req-1");
+        let copy = WorkspaceCopy::create(workspace.path(), &root).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(copy.path().join("Main.java")).unwrap(),
+            "class Main {}\n"
+        );
+        assert!(!copy.path().join(".git").exists());
+
+        std::fs::write(copy.path().join("Main.java"), "class App {}\n").unwrap();
+        drop(copy);
+        assert!(!root.exists());
+        assert_eq!(
+            std::fs::read_to_string(workspace.path().join("Main.java")).unwrap(),
+            "class Main {}\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_workspace_copy_keeps_links_inside_the_copy() {
+        let outside = tempfile::tempdir().unwrap();
+        let outside_file = outside.path().join("shared.properties");
+        std::fs::write(&outside_file, "javax=1\n").unwrap();
+        let workspace = tempfile::tempdir().unwrap();
+        std::fs::write(workspace.path().join("Main.java"), "class Main {}\n").unwrap();
+        let link = |original: &Path, name: &str| {
+            std::os::unix::fs::symlink(original, workspace.path().join(name)).unwrap()
+        };
+        link(&workspace.path().join("Main.java"), "Absolute.java");
+        link(Path::new("Main.java"), "Relative.java");
+        link(&outside_file, "shared.properties");
+        link(outside.path(), "outside");
+
+        let scratch = tempfile::tempdir().unwrap();
+        let copy = WorkspaceCopy::create(workspace.path(), scratch.path().join("req-1")).unwrap();
+        let copied_main = std::fs::canonicalize(copy.path().join("Main.java")).unwrap();
+        for name in ["Absolute.java", "Relative.java"] {
+            let resolved = std::fs::canonicalize(copy.path().join(name)).unwrap();
+            assert_eq!(resolved, copied_main, "{}", name);
+        }
+
+        // Edits through what linked out of the workspace don't reach the linked file
+        std::fs::write(copy.path().join("shared.properties"), "jakarta=1\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&outside_file).unwrap(), "javax=1\n");
+        assert!(!copy.path().join("outside").exists());
+    }
+
+    #[test]
+    fn test_distant_changes_get_separate_hunks() {
+        let old: String = (1..=20).map(|n| format!("line {}\n", n)).collect();
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 19\n", "");
+
+        let diff = unified_diff("f.txt", Some(&old), Some(&new));
+        let headers: Vec<&str> = diff.lines().filter(|line| line.starts_with("@@")).collect();
+        assert_eq!(headers, ["@@ -1,5 +1,5 @@", "@@ -16,5 +16,4 @@"]);
+    }
+}
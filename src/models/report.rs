@@ -0,0 +1,514 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::incidents::MigrationIncident;
+use super::line_endings::FileLineEnding;
+use super::text_encoding::TranscodedFile;
+
+/// Summary of a completed generate_fix request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct MigrationReport {
+    /// Incidents included in the prompt sent to the agent
+    pub incidents_attempted: usize,
+    /// Incidents whose file was successfully modified
+    pub incidents_fixed: usize,
+    /// Incidents that were trimmed or whose file was never touched
+    pub incidents_skipped: usize,
+    /// Incidents whose file modifications all failed
+    pub incidents_failed: usize,
+    pub files_modified: usize,
+    pub tool_calls: usize,
+    pub elapsed_ms: u64,
+    /// Applied changes grouped per file, sorted by path
+    pub file_changes: Vec<FileChange>,
+    /// Outcome of each incident, in request order
+    #[serde(default)]
+    pub incidents: Vec<IncidentResult>,
+    /// Patch file holding the changes, for `patch` apply mode; the workspace was restored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch_path: Option<String>,
+    /// Directory holding copies of the modified files as they were before the request,
+    /// when `backup_strategy` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_dir: Option<String>,
+    /// Git stash entry, or HEAD for a clean workspace, holding the tracked files as they
+    /// were before the request, for the `git` backup strategy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_commit: Option<String>,
+    /// Model turns each incident file took, when the request was split into several turns
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub turns_per_file: BTreeMap<String, usize>,
+    /// Fixed incidents whose fix an earlier run already applied, so nothing was written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub already_applied: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Report of `incidents` fixed by `source` in an earlier run whose edits are still in
+    /// their files, so this run wrote nothing for them
+    pub fn already_applied(incidents: &[MigrationIncident], source: FixSource) -> Self {
+        Self {
+            incidents_attempted: incidents.len(),
+            incidents_fixed: incidents.len(),
+            incidents: incidents
+                .iter()
+                .map(|incident| IncidentResult {
+                    incident_id: incident.id.clone(),
+                    outcome: IncidentOutcome::Fixed,
+                    fixed_by: Some(source),
+                    metadata: incident.metadata.clone(),
+                })
+                .collect(),
+            already_applied: incidents.iter().map(|incident| incident.id.clone()).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Add the report of a later turn of the same request. Its incidents are new, and its
+    /// changes follow this report's changes to the same file.
+    pub fn merge(&mut self, later: MigrationReport) {
+        self.incidents_attempted += later.incidents_attempted;
+        self.incidents_fixed += later.incidents_fixed;
+        self.incidents_skipped += later.incidents_skipped;
+        self.incidents_failed += later.incidents_failed;
+        self.tool_calls += later.tool_calls;
+        self.elapsed_ms = self.elapsed_ms.max(later.elapsed_ms);
+        self.incidents.extend(later.incidents);
+        self.already_applied.extend(later.already_applied);
+
+        for change in later.file_changes {
+            match self.file_changes.iter_mut().find(|file| file.path == change.path) {
+                Some(file) => file.changes.extend(change.changes),
+                None => self.file_changes.push(change),
+            }
+        }
+        self.file_changes.sort_by(|a, b| a.path.cmp(&b.path));
+        self.files_modified = self.file_changes.len();
+    }
+
+    /// Note the original encoding on changes to files that were transcoded for the agent
+    pub fn record_encodings(&mut self, transcoded: &[TranscodedFile]) {
+        for change in &mut self.file_changes {
+            if let Some(file) = transcoded
+                .iter()
+                .find(|file| file.path.display().to_string() == change.path)
+            {
+                change.encoding = Some(file.encoding.clone());
+            }
+        }
+    }
+
+    /// Note the original line ending on changes to files that had one
+    pub fn record_line_endings(&mut self, line_endings: &[FileLineEnding]) {
+        for change in &mut self.file_changes {
+            if let Some(file) = line_endings
+                .iter()
+                .find(|file| file.path.display().to_string() == change.path)
+            {
+                change.line_ending = Some(file.line_ending.as_str().to_string());
+            }
+        }
+    }
+}
+
+/// Changes applied to a single file during a fix
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileChange {
+    pub path: String,
+    /// Tool names (with editor command when present) that modified the file, in order
+    pub changes: Vec<String>,
+    /// Original encoding of a non-UTF-8 file; changes were written back in this encoding
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Line ending the file used before the fix ("lf" or "crlf"); CRLF is kept on write
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_ending: Option<String>,
+}
+
+/// Outcome of a single incident, as counted in the report totals
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IncidentOutcome {
+    Fixed,
+    Failed,
+    Skipped,
+}
+
+/// What fixed an incident
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FixSource {
+    /// The model's edits
+    #[default]
+    Model,
+    /// The incident's `suggested_replacement`, applied without calling the model
+    SuggestedReplacement,
+    /// Edits that fixed the same incident in an earlier request, replayed from the fix cache
+    Cache,
+}
+
+/// A `str_replace` edit applied to a file, which can be replayed on the same content
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TextEdit {
+    pub old_str: String,
+    pub new_str: String,
+}
+
+/// Per-incident result, echoing the incident's tooling metadata back to the client
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IncidentResult {
+    pub incident_id: String,
+    pub outcome: IncidentOutcome,
+    /// What fixed the incident, for `fixed` outcomes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_by: Option<FixSource>,
+    /// The incident's `metadata`, unchanged
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub metadata: serde_json::Value,
+}
+
+/// Change in an incident's outcome, streamed as it happens
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IncidentStatus {
+    pub incident_id: String,
+    pub outcome: IncidentOutcome,
+}
+
+/// An incident as tracked by the collector
+#[derive(Debug)]
+struct TrackedIncident {
+    id: String,
+    path: PathBuf,
+    /// Left out of the prompt, so always skipped
+    trimmed: bool,
+    metadata: serde_json::Value,
+}
+
+/// Accumulates tool activity from the agent stream to build a `MigrationReport`.
+///
+/// Incident outcomes are decided once, as tool responses arrive; the changes returned by
+/// `record_tool_response` are what gets streamed and `finish` reports the same state.
+#[derive(Debug, Default)]
+pub struct MigrationReportCollector {
+    workspace: PathBuf,
+    tool_calls: usize,
+    /// Pending file-modifying tool calls by tool request ID
+    pending: HashMap<String, (PathBuf, String)>,
+    /// Edits of pending `str_replace` calls by tool request ID
+    pending_edits: HashMap<String, TextEdit>,
+    applied: BTreeMap<PathBuf, Vec<String>>,
+    /// Applied edits of each modified file; `None` once any other kind of modification
+    /// was applied to it, as those cannot be replayed
+    applied_edits: BTreeMap<PathBuf, Option<Vec<TextEdit>>>,
+    incidents: Vec<TrackedIncident>,
+    /// Outcome of each attempted incident whose file a tool call modified or failed to
+    outcomes: HashMap<String, IncidentOutcome>,
+    /// Reported as what fixed the fixed incidents
+    fixed_by: FixSource,
+}
+
+impl MigrationReportCollector {
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        Self {
+            workspace: workspace.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Track the request's incidents; those in `trimmed_incident_ids` are reported skipped
+    pub fn with_incidents(
+        mut self,
+        incidents: &[MigrationIncident],
+        trimmed_incident_ids: &[String],
+    ) -> Self {
+        let trimmed: BTreeSet<&str> = trimmed_incident_ids.iter().map(|id| id.as_str()).collect();
+        self.incidents = incidents
+            .iter()
+            .map(|incident| TrackedIncident {
+                id: incident.id.clone(),
+                path: self.resolve(&incident.file_path()),
+                trimmed: trimmed.contains(incident.id.as_str()),
+                metadata: incident.metadata.clone(),
+            })
+            .collect();
+        self
+    }
+
+    /// Report fixed incidents as fixed by `source` rather than the model
+    pub fn with_fix_source(mut self, source: FixSource) -> Self {
+        self.fixed_by = source;
+        self
+    }
+
+    /// Record a tool request; calls carrying a `path` argument (other than views) count as modifications
+    pub fn record_tool_request(
+        &mut self,
+        id: &str,
+        tool_name: &str,
+        arguments: Option<&serde_json::Map<String, serde_json::Value>>,
+    ) {
+        self.tool_calls += 1;
+
+        let Some(arguments) = arguments else { return };
+        let Some(path) = arguments.get("path").and_then(|p| p.as_str()) else {
+            return;
+        };
+        let command = arguments.get("command").and_then(|c| c.as_str());
+        if command == Some("view") {
+            return;
+        }
+
+        let change = match command {
+            Some(command) => format!("{}:{}", tool_name, command),
+            None => tool_name.to_string(),
+        };
+        self.pending
+            .insert(id.to_string(), (self.resolve(Path::new(path)), change));
+
+        let text = |name: &str| arguments.get(name).and_then(|value| value.as_str());
+        if let (Some("str_replace"), Some(old_str), Some(new_str)) =
+            (command, text("old_str"), text("new_str"))
+        {
+            self.pending_edits.insert(
+                id.to_string(),
+                TextEdit {
+                    old_str: old_str.to_string(),
+                    new_str: new_str.to_string(),
+                },
+            );
+        }
+    }
+
+    /// Record the outcome of a tool request and return the incidents whose outcome it
+    /// changed, in request order. One successful modification fixes an incident for good;
+    /// failures only count while none succeeded.
+    pub fn record_tool_response(&mut self, id: &str, success: bool) -> Vec<IncidentStatus> {
+        let edit = self.pending_edits.remove(id);
+        let Some((path, change)) = self.pending.remove(id) else {
+            return Vec::new();
+        };
+        let outcome = if success {
+            IncidentOutcome::Fixed
+        } else {
+            IncidentOutcome::Failed
+        };
+
+        let mut changed = Vec::new();
+        for incident in &self.incidents {
+            if incident.trimmed || incident.path != path {
+                continue;
+            }
+            let current = self.outcomes.get(&incident.id).copied();
+            if current == Some(IncidentOutcome::Fixed) || current == Some(outcome) {
+                continue;
+            }
+            self.outcomes.insert(incident.id.clone(), outcome);
+            changed.push(IncidentStatus {
+                incident_id: incident.id.clone(),
+                outcome,
+            });
+        }
+        if success {
+            let edits = self
+                .applied_edits
+                .entry(path.clone())
+                .or_insert_with(|| Some(Vec::new()));
+            match edit {
+                Some(edit) => {
+                    if let Some(edits) = edits {
+                        edits.push(edit);
+                    }
+                }
+                None => *edits = None,
+            }
+            self.applied.entry(path).or_default().push(change);
+        }
+        changed
+    }
+
+    /// Edits applied to the file of each fixed incident, by incident ID, for incidents
+    /// whose file was only changed by `str_replace` edits
+    pub fn fixed_edits(&self) -> Vec<(String, Vec<TextEdit>)> {
+        self.incidents
+            .iter()
+            .filter(|incident| self.outcomes.get(&incident.id) == Some(&IncidentOutcome::Fixed))
+            .filter_map(|incident| {
+                let edits = self.applied_edits.get(&incident.path)?.as_ref()?;
+                Some((incident.id.clone(), edits.clone()))
+            })
+            .collect()
+    }
+
+    /// Build the final report from the tracked incidents
+    pub fn finish(self, elapsed_ms: u64) -> MigrationReport {
+        let mut report = MigrationReport {
+            tool_calls: self.tool_calls,
+            files_modified: self.applied.len(),
+            elapsed_ms,
+            ..MigrationReport::default()
+        };
+
+        for incident in self.incidents {
+            if !incident.trimmed {
+                report.incidents_attempted += 1;
+            }
+            let outcome = self
+                .outcomes
+                .get(&incident.id)
+                .copied()
+                .unwrap_or(IncidentOutcome::Skipped);
+            match outcome {
+                IncidentOutcome::Fixed => report.incidents_fixed += 1,
+                IncidentOutcome::Failed => report.incidents_failed += 1,
+                IncidentOutcome::Skipped => report.incidents_skipped += 1,
+            }
+            report.incidents.push(IncidentResult {
+                incident_id: incident.id,
+                outcome,
+                fixed_by: (outcome == IncidentOutcome::Fixed).then_some(self.fixed_by),
+                metadata: incident.metadata,
+            });
+        }
+
+        report.file_changes = self
+            .applied
+            .into_iter()
+            .map(|(path, changes)| FileChange {
+                path: path.display().to_string(),
+                changes,
+                encoding: None,
+                line_ending: None,
+            })
+            .collect();
+
+        report
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.workspace.join(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn incident(id: &str, uri: &str) -> MigrationIncident {
+        MigrationIncident::new(
+            id.to_string(),
+            uri.to_string(),
+            "message".to_string(),
+            String::new(),
+        )
+    }
+
+    fn args(value: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn test_report_counts_match_incidents() {
+        let incidents = vec![
+            incident("fixed", "file:///ws/src/A.java"),
+            incident("failed", "file:///ws/src/B.java"),
+            incident("untouched", "file:///ws/src/C.java"),
+            incident("trimmed", "file:///ws/src/D.java"),
+        ];
+
+        let mut collector = MigrationReportCollector::new("/ws")
+            .with_incidents(&incidents, &["trimmed".to_string()]);
+        collector.record_tool_request(
+            "1",
+            "developer__text_editor",
+            Some(&args(serde_json::json!({"command": "view", "path": "/ws/src/A.java"}))),
+        );
+        collector.record_tool_response("1", true);
+        collector.record_tool_request(
+            "2",
+            "developer__text_editor",
+            Some(&args(serde_json::json!({"command": "str_replace", "path": "src/A.java"}))),
+        );
+        collector.record_tool_response("2", true);
+        collector.record_tool_request(
+            "3",
+            "developer__text_editor",
+            Some(&args(serde_json::json!({"command": "write", "path": "/ws/src/B.java"}))),
+        );
+        collector.record_tool_response("3", false);
+        collector.record_tool_request("4", "developer__shell", None);
+        collector.record_tool_response("4", true);
+
+        let report = collector.finish(42);
+
+        assert_eq!(report.incidents_attempted, 3);
+        assert_eq!(report.incidents_fixed, 1);
+        assert_eq!(report.incidents_failed, 1);
+        assert_eq!(report.incidents_skipped, 2);
+        assert_eq!(
+            report.incidents_fixed + report.incidents_failed + report.incidents_skipped,
+            incidents.len()
+        );
+        assert_eq!(report.tool_calls, 4);
+        assert_eq!(report.files_modified, 1);
+        assert_eq!(report.elapsed_ms, 42);
+        assert_eq!(
+            report.file_changes,
+            vec![FileChange {
+                path: "/ws/src/A.java".to_string(),
+                changes: vec!["developer__text_editor:str_replace".to_string()],
+                encoding: None,
+                line_ending: None,
+            }]
+        );
+        let outcomes: Vec<IncidentOutcome> =
+            report.incidents.iter().map(|result| result.outcome).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                IncidentOutcome::Fixed,
+                IncidentOutcome::Failed,
+                IncidentOutcome::Skipped,
+                IncidentOutcome::Skipped,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_turn_reports_merge_into_one() {
+        let incidents: Vec<MigrationIncident> = (1..=4)
+            .map(|i| incident(&format!("a{i}"), "file:///ws/src/A.java"))
+            .collect();
+        let turn = |incidents: &[MigrationIncident], id: &str, success: bool, elapsed_ms| {
+            let mut collector = MigrationReportCollector::new("/ws").with_incidents(incidents, &[]);
+            collector.record_tool_request(
+                id,
+                "developer__text_editor",
+                Some(&args(serde_json::json!({"command": "str_replace", "path": "src/A.java"}))),
+            );
+            collector.record_tool_response(id, success);
+            collector.finish(elapsed_ms)
+        };
+
+        let mut report = MigrationReport::default();
+        report.merge(turn(&incidents[..2], "1", true, 100));
+        report.merge(turn(&incidents[2..], "2", true, 250));
+
+        assert_eq!(report.incidents_attempted, 4);
+        assert_eq!(report.incidents_fixed, 4);
+        assert_eq!(report.tool_calls, 2);
+        assert_eq!(report.elapsed_ms, 250);
+        // Both turns edited the same file: one change entry, edits in turn order
+        assert_eq!(report.files_modified, 1);
+        assert_eq!(
+            report.file_changes[0].changes,
+            vec!["developer__text_editor:str_replace", "developer__text_editor:str_replace"]
+        );
+        let ids: Vec<&str> = report.incidents.iter().map(|i| i.incident_id.as_str()).collect();
+        assert_eq!(ids, vec!["a1", "a2", "a3", "a4"]);
+    }
+}
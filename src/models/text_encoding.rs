@@ -0,0 +1,290 @@
+//! Reading legacy-encoded source files as UTF-8 and restoring their original encoding.
+
+use encoding_rs::{Encoding, UTF_8};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::{KaiakError, KaiakResult};
+
+/// Largest file `read_text` reads unless configured otherwise, 64 MiB
+pub const DEFAULT_MAX_READABLE_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Text decoded from a file along with the encoding it was stored in
+#[derive(Debug, Clone)]
+pub struct DecodedText {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    /// The file had no content at all
+    pub empty: bool,
+}
+
+/// Decode file content, detecting the encoding when it is not UTF-8.
+/// A BOM wins; otherwise valid UTF-8 is taken as is and anything else is guessed by chardetng.
+pub fn decode(bytes: &[u8]) -> DecodedText {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        let (text, _) = encoding.decode_with_bom_removal(bytes);
+        return DecodedText {
+            text: text.into_owned(),
+            encoding,
+            empty: false,
+        };
+    }
+
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return DecodedText {
+            text: text.to_string(),
+            encoding: UTF_8,
+            empty: bytes.is_empty(),
+        };
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, false);
+    let (text, _, _) = encoding.decode(bytes);
+    DecodedText {
+        text: text.into_owned(),
+        encoding,
+        empty: false,
+    }
+}
+
+/// Encode text into `encoding`, or None if a character has no mapping in it
+pub fn encode(text: &str, encoding: &'static Encoding) -> Option<Vec<u8>> {
+    // encoding_rs cannot produce UTF-16; it would silently substitute UTF-8
+    if encoding.output_encoding() != encoding {
+        return None;
+    }
+    let (bytes, _, had_unmappable) = encoding.encode(text);
+    if had_unmappable {
+        None
+    } else {
+        Some(bytes.into_owned())
+    }
+}
+
+/// Read a file as text in whatever encoding it uses. A zero-byte file reads as empty
+/// text; a file over `max_bytes` is refused before any of it is read.
+pub fn read_text(path: &Path, max_bytes: u64) -> KaiakResult<DecodedText> {
+    let file_path = || Some(path.display().to_string());
+    let size = std::fs::metadata(path)
+        .map_err(|e| {
+            KaiakError::file_operation(format!("Failed to read file: {}", e), file_path())
+        })?
+        .len();
+    if size > max_bytes {
+        return Err(KaiakError::file_operation(
+            format!(
+                "File is {} bytes, over the {} byte max_readable_file_bytes limit",
+                size, max_bytes
+            ),
+            file_path(),
+        ));
+    }
+    let bytes = std::fs::read(path).map_err(|e| {
+        KaiakError::file_operation(format!("Failed to read file: {}", e), file_path())
+    })?;
+    Ok(decode(&bytes))
+}
+
+/// A file temporarily rewritten as UTF-8 for the agent
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TranscodedFile {
+    pub path: PathBuf,
+    /// WHATWG name of the original encoding, e.g. "windows-1252" or "Shift_JIS"
+    pub encoding: String,
+    /// Content before it was transcoded, for backups
+    #[serde(skip)]
+    pub original: Vec<u8>,
+}
+
+/// Rewrite non-UTF-8 text files as UTF-8 so the agent's tools can read and edit them.
+/// Binary files, BOM-marked files, files over `max_bytes`, and files whose bytes would
+/// not survive a decode/encode round trip are left untouched.
+pub fn transcode_to_utf8<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    max_bytes: u64,
+) -> Vec<TranscodedFile> {
+    let mut transcoded = Vec::new();
+    for path in paths {
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.len() <= max_bytes => {}
+            Ok(metadata) => {
+                debug!(
+                    "Not transcoding {}: {} bytes is over the {} byte limit",
+                    path.display(),
+                    metadata.len(),
+                    max_bytes
+                );
+                continue;
+            }
+            Err(_) => continue,
+        }
+        let Ok(bytes) = std::fs::read(path) else {
+            continue;
+        };
+        if bytes.contains(&0)
+            || std::str::from_utf8(&bytes).is_ok()
+            || Encoding::for_bom(&bytes).is_some()
+        {
+            continue;
+        }
+
+        let decoded = decode(&bytes);
+        if encode(&decoded.text, decoded.encoding).as_deref() != Some(bytes.as_slice()) {
+            debug!(
+                "Not transcoding {}: {} does not round-trip",
+                path.display(),
+                decoded.encoding.name()
+            );
+            continue;
+        }
+
+        match std::fs::write(path, decoded.text.as_bytes()) {
+            Ok(()) => {
+                info!(
+                    "Transcoded {} from {} to UTF-8",
+                    path.display(),
+                    decoded.encoding.name()
+                );
+                transcoded.push(TranscodedFile {
+                    path: path.to_path_buf(),
+                    encoding: decoded.encoding.name().to_string(),
+                    original: bytes,
+                });
+            }
+            Err(e) => warn!("Failed to transcode {}: {}", path.display(), e),
+        }
+    }
+    transcoded
+}
+
+/// Convert files written by `transcode_to_utf8` back to their original encoding.
+/// A file that gained characters its encoding cannot represent is left as UTF-8.
+pub fn restore_encodings(files: &[TranscodedFile]) {
+    for file in files {
+        let Some(encoding) = Encoding::for_label(file.encoding.as_bytes()) else {
+            continue;
+        };
+        let result =
+            std::fs::read_to_string(&file.path).and_then(|text| match encode(&text, encoding) {
+                Some(bytes) => std::fs::write(&file.path, bytes),
+                None => {
+                    warn!(
+                        "{} now contains characters {} cannot represent; leaving it as UTF-8",
+                        file.path.display(),
+                        file.encoding
+                    );
+                    Ok(())
+                }
+            });
+        if let Err(e) = result {
+            warn!(
+                "Failed to restore {} encoding of {}: {}",
+                file.encoding,
+                file.path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY_SOURCE: &str = "// Le café est très apprécié à Genève, où l'été est doux.\n\
+                                 String greeting = \"déjà vu\";\n";
+
+    #[test]
+    fn test_latin1_modification_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Legacy.java");
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252.encode(LEGACY_SOURCE);
+        std::fs::write(&path, &latin1).unwrap();
+
+        let transcoded = transcode_to_utf8([path.as_path()], DEFAULT_MAX_READABLE_FILE_BYTES);
+        assert_eq!(transcoded.len(), 1);
+        assert_eq!(transcoded[0].encoding, "windows-1252");
+
+        // The agent reads and edits the file as UTF-8
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text, LEGACY_SOURCE);
+        std::fs::write(&path, text.replace("déjà vu", "à bientôt")).unwrap();
+
+        restore_encodings(&transcoded);
+
+        let edited = LEGACY_SOURCE.replace("déjà vu", "à bientôt");
+        let (expected, _, _) = encoding_rs::WINDOWS_1252.encode(&edited);
+        assert_eq!(std::fs::read(&path).unwrap(), expected.as_ref());
+    }
+
+    #[test]
+    fn test_file_over_limit_is_not_transcoded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Legacy.java");
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252.encode(LEGACY_SOURCE);
+        std::fs::write(&path, &latin1).unwrap();
+
+        assert!(transcode_to_utf8([path.as_path()], latin1.len() as u64 - 1).is_empty());
+        assert_eq!(std::fs::read(&path).unwrap(), latin1.as_ref());
+    }
+
+    #[test]
+    fn test_unmappable_edit_stays_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Legacy.java");
+        let (latin1, _, _) = encoding_rs::WINDOWS_1252.encode(LEGACY_SOURCE);
+        std::fs::write(&path, &latin1).unwrap();
+
+        let transcoded = transcode_to_utf8([path.as_path()], DEFAULT_MAX_READABLE_FILE_BYTES);
+        let edited = LEGACY_SOURCE.replace("déjà vu", "既視感");
+        std::fs::write(&path, &edited).unwrap();
+        restore_encodings(&transcoded);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), edited);
+    }
+
+    #[test]
+    fn test_utf8_and_binary_files_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let utf8 = dir.path().join("Main.java");
+        std::fs::write(&utf8, "class Main { /* héllo */ }\n").unwrap();
+        let binary = dir.path().join("logo.png");
+        std::fs::write(&binary, [0x89, b'P', b'N', b'G', 0x00, 0xe9]).unwrap();
+
+        assert!(transcode_to_utf8(
+            [utf8.as_path(), binary.as_path()],
+            DEFAULT_MAX_READABLE_FILE_BYTES
+        )
+        .is_empty());
+        assert_eq!(
+            read_text(&utf8, DEFAULT_MAX_READABLE_FILE_BYTES)
+                .unwrap()
+                .encoding,
+            UTF_8
+        );
+    }
+
+    #[test]
+    fn test_empty_file_reads_empty_and_oversized_file_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("Empty.java");
+        std::fs::write(&empty, "").unwrap();
+        let decoded = read_text(&empty, DEFAULT_MAX_READABLE_FILE_BYTES).unwrap();
+        assert_eq!(decoded.text, "");
+        assert!(decoded.empty);
+
+        let large = dir.path().join("Large.java");
+        std::fs::write(&large, "class Large {}\n").unwrap();
+        assert!(!read_text(&large, 15).unwrap().empty);
+        match read_text(&large, 14) {
+            Err(KaiakError::FileOperation { message, file_path }) => {
+                assert!(message.contains("15 bytes"), "{}", message);
+                assert_eq!(file_path, Some(large.display().to_string()));
+            }
+            other => panic!("expected a file operation error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Model token usage accounting for a generate_fix request.
+
+use serde::{Deserialize, Serialize};
+
+/// Token counts for one model turn or a whole request
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// Some counts were estimated from message text because the provider reported none
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+impl TokenUsage {
+    pub fn new(prompt_tokens: u64, completion_tokens: u64, estimated: bool) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            estimated,
+        }
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.estimated |= other.estimated;
+    }
+}
+
+/// Accumulates usage across the model turns of a request, including the follow-up
+/// turns driven by tool calls.
+///
+/// Provider-reported counts are read from the session's running input/output counters;
+/// a turn that did not move them is estimated instead.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    total: TokenUsage,
+    /// Session input/output counters as of the last turn
+    reported: (u64, u64),
+    /// Estimated size of the conversation so far, which is resent as the next prompt
+    context_tokens: u64,
+}
+
+impl UsageTracker {
+    /// Start tracking from the prompt's estimated size and the session's current counters
+    pub fn new(prompt_tokens: u64, reported: Option<(u64, u64)>) -> Self {
+        Self {
+            total: TokenUsage::default(),
+            reported: reported.unwrap_or_default(),
+            context_tokens: prompt_tokens,
+        }
+    }
+
+    /// Add input the next turn will see, such as tool results
+    pub fn record_input(&mut self, tokens: u64) {
+        self.context_tokens += tokens;
+    }
+
+    /// Record a completed model turn and return its usage.
+    /// `reported` is the session's counters after the turn, if the provider keeps them.
+    pub fn record_turn(
+        &mut self,
+        completion_tokens: u64,
+        reported: Option<(u64, u64)>,
+    ) -> TokenUsage {
+        let turn = match reported {
+            Some((input, output)) if (input, output) != self.reported => {
+                let turn = TokenUsage::new(
+                    input.saturating_sub(self.reported.0),
+                    output.saturating_sub(self.reported.1),
+                    false,
+                );
+                self.reported = (input, output);
+                turn
+            }
+            _ => TokenUsage::new(self.context_tokens, completion_tokens, true),
+        };
+        self.context_tokens += completion_tokens;
+        self.total.add(&turn);
+        turn
+    }
+
+    pub fn total(&self) -> TokenUsage {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_accumulates_across_turns() {
+        let mut tracker = UsageTracker::new(100, Some((500, 40)));
+        let mut totals = vec![tracker.total()];
+
+        // Reported by the provider: the delta of the session counters
+        let first = tracker.record_turn(20, Some((650, 70)));
+        assert_eq!(first, TokenUsage::new(150, 30, false));
+        totals.push(tracker.total());
+
+        // A tool result feeds the follow-up turn, which the provider did not report
+        tracker.record_input(30);
+        let second = tracker.record_turn(10, Some((650, 70)));
+        assert_eq!(second, TokenUsage::new(100 + 20 + 30, 10, true));
+        totals.push(tracker.total());
+
+        let third = tracker.record_turn(5, None);
+        assert!(third.estimated);
+        totals.push(tracker.total());
+
+        for pair in totals.windows(2) {
+            assert!(pair[1].prompt_tokens >= pair[0].prompt_tokens);
+            assert!(pair[1].completion_tokens >= pair[0].completion_tokens);
+            assert!(pair[1].total_tokens > pair[0].total_tokens);
+        }
+        let total = tracker.total();
+        assert_eq!(total.total_tokens, total.prompt_tokens + total.completion_tokens);
+        assert!(total.estimated);
+    }
+}
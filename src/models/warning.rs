@@ -0,0 +1,29 @@
+//! Non-fatal issues collected while processing a request.
+
+use serde::{Deserialize, Serialize};
+
+/// Something that did not fail the request but may affect the result
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Warning {
+    /// Stable machine-readable code, e.g. "incident_skipped"
+    pub code: String,
+    pub message: String,
+    /// Details such as the affected incident ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<serde_json::Value>,
+}
+
+impl Warning {
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+            context: None,
+        }
+    }
+
+    pub fn with_context(mut self, context: serde_json::Value) -> Self {
+        self.context = Some(context);
+        self
+    }
+}
@@ -4,7 +4,7 @@ pub mod server;
 // Export specific items to avoid naming conflicts
 pub use transport::{Transport, TransportConfig as OldTransportConfig};
 pub use server::{
-    start_server, start_stdio_server, start_unix_socket_server,
+    start_server, check_server, start_stdio_server, start_unix_socket_server,
     create_default_server_config, validate_server_config,
-    TransportConfig,
+    ServerCheck, TransportConfig,
 };
\ No newline at end of file
@@ -9,7 +9,7 @@ use crate::{
         create_kaiak_server,
         transport::TransportConfig as JsonRpcTransportConfig,
     },
-    models::configuration::ServerConfig,
+    models::configuration::{Framing, ServerConfig},
 };
 
 /// Transport configuration for the server
@@ -21,45 +21,133 @@ pub enum TransportConfig {
     UnixSocket { path: String },
 }
 
-impl From<TransportConfig> for JsonRpcTransportConfig {
-    fn from(config: TransportConfig) -> Self {
-        match config {
-            TransportConfig::Stdio => JsonRpcTransportConfig::Stdio,
-            TransportConfig::UnixSocket { path } => JsonRpcTransportConfig::UnixSocket { path },
+impl TransportConfig {
+    /// The JSON-RPC transport for this configuration, framing messages as given
+    fn into_jsonrpc(self, framing: Framing) -> JsonRpcTransportConfig {
+        match self {
+            TransportConfig::Stdio => JsonRpcTransportConfig::Stdio { framing },
+            TransportConfig::UnixSocket { path } => {
+                JsonRpcTransportConfig::UnixSocket { path, framing }
+            }
         }
     }
 }
 
-/// Create and start the Kaiak JSON-RPC server with the specified configuration
-pub async fn start_server(
-    server_config: Arc<ServerConfig>,
+/// Resolve the transport to serve on and record it in a copy of the server configuration
+fn resolve_transport(
+    server_config: &ServerConfig,
     transport_config: Option<TransportConfig>,
-) -> Result<()> {
-    info!("Starting Kaiak JSON-RPC server");
-
+) -> Result<(Arc<ServerConfig>, JsonRpcTransportConfig)> {
     // Use transport from configuration if not explicitly provided
     let transport = if let Some(transport) = transport_config {
-        transport.into()
+        transport.into_jsonrpc(server_config.init_config.framing)
     } else {
         JsonRpcTransportConfig::from_init_config(&server_config.init_config)?
     };
 
     // Override the transport in server config for consistency
-    let mut config_copy = (*server_config).clone();
+    let mut config_copy = server_config.clone();
     match &transport {
-        JsonRpcTransportConfig::Stdio => {
+        JsonRpcTransportConfig::Stdio { .. } => {
             config_copy.init_config.transport = "stdio".to_string();
             config_copy.init_config.socket_path = None;
         },
-        JsonRpcTransportConfig::UnixSocket { path } => {
+        JsonRpcTransportConfig::UnixSocket { path, .. } => {
             config_copy.init_config.transport = "socket".to_string();
             config_copy.init_config.socket_path = Some(path.clone());
         },
     }
-    let server_config = Arc::new(config_copy);
+    Ok((Arc::new(config_copy), transport))
+}
+
+/// Outcome of a dry-run server startup
+#[derive(Debug, Clone)]
+pub struct ServerCheck {
+    /// The transport that would be served, e.g. "unix socket /run/kaiak.sock"
+    pub transport: String,
+    /// Methods that would be registered, sorted by name
+    pub methods: Vec<String>,
+}
+
+/// Build the server as `start_server` would — open the transport and register
+/// all methods — then tear it down again without serving any requests.
+pub async fn check_server(
+    server_config: Arc<ServerConfig>,
+    transport_config: Option<TransportConfig>,
+) -> Result<ServerCheck> {
+    let (server_config, transport) = resolve_transport(&server_config, transport_config)?;
+    validate_server_config(&server_config)?;
+
+    let transport_description = match &transport {
+        JsonRpcTransportConfig::Stdio { .. } => "stdio".to_string(),
+        JsonRpcTransportConfig::UnixSocket { path, .. } => {
+            // Binding replaces an existing socket file, which would cut off a live server
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                anyhow::bail!("Socket {} is already in use by a running server", path);
+            }
+            format!("unix socket {}", path)
+        },
+    };
 
-    // Create and start JSON-RPC server
     let session_manager = Arc::new(crate::agent::GooseAgentManager::new());
+    let mut kaiak_server = create_kaiak_server(server_config, session_manager).await?;
+    let mut methods = kaiak_server.get_registered_methods().await;
+    methods.sort();
+    kaiak_server.stop().await?;
+
+    Ok(ServerCheck {
+        transport: transport_description,
+        methods,
+    })
+}
+
+/// Create and start the Kaiak JSON-RPC server with the specified configuration
+pub async fn start_server(
+    server_config: Arc<ServerConfig>,
+    transport_config: Option<TransportConfig>,
+) -> Result<()> {
+    info!("Starting Kaiak JSON-RPC server");
+
+    let (server_config, transport) = resolve_transport(&server_config, transport_config)?;
+
+    // Create and start JSON-RPC server
+    let mut agent_manager = crate::agent::GooseAgentManager::new()
+        .with_max_concurrent_model_calls(
+            server_config
+                .init_config
+                .max_concurrent_model_calls
+                .map(|max| max as usize),
+        )
+        .with_base_config(Arc::new(server_config.base_config.clone()))
+        .with_extension_timeouts(server_config.base_config.extension_timeouts.clone())
+        .with_allowed_providers(server_config.base_config.allowed_providers.clone())
+        .with_scratch_dir(server_config.base_config.scratch_dir.clone())
+        .with_agent_pool(
+            server_config.base_config.agent_pool_size,
+            server_config.base_config.model.clone(),
+        );
+    if let Some(dir) = &server_config.init_config.model_recording_dir {
+        let recorder = crate::agent::FileRecordingInterceptor::new(dir)
+            .map_err(|e| anyhow::anyhow!("Failed to create model recording directory {}: {}", dir, e))?;
+        info!("Recording model exchanges to {}", dir);
+        agent_manager = agent_manager.with_model_interceptor(Arc::new(recorder));
+    }
+    if let Some(dir) = &server_config.init_config.request_log_dir {
+        let retention_days = server_config.init_config.request_log_retention_days;
+        info!("Writing per-request logs to {} (kept for {} days)", dir, retention_days);
+        crate::logging::spawn_request_log_cleanup(
+            dir.into(),
+            crate::logging::request_log_retention(retention_days),
+            crate::logging::REQUEST_LOG_CLEANUP_INTERVAL,
+        );
+        crate::logging::request_log_dir().set(Some(dir.into()));
+    }
+    if let Some(pool) = agent_manager.agent_pool() {
+        info!("Warming agent pool with {} agents", pool.size());
+        let pool = pool.clone();
+        tokio::spawn(async move { pool.fill().await });
+    }
+    let session_manager = Arc::new(agent_manager);
     let mut kaiak_server = create_kaiak_server(server_config.clone(), session_manager).await?;
 
     info!("Starting Kaiak JSON-RPC server with {} transport", transport.description());
@@ -133,11 +221,13 @@ mod tests {
 
     fn create_test_server_config() -> ServerConfig {
         ServerConfig {
+            schema_version: crate::models::configuration::CONFIG_SCHEMA_VERSION,
             init_config: InitConfig {
                 transport: "stdio".to_string(),
                 socket_path: None,
                 log_level: "info".to_string(),
                 max_concurrent_sessions: 10,
+                ..InitConfig::default()
             },
             base_config: BaseConfig::default(),
         }
@@ -146,17 +236,23 @@ mod tests {
     #[test]
     fn test_transport_config_conversion() {
         let stdio_config = TransportConfig::Stdio;
-        let json_rpc_config: JsonRpcTransportConfig = stdio_config.into();
-        assert!(matches!(json_rpc_config, JsonRpcTransportConfig::Stdio));
+        let json_rpc_config = stdio_config.into_jsonrpc(Framing::Ndjson);
+        assert!(matches!(
+            json_rpc_config,
+            JsonRpcTransportConfig::Stdio {
+                framing: Framing::Ndjson
+            }
+        ));
 
         let socket_config = TransportConfig::UnixSocket {
             path: "/tmp/test.sock".to_string(),
         };
-        let json_rpc_config: JsonRpcTransportConfig = socket_config.into();
+        let json_rpc_config = socket_config.into_jsonrpc(Framing::ContentLength);
 
         match json_rpc_config {
-            JsonRpcTransportConfig::UnixSocket { path } => {
+            JsonRpcTransportConfig::UnixSocket { path, framing } => {
                 assert_eq!(path, "/tmp/test.sock");
+                assert_eq!(framing, Framing::ContentLength);
             },
             _ => assert!(false, "Expected UnixSocket transport"),
         }
@@ -180,6 +276,29 @@ mod tests {
         assert!(validate_server_config(&socket_config).is_ok());
     }
 
+    #[tokio::test]
+    async fn test_check_server_binds_and_releases_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(create_test_server_config());
+
+        let socket_path = dir.path().join("kaiak.sock").display().to_string();
+        let check = check_server(
+            config.clone(),
+            Some(TransportConfig::UnixSocket { path: socket_path.clone() }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(check.transport, format!("unix socket {}", socket_path));
+        assert!(check.methods.iter().any(|m| m == crate::jsonrpc::GENERATE_FIX));
+        assert!(!std::path::Path::new(&socket_path).exists());
+
+        let unbindable = dir.path().join("missing/kaiak.sock").display().to_string();
+        let err = check_server(config, Some(TransportConfig::UnixSocket { path: unbindable }))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Failed to bind"), "{}", err);
+    }
+
     #[test]
     fn test_create_default_server_config() {
         let config = create_default_server_config();